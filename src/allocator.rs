@@ -45,6 +45,42 @@ pub fn init_heap(
     Ok(())
 }
 
+/// Heap usage in bytes as `(used, free, total)`
+pub fn heap_stats() -> (usize, usize, usize) {
+    let allocator = ALLOCATOR.lock();
+    let total = allocator.size();
+    let used = allocator.used();
+    (used, total - used, total)
+}
+
+/// How many tags the out-of-memory report lists
+const OOM_TOP_TAGS: usize = 3;
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    let (used, free, total) = heap_stats();
+    crate::println!(
+        "Out of memory: failed to allocate {} bytes (align {})",
+        layout.size(),
+        layout.align()
+    );
+    crate::println!("Heap: {} used, {} free of {} bytes", used, free, total);
+
+    // Reporting must not allocate, and the service may be locked by the failing caller
+    match crate::services::memory_service::MEMORY_SERVICE.try_lock() {
+        Some(service) => {
+            let mut top = [("", 0); OOM_TOP_TAGS];
+            let count = service.largest_tags(&mut top);
+            for (tag, size) in &top[..count] {
+                crate::println!("  {:<16} {} bytes", tag, size);
+            }
+        }
+        None => crate::println!("  (memory service busy; consumers unavailable)"),
+    }
+
+    crate::hlt_loop();
+}
+
 pub struct Dummy;
 
 unsafe impl GlobalAlloc for Dummy {
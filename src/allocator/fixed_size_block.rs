@@ -19,6 +19,14 @@ fn list_index(layout: &Layout) -> Option<usize> {
     BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
 }
 
+/// Bytes an allocation of `layout` takes out of the heap
+fn charged_size(layout: &Layout) -> usize {
+    match list_index(layout) {
+        Some(index) => BLOCK_SIZES[index],
+        None => layout.size(),
+    }
+}
+
 struct ListNode {
     next: Option<&'static mut ListNode>,
 }
@@ -26,6 +34,7 @@ struct ListNode {
 pub struct FixedSizeBlockAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
     fallback_allocator: linked_list_allocator::Heap,
+    used: usize,
 }
 
 impl FixedSizeBlockAllocator {
@@ -35,9 +44,20 @@ impl FixedSizeBlockAllocator {
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            used: 0,
         }
     }
 
+    /// Bytes handed out and not yet freed, counting each small allocation as its whole block
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Size of the heap given to `init`
+    pub fn size(&self) -> usize {
+        self.fallback_allocator.size()
+    }
+
     /// Initialize the allocator with the given heap bounds.
     ///
     /// This function is unsafe because the caller must guarantee that the given
@@ -61,7 +81,7 @@ impl FixedSizeBlockAllocator {
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
-        match list_index(&layout) {
+        let ptr = match list_index(&layout) {
             Some(index) => {
                 match allocator.list_heads[index].take() {
                     Some(node) => {
@@ -79,11 +99,16 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 }
             }
             None => allocator.fallback_alloc(layout),
+        };
+        if !ptr.is_null() {
+            allocator.used += charged_size(&layout);
         }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
+        allocator.used -= charged_size(&layout);
         match list_index(&layout) {
             Some(index) => {
                 let new_node = ListNode {
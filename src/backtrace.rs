@@ -0,0 +1,72 @@
+// Kernel backtraces for EMOS Microkernel
+use x86_64::VirtAddr;
+
+/// Deepest call chain `print_backtrace` will follow
+const MAX_DEPTH: usize = 32;
+
+/// Whether the 16-byte frame record at `frame` can be read
+fn frame_readable(frame: u64) -> bool {
+    if frame == 0 || frame % 8 != 0 {
+        return false;
+    }
+    let last = match frame.checked_add(15) {
+        Some(last) => last,
+        None => return false,
+    };
+    let (start, end) = match (VirtAddr::try_new(frame), VirtAddr::try_new(last)) {
+        (Ok(start), Ok(end)) => (start, end),
+        _ => return false,
+    };
+    // Unknown (paging not installed or busy) is treated as unreadable
+    crate::memory::is_mapped(start) == Some(true) && crate::memory::is_mapped(end) == Some(true)
+}
+
+/// Walk the frame-pointer chain from the caller and print each return address.
+/// Returns the number of frames printed.
+///
+/// Addresses are printed raw, to be resolved against the kernel binary with e.g.
+/// `addr2line -e <kernel>`. Stops after `MAX_DEPTH` frames, at a null or unmapped
+/// frame, when the paging lock is busy, or when the chain stops moving up the stack.
+#[inline(never)]
+pub fn print_backtrace() -> usize {
+    let mut frame: u64;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack)) };
+
+    crate::println!("Backtrace:");
+    let mut depth = 0;
+    while depth < MAX_DEPTH && frame_readable(frame) {
+        // Frame record: [saved rbp, return address]
+        let (next, return_addr) = unsafe { (*(frame as *const u64), *((frame + 8) as *const u64)) };
+        if return_addr == 0 {
+            break;
+        }
+        crate::println!("  #{:<2} {:#018x}", depth, return_addr);
+        depth += 1;
+        if next <= frame {
+            break;
+        }
+        frame = next;
+    }
+    depth
+}
+
+#[test_case]
+fn test_backtrace_stops_within_max_depth() {
+    assert!(print_backtrace() <= MAX_DEPTH);
+}
+
+#[test_case]
+fn test_frame_at_top_of_address_space_is_unreadable() {
+    assert!(!frame_readable(u64::MAX & !7));
+    assert!(!frame_readable(0));
+}
+
+#[test_case]
+fn test_frame_is_unreadable_while_paging_is_busy() {
+    let frame: u64;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack)) };
+    assert!(frame_readable(frame));
+
+    let held = crate::memory::with_kernel_paging(|_, _| frame_readable(frame));
+    assert_eq!(held, Some(false));
+}
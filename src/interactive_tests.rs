@@ -296,3 +296,29 @@ pub fn run_stress_tests() {
     
     println!("   Stress tests completed successfully!");
 }
+
+/// `dumpmappings <addr>`: print how `addr` is mapped, level by level
+pub fn dump_mappings(addr: u64) {
+    let addr = match x86_64::VirtAddr::try_new(addr) {
+        Ok(addr) => addr,
+        Err(_) => {
+            println!("{:#x} is not a canonical address", addr);
+            return;
+        }
+    };
+    match crate::memory::translate_verbose(addr) {
+        Some(info) => {
+            println!("{:#x} -> {:#x} ({} byte page)", addr.as_u64(), info.phys_addr.as_u64(), info.page_size);
+            for (level, flags) in &info.levels {
+                println!("   L{}: {:?}", level, flags);
+            }
+            println!(
+                "   effective: {}{}{}",
+                if info.writable { "rw" } else { "r-" },
+                if info.executable { "x" } else { "-" },
+                if info.user { " user" } else { " kernel" },
+            );
+        }
+        None => println!("{:#x} is not mapped", addr.as_u64()),
+    }
+}
@@ -3,7 +3,8 @@ use crate::{gdt, hlt_loop, println, syscalls};
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use spin;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 use x86_64::PrivilegeLevel;
 use x86_64::VirtAddr;
 
@@ -48,6 +49,10 @@ lazy_static! {
         idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
         // keyboard -> IRQ1
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        // every other PIC line (IRQ2..IRQ15), including the spurious IRQ7/IRQ15
+        for (i, handler) in UNHANDLED_IRQ_HANDLERS.iter().enumerate() {
+            idt[usize::from(PIC_1_OFFSET) + 2 + i].set_handler_fn(*handler);
+        }
         // syscall -> 0x80
         // idt[InterruptIndex::Syscall.as_usize()]
         // .set_handler_fn(syscall_interrupt_handler)
@@ -81,6 +86,7 @@ pub fn print_pic_masks() {
 }
 
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    let _irq = crate::vga_buffer::enter_interrupt();
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
@@ -89,6 +95,34 @@ extern "x86-interrupt" fn page_fault_handler(
     error_code: PageFaultErrorCode,
 ) {
     use x86_64::registers::control::Cr2;
+    use crate::userspace::{grow_user_stack, StackFault};
+
+    let irq = crate::vga_buffer::enter_interrupt();
+
+    // Lazy user stack growth: map the page and let the faulting write retry
+    let is_write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+    let is_present = error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION);
+    let fault = if is_write && !is_present {
+        match grow_user_stack(Cr2::read()) {
+            Ok(_) => return,
+            Err(fault) => Some(fault),
+        }
+    } else {
+        None
+    };
+
+    // User code that faults dies; the kernel carries on without it
+    if error_code.contains(PageFaultErrorCode::USER_MODE) {
+        match fault {
+            Some(StackFault::NotStackAddress) | None => println!("User page fault at {:?}", Cr2::read()),
+            Some(fault) => println!("User stack fault at {:?}: {:?}", Cr2::read(), fault),
+        }
+        if let Some(pid) = terminate_faulting_process() {
+            crate::log::error!("Terminated process PID {}", pid);
+            drop(irq);
+            unsafe { resume_kernel() }
+        }
+    }
 
     println!("EXCEPTION: PAGE FAULT");
     println!("Accessed Address: {:?}", Cr2::read());
@@ -97,33 +131,180 @@ extern "x86-interrupt" fn page_fault_handler(
     hlt_loop();
 }
 
+/// Size of the stack the kernel continues on after a user fault
+const RESUME_STACK_SIZE: usize = 16 * 1024;
+static mut RESUME_STACK: [u8; RESUME_STACK_SIZE] = [0; RESUME_STACK_SIZE];
+
+/// Leave a fault handler for the kernel's executor loop on a fresh stack.
+///
+/// For faults raised by user code whose process is already terminated: nothing of the
+/// interrupted context is resumed, and whatever kernel context entered userspace was
+/// abandoned when it did.
+///
+/// # Safety
+/// The caller must hold no locks or guards, since their destructors never run.
+unsafe fn resume_kernel() -> ! {
+    let top = (core::ptr::addr_of!(RESUME_STACK) as u64 + RESUME_STACK_SIZE as u64) & !0xf;
+    core::arch::asm!(
+        "mov rsp, {top}",
+        "xor ebp, ebp",
+        "call {idle}",
+        "ud2",
+        top = in(reg) top,
+        idle = in(reg) kernel_idle as extern "C" fn() -> !,
+        options(noreturn)
+    );
+}
+
+/// Where `resume_kernel` lands: run tasks as the idle kernel does
+extern "C" fn kernel_idle() -> ! {
+    x86_64::instructions::interrupts::enable();
+    crate::task::executor::run()
+}
+
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
     _error_code: u64,
 ) -> ! {
+    // A double fault cannot return, but one raised by user code leaves the kernel intact:
+    // kill the process and carry on in the executor loop, off the IST stack
+    if from_user_mode(stack_frame.code_segment) {
+        let irq = crate::vga_buffer::enter_interrupt();
+        if let Some(pid) = terminate_faulting_process() {
+            crate::log::error!("EXCEPTION: DOUBLE FAULT in user mode; terminated process PID {}", pid);
+            drop(irq);
+            unsafe { resume_kernel() }
+        }
+    }
     panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
+/// Exit code of a process killed for a fault it caused
+pub const FAULT_EXIT_CODE: i32 = -1;
+
+/// Whether a fault's saved code segment selector belongs to ring 3
+fn from_user_mode(code_segment: u64) -> bool {
+    code_segment & 0b11 == PrivilegeLevel::Ring3 as u64
+}
+
+/// Terminate the current process for a fault it raised and schedule another.
+///
+/// Returns the terminated pid, or `None` if there is no current process or the process
+/// service is locked; the fault cannot safely be pinned on a process then.
+fn terminate_faulting_process() -> Option<crate::process::pcb::ProcessId> {
+    use crate::services::process_service::PROCESS_SERVICE;
+
+    let mut service = PROCESS_SERVICE.try_lock()?;
+    let pid = service.get_current_process()?;
+    service.terminate_process(pid, FAULT_EXIT_CODE).ok()?;
+    service.schedule_next();
+    Some(pid)
+}
+
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    crate::scheduler::on_tick(); // run one task
+    let irq = crate::vga_buffer::enter_interrupt();
+    let now = crate::time::tick();
+    crate::scheduler::on_tick(); // request a pass over the tasks
+    crate::services::process_service::account_tick();
+    crate::services::process_service::wake_sleepers(now);
+    crate::task::timer::wake_expired(now);
+    crate::services::process_service::watchdog_tick();
 
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
+
+    // Tasks are not polled here: `executor::run` does the pass `on_tick` requested,
+    // outside any interrupt frame
+    drop(irq);
+}
+
+/// Number of spurious IRQ7/IRQ15 interrupts seen
+static SPURIOUS_IRQS: AtomicU64 = AtomicU64::new(0);
+
+pub fn spurious_interrupt_count() -> u64 {
+    SPURIOUS_IRQS.load(Ordering::Relaxed)
+}
+
+/// Read the combined in-service register of both PICs (slave in the high byte)
+fn read_pic_isr() -> u16 {
+    use x86_64::instructions::port::Port;
+
+    const OCW3_READ_ISR: u8 = 0x0b;
+    unsafe {
+        let mut master_cmd = Port::<u8>::new(0x20);
+        let mut slave_cmd = Port::<u8>::new(0xA0);
+        master_cmd.write(OCW3_READ_ISR);
+        slave_cmd.write(OCW3_READ_ISR);
+        (u16::from(slave_cmd.read()) << 8) | u16::from(master_cmd.read())
+    }
+}
+
+/// Shared body for IRQs without a dedicated handler.
+///
+/// IRQ7 and IRQ15 may be spurious: the PIC raises them when a request vanishes before
+/// it is acknowledged. A spurious interrupt has no in-service bit and must not get an
+/// EOI (a spurious IRQ15 still needs one for the master's cascade line).
+fn handle_unhandled_irq(irq: u8) {
+    use x86_64::instructions::port::Port;
+
+    let vector = PIC_1_OFFSET + irq;
+    if irq == 7 || irq == 15 {
+        let in_service = read_pic_isr() & (1 << irq) != 0;
+        if !in_service {
+            SPURIOUS_IRQS.fetch_add(1, Ordering::Relaxed);
+            if irq == 15 {
+                unsafe { Port::<u8>::new(0x20).write(0x20) };
+            }
+            return;
+        }
+    }
+
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(vector);
+    }
+}
+
+macro_rules! unhandled_irq_handlers {
+    ($($name:ident => $irq:expr),* $(,)?) => {
+        $(
+            extern "x86-interrupt" fn $name(_stack_frame: InterruptStackFrame) {
+                handle_unhandled_irq($irq);
+            }
+        )*
+
+        /// Catch-all handlers for IRQ2..IRQ15, in IRQ order
+        const UNHANDLED_IRQ_HANDLERS: [HandlerFunc; 14] = [$($name),*];
+    };
+}
+
+unhandled_irq_handlers! {
+    irq2_handler => 2,
+    irq3_handler => 3,
+    irq4_handler => 4,
+    irq5_handler => 5,
+    irq6_handler => 6,
+    irq7_handler => 7,
+    irq8_handler => 8,
+    irq9_handler => 9,
+    irq10_handler => 10,
+    irq11_handler => 11,
+    irq12_handler => 12,
+    irq13_handler => 13,
+    irq14_handler => 14,
+    irq15_handler => 15,
 }
 
 /// Keyboard IRQ handler (IRQ1)
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let _irq = crate::vga_buffer::enter_interrupt();
     use x86_64::instructions::port::Port;
 
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
 
-    // Debug: print 'K' to VGA
-    crate::syscalls::vga_write_byte(b'K');
-
-    // Forward scancode into keyboard service
+    // Forward scancode into keyboard service, which also decodes it
     crate::services::keyboard_service::add_scancode(scancode);
 
     unsafe {
@@ -316,4 +497,35 @@ extern "C" fn syscall_dispatch(
 fn test_breakpoint_exception() {
     // invoke a breakpoint exception
     x86_64::instructions::interrupts::int3();
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_spurious_irq7_is_ignored() {
+    // A software `int` at the IRQ7 vector has no in-service bit, just like a spurious one
+    let before = spurious_interrupt_count();
+    unsafe { core::arch::asm!("int 0x27", options(nostack)) };
+    assert_eq!(spurious_interrupt_count(), before + 1);
+}
+
+#[test_case]
+fn test_user_fault_terminates_process_instead_of_halting() {
+    use alloc::string::String;
+    use crate::process::pcb::{ProcessPriority, ProcessState};
+    use crate::services::process_service::{
+        create_process, get_current_process, get_process_stats, wait_child, yield_to,
+    };
+
+    // User selectors carry RPL 3; the kernel's carry RPL 0
+    assert!(from_user_mode(0x1b));
+    assert!(!from_user_mode(0x08));
+
+    let pid = create_process(String::from("faulting"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    yield_to(pid).unwrap();
+    assert_eq!(terminate_faulting_process(), Some(pid));
+    assert_eq!(get_process_stats(pid).unwrap().state, ProcessState::Zombie);
+    assert_ne!(get_current_process(), Some(pid));
+    if get_current_process() != Some(0) {
+        yield_to(0).unwrap();
+    }
+    assert_eq!(wait_child(0, Some(pid)), Ok(Some((pid, FAULT_EXIT_CODE))));
+}
@@ -1,27 +1,110 @@
 // src/ipc.rs
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use crate::process::pcb::{ProcessError, ProcessId, ProcessState};
+use crate::process::WaitQueue;
+use crate::services::memory_service::MemoryPermissions;
+use crate::syscalls::SyscallError;
+
+#[derive(Debug, Clone)]
 pub struct Message {
     pub sender: ProcessId,
     pub receiver: ProcessId,
     pub data: MessageData,
+    pub correlation_id: u64, // 0 = not part of a request/reply exchange
 }
 
+#[derive(Debug, Clone)]
 pub enum MessageData {
     MemoryRequest(MemoryRequest),
     DeviceRequest(DeviceRequest),
     ServiceRequest(ServiceRequest),
+    /// Bytes copied out of the sender's memory by `send_buffer`
+    Buffer(Vec<u8>),
 }
 
+impl Message {
+    /// Kernel heap the queued message holds: the entry plus its payload
+    fn heap_cost(&self) -> usize {
+        let payload = match &self.data {
+            MessageData::MemoryRequest(_) => 0,
+            MessageData::DeviceRequest(request) => request.payload.len(),
+            MessageData::ServiceRequest(request) => request.payload.len(),
+            MessageData::Buffer(bytes) => bytes.len(),
+        };
+        core::mem::size_of::<Message>() + payload
+    }
+}
 
-use alloc::collections::VecDeque;
-use spin::Mutex;
+/// Largest payload `send_buffer` copies through the kernel
+pub const MAX_BUFFER_LEN: usize = 4 * 1024;
+
+/// Most kernel heap that messages waiting for one receiver may hold before `send_buffer`
+/// refuses more, so a sender cannot fill the heap with messages nobody reads
+pub const MAX_QUEUED_BYTES: usize = 4 * MAX_BUFFER_LEN;
+
+/// Request handled by the memory service
+#[derive(Debug, Clone)]
+pub enum MemoryRequest {
+    Allocate { size: usize, permissions: MemoryPermissions },
+    Deallocate { region_id: u64 },
+}
+
+/// Request addressed to a device
+#[derive(Debug, Clone)]
+pub struct DeviceRequest {
+    pub device_id: u64,
+    pub command: u64,
+    pub payload: Vec<u8>,
+}
+
+/// Generic request/notification addressed to a service
+#[derive(Debug, Clone)]
+pub struct ServiceRequest {
+    pub service_id: u64,
+    pub opcode: u64,
+    pub payload: Vec<u8>,
+}
 
 pub struct MessageQueue {
     messages: Mutex<VecDeque<Message>>,
+    next_correlation_id: AtomicU64,
+    receivers: WaitQueue, // Processes blocked in `receive_blocking`
 }
 
 impl MessageQueue {
+    pub fn new() -> Self {
+        Self {
+            messages: Mutex::new(VecDeque::new()),
+            next_correlation_id: AtomicU64::new(1),
+            receivers: WaitQueue::new(),
+        }
+    }
+
     pub fn send(&self, message: Message) {
         self.messages.lock().push_back(message);
+        self.receivers.notify_all();
+    }
+
+    /// Send `message` unless the messages already queued for its receiver plus this one
+    /// would hold more than `MAX_QUEUED_BYTES`
+    pub fn send_bounded(&self, message: Message) -> Result<(), SyscallError> {
+        let mut queue = self.messages.lock();
+        let queued: usize = queue
+            .iter()
+            .filter(|m| m.receiver == message.receiver)
+            .map(Message::heap_cost)
+            .sum();
+        if queued + message.heap_cost() > MAX_QUEUED_BYTES {
+            return Err(SyscallError::MessageQueueFull);
+        }
+        queue.push_back(message);
+        drop(queue);
+        self.receivers.notify_all();
+        Ok(())
     }
 
     pub fn receive(&self, receiver: ProcessId) -> Option<Message> {
@@ -29,4 +112,276 @@ impl MessageQueue {
         queue.iter().position(|m| m.receiver == receiver)
             .map(|i| queue.remove(i).unwrap())
     }
-}
\ No newline at end of file
+
+    /// Receive a message for `receiver`, blocking it if none is queued.
+    ///
+    /// On `Ok(None)` the receiver has been parked; it is made Ready by the next send
+    /// and should call this again.
+    pub fn receive_blocking(&self, receiver: ProcessId) -> Result<Option<Message>, ProcessError> {
+        match self.receive(receiver) {
+            Some(message) => Ok(Some(message)),
+            None => self.receivers.wait(receiver).map(|_| None),
+        }
+    }
+
+    /// Receive the message for `receiver` carrying `correlation_id`
+    pub fn receive_correlated(&self, receiver: ProcessId, correlation_id: u64) -> Option<Message> {
+        let mut queue = self.messages.lock();
+        queue.iter().position(|m| m.receiver == receiver && m.correlation_id == correlation_id)
+            .map(|i| queue.remove(i).unwrap())
+    }
+
+    /// Allocate a fresh correlation id for a request
+    pub fn next_correlation_id(&self) -> u64 {
+        self.next_correlation_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Enqueue a copy of `data` for each receiver
+    pub fn multicast(&self, sender: ProcessId, receivers: &[ProcessId], data: MessageData) -> usize {
+        let mut queue = self.messages.lock();
+        for &receiver in receivers {
+            queue.push_back(Message {
+                sender,
+                receiver,
+                data: data.clone(),
+                correlation_id: 0,
+            });
+        }
+        drop(queue);
+        self.receivers.notify_all();
+        receivers.len()
+    }
+}
+
+lazy_static! {
+    pub static ref MESSAGE_QUEUE: MessageQueue = MessageQueue::new();
+}
+
+/// IPC API functions
+pub fn send(sender: ProcessId, receiver: ProcessId, data: MessageData) {
+    MESSAGE_QUEUE.send(Message { sender, receiver, data, correlation_id: 0 });
+    route_to_services(receiver);
+}
+
+/// Send `len` bytes of the current process's memory at `ptr` to `receiver`.
+///
+/// The range is checked to be the sender's user memory and copied into the message,
+/// so the receiver only ever sees the bytes, never a pointer into the sender.
+/// Fails with `MessageQueueFull` while the receiver's unread messages hold `MAX_QUEUED_BYTES`.
+pub fn send_buffer(receiver: ProcessId, ptr: u64, len: usize) -> Result<(), SyscallError> {
+    use crate::services::process_service::get_current_process;
+
+    if len > MAX_BUFFER_LEN {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let bytes = crate::syscalls::copy_from_user(ptr, len)?;
+    let sender = get_current_process().unwrap_or(0);
+    MESSAGE_QUEUE.send_bounded(Message { sender, receiver, data: MessageData::Buffer(bytes), correlation_id: 0 })?;
+    route_to_services(receiver);
+    Ok(())
+}
+
+/// Let in-kernel services answer messages addressed to their mailboxes right away
+fn route_to_services(receiver: ProcessId) {
+    use crate::services::device_service::{handle_pending_requests, DEVICE_SERVICE_PID};
+
+    if receiver == DEVICE_SERVICE_PID {
+        handle_pending_requests();
+    }
+}
+
+pub fn receive(receiver: ProcessId) -> Option<Message> {
+    MESSAGE_QUEUE.receive(receiver)
+}
+
+pub fn receive_blocking(receiver: ProcessId) -> Result<Option<Message>, ProcessError> {
+    MESSAGE_QUEUE.receive_blocking(receiver)
+}
+
+/// Send a request from the current process to `receiver`.
+/// Returns the correlation id the reply will carry.
+pub fn call(receiver: ProcessId, data: MessageData) -> u64 {
+    use crate::services::process_service::get_current_process;
+
+    let correlation_id = MESSAGE_QUEUE.next_correlation_id();
+    MESSAGE_QUEUE.send(Message {
+        sender: get_current_process().unwrap_or(0),
+        receiver,
+        data,
+        correlation_id,
+    });
+    route_to_services(receiver);
+    correlation_id
+}
+
+/// Answer `original`, echoing its correlation id back to its sender
+pub fn reply(original: &Message, data: MessageData) {
+    MESSAGE_QUEUE.send(Message {
+        sender: original.receiver,
+        receiver: original.sender,
+        data,
+        correlation_id: original.correlation_id,
+    });
+}
+
+/// Receive the reply to a request previously made with `call`
+pub fn receive_reply(receiver: ProcessId, correlation_id: u64) -> Option<Message> {
+    MESSAGE_QUEUE.receive_correlated(receiver, correlation_id)
+}
+
+/// Send a copy of `data` to every live process except the sender.
+/// Returns the number of messages enqueued.
+pub fn broadcast(data: MessageData, sender: ProcessId) -> usize {
+    use crate::services::process_service::list_processes;
+
+    let receivers: Vec<ProcessId> = list_processes()
+        .into_iter()
+        .filter(|(pid, _, state)| {
+            *pid != sender && !matches!(state, ProcessState::Terminated | ProcessState::Zombie)
+        })
+        .map(|(pid, _, _)| pid)
+        .collect();
+
+    MESSAGE_QUEUE.multicast(sender, &receivers, data)
+}
+
+/// Send a copy of `data` from the current process to each of `receivers`.
+/// Returns the number of messages enqueued.
+pub fn multicast(receivers: &[ProcessId], data: MessageData) -> usize {
+    use crate::services::process_service::get_current_process;
+
+    let sender = get_current_process().unwrap_or(0);
+    MESSAGE_QUEUE.multicast(sender, receivers, data)
+}
+
+#[test_case]
+fn test_broadcast_reaches_every_receiver() {
+    use alloc::format;
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process};
+
+    let pids: Vec<ProcessId> = (0..3)
+        .map(|i| create_process(format!("bcast_{}", i), ProcessPriority::Normal, 4096, 8192).unwrap())
+        .collect();
+
+    let notice = MessageData::ServiceRequest(ServiceRequest {
+        service_id: 0,
+        opcode: 0,
+        payload: Vec::from(&b"shutdown imminent"[..]),
+    });
+    assert!(broadcast(notice, 0) >= pids.len());
+
+    for &pid in &pids {
+        let message = receive(pid).expect("receiver missed the broadcast");
+        assert_eq!(message.sender, 0);
+        match message.data {
+            MessageData::ServiceRequest(req) => assert_eq!(&req.payload[..], b"shutdown imminent"),
+            _ => panic!("unexpected message data"),
+        }
+        assert!(receive(pid).is_none());
+        let _ = terminate_process(pid, 0);
+    }
+}
+
+#[test_case]
+fn test_call_reply_matches_by_correlation_id() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, get_current_process, terminate_process};
+    use alloc::string::ToString;
+
+    let client = get_current_process().unwrap_or(0);
+    let server = create_process("rpc_server".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+
+    let request = |opcode| MessageData::ServiceRequest(ServiceRequest {
+        service_id: server,
+        opcode,
+        payload: Vec::new(),
+    });
+    let first = call(server, request(1));
+    let second = call(server, request(2));
+    assert_ne!(first, second);
+
+    // Server answers both requests in arrival order
+    while let Some(req) = receive(server) {
+        let opcode = match &req.data {
+            MessageData::ServiceRequest(r) => r.opcode,
+            _ => panic!("unexpected request data"),
+        };
+        reply(&req, request(opcode + 100));
+    }
+
+    // Client collects the replies out of order, matching by id
+    for (id, expected) in [(second, 102), (first, 101)] {
+        let resp = receive_reply(client, id).expect("missing reply");
+        assert_eq!(resp.sender, server);
+        match resp.data {
+            MessageData::ServiceRequest(r) => assert_eq!(r.opcode, expected),
+            _ => panic!("unexpected reply data"),
+        }
+    }
+
+    let _ = terminate_process(server, 0);
+}
+
+#[test_case]
+fn test_blocking_receive_parks_until_send() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, get_process_stats, terminate_process};
+    use alloc::string::ToString;
+
+    let receiver = create_process("ipc_receiver".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let state = || get_process_stats(receiver).unwrap().state;
+
+    assert!(receive_blocking(receiver).unwrap().is_none());
+    assert_eq!(state(), ProcessState::Blocked);
+
+    send(0, receiver, MessageData::MemoryRequest(MemoryRequest::Deallocate { region_id: 9 }));
+    assert_eq!(state(), ProcessState::Ready);
+    let message = receive_blocking(receiver).unwrap().expect("message not delivered");
+    assert!(matches!(message.data, MessageData::MemoryRequest(MemoryRequest::Deallocate { region_id: 9 })));
+
+    let _ = terminate_process(receiver, 0);
+}
+
+#[test_case]
+fn test_send_buffer_copies_only_validated_memory() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process};
+    use crate::userspace::{map_initial_user_stack, USER_STACK_TOP};
+    use alloc::string::ToString;
+
+    let receiver = create_process("buffer_receiver".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+
+    // Kernel memory is not the sender's to pass on
+    assert_eq!(send_buffer(receiver, 0xffff_8000_0000_0000, 16), Err(SyscallError::InvalidMemoryRegion));
+    assert_eq!(send_buffer(receiver, u64::MAX - 4, 16), Err(SyscallError::InvalidMemoryRegion));
+    assert!(receive(receiver).is_none());
+
+    map_initial_user_stack().expect("map top stack page");
+    let ptr = USER_STACK_TOP - 64;
+    crate::syscalls::copy_to_user(ptr, b"payload").unwrap();
+    send_buffer(receiver, ptr, 7).unwrap();
+
+    // Later changes to the sender's memory do not reach the queued copy
+    crate::syscalls::copy_to_user(ptr, b"changed").unwrap();
+    match receive(receiver).expect("buffer not delivered").data {
+        MessageData::Buffer(bytes) => assert_eq!(&bytes[..], b"payload"),
+        _ => panic!("unexpected message data"),
+    }
+
+    // What one receiver has waiting is capped
+    let page = USER_STACK_TOP - MAX_BUFFER_LEN as u64;
+    assert_eq!(send_buffer(receiver, page, MAX_BUFFER_LEN + 1), Err(SyscallError::InvalidArgument));
+    let mut sent = 0;
+    while send_buffer(receiver, page, MAX_BUFFER_LEN).is_ok() {
+        sent += 1;
+        assert!(sent * MAX_BUFFER_LEN <= MAX_QUEUED_BYTES, "receiver queue is unbounded");
+    }
+    assert!(sent >= 1);
+    assert_eq!(send_buffer(receiver, page, MAX_BUFFER_LEN), Err(SyscallError::MessageQueueFull));
+    receive(receiver).unwrap();
+    send_buffer(receiver, page, MAX_BUFFER_LEN).unwrap();
+    while receive(receiver).is_some() {}
+
+    let _ = terminate_process(receiver, 0);
+}
@@ -2,11 +2,13 @@
 #![cfg_attr(test, no_main)]
 #![feature(custom_test_frameworks)]
 #![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 pub mod allocator;
 pub mod gdt;
@@ -23,10 +25,22 @@ pub mod tests;
 pub mod interactive_tests;
 pub mod simple_tests;
 pub mod userspace;
+pub mod ipc;
+pub mod rtc;
+pub mod power;
+pub mod lock_debug;
+pub mod time;
+pub mod tsc;
+pub mod random;
+pub mod backtrace;
+pub mod log;
+pub mod syscall_fuzz;
+pub mod shell;
 
 pub fn init() {
     gdt::init();
     interrupts::init_idt();
+    syscalls::init();
     unsafe { 
         interrupts::PICS.lock().initialize();
         // Explicitly unmask IRQ1 (keyboard)
@@ -81,6 +95,33 @@ pub fn exit_qemu(exit_code: QemuExitCode) {
     }
 }
 
+/// Set by the first panic; from then on printing bypasses every lock
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+pub fn panicking() -> bool {
+    PANICKING.load(Ordering::SeqCst)
+}
+
+/// Show a panic on screen and serial without waiting on any lock, since the panicking
+/// code may hold the one a normal print needs. Returns false without printing if a
+/// panic is already being reported, so a panic while reporting cannot recurse.
+pub fn report_panic(info: &PanicInfo) -> bool {
+    x86_64::instructions::interrupts::disable();
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    serial::force_print(format_args!("{}\n", info));
+    println!("{}", info);
+    backtrace::print_backtrace();
+    true
+}
+
+/// Kernel panic handler: report the panic, then halt for good
+pub fn kernel_panic(info: &PanicInfo) -> ! {
+    report_panic(info);
+    hlt_loop();
+}
+
 pub fn hlt_loop() -> ! {
     loop {
         x86_64::instructions::hlt();
@@ -95,8 +136,18 @@ entry_point!(test_kernel_main);
 
 /// Entry point for `cargo xtest`
 #[cfg(test)]
-fn test_kernel_main(_boot_info: &'static BootInfo) -> ! {
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    use memory::BootInfoFrameAllocator;
+    use x86_64::VirtAddr;
+
     init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install_kernel_paging(mapper, frame_allocator);
+    services::process_service::init_process_service();
+
     test_main();
     hlt_loop();
 }
@@ -0,0 +1,158 @@
+// Lock diagnostics for EMOS Microkernel
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use spin::{Mutex, MutexGuard};
+
+/// A `spin::Mutex` that remembers the call site currently holding it.
+///
+/// Taking the lock again from the context that holds it (same interrupt nesting depth)
+/// can never succeed, so `lock` reports the holder and panics. Any other holder is
+/// waited for, up to `SPIN_LIMIT` tries, after which `lock` panics rather than hang;
+/// interrupt handlers should use `try_lock`.
+pub struct TrackedMutex<T> {
+    name: &'static str,
+    holder: AtomicPtr<Location<'static>>,
+    holder_depth: AtomicUsize, // Interrupt nesting depth of the holder
+    inner: Mutex<T>,
+}
+
+/// Tries `lock` makes on a lock held by another context before giving up
+const SPIN_LIMIT: usize = 1 << 28;
+
+pub struct TrackedGuard<'a, T> {
+    lock: &'a TrackedMutex<T>,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> TrackedMutex<T> {
+    pub fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            holder: AtomicPtr::new(ptr::null_mut()),
+            holder_depth: AtomicUsize::new(0),
+            inner: Mutex::new(value),
+        }
+    }
+
+    /// Acquire the lock, waiting for another holder but panicking with the current
+    /// holder on a double-lock from the holding context
+    #[track_caller]
+    pub fn lock(&self) -> TrackedGuard<'_, T> {
+        let caller = Location::caller();
+        for _ in 0..SPIN_LIMIT {
+            if let Some(guard) = self.try_lock_at(caller) {
+                return guard;
+            }
+            if self.held_by_current_context() {
+                match self.holder() {
+                    Some(holder) => panic!("double lock of {} at {} (held by {})", self.name, caller, holder),
+                    None => panic!("double lock of {} at {}", self.name, caller),
+                }
+            }
+            core::hint::spin_loop();
+        }
+        match self.holder() {
+            Some(holder) => panic!("{} still held by {} after spinning at {}", self.name, holder, caller),
+            None => panic!("{} still held after spinning at {}", self.name, caller),
+        }
+    }
+
+    /// Whether the holder runs at the current interrupt depth, i.e. is this very context
+    fn held_by_current_context(&self) -> bool {
+        self.holder().is_some()
+            && self.holder_depth.load(Ordering::SeqCst) == crate::vga_buffer::interrupt_depth()
+    }
+
+    /// Acquire the lock if it is free
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<TrackedGuard<'_, T>> {
+        self.try_lock_at(Location::caller())
+    }
+
+    fn try_lock_at(&self, caller: &'static Location<'static>) -> Option<TrackedGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        self.holder_depth.store(crate::vga_buffer::interrupt_depth(), Ordering::SeqCst);
+        self.holder.store(caller as *const _ as *mut _, Ordering::SeqCst);
+        Some(TrackedGuard { lock: self, guard })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Call site of the current holder, if the lock is held
+    pub fn holder(&self) -> Option<&'static Location<'static>> {
+        let holder = self.holder.load(Ordering::SeqCst);
+        unsafe { holder.as_ref() }
+    }
+
+    fn report(&self) {
+        match self.holder() {
+            Some(holder) => crate::println!("  {:<20} held by {}", self.name, holder),
+            None => crate::println!("  {:<20} free", self.name),
+        }
+    }
+}
+
+impl<T> Deref for TrackedGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for TrackedGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for TrackedGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.holder.store(ptr::null_mut(), Ordering::SeqCst);
+    }
+}
+
+/// Print the holder of every global service lock
+pub fn dump_lock_holders() {
+    crate::println!("Lock holders:");
+    crate::services::process_service::PROCESS_SERVICE.report();
+    crate::services::memory_service::MEMORY_SERVICE.report();
+    crate::services::file_system_service::FILESYSTEM_SERVICE.report();
+    crate::services::device_service::DEVICE_SERVICE.report();
+    crate::process::context::CONTEXT_MANAGER.report();
+}
+
+#[test_case]
+fn test_tracked_mutex_records_holder() {
+    let lock = TrackedMutex::new("test", 0u32);
+    assert!(lock.holder().is_none());
+
+    let mut guard = lock.lock();
+    *guard += 1;
+    let holder = lock.holder().expect("holder not recorded");
+    assert_eq!(holder.file(), file!());
+    assert!(lock.try_lock().is_none());
+
+    drop(guard);
+    assert!(lock.holder().is_none());
+    assert_eq!(*lock.try_lock().unwrap(), 1);
+}
+
+#[test_case]
+fn test_tracked_mutex_waits_for_a_holder_in_another_context() {
+    let lock = TrackedMutex::new("test", 0u32);
+    let guard = lock.lock();
+    assert!(lock.held_by_current_context());
+
+    // As seen from an interrupt handler, the holder is another context
+    let irq = crate::vga_buffer::enter_interrupt();
+    assert!(!lock.held_by_current_context());
+    drop(irq);
+
+    drop(guard);
+    assert!(!lock.held_by_current_context());
+}
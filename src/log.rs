@@ -0,0 +1,166 @@
+// Leveled kernel logging for EMOS Microkernel
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Severity of a log message; lower is more severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+impl Level {
+    fn from_u8(value: u8) -> Level {
+        match value {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            3 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+/// Messages kept for `recent_logs`
+pub const LOG_RING_CAPACITY: usize = 64;
+/// Longer messages are truncated in the ring (but printed in full)
+const LOG_MESSAGE_BYTES: usize = 96;
+
+static THRESHOLD: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// A message as stored in the ring; fixed-size so logging never allocates
+#[derive(Clone, Copy)]
+struct Slot {
+    level: Level,
+    tick: u64,
+    len: usize,
+    bytes: [u8; LOG_MESSAGE_BYTES],
+}
+
+impl Write for Slot {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len == LOG_MESSAGE_BYTES {
+                break;
+            }
+            self.bytes[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+struct LogRing {
+    slots: [Option<Slot>; LOG_RING_CAPACITY],
+    next: usize, // Slot the next message goes in
+}
+
+// Taken with interrupts off so a handler that logs never spins on it
+static RING: Mutex<LogRing> = Mutex::new(LogRing { slots: [None; LOG_RING_CAPACITY], next: 0 });
+
+/// A message returned by `recent_logs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub level: Level,
+    pub tick: u64,
+    pub message: String,
+}
+
+/// Show and keep only messages at `level` or more severe
+pub fn set_log_level(level: Level) {
+    THRESHOLD.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn log_level() -> Level {
+    Level::from_u8(THRESHOLD.load(Ordering::Relaxed))
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= log_level()
+}
+
+/// Print `args` and record it in the ring if `level` passes the threshold.
+/// Use the `error!` .. `trace!` macros rather than calling this directly.
+pub fn log(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    crate::println!("{}", args);
+
+    let mut slot = Slot { level, tick: crate::time::ticks(), len: 0, bytes: [0; LOG_MESSAGE_BYTES] };
+    let _ = slot.write_fmt(args);
+    without_interrupts(|| {
+        let mut ring = RING.lock();
+        let next = ring.next;
+        ring.slots[next] = Some(slot);
+        ring.next = (next + 1) % LOG_RING_CAPACITY;
+    });
+}
+
+/// Up to `n` of the most recent messages, oldest first
+pub fn recent_logs(n: usize) -> Vec<LogEntry> {
+    let (ring, next) = without_interrupts(|| {
+        let ring = RING.lock();
+        (ring.slots, ring.next)
+    });
+    let mut entries: Vec<LogEntry> = (0..LOG_RING_CAPACITY)
+        .rev()
+        .filter_map(|age| ring[(next + age) % LOG_RING_CAPACITY])
+        .take(n)
+        .map(|slot| LogEntry {
+            level: slot.level,
+            tick: slot.tick,
+            message: String::from_utf8_lossy(&slot.bytes[..slot.len]).into_owned(),
+        })
+        .collect();
+    entries.reverse();
+    entries
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { crate::log::log(crate::log::Level::Error, format_args!($($arg)*)) };
+}
+
+// Named with a trailing underscore because `warn` clashes with the builtin
+// attribute in `use`; re-exported under its real name below
+macro_rules! warn_ {
+    ($($arg:tt)*) => { crate::log::log(crate::log::Level::Warn, format_args!($($arg)*)) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { crate::log::log(crate::log::Level::Info, format_args!($($arg)*)) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { crate::log::log(crate::log::Level::Debug, format_args!($($arg)*)) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => { crate::log::log(crate::log::Level::Trace, format_args!($($arg)*)) };
+}
+
+pub(crate) use {debug, error, info, trace, warn_ as warn};
+
+#[test_case]
+fn test_warn_level_drops_info_but_keeps_error() {
+    let previous = log_level();
+    set_log_level(Level::Warn);
+
+    info!("log test: routine detail");
+    error!("log test: something broke");
+
+    let recent = recent_logs(2);
+    set_log_level(previous);
+    assert!(!recent.iter().any(|entry| entry.message == "log test: routine detail"));
+    let last = recent.last().expect("nothing logged");
+    assert_eq!(last.level, Level::Error);
+    assert_eq!(last.message, "log test: something broke");
+}
@@ -9,8 +9,6 @@ extern crate alloc;
 
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
-use emos::memory::{self, BootInfoFrameAllocator};
-use x86_64::structures::paging::FrameAllocator;
 
 use emos::println;
 
@@ -26,7 +24,7 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     emos::init();
 
-    // Avoid IRQs firing while paging/userspace setup is in progress.
+    // Avoid IRQs firing while paging setup is in progress.
     interrupts::disable();
 
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
@@ -38,89 +36,20 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     initialize_services();
 
-    let (user_entry, user_stack_top) = map_userspace(&mut mapper, &mut frame_allocator);
-
-    println!("Loading EMOS shell binary into memory...");
-    emos::userspace::load_shell_to_memory();
+    // From here on pages are mapped on demand through the kernel's global mapper.
+    memory::install_kernel_paging(mapper, frame_allocator);
 
     emos::scheduler::init_pit(100);
-    emos::scheduler::spawn_demo_tasks();
+    emos::scheduler::spawn(emos::task::Task::new(emos::shell::run()));
     interrupts::enable();
-
-    println!("Entering userspace...");
-    //
-    // Recommended API: enter_userspace(entry_rip, user_stack_top)
-    // If your current enter_userspace only takes RIP, make sure it sets RSP internally.
-    //
-    emos::userspace::enter_userspace(user_entry, user_stack_top);
-
-    // CPU should never return here.
-}
-
-/// Maps shell + user stack into user-accessible pages.
-/// Returns (user_entry_rip, user_stack_top).
-fn map_userspace(
-    mapper: &mut impl x86_64::structures::paging::Mapper<x86_64::structures::paging::Size4KiB>,
-    frame_allocator: &mut BootInfoFrameAllocator,
-) -> (u64, u64) {
-    use emos::userspace;
-    use x86_64::VirtAddr;
-    use x86_64::structures::paging::{Mapper, Page, PageTableFlags as Flags};
-
-    let shell_base = VirtAddr::new(0x0040_0000);
-    let shell_len = userspace::SHELL_BIN.len();
-    let num_shell_pages = (shell_len + 4095) / 4096;
-
-    // Writable for loading; you can tighten permissions later (RX) once stable.
-    let shell_flags = Flags::PRESENT | Flags::USER_ACCESSIBLE | Flags::WRITABLE;
-    let user_table_flags = Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE;
-
-    println!("Mapping {} pages for EMOS shell...", num_shell_pages);
-
-    for i in 0..num_shell_pages {
-        let virt = shell_base + (i as u64) * 4096;
-        let page = Page::containing_address(virt);
-
-        let frame = frame_allocator
-            .allocate_frame()
-            .expect("Unable to allocate frame for shell");
-
-        unsafe {
-            mapper
-                .map_to_with_table_flags(page, frame, shell_flags, user_table_flags, frame_allocator)
-                .expect("map shell page")
-                .flush();
-        }
-    }
-
-    let user_stack_top = VirtAddr::new(0x0080_0000);
-    let user_stack_bottom = VirtAddr::new(0x0070_0000);
-
-    let stack_size = user_stack_top.as_u64() - user_stack_bottom.as_u64();
-    let num_stack_pages = ((stack_size as usize) + 4095) / 4096;
-
-    let stack_flags =
-        Flags::PRESENT | Flags::USER_ACCESSIBLE | Flags::WRITABLE | Flags::NO_EXECUTE;
-
-    println!("Mapping {} pages for user stack...", num_stack_pages);
-
-    for i in 0..num_stack_pages {
-        let virt = user_stack_bottom + (i as u64) * 4096;
-        let page = Page::containing_address(virt);
-
-        let frame = frame_allocator
-            .allocate_frame()
-            .expect("Unable to allocate frame for user stack");
-
-        unsafe {
-            mapper
-                .map_to_with_table_flags(page, frame, stack_flags, user_table_flags, frame_allocator)
-                .expect("map stack page")
-                .flush();
-        }
+    if let Some(per_ms) = emos::tsc::calibrate() {
+        println!("TSC calibrated: {} cycles/ms", per_ms);
     }
+    emos::random::seed_from_tsc();
 
-    (shell_base.as_u64(), user_stack_top.as_u64())
+    // The shell and every other task run from the executor from here on
+    println!("Starting kernel shell...");
+    emos::task::executor::run()
 }
 
 /// Initialize all microkernel services
@@ -141,14 +70,16 @@ fn initialize_services() {
     emos::services::process_service::init_process_service();
     println!("Process management service initialized");
 
+    let devices = emos::services::device_service::list_devices();
+    println!("Device service initialized ({} devices)", devices.len());
+
     println!("All services initialized successfully!");
 }
 
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    emos::hlt_loop();
+    emos::kernel_panic(info)
 }
 
 #[cfg(test)]
@@ -1,8 +1,11 @@
+use alloc::vec::Vec;
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use spin::Mutex;
 use x86_64::{
     PhysAddr, VirtAddr,
     structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable,
+        PageTableFlags, PhysFrame, Size4KiB, mapper::MapToError,
     },
 };
 
@@ -19,6 +22,286 @@ pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static>
     }
 }
 
+/// The kernel's page mapper and frame allocator, available after boot-time mapping
+/// so that fault handlers and services can map pages on demand.
+static KERNEL_PAGING: Mutex<Option<(OffsetPageTable<'static>, BootInfoFrameAllocator)>> =
+    Mutex::new(None);
+
+/// Hand the boot-time mapper and frame allocator over to the kernel for on-demand mapping.
+pub fn install_kernel_paging(mapper: OffsetPageTable<'static>, frame_allocator: BootInfoFrameAllocator) {
+    *KERNEL_PAGING.lock() = Some((mapper, frame_allocator));
+}
+
+/// Run `f` with the kernel mapper and frame allocator.
+///
+/// Returns `None` if `install_kernel_paging` has not been called yet.
+pub fn with_kernel_paging<R>(
+    f: impl FnOnce(&mut OffsetPageTable<'static>, &mut BootInfoFrameAllocator) -> R,
+) -> Option<R> {
+    let mut paging = KERNEL_PAGING.lock();
+    paging.as_mut().map(|(mapper, frame_allocator)| f(mapper, frame_allocator))
+}
+
+/// Whether `addr` is mapped in the kernel page tables.
+///
+/// Never blocks, so it is safe from fault and panic handlers: returns `None` if paging
+/// is not installed yet or its lock is held.
+pub fn is_mapped(addr: VirtAddr) -> Option<bool> {
+    use x86_64::structures::paging::Translate;
+
+    let paging = KERNEL_PAGING.try_lock()?;
+    let (mapper, _) = paging.as_ref()?;
+    Some(mapper.translate_addr(addr).is_some())
+}
+
+/// Physical address `addr` is mapped to in the kernel page tables, or `None` if it is
+/// unmapped or paging is not installed yet
+pub fn physical_address(addr: VirtAddr) -> Option<PhysAddr> {
+    use x86_64::structures::paging::Translate;
+
+    with_kernel_paging(|mapper, _| mapper.translate_addr(addr)).flatten()
+}
+
+/// How one virtual address is mapped, as reported by `translate_verbose`
+#[derive(Debug, Clone)]
+pub struct TranslationInfo {
+    pub phys_addr: PhysAddr,
+    pub page_size: u64,
+    /// Flags of each entry walked, from the level 4 table down to the one mapping the page
+    pub levels: Vec<(u8, PageTableFlags)>,
+    // Effective permissions: every level must allow writes or user access, any may forbid execution
+    pub writable: bool,
+    pub user: bool,
+    pub executable: bool,
+}
+
+/// Walk the active page tables for `addr`, recording each level's entry.
+///
+/// Returns `None` if the address is not mapped, or if paging is not installed yet or
+/// its lock is held, so it is safe to call from fault handlers.
+pub fn translate_verbose(addr: VirtAddr) -> Option<TranslationInfo> {
+    use x86_64::registers::control::Cr3;
+
+    let paging = KERNEL_PAGING.try_lock()?;
+    let (mapper, _) = paging.as_ref()?;
+    let phys_offset = mapper.phys_offset();
+
+    let indices = [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
+    let mut table_addr = Cr3::read().0.start_address();
+    let mut levels = Vec::new();
+    for (depth, &index) in indices.iter().enumerate() {
+        let level = 4 - depth as u8;
+        let table: &PageTable = unsafe { &*(phys_offset + table_addr.as_u64()).as_ptr() };
+        let entry = &table[index];
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        levels.push((level, flags));
+
+        // Level 1 entries always map a page; levels 3 and 2 may map a huge one
+        if level == 1 || (level < 4 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+            let page_size = 4096u64 << (9 * (level as u32 - 1));
+            let phys_addr = entry.addr() + (addr.as_u64() & (page_size - 1));
+            let all = |flag| levels.iter().all(|&(_, flags)| flags.contains(flag));
+            let (writable, user) = (all(PageTableFlags::WRITABLE), all(PageTableFlags::USER_ACCESSIBLE));
+            let executable = !levels.iter().any(|&(_, flags)| flags.contains(PageTableFlags::NO_EXECUTE));
+            return Some(TranslationInfo { phys_addr, page_size, levels, writable, user, executable });
+        }
+        table_addr = entry.addr();
+    }
+    None
+}
+
+/// Map a fresh zeroed frame at every unmapped page overlapping `[start, end)`.
+///
+/// Pages that are already mapped are left alone. On failure every page mapped by
+/// this call is unmapped again. Returns the number of pages mapped.
+pub fn map_range(start: VirtAddr, end: VirtAddr, flags: PageTableFlags) -> Result<usize, MapToError<Size4KiB>> {
+    use x86_64::structures::paging::mapper::{Translate, TranslateResult};
+
+    // Pages this call maps carry `FRESH` until it succeeds, so a rollback can tell them
+    // from pages mapped before without keeping a list of them
+    const FRESH: PageTableFlags = PageTableFlags::BIT_9;
+
+    if end <= start {
+        return Ok(0);
+    }
+    let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let pages = Page::<Size4KiB>::range_inclusive(Page::containing_address(start), Page::containing_address(end - 1u64));
+    let is_fresh = |mapper: &OffsetPageTable, page: Page<Size4KiB>| {
+        matches!(mapper.translate(page.start_address()), TranslateResult::Mapped { flags, .. } if flags.contains(FRESH))
+    };
+
+    with_kernel_paging(|mapper, frame_allocator| {
+        let mut mapped = 0;
+        for page in pages {
+            if mapper.translate_page(page).is_ok() {
+                continue;
+            }
+            let result = frame_allocator
+                .allocate_frame()
+                .ok_or(MapToError::FrameAllocationFailed)
+                .and_then(|frame| unsafe {
+                    mapper.map_to_with_table_flags(page, frame, flags | FRESH, table_flags, frame_allocator)
+                });
+            match result {
+                Ok(flush) => {
+                    flush.flush();
+                    unsafe { core::ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, 4096) };
+                    mapped += 1;
+                }
+                Err(e) => {
+                    for page in Page::range(pages.start, page) {
+                        if is_fresh(mapper, page) {
+                            if let Ok((frame, flush)) = mapper.unmap(page) {
+                                flush.flush();
+                                unsafe { frame_allocator.deallocate_frame(frame) };
+                            }
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        for page in pages {
+            if is_fresh(mapper, page) {
+                if let Ok(flush) = unsafe { mapper.update_flags(page, flags) } {
+                    flush.flush();
+                }
+            }
+        }
+        Ok(mapped)
+    })
+    .unwrap_or(Err(MapToError::FrameAllocationFailed))
+}
+
+/// Unmap every page lying entirely inside `[start, end)` and free its frame.
+/// Returns the number of pages unmapped.
+pub fn unmap_range(start: VirtAddr, end: VirtAddr) -> usize {
+    let first = start.align_up(4096u64);
+    let last = end.align_down(4096u64);
+    if last <= first {
+        return 0;
+    }
+    let pages = Page::<Size4KiB>::range(Page::containing_address(first), Page::containing_address(last));
+
+    with_kernel_paging(|mapper, frame_allocator| {
+        let mut unmapped = 0;
+        for page in pages {
+            if let Ok((frame, flush)) = mapper.unmap(page) {
+                flush.flush();
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                unmapped += 1;
+            }
+        }
+        unmapped
+    })
+    .unwrap_or(0)
+}
+
+/// Give every mapped page lying entirely inside `[start, end)` the page flags `flags`.
+/// Returns the number of pages changed.
+pub fn protect_range(start: VirtAddr, end: VirtAddr, flags: PageTableFlags) -> usize {
+    let first = start.align_up(4096u64);
+    let last = end.align_down(4096u64);
+    if last <= first {
+        return 0;
+    }
+    let pages = Page::<Size4KiB>::range(Page::containing_address(first), Page::containing_address(last));
+
+    with_kernel_paging(|mapper, _| {
+        let mut changed = 0;
+        for page in pages {
+            if let Ok(flush) = unsafe { mapper.update_flags(page, flags) } {
+                flush.flush();
+                changed += 1;
+            }
+        }
+        changed
+    })
+    .unwrap_or(0)
+}
+
+/// Allocate `count` zeroed frames, or none at all if the allocator runs dry.
+pub fn allocate_frames(count: usize) -> Option<Vec<PhysFrame>> {
+    with_kernel_paging(|mapper, frame_allocator| {
+        let mut frames = Vec::with_capacity(count);
+        for _ in 0..count {
+            match frame_allocator.allocate_frame() {
+                Some(frame) => frames.push(frame),
+                None => {
+                    for frame in frames {
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                    }
+                    return None;
+                }
+            }
+        }
+        for frame in &frames {
+            let virt = mapper.phys_offset() + frame.start_address().as_u64();
+            unsafe { core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0, 4096) };
+        }
+        Some(frames)
+    })
+    .flatten()
+}
+
+/// Return frames obtained from `allocate_frames` to the allocator.
+pub fn free_frames(frames: &[PhysFrame]) {
+    with_kernel_paging(|_, frame_allocator| {
+        for &frame in frames {
+            unsafe { frame_allocator.deallocate_frame(frame) };
+        }
+    });
+}
+
+/// Map `frames` at consecutive pages starting at the page-aligned `start`.
+///
+/// The frames stay owned by the caller: on failure the pages mapped so far are
+/// unmapped again without freeing their frames.
+pub fn map_frames(start: VirtAddr, frames: &[PhysFrame], flags: PageTableFlags) -> Result<(), MapToError<Size4KiB>> {
+    let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let first = Page::<Size4KiB>::containing_address(start);
+
+    with_kernel_paging(|mapper, frame_allocator| {
+        for (i, &frame) in frames.iter().enumerate() {
+            let result = unsafe { mapper.map_to_with_table_flags(first + i as u64, frame, flags, table_flags, frame_allocator) };
+            match result {
+                Ok(flush) => flush.flush(),
+                Err(e) => {
+                    for page in Page::range(first, first + i as u64) {
+                        if let Ok((_, flush)) = mapper.unmap(page) {
+                            flush.flush();
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(MapToError::FrameAllocationFailed))
+}
+
+/// Unmap `count` pages starting at the page-aligned `start` without freeing their frames.
+/// Returns the number of pages that were mapped.
+pub fn unmap_pages(start: VirtAddr, count: usize) -> usize {
+    let first = Page::<Size4KiB>::containing_address(start);
+
+    with_kernel_paging(|mapper, _| {
+        let mut unmapped = 0;
+        for page in Page::range(first, first + count as u64) {
+            if let Ok((_, flush)) = mapper.unmap(page) {
+                flush.flush();
+                unmapped += 1;
+            }
+        }
+        unmapped
+    })
+    .unwrap_or(0)
+}
+
 /// Returns a mutable reference to the active level 4 table.
 ///
 /// This function is unsafe because the caller must guarantee that the
@@ -68,6 +351,7 @@ unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    free_frames: Vec<PhysFrame>, // Frames returned by `deallocate_frame`, reused first
 }
 
 impl BootInfoFrameAllocator {
@@ -80,6 +364,7 @@ impl BootInfoFrameAllocator {
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            free_frames: Vec::new(),
         }
     }
 
@@ -99,8 +384,49 @@ impl BootInfoFrameAllocator {
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if let Some(frame) = self.free_frames.pop() {
+            return Some(frame);
+        }
         let frame = self.usable_frames().nth(self.next);
         self.next += 1;
         frame
     }
-}
\ No newline at end of file
+}
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.free_frames.push(frame);
+    }
+}
+
+#[test_case]
+fn test_translate_verbose_reports_heap_and_unmapped_addresses() {
+    let heap = VirtAddr::new(crate::allocator::HEAP_START as u64 + 100);
+    let info = translate_verbose(heap).expect("heap not mapped");
+    assert_eq!(info.levels.len(), 4);
+    assert!(info.levels.iter().all(|&(_, flags)| flags.contains(PageTableFlags::PRESENT)));
+    assert!(info.writable);
+    assert_eq!(info.page_size, 4096);
+    assert_eq!(info.phys_addr.as_u64() & 0xfff, 100);
+
+    // Just past the end of the heap nothing is mapped
+    let past_heap = VirtAddr::new((crate::allocator::HEAP_START + crate::allocator::HEAP_SIZE) as u64);
+    assert!(translate_verbose(past_heap).is_none());
+}
+
+#[test_case]
+fn test_map_range_keeps_mapped_pages_and_drops_its_marker() {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let start = VirtAddr::new(0x0230_0000);
+    assert_eq!(map_range(start + 4096u64, start + 8192u64, flags).ok(), Some(1));
+    unsafe { (start + 4096u64).as_mut_ptr::<u8>().write(0x59) };
+
+    // Only the two unmapped pages around it are new
+    assert_eq!(map_range(start, start + 3 * 4096u64, flags).ok(), Some(2));
+    assert_eq!(unsafe { (start + 4096u64).as_ptr::<u8>().read() }, 0x59);
+    for page in 0..3u64 {
+        let info = translate_verbose(start + page * 4096).expect("page not mapped");
+        assert!(info.levels.last().is_some_and(|&(_, flags)| !flags.contains(PageTableFlags::BIT_9)));
+    }
+    assert_eq!(unmap_range(start, start + 3 * 4096u64), 3);
+}
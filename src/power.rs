@@ -0,0 +1,61 @@
+// Machine power control for EMOS Microkernel
+use crate::{exit_qemu, hlt_loop, QemuExitCode};
+use x86_64::instructions::port::Port;
+
+const KBC_STATUS_PORT: u16 = 0x64;
+const KBC_INPUT_FULL: u8 = 0x02;
+const KBC_PULSE_RESET: u8 = 0xFE;
+
+/// ACPI PM1a control ports and SLP_TYP|SLP_EN values used by common emulators
+const ACPI_SHUTDOWN_PORTS: &[(u16, u16)] = &[
+    (0x604, 0x2000),  // QEMU (PIIX4/ICH9)
+    (0xB004, 0x2000), // Bochs and older QEMU
+    (0x4004, 0x3400), // VirtualBox
+];
+
+/// Reset the machine.
+///
+/// Pulses the CPU reset line through the keyboard controller; if that does not
+/// take effect, forces a triple fault.
+pub fn reboot() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    unsafe {
+        let mut status = Port::<u8>::new(KBC_STATUS_PORT);
+        // Wait for the controller's input buffer to drain before sending the command
+        for _ in 0..100_000 {
+            if status.read() & KBC_INPUT_FULL == 0 {
+                break;
+            }
+        }
+        status.write(KBC_PULSE_RESET);
+    }
+
+    // Triple fault: with an empty IDT, the breakpoint can't be delivered
+    use x86_64::structures::idt::InterruptDescriptorTable;
+    use lazy_static::lazy_static;
+    lazy_static! {
+        static ref EMPTY_IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
+    }
+    EMPTY_IDT.load();
+    x86_64::instructions::interrupts::int3();
+
+    hlt_loop();
+}
+
+/// Power the machine off.
+///
+/// Under QEMU this exits through the isa-debug-exit device (as the test harness
+/// does); otherwise it falls back to the ACPI sleep ports of common emulators.
+pub fn shutdown() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    exit_qemu(QemuExitCode::Success);
+
+    for &(port, value) in ACPI_SHUTDOWN_PORTS {
+        unsafe { Port::<u16>::new(port).write(value) };
+    }
+
+    crate::println!("Shutdown failed; it is now safe to turn off the machine.");
+    hlt_loop();
+}
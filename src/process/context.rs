@@ -2,12 +2,19 @@
 use crate::process::pcb::{ProcessId, ProcessControlBlock, CpuRegisters, ProcessError};
 use alloc::collections::BTreeMap;
 use lazy_static::lazy_static;
-use spin::Mutex;
+use crate::lock_debug::TrackedMutex;
+
+/// Callback run after every successful context switch with the outgoing and incoming pids.
+///
+/// Hooks run with the context manager and usually the process service locked, possibly
+/// from the timer interrupt, so they must be short and must not call back into either.
+pub type SwitchHook = fn(Option<ProcessId>, ProcessId);
 
 /// Context switching manager
 pub struct ContextManager {
     current_process: Option<ProcessId>,
     kernel_stack: u64, // Kernel stack pointer
+    switch_hook: Option<SwitchHook>,
 }
 
 impl ContextManager {
@@ -15,6 +22,7 @@ impl ContextManager {
         Self {
             current_process: None,
             kernel_stack: 0xFFFF_8000_0000_0000, // High kernel stack
+            switch_hook: None,
         }
     }
 
@@ -23,7 +31,7 @@ impl ContextManager {
         if let Some(pcb) = processes.get_mut(&pid) {
             // Save current CPU registers to PCB
             pcb.registers = self.get_current_registers();
-            crate::println!("Saved context for process PID {}", pid);
+            crate::log::debug!("Saved context for process PID {}", pid);
             Ok(())
         } else {
             Err(ProcessError::ProcessNotFound)
@@ -36,7 +44,7 @@ impl ContextManager {
             // Restore CPU registers from PCB
             self.set_registers(&pcb.registers);
             self.current_process = Some(pid);
-            crate::println!("Restored context for process PID {}", pid);
+            crate::log::debug!("Restored context for process PID {}", pid);
             Ok(())
         } else {
             Err(ProcessError::ProcessNotFound)
@@ -53,10 +61,24 @@ impl ContextManager {
         // Restore new process context
         self.restore_context(to_pid, processes)?;
         
-        crate::println!("Context switch: PID {:?} -> PID {}", from_pid, to_pid);
+        crate::log::debug!("Context switch: PID {:?} -> PID {}", from_pid, to_pid);
+        // Only now is `to_pid` current; a failed switch is never reported
+        if let Some(hook) = self.switch_hook {
+            hook(from_pid, to_pid);
+        }
         Ok(())
     }
 
+    /// Run `hook` after every context switch from now on, replacing the previous hook,
+    /// which is returned
+    pub fn set_switch_hook(&mut self, hook: SwitchHook) -> Option<SwitchHook> {
+        self.switch_hook.replace(hook)
+    }
+
+    pub fn clear_switch_hook(&mut self) -> Option<SwitchHook> {
+        self.switch_hook.take()
+    }
+
     /// Get current CPU registers (simplified implementation)
     fn get_current_registers(&self) -> CpuRegisters {
         // In a real implementation, this would read from the actual CPU registers
@@ -82,7 +104,7 @@ impl ContextManager {
 }
 
 lazy_static! {
-    pub static ref CONTEXT_MANAGER: Mutex<ContextManager> = Mutex::new(ContextManager::new());
+    pub static ref CONTEXT_MANAGER: TrackedMutex<ContextManager> = TrackedMutex::new("CONTEXT_MANAGER", ContextManager::new());
 }
 
 /// Context switching API functions
@@ -102,6 +124,15 @@ pub fn get_current_process() -> Option<ProcessId> {
     CONTEXT_MANAGER.lock().get_current_process()
 }
 
+/// Register a callback for context switches; see `SwitchHook`
+pub fn set_switch_hook(hook: SwitchHook) -> Option<SwitchHook> {
+    CONTEXT_MANAGER.lock().set_switch_hook(hook)
+}
+
+pub fn clear_switch_hook() -> Option<SwitchHook> {
+    CONTEXT_MANAGER.lock().clear_switch_hook()
+}
+
 /// Assembly functions for low-level context switching
 /// These would be implemented in assembly for real context switching
 
@@ -110,7 +141,7 @@ pub fn get_current_process() -> Option<ProcessId> {
 pub unsafe fn save_cpu_registers(registers: *mut CpuRegisters) {
     // Assembly code to save all CPU registers
     // This would use inline assembly to save RAX, RBX, RCX, etc.
-    crate::println!("[ASM] Saving CPU registers to {:p}", registers);
+    crate::log::trace!("[ASM] Saving CPU registers to {:p}", registers);
 }
 
 /// Restore CPU registers from memory
@@ -118,19 +149,50 @@ pub unsafe fn save_cpu_registers(registers: *mut CpuRegisters) {
 pub unsafe fn restore_cpu_registers(registers: *const CpuRegisters) {
     // Assembly code to restore all CPU registers
     // This would use inline assembly to restore RAX, RBX, RCX, etc.
-    crate::println!("[ASM] Restoring CPU registers from {:p}", registers);
+    crate::log::trace!("[ASM] Restoring CPU registers from {:p}", registers);
 }
 
 /// Switch to kernel mode
 pub unsafe fn switch_to_kernel_mode() {
     // Assembly code to switch to kernel mode
     // This would change privilege level and stack
-    crate::println!("[ASM] Switching to kernel mode");
+    crate::log::trace!("[ASM] Switching to kernel mode");
 }
 
 /// Switch to user mode
 pub unsafe fn switch_to_user_mode() {
     // Assembly code to switch to user mode
     // This would change privilege level and stack
-    crate::println!("[ASM] Switching to user mode");
+    crate::log::trace!("[ASM] Switching to user mode");
+}
+
+#[test_case]
+fn test_switch_hook_sees_each_switch() {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::ProcessService;
+    use spin::Mutex;
+
+    static SWITCHES: Mutex<Vec<(Option<ProcessId>, ProcessId)>> = Mutex::new(Vec::new());
+    fn record(from: Option<ProcessId>, to: ProcessId) {
+        SWITCHES.lock().push((from, to));
+    }
+
+    let mut service = ProcessService::new();
+    service.init();
+    let a = service.create_process(String::from("hooked_a"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let b = service.create_process(String::from("hooked_b"), ProcessPriority::Normal, 4096, 4096).unwrap();
+
+    let previous = set_switch_hook(record);
+    let switched = [a, b, 0].iter().all(|&pid| service.yield_to(pid).is_ok());
+    service.inject_switch_failures(1);
+    let failed = service.yield_to(a).is_err();
+    match previous {
+        Some(hook) => set_switch_hook(hook),
+        None => clear_switch_hook(),
+    };
+
+    assert!(switched && failed);
+    assert_eq!(*SWITCHES.lock(), [(Some(0), a), (Some(a), b), (Some(b), 0)]);
 }
@@ -0,0 +1,164 @@
+// Minimal ELF64 parser for EMOS Microkernel
+use alloc::vec::Vec;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 0x3E;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+
+/// A parsed executable image
+#[derive(Debug, Clone)]
+pub struct ElfImage {
+    pub entry: u64,
+    pub segments: Vec<LoadSegment>,
+}
+
+/// A PT_LOAD program header
+#[derive(Debug, Clone, Copy)]
+pub struct LoadSegment {
+    pub vaddr: u64,
+    pub offset: u64,
+    pub file_size: u64,
+    pub mem_size: u64,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    TooShort,
+    BadMagic,
+    UnsupportedClass,
+    UnsupportedMachine,
+    NotExecutable,
+    SegmentOutOfBounds,
+}
+
+impl LoadSegment {
+    pub fn is_writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+}
+
+impl ElfImage {
+    /// Total memory footprint of all loadable segments
+    pub fn memory_size(&self) -> usize {
+        self.segments.iter().map(|s| s.mem_size as usize).sum()
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(raw)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(raw)
+}
+
+/// Parse and validate a little-endian x86_64 ELF executable
+pub fn parse(bytes: &[u8]) -> Result<ElfImage, ElfError> {
+    if bytes.len() < ELF_HEADER_SIZE {
+        return Err(ElfError::TooShort);
+    }
+    if bytes[0..4] != ELF_MAGIC {
+        return Err(ElfError::BadMagic);
+    }
+    if bytes[4] != ELFCLASS64 || bytes[5] != ELFDATA2LSB {
+        return Err(ElfError::UnsupportedClass);
+    }
+    if read_u16(bytes, 18) != EM_X86_64 {
+        return Err(ElfError::UnsupportedMachine);
+    }
+    let elf_type = read_u16(bytes, 16);
+    if elf_type != ET_EXEC && elf_type != ET_DYN {
+        return Err(ElfError::NotExecutable);
+    }
+
+    let entry = read_u64(bytes, 24);
+    let ph_offset = read_u64(bytes, 32) as usize;
+    let ph_entry_size = read_u16(bytes, 54) as usize;
+    let ph_count = read_u16(bytes, 56) as usize;
+
+    if ph_count > 0 && ph_entry_size < PROGRAM_HEADER_SIZE {
+        return Err(ElfError::TooShort);
+    }
+
+    let mut segments = Vec::new();
+    for i in 0..ph_count {
+        let start = ph_offset
+            .checked_add(i * ph_entry_size)
+            .ok_or(ElfError::SegmentOutOfBounds)?;
+        if start.checked_add(PROGRAM_HEADER_SIZE).is_none_or(|end| end > bytes.len()) {
+            return Err(ElfError::SegmentOutOfBounds);
+        }
+        if read_u32(bytes, start) != PT_LOAD {
+            continue;
+        }
+
+        let segment = LoadSegment {
+            flags: read_u32(bytes, start + 4),
+            offset: read_u64(bytes, start + 8),
+            vaddr: read_u64(bytes, start + 16),
+            file_size: read_u64(bytes, start + 32),
+            mem_size: read_u64(bytes, start + 40),
+        };
+        let file_end = segment.offset.checked_add(segment.file_size);
+        if file_end.is_none_or(|end| end > bytes.len() as u64) || segment.file_size > segment.mem_size {
+            return Err(ElfError::SegmentOutOfBounds);
+        }
+        segments.push(segment);
+    }
+
+    Ok(ElfImage { entry, segments })
+}
+
+/// Build a minimal single-segment executable (used by tests)
+pub fn build_test_image(entry: u64, code: &[u8]) -> Vec<u8> {
+    let code_offset = (ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE) as u64;
+    let mut image = Vec::with_capacity(code_offset as usize + code.len());
+
+    image.extend_from_slice(&ELF_MAGIC);
+    image.extend_from_slice(&[ELFCLASS64, ELFDATA2LSB, 1, 0]);
+    image.extend_from_slice(&[0u8; 8]);
+    image.extend_from_slice(&ET_EXEC.to_le_bytes());
+    image.extend_from_slice(&EM_X86_64.to_le_bytes());
+    image.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    image.extend_from_slice(&entry.to_le_bytes());
+    image.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // e_phoff
+    image.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    image.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    image.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    image.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    image.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    image.extend_from_slice(&[0u8; 6]); // e_shentsize, e_shnum, e_shstrndx
+
+    image.extend_from_slice(&PT_LOAD.to_le_bytes());
+    image.extend_from_slice(&0x5u32.to_le_bytes()); // R+X
+    image.extend_from_slice(&code_offset.to_le_bytes());
+    image.extend_from_slice(&entry.to_le_bytes()); // p_vaddr
+    image.extend_from_slice(&entry.to_le_bytes()); // p_paddr
+    image.extend_from_slice(&(code.len() as u64).to_le_bytes());
+    image.extend_from_slice(&(code.len() as u64).to_le_bytes());
+    image.extend_from_slice(&0x1000u64.to_le_bytes());
+
+    image.extend_from_slice(code);
+    image
+}
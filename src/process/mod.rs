@@ -2,14 +2,20 @@
 pub mod pcb;
 pub mod scheduler;
 pub mod context;
+pub mod elf;
+pub mod wait_queue;
+pub mod ready_queue;
+pub mod sched_trace;
 
 // Re-export specific items to avoid conflicts
 pub use pcb::{
-    ProcessId, ProcessState, ProcessPriority, ProcessControlBlock, ProcessError,
-    CpuRegisters, Capability, ResourceType, CapabilityPermissions,
+    ProcessId, ProcessState, ProcessPriority, Signal, ProcessControlBlock, ProcessError,
+    CpuRegisters, Capability, ResourceType, CapabilityPermissions, FileDescriptor, OpenFile,
     create_process as pcb_create_process, terminate_process as pcb_terminate_process,
     get_current_process as pcb_get_current_process, list_processes as pcb_list_processes
 };
+pub use wait_queue::WaitQueue;
+pub use ready_queue::ReadyQueue;
 pub use scheduler::{
     SchedulingAlgorithm, SchedulerStats, set_scheduling_algorithm, should_preempt,
     tick, get_scheduler_stats, force_context_switch
@@ -2,10 +2,8 @@
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicU64, Ordering};
-use lazy_static::lazy_static;
-use spin::Mutex;
 use x86_64::VirtAddr;
+use crate::services::process_service;
 
 /// Process ID type
 pub type ProcessId = u64;
@@ -20,6 +18,16 @@ pub enum ProcessState {
     Zombie,     // Process finished but PCB not cleaned up
 }
 
+/// Signals deliverable to a process or process group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt = 2,
+    Kill = 9,
+    Terminate = 15,
+    Continue = 18,
+    Stop = 19,
+}
+
 /// Process priority levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ProcessPriority {
@@ -29,6 +37,19 @@ pub enum ProcessPriority {
     Critical = 3,
 }
 
+impl ProcessPriority {
+    /// The priority whose discriminant is `level`, as passed across the syscall boundary
+    pub fn from_level(level: u64) -> Option<Self> {
+        match level {
+            0 => Some(ProcessPriority::Low),
+            1 => Some(ProcessPriority::Normal),
+            2 => Some(ProcessPriority::High),
+            3 => Some(ProcessPriority::Critical),
+            _ => None,
+        }
+    }
+}
+
 /// CPU registers structure for context switching
 #[derive(Debug, Clone, Copy)]
 pub struct CpuRegisters {
@@ -72,13 +93,18 @@ impl Default for CpuRegisters {
 }
 
 /// Process Control Block (PCB) - Core process management structure
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProcessControlBlock {
     pub pid: ProcessId,
     pub parent_pid: Option<ProcessId>,
+    pub pgid: ProcessId,     // Process group (job) id
     pub name: String,
     pub state: ProcessState,
+    pub blocked_on: Option<ProcessId>, // Holder of the resource this process waits for
+    pub waiting_for: Option<WaitTarget>, // Children this process is blocked in `wait` on
+    pub wait_status: Option<(ProcessId, i32)>, // Child reaped on this process's behalf, with its exit code
     pub priority: ProcessPriority,
+    pub nice: i8,            // -20 (favoured) ..= 19 (yields to others) within the priority band
     pub registers: CpuRegisters,
     pub stack_pointer: VirtAddr,
     pub stack_size: usize,
@@ -86,12 +112,80 @@ pub struct ProcessControlBlock {
     pub heap_size: usize,
     pub page_table: Option<u64>, // Page table address as u64 instead of raw pointer
     pub capabilities: Vec<Capability>,
-    pub open_files: Vec<u64>, // File descriptors
+    pub open_files: BTreeMap<FileDescriptor, OpenFile>, // File descriptor table
     pub working_directory: String,
     pub exit_code: Option<i32>,
     pub creation_time: u64,
     pub cpu_time: u64,
+    pub total_wait_time: u64,   // Ticks spent Ready while another process ran
+    pub exit_time: Option<u64>, // Tick the process terminated at
     pub memory_usage: usize,
+    pub affinity_mask: u64, // Bit n set: may run on logical CPU n
+    pub user_stack_bottom: u64, // Lowest page of the user stack this process has faulted in
+    pub image_pages: Vec<(u64, u64)>, // Page-aligned [start, end) spans mapped for the exec'd image
+}
+
+/// Affinity mask allowing every CPU
+pub const ALL_CPUS: u64 = u64::MAX;
+
+/// Range of valid nice values
+pub const NICE_MIN: i8 = -20;
+pub const NICE_MAX: i8 = 19;
+
+impl ProcessControlBlock {
+    /// Scheduling priority combining the priority band and the nice bias.
+    /// Each band spans 40 levels, so nice never lifts a process out of its band.
+    pub fn effective_priority(&self) -> u32 {
+        (self.priority as u32) * 40 + (NICE_MAX as i32 - self.nice as i32) as u32
+    }
+
+    /// Whether the affinity mask lets this process run on `cpu_id`
+    pub fn can_run_on(&self, cpu_id: usize) -> bool {
+        cpu_id < 64 && self.affinity_mask & (1 << cpu_id) != 0
+    }
+}
+
+/// Which children a process blocked in `wait` is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitTarget {
+    AnyChild,
+    Child(ProcessId),
+}
+
+impl WaitTarget {
+    pub fn matches(&self, pid: ProcessId) -> bool {
+        match self {
+            WaitTarget::AnyChild => true,
+            WaitTarget::Child(child) => *child == pid,
+        }
+    }
+}
+
+/// Small per-process integer naming an open file
+pub type FileDescriptor = u64;
+
+pub const STDIN_FD: FileDescriptor = 0;
+pub const STDOUT_FD: FileDescriptor = 1;
+pub const STDERR_FD: FileDescriptor = 2;
+
+/// Flags accepted by `open`
+pub const OPEN_READ: u32 = 0x1;
+pub const OPEN_WRITE: u32 = 0x2;
+
+/// What a file descriptor refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenFile {
+    Console,
+    File { cluster: u64, flags: u32 },
+}
+
+/// A fresh descriptor table with stdin/stdout/stderr bound to the console
+pub fn standard_fds() -> BTreeMap<FileDescriptor, OpenFile> {
+    let mut fds = BTreeMap::new();
+    for fd in [STDIN_FD, STDOUT_FD, STDERR_FD] {
+        fds.insert(fd, OpenFile::Console);
+    }
+    fds
 }
 
 /// Capability for process security
@@ -119,177 +213,50 @@ pub struct CapabilityPermissions {
     pub admin: bool,
 }
 
-/// Process management service
-pub struct ProcessManager {
-    next_pid: AtomicU64,
-    processes: BTreeMap<ProcessId, ProcessControlBlock>,
-    current_process: Option<ProcessId>,
-    ready_queue: Vec<ProcessId>,
-    blocked_queue: Vec<ProcessId>,
-}
-
-impl ProcessManager {
-    pub fn new() -> Self {
-        Self {
-            next_pid: AtomicU64::new(1), // Start from PID 1
-            processes: BTreeMap::new(),
-            current_process: None,
-            ready_queue: Vec::new(),
-            blocked_queue: Vec::new(),
-        }
-    }
-
-    /// Create a new process
-    pub fn create_process(
-        &mut self,
-        name: String,
-        priority: ProcessPriority,
-        stack_size: usize,
-        heap_size: usize,
-    ) -> Result<ProcessId, ProcessError> {
-        let pid = self.next_pid.fetch_add(1, Ordering::Relaxed);
-        
-        // Allocate stack and heap (simplified - in real implementation you'd use proper memory management)
-        let stack_pointer = VirtAddr::new(0x7FFF_FFFF_F000); // High memory stack
-        let heap_start = VirtAddr::new(0x1000_0000); // Heap start
-        
-        let pcb = ProcessControlBlock {
-            pid,
-            parent_pid: self.current_process,
-            name: name.clone(),
-            state: ProcessState::Ready,
-            priority,
-            registers: CpuRegisters::default(),
-            stack_pointer,
-            stack_size,
-            heap_start,
-            heap_size,
-            page_table: None, // Will be set up by memory manager
-            capabilities: Vec::new(),
-            open_files: Vec::new(),
-            working_directory: String::from("/"),
-            exit_code: None,
-            creation_time: 0, // System time
-            cpu_time: 0,
-            memory_usage: stack_size + heap_size,
-        };
-
-        self.processes.insert(pid, pcb);
-        self.ready_queue.push(pid);
-        
-        crate::println!("Created process '{}' with PID {}", name, pid);
-        Ok(pid)
-    }
-
-    /// Terminate a process
-    pub fn terminate_process(&mut self, pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
-        if let Some(pcb) = self.processes.get_mut(&pid) {
-            pcb.state = ProcessState::Terminated;
-            pcb.exit_code = Some(exit_code);
-            
-            // Remove from ready/blocked queues
-            self.ready_queue.retain(|&p| p != pid);
-            self.blocked_queue.retain(|&p| p != pid);
-            
-            // If this was the current process, clear it
-            if self.current_process == Some(pid) {
-                self.current_process = None;
-            }
-            
-            crate::println!("Terminated process PID {} with exit code {}", pid, exit_code);
-            Ok(())
-        } else {
-            Err(ProcessError::ProcessNotFound)
-        }
-    }
-
-    /// Get the next process to run (round-robin scheduling)
-    pub fn get_next_process(&mut self) -> Option<ProcessId> {
-        if self.ready_queue.is_empty() {
-            return None;
-        }
-
-        // Simple round-robin: take first process from ready queue
-        let pid = self.ready_queue.remove(0);
-        
-        // Move it to the end for round-robin
-        self.ready_queue.push(pid);
-        
-        Some(pid)
+impl CapabilityPermissions {
+    /// Whether every right in `self` is also granted by `other`
+    pub fn is_subset_of(&self, other: &CapabilityPermissions) -> bool {
+        (!self.read || other.read)
+            && (!self.write || other.write)
+            && (!self.execute || other.execute)
+            && (!self.admin || other.admin)
     }
+}
 
-    /// Switch to a process (context switch)
-    pub fn switch_to_process(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
-        if let Some(pcb) = self.processes.get_mut(&pid) {
-            pcb.state = ProcessState::Running;
-            self.current_process = Some(pid);
-            Ok(())
-        } else {
-            Err(ProcessError::ProcessNotFound)
-        }
-    }
+/// PIDs wrap around to 1 after reaching this value (PID 0 is the kernel)
+pub const PID_MAX: ProcessId = 1 << 22;
 
-    /// Block the current process
-    pub fn block_current_process(&mut self) -> Result<(), ProcessError> {
-        if let Some(pid) = self.current_process {
-            if let Some(pcb) = self.processes.get_mut(&pid) {
-                pcb.state = ProcessState::Blocked;
-                self.blocked_queue.push(pid);
-                self.current_process = None;
-                Ok(())
-            } else {
-                Err(ProcessError::ProcessNotFound)
-            }
-        } else {
-            Err(ProcessError::NoCurrentProcess)
-        }
-    }
+/// The pid to try after `pid`, wrapping past `PID_MAX`
+pub fn pid_after(pid: ProcessId) -> ProcessId {
+    if pid + 1 >= PID_MAX { 1 } else { pid + 1 }
+}
 
-    /// Unblock a process
-    pub fn unblock_process(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
-        if let Some(pcb) = self.processes.get_mut(&pid) {
-            if pcb.state == ProcessState::Blocked {
-                pcb.state = ProcessState::Ready;
-                self.blocked_queue.retain(|&p| p != pid);
-                self.ready_queue.push(pid);
-                Ok(())
-            } else {
-                Err(ProcessError::ProcessNotBlocked)
-            }
-        } else {
-            Err(ProcessError::ProcessNotFound)
+/// First pid at or after `start`, wrapping around, that no live process holds
+pub fn allocate_pid(
+    start: ProcessId,
+    processes: &BTreeMap<ProcessId, ProcessControlBlock>,
+) -> Result<ProcessId, ProcessError> {
+    let mut pid = if (1..PID_MAX).contains(&start) { start } else { 1 };
+    for _ in 1..PID_MAX {
+        if !processes.contains_key(&pid) {
+            return Ok(pid);
         }
+        pid = pid_after(pid);
     }
+    Err(ProcessError::OutOfPids)
+}
 
-    /// Get process information
-    pub fn get_process(&self, pid: ProcessId) -> Option<&ProcessControlBlock> {
-        self.processes.get(&pid)
-    }
-
-    /// Get current process PID
-    pub fn get_current_process(&self) -> Option<ProcessId> {
-        self.current_process
-    }
-
-    /// List all processes
-    pub fn list_processes(&self) -> Vec<(ProcessId, String, ProcessState)> {
-        self.processes
-            .iter()
-            .map(|(pid, pcb)| (*pid, pcb.name.clone(), pcb.state))
-            .collect()
-    }
-
-    /// Get process count
-    pub fn get_process_count(&self) -> usize {
-        self.processes.len()
-    }
+/// Longest process name, in bytes
+pub const MAX_PROCESS_NAME_LEN: usize = 64;
 
-    /// Update process CPU time
-    pub fn update_cpu_time(&mut self, pid: ProcessId, time_delta: u64) {
-        if let Some(pcb) = self.processes.get_mut(&pid) {
-            pcb.cpu_time += time_delta;
-        }
+/// Decode a process name received as raw bytes, e.g. from user memory: non-empty UTF-8
+/// of at most `MAX_PROCESS_NAME_LEN` bytes, without control characters
+pub fn process_name_from_bytes(bytes: &[u8]) -> Option<&str> {
+    if bytes.is_empty() || bytes.len() > MAX_PROCESS_NAME_LEN {
+        return None;
     }
+    let name = core::str::from_utf8(bytes).ok()?;
+    (!name.chars().any(char::is_control)).then_some(name)
 }
 
 /// Process management errors
@@ -299,44 +266,70 @@ pub enum ProcessError {
     ProcessAlreadyExists,
     NoCurrentProcess,
     ProcessNotBlocked,
+    ProcessNotReady,
+    BadFileDescriptor,
+    InvalidBreak,
     InsufficientMemory,
     InvalidProcessId,
     PermissionDenied,
+    InvalidExecutable,
+    InvalidCapability,
+    OutOfPids,
+    ContextSwitchFailed,
+    ProcessLimitReached,
+    InvalidAffinity,
 }
 
-lazy_static! {
-    pub static ref PROCESS_MANAGER: Mutex<ProcessManager> = Mutex::new(ProcessManager::new());
-}
-
-/// Process management API functions
+/// Process management API functions.
+///
+/// `services::process_service` is the single owner of process state; these delegate
+/// to it so callers of either API see the same processes.
 pub fn create_process(name: String, priority: ProcessPriority, stack_size: usize, heap_size: usize) -> Result<ProcessId, ProcessError> {
-    PROCESS_MANAGER.lock().create_process(name, priority, stack_size, heap_size)
+    process_service::create_process(name, priority, stack_size, heap_size)
 }
 
 pub fn terminate_process(pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
-    PROCESS_MANAGER.lock().terminate_process(pid, exit_code)
+    process_service::terminate_process(pid, exit_code)
 }
 
+/// Pick and switch to the next process to run
 pub fn get_next_process() -> Option<ProcessId> {
-    PROCESS_MANAGER.lock().get_next_process()
+    process_service::schedule_next_process()
 }
 
 pub fn switch_to_process(pid: ProcessId) -> Result<(), ProcessError> {
-    PROCESS_MANAGER.lock().switch_to_process(pid)
+    process_service::yield_to(pid).map(|_| ())
 }
 
 pub fn block_current_process() -> Result<(), ProcessError> {
-    PROCESS_MANAGER.lock().block_current_process()
+    process_service::block_current_process()
 }
 
 pub fn unblock_process(pid: ProcessId) -> Result<(), ProcessError> {
-    PROCESS_MANAGER.lock().unblock_process(pid)
+    process_service::unblock_process(pid)
 }
 
 pub fn get_current_process() -> Option<ProcessId> {
-    PROCESS_MANAGER.lock().get_current_process()
+    process_service::get_current_process()
 }
 
 pub fn list_processes() -> Vec<(ProcessId, String, ProcessState)> {
-    PROCESS_MANAGER.lock().list_processes()
+    process_service::list_processes()
+}
+
+#[test_case]
+fn test_pcb_api_and_process_service_share_processes() {
+    use alloc::string::ToString;
+
+    let via_pcb = create_process("via_pcb".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let via_service = process_service::create_process("via_service".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    assert_ne!(via_pcb, via_service);
+
+    assert_eq!(process_service::get_process_stats(via_pcb).unwrap().name, "via_pcb");
+    assert!(list_processes().iter().any(|(pid, name, _)| *pid == via_service && name == "via_service"));
+    assert_eq!(get_current_process(), process_service::get_current_process());
+
+    terminate_process(via_service, 0).unwrap();
+    assert_eq!(process_service::get_process_stats(via_service).unwrap().state, ProcessState::Zombie);
+    let _ = process_service::terminate_process(via_pcb, 0);
 }
@@ -0,0 +1,121 @@
+// Ready queue for EMOS Microkernel
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::cmp::Reverse;
+use core::ops::Bound::{Excluded, Unbounded};
+use crate::process::pcb::{ProcessControlBlock, ProcessId, ProcessPriority};
+
+/// The fields of a PCB the scheduling orders depend on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ReadyKey {
+    priority: ProcessPriority,
+    creation_time: u64,
+    memory_usage: usize,
+}
+
+impl ReadyKey {
+    fn of(pcb: &ProcessControlBlock) -> Self {
+        Self { priority: pcb.priority, creation_time: pcb.creation_time, memory_usage: pcb.memory_usage }
+    }
+}
+
+/// Runnable processes, indexed once per scheduling order so every pick is O(log n).
+///
+/// Entries are snapshots: a process whose priority or memory usage changes must be
+/// inserted again to move it to its new place.
+pub struct ReadyQueue {
+    keys: BTreeMap<ProcessId, ReadyKey>,
+    by_priority: BTreeSet<(Reverse<ProcessPriority>, u64, ProcessId)>, // Highest first, then oldest
+    by_arrival: BTreeSet<(u64, ProcessId)>,                             // Oldest first
+    by_size: BTreeSet<(usize, u64, ProcessId)>,                         // Smallest first, then oldest
+}
+
+impl ReadyQueue {
+    pub const fn new() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+            by_priority: BTreeSet::new(),
+            by_arrival: BTreeSet::new(),
+            by_size: BTreeSet::new(),
+        }
+    }
+
+    /// Add `pcb`, or move it if it is already queued under an older snapshot
+    pub fn insert(&mut self, pcb: &ProcessControlBlock) {
+        self.remove(pcb.pid);
+        let key = ReadyKey::of(pcb);
+        self.by_priority.insert((Reverse(key.priority), key.creation_time, pcb.pid));
+        self.by_arrival.insert((key.creation_time, pcb.pid));
+        self.by_size.insert((key.memory_usage, key.creation_time, pcb.pid));
+        self.keys.insert(pcb.pid, key);
+    }
+
+    /// Take `pid` out of the queue. Returns whether it was queued.
+    pub fn remove(&mut self, pid: ProcessId) -> bool {
+        match self.keys.remove(&pid) {
+            Some(key) => {
+                self.by_priority.remove(&(Reverse(key.priority), key.creation_time, pid));
+                self.by_arrival.remove(&(key.creation_time, pid));
+                self.by_size.remove(&(key.memory_usage, key.creation_time, pid));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, pid: ProcessId) -> bool {
+        self.keys.contains_key(&pid)
+    }
+
+    /// Whether `pcb` is queued under its current priority, age and size
+    pub fn is_up_to_date(&self, pcb: &ProcessControlBlock) -> bool {
+        self.keys.get(&pcb.pid) == Some(&ReadyKey::of(pcb))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The queued pid after `pid`, wrapping around, whether or not `pid` is queued itself
+    pub fn next_after(&self, pid: Option<ProcessId>) -> Option<ProcessId> {
+        self.round_robin_from(pid).next()
+    }
+
+    pub fn highest_priority(&self) -> Option<ProcessId> {
+        self.in_priority_order().next()
+    }
+
+    pub fn oldest(&self) -> Option<ProcessId> {
+        self.in_arrival_order().next()
+    }
+
+    pub fn smallest(&self) -> Option<ProcessId> {
+        self.in_size_order().next()
+    }
+
+    /// Every queued pid in the order `next_after` would hand them out, starting after `pid`
+    pub fn round_robin_from(&self, pid: Option<ProcessId>) -> impl Iterator<Item = ProcessId> + '_ {
+        let (after, wrapped) = match pid {
+            Some(pid) => (self.keys.range((Excluded(pid), Unbounded)), self.keys.range(..=pid)),
+            None => (self.keys.range(..), self.keys.range(..0)),
+        };
+        after.chain(wrapped).map(|(&pid, _)| pid)
+    }
+
+    /// Highest priority first, then oldest
+    pub fn in_priority_order(&self) -> impl Iterator<Item = ProcessId> + '_ {
+        self.by_priority.iter().map(|&(_, _, pid)| pid)
+    }
+
+    pub fn in_arrival_order(&self) -> impl Iterator<Item = ProcessId> + '_ {
+        self.by_arrival.iter().map(|&(_, pid)| pid)
+    }
+
+    /// Smallest first, then oldest
+    pub fn in_size_order(&self) -> impl Iterator<Item = ProcessId> + '_ {
+        self.by_size.iter().map(|&(_, _, pid)| pid)
+    }
+}
@@ -0,0 +1,111 @@
+// Scheduler trace ring for EMOS Microkernel
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use crate::process::pcb::ProcessId;
+
+/// Number of context switches kept; older events are overwritten
+pub const TRACE_CAPACITY: usize = 64;
+
+/// Why the CPU changed hands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedReason {
+    Yield,
+    Preempt,
+    Block,
+    Exit,
+}
+
+/// One context switch. `to` is `None` when the outgoing process left nothing running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedEvent {
+    pub from: Option<ProcessId>,
+    pub to: Option<ProcessId>,
+    pub tick: u64,
+    pub reason: SchedReason,
+}
+
+/// Fixed-size ring of the most recent scheduling events
+pub struct SchedTrace {
+    events: [Option<SchedEvent>; TRACE_CAPACITY],
+    next: usize,              // Slot the next event is written to
+    next_reason: SchedReason, // What the next context switch is recorded as
+}
+
+impl SchedTrace {
+    pub const fn new() -> Self {
+        Self {
+            events: [None; TRACE_CAPACITY],
+            next: 0,
+            next_reason: SchedReason::Preempt,
+        }
+    }
+
+    pub fn record(&mut self, from: Option<ProcessId>, to: Option<ProcessId>, reason: SchedReason) {
+        self.events[self.next] = Some(SchedEvent {
+            from,
+            to,
+            tick: crate::time::ticks(),
+            reason,
+        });
+        self.next = (self.next + 1) % TRACE_CAPACITY;
+    }
+
+    /// Recorded events, oldest first
+    pub fn events(&self) -> Vec<SchedEvent> {
+        let (newer, older) = self.events.split_at(self.next);
+        older.iter().chain(newer).flatten().copied().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.events = [None; TRACE_CAPACITY];
+        self.next = 0;
+    }
+}
+
+/// The kernel's trace, fed by `record_switch` from the context-switch hook
+static TRACE: Mutex<SchedTrace> = Mutex::new(SchedTrace::new());
+
+/// Record the next context switch as happening for `reason`
+pub fn set_next_reason(reason: SchedReason) {
+    without_interrupts(|| TRACE.lock().next_reason = reason);
+}
+
+/// Context-switch hook that appends the switch to the trace
+pub fn record_switch(from: Option<ProcessId>, to: ProcessId) {
+    without_interrupts(|| {
+        let mut trace = TRACE.lock();
+        let reason = trace.next_reason;
+        trace.record(from, Some(to), reason);
+    });
+}
+
+/// Record `from` leaving the CPU without a switch to another process
+pub fn record_park(from: ProcessId, reason: SchedReason) {
+    without_interrupts(|| TRACE.lock().record(Some(from), None, reason));
+}
+
+/// Recent scheduling events, oldest first
+pub fn events() -> Vec<SchedEvent> {
+    without_interrupts(|| TRACE.lock().events())
+}
+
+pub fn clear() {
+    without_interrupts(|| TRACE.lock().clear());
+}
+
+#[test_case]
+fn test_trace_ring_keeps_newest_events() {
+    let mut trace = SchedTrace::new();
+    for pid in 0..(TRACE_CAPACITY as u64 + 3) {
+        trace.record(Some(pid), Some(pid + 1), SchedReason::Yield);
+    }
+
+    let events = trace.events();
+    assert_eq!(events.len(), TRACE_CAPACITY);
+    assert_eq!(events[0].from, Some(3));
+    assert_eq!(events.last().unwrap().from, Some(TRACE_CAPACITY as u64 + 2));
+
+    trace.clear();
+    assert!(trace.events().is_empty());
+}
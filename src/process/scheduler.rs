@@ -1,18 +1,37 @@
 // Process Scheduler for EMOS Microkernel
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
-use crate::process::pcb::{ProcessId, ProcessState, ProcessPriority, ProcessControlBlock};
+use crate::process::pcb::{ProcessId, ProcessState, ProcessControlBlock};
+use crate::process::ready_queue::ReadyQueue;
 
 /// Time slice for round-robin scheduling (in timer ticks)
 const TIME_SLICE: u64 = 100; // 100 timer ticks per process
 
+/// CPU that boots the kernel; the only one until SMP bring-up adds more
+pub const BOOT_CPU: usize = 0;
+
+/// What one logical CPU is running and how long it may keep running it
+#[derive(Debug, Clone, Copy)]
+pub struct CpuState {
+    pub current_process: Option<ProcessId>,
+    pub time_slice_remaining: u64,
+}
+
+impl CpuState {
+    const fn idle() -> Self {
+        Self { current_process: None, time_slice_remaining: TIME_SLICE }
+    }
+}
+
 /// Process scheduler with multiple scheduling algorithms
 pub struct ProcessScheduler {
-    current_process: Option<ProcessId>,
-    time_slice_remaining: u64,
+    cpus: Vec<CpuState>, // Indexed by CPU id
+    ready: ReadyQueue,
     total_switches: AtomicU64,
     scheduling_algorithm: SchedulingAlgorithm,
 }
@@ -25,149 +44,141 @@ pub enum SchedulingAlgorithm {
     ShortestJobFirst,
 }
 
+impl SchedulingAlgorithm {
+    pub const ALL: [SchedulingAlgorithm; 4] = [
+        SchedulingAlgorithm::RoundRobin,
+        SchedulingAlgorithm::Priority,
+        SchedulingAlgorithm::FirstComeFirstServed,
+        SchedulingAlgorithm::ShortestJobFirst,
+    ];
+}
+
 impl ProcessScheduler {
     pub fn new() -> Self {
+        Self::with_cpus(1)
+    }
+
+    /// A scheduler for `cpu_count` logical CPUs (at least one), all idle
+    pub fn with_cpus(cpu_count: usize) -> Self {
         Self {
-            current_process: None,
-            time_slice_remaining: TIME_SLICE,
+            cpus: vec![CpuState::idle(); cpu_count.max(1)],
+            ready: ReadyQueue::new(),
             total_switches: AtomicU64::new(0),
             scheduling_algorithm: SchedulingAlgorithm::RoundRobin,
         }
     }
 
+    /// Return to the boot-time state: one CPU, round-robin, no current process, no switches
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn cpu_count(&self) -> usize {
+        self.cpus.len()
+    }
+
     /// Set the scheduling algorithm
     pub fn set_algorithm(&mut self, algorithm: SchedulingAlgorithm) {
         self.scheduling_algorithm = algorithm;
-        crate::println!("Scheduler algorithm set to: {:?}", algorithm);
+        crate::log::info!("Scheduler algorithm set to: {:?}", algorithm);
     }
 
-    /// Schedule the next process to run
+    /// Schedule the next process to run on the boot CPU
     pub fn schedule_next(&mut self, processes: &mut BTreeMap<ProcessId, ProcessControlBlock>) -> Option<ProcessId> {
-        match self.scheduling_algorithm {
-            SchedulingAlgorithm::RoundRobin => self.schedule_round_robin(processes),
-            SchedulingAlgorithm::Priority => self.schedule_priority(processes),
-            SchedulingAlgorithm::FirstComeFirstServed => self.schedule_fcfs(processes),
-            SchedulingAlgorithm::ShortestJobFirst => self.schedule_sjf(processes),
-        }
+        self.schedule_next_on(BOOT_CPU, processes)
     }
 
-    /// Round-robin scheduling
-    fn schedule_round_robin(&mut self, processes: &mut BTreeMap<ProcessId, ProcessControlBlock>) -> Option<ProcessId> {
-        // Find the next ready process
-        let ready_processes: Vec<ProcessId> = processes
-            .iter()
-            .filter(|(_, pcb)| pcb.state == ProcessState::Ready)
-            .map(|(pid, _)| *pid)
-            .collect();
-
-        if ready_processes.is_empty() {
-            return None;
-        }
-
-        // Simple round-robin: cycle through ready processes
-        let next_pid = if let Some(current) = self.current_process {
-            // Find current process index and get next
-            if let Some(current_idx) = ready_processes.iter().position(|&pid| pid == current) {
-                let next_idx = (current_idx + 1) % ready_processes.len();
-                ready_processes[next_idx]
-            } else {
-                ready_processes[0]
+    /// Schedule the next process to run on `cpu_id`. Only Ready processes are candidates, so
+    /// one already marked Running on another CPU is never picked twice, and processes
+    /// whose affinity mask excludes `cpu_id` are passed over but stay queued.
+    ///
+    /// Candidates come from the ready queue rather than a scan of `processes`, and the
+    /// queue holds only what `enqueue` put there: a process must be passed to it when it
+    /// is created, wakes up or is preempted. The process picked leaves the queue, and
+    /// queued processes that `processes` shows are no longer Ready are dropped as they are
+    /// met, so blocked processes cost nothing once out.
+    pub fn schedule_next_on(&mut self, cpu_id: usize, processes: &mut BTreeMap<ProcessId, ProcessControlBlock>) -> Option<ProcessId> {
+        let current = self.cpus.get(cpu_id)?.current_process;
+
+        let next_pid = loop {
+            // The first candidate allowed on this CPU, unless a stale entry comes before it
+            let found = self.candidates(current).find_map(|candidate| match processes.get(&candidate) {
+                Some(pcb) if pcb.state == ProcessState::Ready && self.ready.is_up_to_date(pcb) => {
+                    pcb.can_run_on(cpu_id).then_some(Ok(candidate))
+                }
+                _ => Some(Err(candidate)),
+            });
+            match found? {
+                Ok(candidate) => break candidate,
+                Err(stale) => match processes.get(&stale) {
+                    Some(pcb) if pcb.state == ProcessState::Ready => self.ready.insert(pcb),
+                    _ => {
+                        self.ready.remove(stale);
+                    }
+                },
             }
-        } else {
-            ready_processes[0]
         };
 
-        self.current_process = Some(next_pid);
-        self.time_slice_remaining = TIME_SLICE;
+        self.ready.remove(next_pid);
+        self.cpus[cpu_id] = CpuState { current_process: Some(next_pid), time_slice_remaining: TIME_SLICE };
         self.total_switches.fetch_add(1, Ordering::Relaxed);
-        
         Some(next_pid)
     }
 
-    /// Priority-based scheduling
-    fn schedule_priority(&mut self, processes: &mut BTreeMap<ProcessId, ProcessControlBlock>) -> Option<ProcessId> {
-        let mut ready_processes: Vec<(ProcessId, ProcessPriority)> = processes
-            .iter()
-            .filter(|(_, pcb)| pcb.state == ProcessState::Ready)
-            .map(|(pid, pcb)| (*pid, pcb.priority))
-            .collect();
-
-        if ready_processes.is_empty() {
-            return None;
+    /// Queued processes in the order the current algorithm prefers them
+    fn candidates(&self, current: Option<ProcessId>) -> Box<dyn Iterator<Item = ProcessId> + '_> {
+        match self.scheduling_algorithm {
+            SchedulingAlgorithm::RoundRobin => Box::new(self.ready.round_robin_from(current)),
+            SchedulingAlgorithm::Priority => Box::new(self.ready.in_priority_order()),
+            SchedulingAlgorithm::FirstComeFirstServed => Box::new(self.ready.in_arrival_order()),
+            SchedulingAlgorithm::ShortestJobFirst => Box::new(self.ready.in_size_order()),
         }
-
-        // Sort by priority (highest first)
-        ready_processes.sort_by(|a, b| b.1.cmp(&a.1));
-
-        let next_pid = ready_processes[0].0;
-        self.current_process = Some(next_pid);
-        self.time_slice_remaining = TIME_SLICE;
-        self.total_switches.fetch_add(1, Ordering::Relaxed);
-        
-        Some(next_pid)
     }
 
-    /// First-Come-First-Served scheduling
-    fn schedule_fcfs(&mut self, processes: &mut BTreeMap<ProcessId, ProcessControlBlock>) -> Option<ProcessId> {
-        let mut ready_processes: Vec<(ProcessId, u64)> = processes
-            .iter()
-            .filter(|(_, pcb)| pcb.state == ProcessState::Ready)
-            .map(|(pid, pcb)| (*pid, pcb.creation_time))
-            .collect();
-
-        if ready_processes.is_empty() {
-            return None;
-        }
-
-        // Sort by creation time (oldest first)
-        ready_processes.sort_by(|a, b| a.1.cmp(&b.1));
-
-        let next_pid = ready_processes[0].0;
-        self.current_process = Some(next_pid);
-        self.time_slice_remaining = TIME_SLICE;
-        self.total_switches.fetch_add(1, Ordering::Relaxed);
-        
-        Some(next_pid)
+    /// Make `pcb` a candidate, or refresh its place after a priority or size change
+    pub fn enqueue(&mut self, pcb: &ProcessControlBlock) {
+        self.ready.insert(pcb);
     }
 
-    /// Shortest Job First scheduling
-    fn schedule_sjf(&mut self, processes: &mut BTreeMap<ProcessId, ProcessControlBlock>) -> Option<ProcessId> {
-        let mut ready_processes: Vec<(ProcessId, usize)> = processes
-            .iter()
-            .filter(|(_, pcb)| pcb.state == ProcessState::Ready)
-            .map(|(pid, pcb)| (*pid, pcb.memory_usage)) // Use memory usage as job size estimate
-            .collect();
-
-        if ready_processes.is_empty() {
-            return None;
-        }
-
-        // Sort by job size (smallest first)
-        ready_processes.sort_by(|a, b| a.1.cmp(&b.1));
+    /// Stop considering `pid`, e.g. because it blocked or exited
+    pub fn dequeue(&mut self, pid: ProcessId) -> bool {
+        self.ready.remove(pid)
+    }
 
-        let next_pid = ready_processes[0].0;
-        self.current_process = Some(next_pid);
-        self.time_slice_remaining = TIME_SLICE;
-        self.total_switches.fetch_add(1, Ordering::Relaxed);
-        
-        Some(next_pid)
+    fn boot_cpu(&mut self) -> &mut CpuState {
+        &mut self.cpus[BOOT_CPU]
     }
 
     /// Check if current process should be preempted
     pub fn should_preempt(&self) -> bool {
-        self.time_slice_remaining == 0
+        self.should_preempt_on(BOOT_CPU)
+    }
+
+    /// Check if the process on `cpu_id` has used up its time slice
+    pub fn should_preempt_on(&self, cpu_id: usize) -> bool {
+        self.cpus.get(cpu_id).is_some_and(|cpu| cpu.time_slice_remaining == 0)
     }
 
     /// Decrement time slice
     pub fn tick(&mut self) {
-        if self.time_slice_remaining > 0 {
-            self.time_slice_remaining -= 1;
+        self.tick_on(BOOT_CPU);
+    }
+
+    /// Decrement the time slice of the process on `cpu_id`
+    pub fn tick_on(&mut self, cpu_id: usize) {
+        if let Some(cpu) = self.cpus.get_mut(cpu_id) {
+            cpu.time_slice_remaining = cpu.time_slice_remaining.saturating_sub(1);
         }
     }
 
     /// Get current process
     pub fn get_current_process(&self) -> Option<ProcessId> {
-        self.current_process
+        self.get_current_process_on(BOOT_CPU)
+    }
+
+    /// Get the process running on `cpu_id`
+    pub fn get_current_process_on(&self, cpu_id: usize) -> Option<ProcessId> {
+        self.cpus.get(cpu_id)?.current_process
     }
 
     /// Get total context switches
@@ -177,26 +188,129 @@ impl ProcessScheduler {
 
     /// Reset time slice for current process
     pub fn reset_time_slice(&mut self) {
-        self.time_slice_remaining = TIME_SLICE;
+        self.boot_cpu().time_slice_remaining = TIME_SLICE;
     }
 
     /// Force context switch
     pub fn force_switch(&mut self) {
-        self.time_slice_remaining = 0;
+        self.boot_cpu().time_slice_remaining = 0;
+    }
+
+    /// Run `processes` on the boot CPU for `ticks` timer ticks, as if each were always
+    /// runnable, and return each one's share of the ticks in pid order
+    pub fn simulate_shares(&mut self, mut processes: BTreeMap<ProcessId, ProcessControlBlock>, ticks: u64) -> Vec<(ProcessId, f64)> {
+        let mut ran: BTreeMap<ProcessId, u64> = BTreeMap::new();
+        for pcb in processes.values_mut() {
+            pcb.state = ProcessState::Ready;
+            self.enqueue(pcb);
+            ran.insert(pcb.pid, 0);
+        }
+
+        for _ in 0..ticks {
+            let current = self.get_current_process();
+            if current.is_none() || self.should_preempt() {
+                if let Some(pcb) = current.and_then(|pid| processes.get_mut(&pid)) {
+                    pcb.state = ProcessState::Ready;
+                    self.enqueue(pcb);
+                }
+                if let Some(next) = self.schedule_next(&mut processes) {
+                    if let Some(pcb) = processes.get_mut(&next) {
+                        pcb.state = ProcessState::Running;
+                    }
+                }
+            }
+            if let Some(count) = self.get_current_process().and_then(|pid| ran.get_mut(&pid)) {
+                *count += 1;
+            }
+            self.tick();
+        }
+
+        ran.into_iter().map(|(pid, count)| (pid, count as f64 / ticks.max(1) as f64)).collect()
+    }
+
+    /// Run `processes` on the boot CPU until each has had the ticks of CPU time `bursts`
+    /// gives it (or `max_ticks` pass), charging CPU and wait time to the PCBs the way
+    /// `account_tick` does, and summarise how long they waited. Every process arrives at
+    /// tick 0, so a process's turnaround is the tick it exits at.
+    pub fn run_workload(
+        &mut self,
+        mut processes: BTreeMap<ProcessId, ProcessControlBlock>,
+        bursts: &BTreeMap<ProcessId, u64>,
+        max_ticks: u64,
+    ) -> WorkloadResult {
+        for pcb in processes.values_mut() {
+            pcb.state = ProcessState::Ready;
+            pcb.cpu_time = 0;
+            pcb.total_wait_time = 0;
+            pcb.exit_time = None;
+            self.enqueue(pcb);
+        }
+
+        let mut context_switches = 0;
+        for tick in 0..max_ticks {
+            if processes.values().all(|pcb| pcb.exit_time.is_some()) {
+                break;
+            }
+            let current = self.get_current_process();
+            if current.is_none() || self.should_preempt() {
+                if let Some(pcb) = current.and_then(|pid| processes.get_mut(&pid)) {
+                    pcb.state = ProcessState::Ready;
+                    self.enqueue(pcb);
+                }
+                if let Some(next) = self.schedule_next(&mut processes) {
+                    if let Some(pcb) = processes.get_mut(&next) {
+                        pcb.state = ProcessState::Running;
+                    }
+                    if current != Some(next) {
+                        context_switches += 1;
+                    }
+                }
+            }
+
+            for pcb in processes.values_mut() {
+                match pcb.state {
+                    ProcessState::Running => pcb.cpu_time += 1,
+                    ProcessState::Ready => pcb.total_wait_time += 1,
+                    _ => {}
+                }
+            }
+            self.tick();
+
+            // A process that has used its burst exits, leaving the CPU idle until the next pick
+            if let Some(pcb) = self.get_current_process().and_then(|pid| processes.get_mut(&pid)) {
+                if pcb.cpu_time >= bursts.get(&pcb.pid).copied().unwrap_or(0) {
+                    pcb.state = ProcessState::Terminated;
+                    pcb.exit_time = Some(tick + 1);
+                    self.dequeue(pcb.pid);
+                    self.boot_cpu().current_process = None;
+                }
+            }
+        }
+
+        let count = processes.len().max(1) as f64;
+        let finished: Vec<&ProcessControlBlock> = processes.values().filter(|pcb| pcb.exit_time.is_some()).collect();
+        WorkloadResult {
+            algorithm: self.scheduling_algorithm,
+            completed: finished.len(),
+            average_wait: processes.values().map(|pcb| pcb.total_wait_time).sum::<u64>() as f64 / count,
+            average_turnaround: finished.iter().filter_map(|pcb| pcb.exit_time).sum::<u64>() as f64
+                / finished.len().max(1) as f64,
+            context_switches,
+        }
     }
 
     /// Get scheduler statistics
     pub fn get_stats(&self) -> SchedulerStats {
         SchedulerStats {
-            current_process: self.current_process,
-            time_slice_remaining: self.time_slice_remaining,
+            current_process: self.cpus[BOOT_CPU].current_process,
+            time_slice_remaining: self.cpus[BOOT_CPU].time_slice_remaining,
             total_switches: self.get_total_switches(),
             algorithm: self.scheduling_algorithm,
         }
     }
 }
 
-/// Scheduler statistics
+/// Scheduler statistics; the per-process fields describe the boot CPU
 #[derive(Debug)]
 pub struct SchedulerStats {
     pub current_process: Option<ProcessId>,
@@ -205,6 +319,16 @@ pub struct SchedulerStats {
     pub algorithm: SchedulingAlgorithm,
 }
 
+/// How one algorithm handled a workload in `run_workload`, in ticks
+#[derive(Debug, Clone, Copy)]
+pub struct WorkloadResult {
+    pub algorithm: SchedulingAlgorithm,
+    pub completed: usize, // Processes that got their whole burst
+    pub average_wait: f64,
+    pub average_turnaround: f64, // Over completed processes
+    pub context_switches: u64,   // Picks of a different process than the one running
+}
+
 lazy_static! {
     pub static ref SCHEDULER: Mutex<ProcessScheduler> = Mutex::new(ProcessScheduler::new());
 }
@@ -233,3 +357,301 @@ pub fn get_scheduler_stats() -> SchedulerStats {
 pub fn force_context_switch() {
     SCHEDULER.lock().force_switch();
 }
+
+/// Each of `pids`' share of `ticks` ticks when a fresh scheduler using the current
+/// algorithm runs copies of them. Live processes and the global scheduler are untouched.
+pub fn measure_fairness(pids: &[ProcessId], ticks: u64) -> Vec<(ProcessId, f64)> {
+    use crate::services::process_service::PROCESS_SERVICE;
+
+    let processes: BTreeMap<ProcessId, ProcessControlBlock> = {
+        let service = PROCESS_SERVICE.lock();
+        pids.iter().filter_map(|&pid| service.get_process(pid)).map(|pcb| (pcb.pid, pcb.clone())).collect()
+    };
+    let mut scheduler = ProcessScheduler::new();
+    scheduler.scheduling_algorithm = SCHEDULER.lock().scheduling_algorithm;
+    scheduler.simulate_shares(processes, ticks)
+}
+
+/// Panic unless every share is within `tolerance` of an equal split
+pub fn assert_fair(shares: &[(ProcessId, f64)], tolerance: f64) {
+    let fair = 1.0 / shares.len().max(1) as f64;
+    for &(pid, share) in shares {
+        let deviation = if share > fair { share - fair } else { fair - share };
+        assert!(
+            deviation <= tolerance,
+            "PID {} got {:.3} of the CPU, expected {:.3} +/- {:.3}",
+            pid, share, fair, tolerance
+        );
+    }
+}
+
+#[test_case]
+fn test_equal_priority_processes_schedule_in_pid_order() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::ProcessService;
+
+    let mut service = ProcessService::new();
+    let pids: Vec<ProcessId> = (0..4)
+        .map(|i| {
+            let name = alloc::format!("tie_{}", i);
+            service.create_process(name, ProcessPriority::Normal, 4096, 4096).unwrap()
+        })
+        .collect();
+    let ready: BTreeMap<ProcessId, ProcessControlBlock> = pids
+        .iter()
+        .map(|&pid| {
+            let mut pcb = service.get_process(pid).unwrap().clone();
+            pcb.state = ProcessState::Ready;
+            (pid, pcb)
+        })
+        .collect();
+
+    for algorithm in [SchedulingAlgorithm::Priority, SchedulingAlgorithm::FirstComeFirstServed, SchedulingAlgorithm::ShortestJobFirst] {
+        let mut scheduler = ProcessScheduler::new();
+        scheduler.scheduling_algorithm = algorithm;
+        let mut processes = ready.clone();
+        for pcb in processes.values() {
+            scheduler.enqueue(pcb);
+        }
+        let mut order = Vec::new();
+        while let Some(pid) = scheduler.schedule_next(&mut processes) {
+            processes.get_mut(&pid).unwrap().state = ProcessState::Running;
+            order.push(pid);
+        }
+        assert_eq!(order, pids, "{:?} broke a tie out of pid order", algorithm);
+    }
+}
+
+#[test_case]
+fn test_cpus_hold_different_current_processes() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::ProcessService;
+
+    let mut service = ProcessService::new();
+    let mut processes = BTreeMap::new();
+    for i in 0..2 {
+        let pid = service.create_process(alloc::format!("cpu_{}", i), ProcessPriority::Normal, 4096, 4096).unwrap();
+        let mut pcb = service.get_process(pid).unwrap().clone();
+        pcb.state = ProcessState::Ready;
+        processes.insert(pid, pcb);
+    }
+
+    let mut scheduler = ProcessScheduler::with_cpus(2);
+    for pcb in processes.values() {
+        scheduler.enqueue(pcb);
+    }
+    assert_eq!(scheduler.cpu_count(), 2);
+    let first = scheduler.schedule_next_on(0, &mut processes).unwrap();
+    processes.get_mut(&first).unwrap().state = ProcessState::Running;
+    let second = scheduler.schedule_next_on(1, &mut processes).unwrap();
+    processes.get_mut(&second).unwrap().state = ProcessState::Running;
+
+    assert_ne!(first, second);
+    assert_eq!(scheduler.get_current_process_on(0), Some(first));
+    assert_eq!(scheduler.get_current_process_on(1), Some(second));
+    assert_eq!(scheduler.get_current_process(), Some(first));
+
+    // Slices run down independently
+    scheduler.force_switch();
+    assert!(scheduler.should_preempt_on(0));
+    assert!(!scheduler.should_preempt_on(1));
+    assert_eq!(scheduler.schedule_next_on(2, &mut processes), None);
+}
+
+#[test_case]
+fn test_schedule_cost_does_not_grow_with_process_count() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::ProcessService;
+
+    const ROUNDS: u64 = 200;
+
+    let mut service = ProcessService::new();
+    let pid = service.create_process(alloc::string::String::from("bench"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let template = service.get_process(pid).unwrap().clone();
+
+    // `count` processes, spread over the pid range, of which `ready` are runnable and the
+    // rest blocked after being queued. Cheapest of a few runs, so a timer interrupt
+    // landing in one does not skew it.
+    let cycles_per_schedule = |count: u64, ready: u64| {
+        let mut processes: BTreeMap<ProcessId, ProcessControlBlock> = (1..=count)
+            .map(|pid| {
+                let mut pcb = template.clone();
+                pcb.pid = pid;
+                pcb.creation_time = pid;
+                pcb.state = ProcessState::Ready;
+                (pid, pcb)
+            })
+            .collect();
+        (0..3)
+            .map(|_| {
+                let mut scheduler = ProcessScheduler::new();
+                for pcb in processes.values_mut() {
+                    pcb.state = ProcessState::Ready;
+                    scheduler.enqueue(pcb);
+                    if pcb.pid % (count / ready) != 0 {
+                        pcb.state = ProcessState::Blocked;
+                    }
+                }
+                // The first pass drops the blocked processes from the queue
+                for _ in 0..ready {
+                    let pid = scheduler.schedule_next(&mut processes).unwrap();
+                    scheduler.enqueue(&processes[&pid]);
+                }
+                let start = crate::tsc::read();
+                for _ in 0..ROUNDS {
+                    let pid = scheduler.schedule_next(&mut processes).unwrap();
+                    scheduler.enqueue(&processes[&pid]);
+                }
+                crate::tsc::read().wrapping_sub(start) / ROUNDS
+            })
+            .min()
+            .unwrap()
+    };
+
+    let small = cycles_per_schedule(16, 16);
+    let large = cycles_per_schedule(512, 512);
+    let mostly_blocked = cycles_per_schedule(512, 16);
+    crate::println!(
+        "schedule_next: {} cycles with 16 processes, {} with 512, {} with 16 of 512 ready",
+        small, large, mostly_blocked,
+    );
+    // A full scan would cost about 32 times as much with 512 processes
+    assert!(large < small.max(1) * 4, "scheduling cost grew from {} to {} cycles", small, large);
+    assert!(mostly_blocked < small.max(1) * 4, "blocked processes raised the cost to {} cycles", mostly_blocked);
+}
+
+#[test_case]
+fn test_pinned_process_only_runs_on_its_cpu() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::ProcessService;
+
+    let mut service = ProcessService::new();
+    let mut processes: BTreeMap<ProcessId, ProcessControlBlock> = BTreeMap::new();
+    for i in 0..3 {
+        let pid = service.create_process(alloc::format!("affinity_{}", i), ProcessPriority::Normal, 4096, 4096).unwrap();
+        let mut pcb = service.get_process(pid).unwrap().clone();
+        pcb.state = ProcessState::Ready;
+        processes.insert(pid, pcb);
+    }
+    let pinned = *processes.keys().nth(1).unwrap();
+    assert_eq!(service.set_affinity(pinned, 0), Err(crate::process::pcb::ProcessError::InvalidAffinity));
+    service.set_affinity(pinned, 1 << 1).unwrap();
+    processes.get_mut(&pinned).unwrap().affinity_mask = service.get_process(pinned).unwrap().affinity_mask;
+    assert!(!processes[&pinned].can_run_on(0));
+
+    for algorithm in [SchedulingAlgorithm::RoundRobin, SchedulingAlgorithm::Priority] {
+        let mut scheduler = ProcessScheduler::with_cpus(2);
+        scheduler.scheduling_algorithm = algorithm;
+        let mut processes = processes.clone();
+        for pcb in processes.values() {
+            scheduler.enqueue(pcb);
+        }
+        for _ in 0..10 {
+            let pid = scheduler.schedule_next_on(0, &mut processes).unwrap();
+            assert_ne!(pid, pinned, "{:?} ran a process pinned to CPU 1 on CPU 0", algorithm);
+            scheduler.enqueue(&processes[&pid]); // Preempted at once
+        }
+        // Still queued, and CPU 1 may take it
+        let mut picked = Vec::new();
+        while let Some(pid) = scheduler.schedule_next_on(1, &mut processes) {
+            processes.get_mut(&pid).unwrap().state = ProcessState::Running;
+            picked.push(pid);
+        }
+        assert!(picked.contains(&pinned));
+    }
+}
+
+#[test_case]
+fn test_round_robin_shares_cpu_equally() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process, wait_child};
+
+    let pids: Vec<ProcessId> = (0..4)
+        .map(|i| create_process(alloc::format!("fair_{}", i), ProcessPriority::Normal, 4096, 4096).unwrap())
+        .collect();
+    let previous = SCHEDULER.lock().scheduling_algorithm;
+    set_scheduling_algorithm(SchedulingAlgorithm::RoundRobin);
+    let shares = measure_fairness(&pids, 40 * TIME_SLICE);
+    set_scheduling_algorithm(previous);
+
+    assert_eq!(shares.iter().map(|&(pid, _)| pid).collect::<Vec<_>>(), pids);
+    assert_fair(&shares, 0.01);
+    for pid in pids {
+        let _ = terminate_process(pid, 0);
+        let _ = wait_child(0, Some(pid));
+    }
+}
+
+#[test_case]
+fn test_every_algorithm_completes_mixed_workload() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::ProcessService;
+
+    let mut service = ProcessService::new();
+    let mut processes = BTreeMap::new();
+    let mut bursts = BTreeMap::new();
+    let priorities = [ProcessPriority::Low, ProcessPriority::High, ProcessPriority::Normal, ProcessPriority::Critical];
+    for (i, &priority) in priorities.iter().enumerate() {
+        let pid = service.create_process(alloc::format!("load_{}", i), priority, 4096 * (4 - i), 4096).unwrap();
+        processes.insert(pid, service.get_process(pid).unwrap().clone());
+        bursts.insert(pid, 50 + 60 * i as u64);
+    }
+    let total: u64 = bursts.values().sum();
+
+    for algorithm in SchedulingAlgorithm::ALL {
+        let mut scheduler = ProcessScheduler::new();
+        scheduler.set_algorithm(algorithm);
+        let result = scheduler.run_workload(processes.clone(), &bursts, total * 2);
+        assert_eq!(result.completed, priorities.len(), "{:?} left work unfinished", algorithm);
+        assert!(result.context_switches >= priorities.len() as u64);
+        // The CPU never idles while work remains, so the last exit is at the total burst
+        assert!(result.average_turnaround <= total as f64);
+    }
+}
+
+#[test_case]
+fn test_preempted_process_is_scheduled_again() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::ProcessService;
+
+    let mut service = ProcessService::new();
+    let mut processes: BTreeMap<ProcessId, ProcessControlBlock> = BTreeMap::new();
+    let mut scheduler = ProcessScheduler::new();
+    for i in 0..2 {
+        let pid = service.create_process(alloc::format!("preempt_{}", i), ProcessPriority::Normal, 4096, 4096).unwrap();
+        let pcb = service.get_process(pid).unwrap().clone();
+        scheduler.enqueue(&pcb); // Admitted on creation
+        processes.insert(pid, pcb);
+    }
+    let pids: Vec<ProcessId> = processes.keys().copied().collect();
+
+    // The second blocks without being dequeued; it is dropped when met
+    processes.get_mut(&pids[1]).unwrap().state = ProcessState::Blocked;
+    assert_eq!(scheduler.schedule_next(&mut processes), Some(pids[0]));
+    processes.get_mut(&pids[0]).unwrap().state = ProcessState::Running;
+    // Nothing else is runnable while it runs, and nothing is left queued
+    assert_eq!(scheduler.schedule_next(&mut processes), None);
+    assert!(scheduler.ready.is_empty());
+
+    // Preempt the running process and wake the blocked one
+    for &pid in &pids {
+        let pcb = processes.get_mut(&pid).unwrap();
+        pcb.state = ProcessState::Ready;
+        scheduler.enqueue(pcb);
+    }
+    let mut picked = Vec::new();
+    while let Some(pid) = scheduler.schedule_next(&mut processes) {
+        processes.get_mut(&pid).unwrap().state = ProcessState::Running;
+        picked.push(pid);
+    }
+    picked.sort();
+    assert_eq!(picked, pids);
+
+    // A pid handed out again after wrap-around is a new process and is admitted like one
+    let mut reused = processes.remove(&pids[1]).unwrap();
+    reused.state = ProcessState::Ready;
+    reused.creation_time += 1;
+    scheduler.enqueue(&reused);
+    processes.insert(pids[1], reused);
+    assert_eq!(scheduler.schedule_next(&mut processes), Some(pids[1]));
+}
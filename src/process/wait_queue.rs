@@ -0,0 +1,86 @@
+// Wait queues for EMOS Microkernel
+use alloc::collections::VecDeque;
+use spin::Mutex;
+use crate::process::pcb::{ProcessError, ProcessId};
+use crate::services::process_service::{block_process, unblock_process};
+
+/// Processes parked until some event happens, woken in the order they arrived.
+///
+/// A woken process must re-check its condition: `notify_*` only makes it Ready.
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<ProcessId>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Block `pid` and park it on this queue
+    pub fn wait(&self, pid: ProcessId) -> Result<(), ProcessError> {
+        block_process(pid)?;
+        self.waiters.lock().push_back(pid);
+        Ok(())
+    }
+
+    /// Wake the longest-waiting process that is still blocked
+    pub fn notify_one(&self) -> Option<ProcessId> {
+        loop {
+            let pid = self.waiters.lock().pop_front()?;
+            // Waiters that exited or were woken some other way are skipped
+            if unblock_process(pid).is_ok() {
+                return Some(pid);
+            }
+        }
+    }
+
+    /// Wake every parked process. Returns the number woken.
+    pub fn notify_all(&self) -> usize {
+        let waiters: VecDeque<ProcessId> = core::mem::take(&mut *self.waiters.lock());
+        waiters.into_iter().filter(|&pid| unblock_process(pid).is_ok()).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiters.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.lock().is_empty()
+    }
+}
+
+#[test_case]
+fn test_notify_wakes_waiters_in_fifo_order() {
+    use alloc::format;
+    use alloc::vec::Vec;
+    use crate::process::pcb::{ProcessPriority, ProcessState};
+    use crate::services::process_service::{create_process, get_process_stats, terminate_process};
+
+    let state = |pid| get_process_stats(pid).unwrap().state;
+    let queue = WaitQueue::new();
+    let pids: Vec<ProcessId> = (0..4)
+        .map(|i| create_process(format!("waiter_{}", i), ProcessPriority::Normal, 4096, 8192).unwrap())
+        .collect();
+    for &pid in &pids {
+        queue.wait(pid).unwrap();
+        assert_eq!(state(pid), ProcessState::Blocked);
+    }
+
+    // notify_one wakes exactly the oldest waiter
+    assert_eq!(queue.notify_one(), Some(pids[0]));
+    assert_eq!(state(pids[0]), ProcessState::Ready);
+    assert_eq!(state(pids[1]), ProcessState::Blocked);
+    assert_eq!(queue.notify_one(), Some(pids[1]));
+    assert_eq!(queue.len(), 2);
+
+    assert_eq!(queue.notify_all(), 2);
+    assert!(pids.iter().all(|&pid| state(pid) == ProcessState::Ready));
+    assert!(queue.is_empty());
+    assert_eq!(queue.notify_one(), None);
+
+    for pid in pids {
+        let _ = terminate_process(pid, 0);
+    }
+}
@@ -0,0 +1,77 @@
+// Pseudo-random numbers for EMOS Microkernel
+use spin::Mutex;
+
+/// Used until `seed_from_tsc` runs; any nonzero value works for xorshift
+const DEFAULT_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// xorshift64* generator. Fast and reproducible, but not suitable for secrets.
+///
+/// Code that needs a repeatable sequence (tests, lottery draws) should own an `Rng`
+/// built from its own seed instead of drawing from the shared kernel generator.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        // An all-zero state would only ever produce zeros
+        Rng { state: if seed == 0 { DEFAULT_SEED } else { seed } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+static KERNEL_RNG: Mutex<Rng> = Mutex::new(Rng::new(DEFAULT_SEED));
+
+/// Reseed the kernel generator from the timestamp counter; called once at boot
+pub fn seed_from_tsc() {
+    seed(crate::tsc::read());
+}
+
+/// Reseed the kernel generator with a fixed value
+pub fn seed(value: u64) {
+    *KERNEL_RNG.lock() = Rng::new(value);
+}
+
+pub fn next_u64() -> u64 {
+    KERNEL_RNG.lock().next_u64()
+}
+
+pub fn fill_bytes(buf: &mut [u8]) {
+    KERNEL_RNG.lock().fill_bytes(buf);
+}
+
+#[test_case]
+fn test_fixed_seed_is_reproducible() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    for _ in 0..16 {
+        assert_eq!(a.next_u64(), b.next_u64());
+    }
+
+    // fill_bytes draws the same stream, including a partial final word
+    let mut bytes = [0u8; 12];
+    Rng::new(7).fill_bytes(&mut bytes);
+    let mut words = Rng::new(7);
+    let first = words.next_u64().to_le_bytes();
+    let second = words.next_u64().to_le_bytes();
+    assert_eq!(&bytes[..8], &first);
+    assert_eq!(&bytes[8..], &second[..4]);
+
+    assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+}
@@ -0,0 +1,178 @@
+// CMOS Real-Time Clock for EMOS Microkernel
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::instructions::port::Port;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+const REG_STATUS_D: u8 = 0x0D;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 0x80;
+const STATUS_B_24_HOUR: u8 = 0x02;
+const STATUS_B_BINARY: u8 = 0x04;
+const HOUR_PM: u8 = 0x80;
+
+/// Bit 7 of the address port masks NMI
+const NMI_DISABLE: u8 = 0x80;
+
+/// Whether NMI is meant to be masked. The address port is write-only, so the bit is
+/// tracked here and put back after every CMOS access.
+static NMI_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Wall-clock date and time as reported by the RTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Raw register snapshot, used to detect reads that straddle an update
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+/// Mask or unmask NMI, leaving `reg` selected
+fn select(reg: u8, nmi_disabled: bool) {
+    let nmi = if nmi_disabled { NMI_DISABLE } else { 0 };
+    unsafe { Port::<u8>::new(CMOS_ADDRESS).write(nmi | reg) };
+}
+
+/// Mask or unmask non-maskable interrupts
+pub fn set_nmi_enabled(enabled: bool) {
+    NMI_DISABLED.store(!enabled, Ordering::SeqCst);
+    select(REG_STATUS_D, !enabled);
+}
+
+fn read_register(reg: u8) -> u8 {
+    // Keep NMI masked while we poke CMOS, then put back whatever was set before
+    select(reg, true);
+    let value = unsafe { Port::<u8>::new(CMOS_DATA).read() };
+    select(REG_STATUS_D, NMI_DISABLED.load(Ordering::SeqCst));
+    value
+}
+
+fn update_in_progress() -> bool {
+    read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+}
+
+fn read_raw() -> RawTime {
+    while update_in_progress() {
+        core::hint::spin_loop();
+    }
+    RawTime {
+        second: read_register(REG_SECONDS),
+        minute: read_register(REG_MINUTES),
+        hour: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+    }
+}
+
+/// Convert a packed BCD byte to binary
+pub fn bcd_to_binary(value: u8) -> u8 {
+    (value & 0x0f) + (value >> 4) * 10
+}
+
+/// Decode raw register values according to the status register B format flags
+fn decode(raw: RawTime, status_b: u8) -> DateTime {
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let convert = |v: u8| if binary { v } else { bcd_to_binary(v) };
+
+    let pm = raw.hour & HOUR_PM != 0;
+    let mut hour = convert(raw.hour & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12-hour clock: 12 AM is 0, 12 PM is 12
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    DateTime {
+        year: 2000 + convert(raw.year) as u16,
+        month: convert(raw.month),
+        day: convert(raw.day),
+        hour,
+        minute: convert(raw.minute),
+        second: convert(raw.second),
+    }
+}
+
+/// Read the current wall-clock time.
+///
+/// Reads until two consecutive snapshots agree so a read never mixes values from
+/// before and after an RTC update.
+pub fn read_datetime() -> DateTime {
+    let mut last = read_raw();
+    loop {
+        let current = read_raw();
+        if current == last {
+            break;
+        }
+        last = current;
+    }
+    decode(last, read_register(REG_STATUS_B))
+}
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    pub fn to_unix_timestamp(&self) -> u64 {
+        // Days from civil algorithm (proleptic Gregorian calendar)
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        (days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64) as u64
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[test_case]
+fn test_decode_bcd_12_hour() {
+    // 11:59:30 PM, 2024-02-29 in BCD, 12-hour mode
+    let raw = RawTime { second: 0x30, minute: 0x59, hour: HOUR_PM | 0x11, day: 0x29, month: 0x02, year: 0x24 };
+    let time = decode(raw, 0);
+    assert_eq!(time, DateTime { year: 2024, month: 2, day: 29, hour: 23, minute: 59, second: 30 });
+    assert_eq!(time.to_unix_timestamp(), 1709251170);
+}
+
+#[test_case]
+fn test_read_datetime_is_sane() {
+    let now = read_datetime();
+    assert!((1..=12).contains(&now.month));
+    assert!((1..=31).contains(&now.day));
+    assert!(now.hour < 24 && now.minute < 60 && now.second < 60);
+}
@@ -1,34 +1,26 @@
-use alloc::boxed::Box;
-use alloc::collections::VecDeque;
-use core::cell::RefCell;
-use core::future::{Future, poll_fn};
-use core::pin::Pin;
-use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
-use spin::Mutex;
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
 
 use crate::print;
+use crate::task::{executor, JoinHandle, Task};
 
-/// Our task queue (simple round-robin)
-static TASK_QUEUE: Mutex<RefCell<VecDeque<Task>>> =
-    Mutex::new(RefCell::new(VecDeque::new()));
+/// Input clock of the 8253/8254 PIT in Hz
+pub const PIT_BASE_HZ: u32 = 1_193_182;
 
-/// Simple wrapper for a boxed future
-pub struct Task {
-    future: Pin<Box<dyn Future<Output = ()> + Send + 'static>>,
-}
-
-impl Task {
-    pub fn new(fut: impl Future<Output = ()> + Send + 'static) -> Self {
-        Task {
-            future: Box::pin(fut), // Pin the future
-        }
-    }
+/// Divisor for the rate closest to `hz` that the PIT can produce, and that rate.
+///
+/// The 16-bit divisor limits the rate to roughly 18.2 Hz (divisor 65535) up to the
+/// base clock (divisor 1); requests outside that range are clamped.
+pub fn pit_divisor(hz: u32) -> (u16, u32) {
+    let divisor = (PIT_BASE_HZ / hz.max(1)).clamp(1, u16::MAX as u32);
+    (divisor as u16, PIT_BASE_HZ / divisor)
 }
 
 /// Initialize the PIT for timer interrupts.
-/// `hz` = frequency in Hertz.
-pub fn init_pit(hz: u32) {
-    let divisor: u16 = (1193180 / hz) as u16; // PIT runs at 1.193182 MHz
+/// `hz` = requested frequency in Hertz; returns the frequency actually programmed.
+pub fn init_pit(hz: u32) -> u32 {
+    let (divisor, actual_hz) = pit_divisor(hz);
     unsafe {
         use x86_64::instructions::port::Port;
         let mut command = Port::<u8>::new(0x43);
@@ -39,42 +31,73 @@ pub fn init_pit(hz: u32) {
         channel0.write((divisor & 0xFF) as u8); // low byte
         channel0.write((divisor >> 8) as u8);   // high byte
     }
-    print!("[PIT init {} Hz]", hz);
+    crate::time::set_timer_hz(actual_hz);
+    print!("[PIT init {} Hz]", actual_hz);
+    actual_hz
 }
 
+/// Set by the timer interrupt and by task wakers when an executor pass is due
+static PASS_PENDING: AtomicBool = AtomicBool::new(false);
+
 /// Called on each timer interrupt.
-/// This advances the scheduler and runs one task.
+/// Only records that a pass is due; `run_bottom_half` does the polling outside the handler.
 pub fn on_tick() {
-    let mut queue = TASK_QUEUE.lock();
-    let mut queue_ref = queue.borrow_mut();
-
-    if let Some(mut task) = queue_ref.pop_front() {
-        // poll the task
-        let waker = dummy_waker();
-        let mut cx = Context::from_waker(&waker);
-
-        match task.future.as_mut().poll(&mut cx) {
-            Poll::Ready(_) => {
-                // task is done, drop it
-                print!("[task done]");
-            }
-            Poll::Pending => {
-                // push back for round-robin
-                queue_ref.push_back(task);
-            }
+    request_pass();
+}
+
+/// Ask `executor::run` for a pass; safe to call from interrupt handlers
+pub fn request_pass() {
+    PASS_PENDING.store(true, Ordering::Release);
+}
+
+/// Whether a tick has requested a pass that has not run yet
+pub fn pass_pending() -> bool {
+    PASS_PENDING.load(Ordering::Acquire)
+}
+
+/// Run the executor passes requested by `on_tick`, with interrupts enabled so that
+/// further ticks and keyboard input are serviced while tasks are polled.
+///
+/// `executor::run` calls this; the timer handler only sets the pending flag, so tasks
+/// are never polled inside an interrupt frame. Ticks arriving during a pass set the flag
+/// again, and the extra pass runs before this returns.
+/// Restores the caller's interrupt state and returns the number of passes run.
+pub fn run_bottom_half() -> usize {
+    use x86_64::instructions::interrupts;
+
+    let enabled = interrupts::are_enabled();
+    let mut passes = 0;
+    while PASS_PENDING.swap(false, Ordering::AcqRel) {
+        interrupts::enable();
+        executor::run_pass();
+        if !enabled {
+            interrupts::disable();
         }
+        passes += 1;
     }
+    passes
 }
 
-/// Spawn a new task into the queue.
-pub fn spawn(task: Task) {
-    TASK_QUEUE.lock().borrow_mut().push_back(task);
+/// Spawn a new task on the executor.
+pub fn spawn(task: Task) -> JoinHandle {
+    executor::spawn(task)
 }
 
 /// Yield control back to the scheduler.
-/// This future will always return Poll::Pending, allowing other tasks to run.
+/// Wakes itself and returns Poll::Pending once, so the task resumes on the next
+/// scheduling pass.
 pub async fn yield_task() {
-    poll_fn(|_| Poll::Pending).await
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
 }
 
 /// Add some demo tasks.
@@ -98,16 +121,67 @@ pub fn spawn_demo_tasks() {
     }));
 }
 
-/// Dummy waker (since we’re not using async executors yet).
-fn dummy_raw_waker() -> RawWaker {
-    fn no_op(_: *const ()) {}
-    fn clone(_: *const ()) -> RawWaker {
-        dummy_raw_waker()
-    }
-    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
-    RawWaker::new(core::ptr::null(), &VTABLE)
+#[test_case]
+fn test_pit_divisor_clamps_to_representable_range() {
+    assert_eq!(pit_divisor(100), (11931, 100));
+    // Too slow for a 16-bit divisor: clamp to the slowest rate
+    assert_eq!(pit_divisor(1), (u16::MAX, 18));
+    assert_eq!(pit_divisor(0), (u16::MAX, 18));
+    // Faster than the input clock: divisor 1
+    assert_eq!(pit_divisor(5_000_000), (1, PIT_BASE_HZ));
+
+    let (divisor, actual) = pit_divisor(1000);
+    assert_eq!(actual, PIT_BASE_HZ / divisor as u32);
+    assert!(actual.abs_diff(1000) < 2);
 }
 
-fn dummy_waker() -> Waker {
-    unsafe { Waker::from_raw(dummy_raw_waker()) }
+#[test_case]
+fn test_interrupts_are_serviced_while_bottom_half_polls() {
+    use core::sync::atomic::AtomicU64;
+
+    static TICKS_SEEN: AtomicU64 = AtomicU64::new(0);
+    const TIMEOUT_NS: u64 = 500_000_000;
+
+    // A task that only finishes once timer interrupts arrive while it is being polled
+    spawn(Task::new(async {
+        let start = crate::time::ticks();
+        let began = crate::tsc::read();
+        while crate::time::ticks() < start + 2 && crate::tsc::elapsed_ns(began) < TIMEOUT_NS {
+            core::hint::spin_loop();
+        }
+        TICKS_SEEN.store(crate::time::ticks() - start, Ordering::SeqCst);
+    }));
+
+    // Do what a tick and the executor loop do
+    on_tick();
+    assert!(run_bottom_half() >= 1);
+
+    assert!(TICKS_SEEN.load(Ordering::SeqCst) >= 2, "timer interrupts were held off during the pass");
+}
+
+#[test_case]
+fn test_timer_interrupt_requests_a_pass_without_polling() {
+    use core::sync::atomic::AtomicUsize;
+
+    static POLLS: AtomicUsize = AtomicUsize::new(0);
+    const TIMEOUT_NS: u64 = 500_000_000;
+
+    run_bottom_half();
+    spawn(Task::new(poll_fn(|_| {
+        POLLS.fetch_add(1, Ordering::SeqCst);
+        Poll::Ready(())
+    })));
+
+    // Let a real timer interrupt arrive
+    let start = crate::time::ticks();
+    let began = crate::tsc::read();
+    while crate::time::ticks() == start && crate::tsc::elapsed_ns(began) < TIMEOUT_NS {
+        core::hint::spin_loop();
+    }
+    assert!(crate::time::ticks() > start, "no timer interrupt arrived");
+    assert!(pass_pending());
+    assert_eq!(POLLS.load(Ordering::SeqCst), 0, "the timer interrupt polled a task");
+
+    assert!(run_bottom_half() >= 1);
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1);
 }
@@ -1,12 +1,25 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
 use x86_64::instructions::port::Port;
 
+/// I/O base of the first 16550 UART
+const COM1: u16 = 0x3F8;
+const MODEM_CONTROL: u16 = COM1 + 4;
+const LINE_STATUS: u16 = COM1 + 5;
+const MCR_LOOPBACK: u8 = 0x10;
+const LSR_DATA_READY: u8 = 0x01;
+/// Depth of the UART receive FIFO; a loopback echo longer than this loses bytes
+const FIFO_DEPTH: usize = 16;
+
+/// Whether `println!` output is copied to the serial port as well as the screen
+static MIRROR_PRINTLN: AtomicBool = AtomicBool::new(false);
 
 lazy_static! {
     pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
+        let mut serial_port = unsafe { SerialPort::new(COM1) };
         serial_port.init();
         Mutex::new(serial_port)
     };
@@ -38,12 +51,90 @@ macro_rules! serial_println {
         concat!($fmt, "\n"), $($arg)*));
 }
 
+/// Short form of `serial_print!`
+#[macro_export]
+macro_rules! sprint {
+    ($($arg:tt)*) => ($crate::serial_print!($($arg)*));
+}
+
+/// Short form of `serial_println!`
+#[macro_export]
+macro_rules! sprintln {
+    ($($arg:tt)*) => ($crate::serial_println!($($arg)*));
+}
+
+/// Copy everything `println!` prints to the serial port too, for headless runs
+pub fn set_mirror_println(enabled: bool) {
+    MIRROR_PRINTLN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn mirrors_println() -> bool {
+    MIRROR_PRINTLN.load(Ordering::Relaxed)
+}
+
+/// Called by `vga_buffer::_print`. Only try-locks, since it may run in an interrupt
+/// handler that preempted a serial print; mirrored text is dropped in that case.
+pub(crate) fn mirror(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+    if !mirrors_println() {
+        return;
+    }
+    if let Some(mut port) = SERIAL1.try_lock() {
+        let _ = port.write_fmt(args);
+    }
+}
+
+/// Send `bytes` with the UART in loopback mode and return what came back on its receive
+/// side, which is exactly what a connected host would have read.
+///
+/// `bytes` must not hold backspace or DEL, which `send` expands. Nothing reaches the
+/// real line while looped back.
+pub fn loopback(bytes: &[u8]) -> Vec<u8> {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let mut port = SERIAL1.lock();
+        let mut modem_control = Port::<u8>::new(MODEM_CONTROL);
+        let mut line_status = Port::<u8>::new(LINE_STATUS);
+        let mut data = Port::<u8>::new(COM1);
+        let mut read_ready = || unsafe {
+            match line_status.read() & LSR_DATA_READY {
+                0 => None,
+                _ => Some(data.read()),
+            }
+        };
+
+        let saved = unsafe { modem_control.read() };
+        unsafe { modem_control.write(saved | MCR_LOOPBACK) };
+        while read_ready().is_some() {}
+
+        let mut echoed = Vec::with_capacity(bytes.len());
+        for chunk in bytes.chunks(FIFO_DEPTH) {
+            for &byte in chunk {
+                port.send(byte);
+            }
+            // The transmitter may still be shifting the last byte out
+            let expected = echoed.len() + chunk.len();
+            let mut spins = 0;
+            while echoed.len() < expected && spins < 100_000 {
+                match read_ready() {
+                    Some(byte) => echoed.push(byte),
+                    None => spins += 1,
+                }
+            }
+        }
+
+        unsafe { modem_control.write(saved) };
+        echoed
+    })
+}
+
 
 #[inline(always)]
 pub fn write_byte_raw(byte: u8) {
     unsafe {
         // COM1 data register
-        let mut data = Port::<u8>::new(0x3F8);
+        let mut data = Port::<u8>::new(COM1);
         data.write(byte);
     }
 }
@@ -53,4 +144,41 @@ pub fn write_str_raw(s: &str) {
     for &b in s.as_bytes() {
         write_byte_raw(b);
     }
-}
\ No newline at end of file
+}
+
+/// Formats through `write_str_raw`, so it never waits on `SERIAL1`
+struct RawWriter;
+
+impl core::fmt::Write for RawWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        write_str_raw(s);
+        Ok(())
+    }
+}
+
+/// Print to COM1 without taking `SERIAL1`, for when its holder may never release it
+pub fn force_print(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+    let _ = RawWriter.write_fmt(args);
+}
+
+#[test_case]
+fn test_serial_loopback_returns_written_bytes() {
+    use core::fmt::Write;
+
+    struct Capture(Vec<u8>);
+    impl Write for Capture {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            self.0.extend(loopback(s.as_bytes()));
+            Ok(())
+        }
+    }
+
+    let mut capture = Capture(Vec::new());
+    writeln!(capture, "emos serial {}", 42).unwrap();
+    assert_eq!(capture.0, b"emos serial 42\n");
+
+    // Longer than the FIFO, so it goes through in several rounds
+    let long = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    assert_eq!(loopback(long), long);
+}
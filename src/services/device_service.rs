@@ -0,0 +1,194 @@
+// Device Service for EMOS Microkernel
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use crate::ipc::{DeviceRequest, Message, MessageData};
+use crate::lock_debug::TrackedMutex;
+use crate::process::pcb::ProcessId;
+
+/// Mailbox the device service receives `DeviceRequest` messages on
+pub const DEVICE_SERVICE_PID: ProcessId = u64::MAX - 1;
+
+/// Well-known device ids
+pub const VGA_DEVICE: u64 = 1;
+pub const KEYBOARD_DEVICE: u64 = 2;
+
+/// `DeviceRequest::command` values
+pub const DEVICE_READ: u64 = 0;  // payload: requested length as a little-endian u64
+pub const DEVICE_WRITE: u64 = 1; // payload: bytes to write
+
+/// `command` field of a reply: 0 on success, otherwise the error code
+pub const DEVICE_OK: u64 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceError {
+    DeviceNotFound = 1,
+    DeviceExists = 2,
+    UnsupportedCommand = 3,
+    InvalidRequest = 4,
+}
+
+/// A driver the device service can route requests to
+pub trait Device: Send {
+    fn name(&self) -> &'static str;
+
+    fn read(&mut self, _len: usize) -> Result<Vec<u8>, DeviceError> {
+        Err(DeviceError::UnsupportedCommand)
+    }
+
+    fn write(&mut self, _data: &[u8]) -> Result<usize, DeviceError> {
+        Err(DeviceError::UnsupportedCommand)
+    }
+}
+
+/// Text-mode console; writes go to the screen
+pub struct VgaDevice;
+
+impl Device for VgaDevice {
+    fn name(&self) -> &'static str {
+        "vga"
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, DeviceError> {
+        crate::vga_buffer::write_bytes(data);
+        Ok(data.len())
+    }
+}
+
+/// PS/2 keyboard; reads drain buffered scancodes without blocking
+pub struct KeyboardDevice;
+
+impl Device for KeyboardDevice {
+    fn name(&self) -> &'static str {
+        "keyboard"
+    }
+
+    fn read(&mut self, len: usize) -> Result<Vec<u8>, DeviceError> {
+        use crate::services::keyboard_service::try_get_scancode;
+
+        Ok(core::iter::from_fn(try_get_scancode).take(len).collect())
+    }
+}
+
+/// Device Service - Owns the registered drivers and executes device requests
+pub struct DeviceService {
+    devices: BTreeMap<u64, Box<dyn Device>>,
+}
+
+impl DeviceService {
+    pub fn new() -> Self {
+        let mut service = Self { devices: BTreeMap::new() };
+        let _ = service.register_device(VGA_DEVICE, Box::new(VgaDevice));
+        let _ = service.register_device(KEYBOARD_DEVICE, Box::new(KeyboardDevice));
+        service
+    }
+
+    pub fn register_device(&mut self, id: u64, device: Box<dyn Device>) -> Result<(), DeviceError> {
+        if self.devices.contains_key(&id) {
+            return Err(DeviceError::DeviceExists);
+        }
+        self.devices.insert(id, device);
+        Ok(())
+    }
+
+    /// Registered devices as (id, name)
+    pub fn list_devices(&self) -> Vec<(u64, &'static str)> {
+        self.devices.iter().map(|(&id, device)| (id, device.name())).collect()
+    }
+
+    /// Execute a request, returning the bytes read or the byte count written (as a u64)
+    pub fn handle_request(&mut self, request: &DeviceRequest) -> Result<Vec<u8>, DeviceError> {
+        let device = self.devices.get_mut(&request.device_id).ok_or(DeviceError::DeviceNotFound)?;
+        match request.command {
+            DEVICE_READ => {
+                let raw: [u8; 8] = request.payload.get(..8)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or(DeviceError::InvalidRequest)?;
+                device.read(u64::from_le_bytes(raw) as usize)
+            }
+            DEVICE_WRITE => {
+                let written = device.write(&request.payload)?;
+                Ok((written as u64).to_le_bytes().to_vec())
+            }
+            _ => Err(DeviceError::UnsupportedCommand),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref DEVICE_SERVICE: TrackedMutex<DeviceService> = TrackedMutex::new("DEVICE_SERVICE", DeviceService::new());
+}
+
+/// Device service API functions
+pub fn register_device(id: u64, device: Box<dyn Device>) -> Result<(), DeviceError> {
+    DEVICE_SERVICE.lock().register_device(id, device)
+}
+
+pub fn list_devices() -> Vec<(u64, &'static str)> {
+    DEVICE_SERVICE.lock().list_devices()
+}
+
+/// Execute a request directly, without going through the mailbox
+pub fn execute(request: &DeviceRequest) -> Result<Vec<u8>, DeviceError> {
+    DEVICE_SERVICE.lock().handle_request(request)
+}
+
+/// Answer every `DeviceRequest` waiting in the device service's mailbox.
+///
+/// Each reply is a `DeviceRequest` for the same device whose `command` is `DEVICE_OK`
+/// or a `DeviceError` code, carrying the result bytes as payload.
+/// Returns the number of requests handled.
+pub fn handle_pending_requests() -> usize {
+    let mut handled = 0;
+    while let Some(message) = crate::ipc::receive(DEVICE_SERVICE_PID) {
+        handled += 1;
+        let request = match &message.data {
+            MessageData::DeviceRequest(request) => request,
+            _ => continue, // Not a device request; nothing to answer
+        };
+        let result = DEVICE_SERVICE.lock().handle_request(request);
+        reply(&message, request.device_id, result);
+    }
+    handled
+}
+
+fn reply(message: &Message, device_id: u64, result: Result<Vec<u8>, DeviceError>) {
+    let (command, payload) = match result {
+        Ok(payload) => (DEVICE_OK, payload),
+        Err(e) => (e as u64, Vec::new()),
+    };
+    crate::ipc::reply(message, MessageData::DeviceRequest(DeviceRequest { device_id, command, payload }));
+}
+
+#[test_case]
+fn test_device_request_writes_to_vga() {
+    use crate::vga_buffer::{char_at, LAST_ROW};
+
+    let request = |device_id, command, payload: &[u8]| MessageData::DeviceRequest(DeviceRequest {
+        device_id,
+        command,
+        payload: payload.to_vec(),
+    });
+
+    crate::println!();
+    let id = crate::ipc::call(DEVICE_SERVICE_PID, request(VGA_DEVICE, DEVICE_WRITE, b"Z"));
+    assert_eq!(char_at(LAST_ROW, 0), b'Z');
+
+    let sender = crate::services::process_service::get_current_process().unwrap_or(0);
+    let reply = crate::ipc::receive_reply(sender, id).expect("device service did not reply");
+    match reply.data {
+        MessageData::DeviceRequest(r) => {
+            assert_eq!(r.command, DEVICE_OK);
+            assert_eq!(r.payload, 1u64.to_le_bytes());
+        }
+        _ => panic!("unexpected reply data"),
+    }
+
+    // Unknown devices are reported back to the caller
+    let id = crate::ipc::call(DEVICE_SERVICE_PID, request(99, DEVICE_WRITE, b"x"));
+    match crate::ipc::receive_reply(sender, id).unwrap().data {
+        MessageData::DeviceRequest(r) => assert_eq!(r.command, DeviceError::DeviceNotFound as u64),
+        _ => panic!("unexpected reply data"),
+    }
+}
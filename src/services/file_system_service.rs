@@ -1,12 +1,24 @@
 // FAT-inspired File System Service for Microkernel (no_std compatible)
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
-use spin::Mutex;
+use crate::ipc::{MessageData, ServiceRequest};
+use crate::lock_debug::TrackedMutex;
+use crate::process::pcb::ProcessId;
+
+/// Sender (and `service_id`) of the filesystem's change notifications
+pub const FILESYSTEM_SERVICE_PID: ProcessId = u64::MAX - 2;
+
+/// Bytes of file data a new filesystem can hold. File data lives on the kernel heap,
+/// so it gets a quarter of it.
+pub const DEFAULT_CAPACITY: usize = crate::allocator::HEAP_SIZE / 4;
+
+/// Longest file or directory name, in bytes
+pub const MAX_NAME_LEN: usize = 255;
 
 /// FAT-inspired File System Service - Handles file operations
 pub struct FileSystemService {
@@ -15,6 +27,42 @@ pub struct FileSystemService {
     directories: BTreeMap<u64, DirectoryEntry>,
     current_directory: u64,
     fat_table: BTreeMap<u64, u64>, // Cluster chain mapping
+    free_clusters: Vec<u64>, // Clusters released by deletes, reused before bumping next_cluster
+    readonly: bool, // Reject every mutating operation
+    capacity: usize, // Limit on the total size of all file data
+    journal: Option<JournalOp>, // Mutation in progress; cleared once fully applied
+    watchers: BTreeMap<u64, Vec<ProcessId>>, // Processes told about changes to each cluster
+    pending_events: Vec<WatchNotice>, // Changes not yet sent to their watchers
+    caller_roots: Option<Vec<u64>>, // Subtrees the process being served may modify; None for no limit
+    next_link_seq: u64, // Numbers each entry linked into a directory; never reused
+}
+
+/// Change reported to a cluster's watchers, sent as the `opcode` of a `ServiceRequest`
+/// whose payload is the cluster number in little-endian bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum FileEvent {
+    Written = 1,
+    Deleted = 2,
+}
+
+/// A change waiting to be sent to the processes that watched its cluster
+#[derive(Debug, Clone)]
+pub struct WatchNotice {
+    pub watchers: Vec<ProcessId>,
+    pub cluster: u64,
+    pub event: FileEvent,
+}
+
+/// A multi-step mutation, recorded before its first step so `recover` can finish or undo
+/// it if the operation is abandoned partway through. The journal lives in RAM beside the
+/// tree it describes, so this is rollback within a session: neither survives a reboot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalOp {
+    /// Allocate `cluster` for a new file or directory and link it into `parent`
+    Create { parent: u64, cluster: u64 },
+    /// Unlink `cluster` from `parent` and free it
+    Delete { parent: u64, cluster: u64 },
 }
 
 #[derive(Debug, Clone)]
@@ -37,6 +85,38 @@ pub struct DirectoryEntry {
     pub children: Vec<u64>,
     pub created_at: u64,
     pub attributes: FileAttributes,
+    links: BTreeMap<u64, u64>, // Children by the sequence number they were linked under
+}
+
+/// One child of a directory, as returned by `readdir`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub cluster: u64,
+    pub name: String,
+    pub is_directory: bool,
+}
+
+/// Position of a `readdir` walk over one directory, from `opendir`.
+///
+/// The handle borrows nothing, so the directory may change between calls: entries
+/// removed before the cursor reaches them are skipped, and entries added are returned
+/// once the cursor gets to the end. An entry deleted and created again is a new entry,
+/// even if it reuses the cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirHandle {
+    directory: u64,
+    next: u64, // Lowest link sequence number not yet read
+}
+
+/// A copy of the filesystem's contents, taken by `snapshot` and put back by `restore`
+#[derive(Debug, Clone)]
+pub struct FsSnapshot {
+    next_cluster: u64,
+    files: BTreeMap<u64, FileEntry>,
+    directories: BTreeMap<u64, DirectoryEntry>,
+    current_directory: u64,
+    fat_table: BTreeMap<u64, u64>,
+    free_clusters: Vec<u64>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,17 +127,49 @@ pub enum FilePermissions {
     Execute,
 }
 
+/// FAT attribute byte; a file may carry several attributes at once
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum FileAttributes {
-    Archive = 0x20,
-    Directory = 0x10,
-    VolumeLabel = 0x08,
-    System = 0x04,
-    Hidden = 0x02,
-    ReadOnly = 0x01,
+pub struct FileAttributes(u8);
+
+impl FileAttributes {
+    pub const READ_ONLY: Self = Self(0x01);
+    pub const HIDDEN: Self = Self(0x02);
+    pub const SYSTEM: Self = Self(0x04);
+    pub const VOLUME_LABEL: Self = Self(0x08);
+    pub const DIRECTORY: Self = Self(0x10);
+    pub const ARCHIVE: Self = Self(0x20);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether every attribute in `other` is set
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl core::ops::BitOr for FileAttributes {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum FileSystemError {
     FileNotFound,
     DirectoryNotFound,
@@ -70,6 +182,40 @@ pub enum FileSystemError {
     ClusterChainError,
 }
 
+impl core::fmt::Display for FileSystemError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let message = match self {
+            FileSystemError::FileNotFound => "no such file or directory",
+            FileSystemError::DirectoryNotFound => "no such directory",
+            FileSystemError::PermissionDenied => "permission denied",
+            FileSystemError::FileExists => "file exists",
+            FileSystemError::DirectoryNotEmpty => "directory not empty",
+            FileSystemError::InvalidPath => "invalid path",
+            FileSystemError::OutOfSpace => "no space left on filesystem",
+            FileSystemError::InvalidCluster => "invalid cluster",
+            FileSystemError::ClusterChainError => "corrupt cluster chain",
+        };
+        f.write_str(message)
+    }
+}
+
+/// Check that `name` can name a single file or directory: non-empty, at most
+/// `MAX_NAME_LEN` bytes, and free of `/` and NUL
+pub fn validate_name(name: &str) -> Result<(), FileSystemError> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN || name.contains(['/', '\0']) {
+        return Err(FileSystemError::InvalidPath);
+    }
+    Ok(())
+}
+
+/// Decode a name received as raw bytes, e.g. from user memory, rejecting invalid UTF-8
+/// as well as anything `validate_name` refuses
+pub fn name_from_bytes(bytes: &[u8]) -> Result<&str, FileSystemError> {
+    let name = core::str::from_utf8(bytes).map_err(|_| FileSystemError::InvalidPath)?;
+    validate_name(name)?;
+    Ok(name)
+}
+
 impl FileSystemService {
     pub fn new() -> Self {
         let mut service = Self {
@@ -78,6 +224,14 @@ impl FileSystemService {
             directories: BTreeMap::new(),
             current_directory: 0,
             fat_table: BTreeMap::new(),
+            free_clusters: Vec::new(),
+            readonly: false,
+            capacity: DEFAULT_CAPACITY,
+            journal: None,
+            watchers: BTreeMap::new(),
+            pending_events: Vec::new(),
+            caller_roots: None,
+            next_link_seq: 0,
         };
         
         // Create root directory (cluster 0)
@@ -85,6 +239,11 @@ impl FileSystemService {
         service
     }
 
+    /// Return to an empty, writable filesystem holding only the root directory
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
     fn create_root_directory(&mut self) {
         let root_cluster = 0;
         let root_dir = DirectoryEntry {
@@ -93,31 +252,243 @@ impl FileSystemService {
             parent: None,
             children: Vec::new(),
             created_at: 0, // System boot time
-            attributes: FileAttributes::Directory,
+            attributes: FileAttributes::DIRECTORY,
+            links: BTreeMap::new(),
         };
         self.directories.insert(root_cluster, root_dir);
         self.current_directory = root_cluster;
     }
 
-    /// Allocate a new cluster (FAT-style)
+    /// Switch the whole filesystem into (or out of) read-only mode
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+    }
+
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Limit the total size of all file data to `bytes`. Existing files are kept even if
+    /// they already exceed it; only later growth is refused.
+    pub fn set_capacity(&mut self, bytes: usize) {
+        self.capacity = bytes;
+    }
+
+    /// Total size of all file data
+    pub fn used_bytes(&self) -> usize {
+        self.files.values().map(|file| file.data.len()).sum()
+    }
+
+    /// Send `watcher` a message whenever the file or directory at `cluster` changes
+    pub fn watch(&mut self, cluster: u64, watcher: ProcessId) -> Result<(), FileSystemError> {
+        if !self.files.contains_key(&cluster) && !self.directories.contains_key(&cluster) {
+            return Err(FileSystemError::FileNotFound);
+        }
+        let watchers = self.watchers.entry(cluster).or_default();
+        if !watchers.contains(&watcher) {
+            watchers.push(watcher);
+        }
+        Ok(())
+    }
+
+    /// Stop notifying `watcher` about `cluster`. Returns whether it was watching.
+    pub fn unwatch(&mut self, cluster: u64, watcher: ProcessId) -> bool {
+        let watchers = match self.watchers.get_mut(&cluster) {
+            Some(watchers) => watchers,
+            None => return false,
+        };
+        let before = watchers.len();
+        watchers.retain(|&pid| pid != watcher);
+        let removed = watchers.len() != before;
+        if watchers.is_empty() {
+            self.watchers.remove(&cluster);
+        }
+        removed
+    }
+
+    /// Queue `event` for the watchers of `cluster`. Nothing is sent here: delivery can
+    /// wake blocked receivers, which takes the process service lock, so it waits for
+    /// `deliver_events` once this service is unlocked.
+    fn notify_watchers(&mut self, cluster: u64, event: FileEvent) {
+        let watchers = match self.watchers.get(&cluster) {
+            Some(watchers) => watchers.clone(),
+            None => return,
+        };
+        self.pending_events.push(WatchNotice { watchers, cluster, event });
+        if event == FileEvent::Deleted {
+            // The cluster may be reused by an unrelated file
+            self.watchers.remove(&cluster);
+        }
+    }
+
+    /// Take the notices queued since the last call, for `deliver_events`
+    pub fn take_events(&mut self) -> Vec<WatchNotice> {
+        core::mem::take(&mut self.pending_events)
+    }
+
+    /// Stop every watch `watcher` holds, e.g. because it exited
+    pub fn remove_watcher(&mut self, watcher: ProcessId) {
+        self.watchers.retain(|_, watchers| {
+            watchers.retain(|&pid| pid != watcher);
+            !watchers.is_empty()
+        });
+    }
+
+    /// Bytes of file data under `cluster`: a file's own size, or the sum over a directory's
+    /// subtree. Entries carry no overhead, and unknown clusters count as 0.
+    pub fn disk_usage(&self, cluster: u64) -> usize {
+        let mut visited = BTreeSet::new();
+        self.subtree_usage(cluster, &mut visited)
+    }
+
+    /// Bytes of file data in the whole tree, starting from the root directory
+    pub fn total_usage(&self) -> usize {
+        self.disk_usage(0)
+    }
+
+    // `visited` stops the walk if a corrupt tree links a directory into its own subtree
+    fn subtree_usage(&self, cluster: u64, visited: &mut BTreeSet<u64>) -> usize {
+        if !visited.insert(cluster) {
+            return 0;
+        }
+        if let Some(file) = self.files.get(&cluster) {
+            return file.data.len();
+        }
+        match self.directories.get(&cluster) {
+            Some(dir) => dir.children.iter().map(|&child| self.subtree_usage(child, visited)).sum(),
+            None => 0,
+        }
+    }
+
+    /// Bytes a file currently `current` bytes long could grow to without exceeding capacity
+    fn room_for(&self, current: usize) -> usize {
+        self.capacity.saturating_sub(self.used_bytes() - current)
+    }
+
+    /// Fail unless `cluster` may be modified: the filesystem is writable and `cluster` is
+    /// inside a subtree the process being served may modify
+    fn check_writable(&self, cluster: u64) -> Result<(), FileSystemError> {
+        let permitted = match &self.caller_roots {
+            Some(roots) => self.ancestors(cluster).any(|ancestor| roots.contains(&ancestor)),
+            None => true,
+        };
+        if self.readonly || !permitted {
+            Err(FileSystemError::PermissionDenied)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `cluster` followed by each directory above it, up to the root
+    fn ancestors(&self, cluster: u64) -> impl Iterator<Item = u64> + '_ {
+        core::iter::successors(Some(cluster), move |&child| match self.directories.get(&child) {
+            Some(dir) => dir.parent,
+            None => self.directories.values().find(|dir| dir.children.contains(&child)).map(|dir| dir.cluster),
+        })
+    }
+
+    /// Allocate a new cluster (FAT-style), reusing a freed one if possible
     fn allocate_cluster(&mut self) -> u64 {
-        let cluster = self.next_cluster.fetch_add(1, Ordering::Relaxed);
+        let cluster = self.free_clusters.pop()
+            .unwrap_or_else(|| self.next_cluster.fetch_add(1, Ordering::Relaxed));
         self.fat_table.insert(cluster, 0xFFFFFFFF); // End of chain marker
         cluster
     }
 
+    /// The cluster the next `allocate_cluster` call will return
+    fn peek_cluster(&self) -> u64 {
+        self.free_clusters.last().copied()
+            .unwrap_or_else(|| self.next_cluster.load(Ordering::Relaxed))
+    }
+
+    /// Return a cluster to the free list
+    fn free_cluster(&mut self, cluster: u64) {
+        if self.fat_table.remove(&cluster).is_some() {
+            self.free_clusters.push(cluster);
+        }
+    }
+
+    /// Remove `cluster`'s entry, unlink it from `parent` and free it. Safe to repeat, so it
+    /// serves both to undo a partial create and to finish a partial delete.
+    fn discard_cluster(&mut self, parent: u64, cluster: u64) {
+        self.files.remove(&cluster);
+        self.directories.remove(&cluster);
+        if let Some(dir) = self.directories.get_mut(&parent) {
+            dir.children.retain(|&child| child != cluster);
+            dir.links.retain(|_, &mut child| child != cluster);
+        }
+        self.free_cluster(cluster);
+    }
+
+    /// Add `cluster` to the end of `parent`'s children
+    fn link_child(&mut self, parent: u64, cluster: u64) {
+        if let Some(dir) = self.directories.get_mut(&parent) {
+            dir.children.push(cluster);
+            dir.links.insert(self.next_link_seq, cluster);
+            self.next_link_seq += 1;
+        }
+    }
+
+    /// Settle the mutation left in the journal by an operation abandoned earlier in this
+    /// session: a create is rolled back and a delete is completed. Returns the entry that
+    /// was settled.
+    pub fn recover(&mut self) -> Option<JournalOp> {
+        let op = self.journal.take()?;
+        match op {
+            JournalOp::Create { parent, cluster } | JournalOp::Delete { parent, cluster } => {
+                self.discard_cluster(parent, cluster);
+            }
+        }
+        Some(op)
+    }
+
+    /// Number of freed clusters waiting to be reused
+    pub fn free_cluster_count(&self) -> usize {
+        self.free_clusters.len()
+    }
+
     /// Create a new file
     pub fn create_file(
         &mut self,
         name: &str,
         permissions: FilePermissions,
     ) -> Result<u64, FileSystemError> {
-        if name.is_empty() || name.contains('/') {
-            return Err(FileSystemError::InvalidPath);
+        self.create_file_in(self.current_directory, name, permissions)
+    }
+
+    /// Return the cluster of the file at `path`, creating it with `permissions` if it
+    /// does not exist. Like `O_CREAT`, only the final component is created; a missing
+    /// parent directory is still an error.
+    pub fn open_or_create(&mut self, path: &str, permissions: FilePermissions) -> Result<u64, FileSystemError> {
+        match self.resolve_path(path) {
+            Ok(cluster) if self.is_file(cluster) => Ok(cluster),
+            Ok(_) => Err(FileSystemError::FileExists), // A directory already has the name
+            Err(FileSystemError::FileNotFound) => {
+                let (parent, name) = match path.rsplit_once('/') {
+                    Some(("", name)) => (0, name),
+                    Some((parent, name)) => (self.resolve_path(parent)?, name),
+                    None => (self.current_directory, path),
+                };
+                self.create_file_in(parent, name, permissions)
+            }
+            Err(e) => Err(e),
         }
+    }
 
-        // Check if file already exists in current directory
-        if let Some(current_dir) = self.directories.get(&self.current_directory) {
+    fn create_file_in(
+        &mut self,
+        directory: u64,
+        name: &str,
+        permissions: FilePermissions,
+    ) -> Result<u64, FileSystemError> {
+        self.check_writable(directory)?;
+        validate_name(name)?;
+        if !self.directories.contains_key(&directory) {
+            return Err(FileSystemError::DirectoryNotFound);
+        }
+
+        // Check if file already exists in the directory
+        if let Some(current_dir) = self.directories.get(&directory) {
             for &child_cluster in &current_dir.children {
                 if let Some(file) = self.files.get(&child_cluster) {
                     if file.name == name {
@@ -127,6 +498,7 @@ impl FileSystemService {
             }
         }
 
+        self.journal = Some(JournalOp::Create { parent: directory, cluster: self.peek_cluster() });
         let cluster = self.allocate_cluster();
         let file = FileEntry {
             cluster,
@@ -136,24 +508,22 @@ impl FileSystemService {
             permissions,
             created_at: 0, // System time
             modified_at: 0,
-            attributes: FileAttributes::Archive,
+            attributes: FileAttributes::ARCHIVE,
         };
 
         self.files.insert(cluster, file);
         
-        // Add to current directory
-        if let Some(current_dir) = self.directories.get_mut(&self.current_directory) {
-            current_dir.children.push(cluster);
-        }
+        // Add to its directory
+        self.link_child(directory, cluster);
+        self.journal = None;
 
         Ok(cluster)
     }
 
     /// Create a new directory
     pub fn create_directory(&mut self, name: &str) -> Result<u64, FileSystemError> {
-        if name.is_empty() || name.contains('/') {
-            return Err(FileSystemError::InvalidPath);
-        }
+        self.check_writable(self.current_directory)?;
+        validate_name(name)?;
 
         // Check if directory already exists
         if let Some(current_dir) = self.directories.get(&self.current_directory) {
@@ -166,6 +536,7 @@ impl FileSystemService {
             }
         }
 
+        self.journal = Some(JournalOp::Create { parent: self.current_directory, cluster: self.peek_cluster() });
         let cluster = self.allocate_cluster();
         let directory = DirectoryEntry {
             cluster,
@@ -173,38 +544,78 @@ impl FileSystemService {
             parent: Some(self.current_directory),
             children: Vec::new(),
             created_at: 0, // System time
-            attributes: FileAttributes::Directory,
+            attributes: FileAttributes::DIRECTORY,
+            links: BTreeMap::new(),
         };
 
         self.directories.insert(cluster, directory);
         
         // Add to current directory
-        if let Some(current_dir) = self.directories.get_mut(&self.current_directory) {
-            current_dir.children.push(cluster);
-        }
+        self.link_child(self.current_directory, cluster);
+        self.journal = None;
 
         Ok(cluster)
     }
 
-    /// Write data to a file
+    /// Replace a file's contents with as much of `data` as fits and return the number of
+    /// bytes written. A short count means the filesystem is full; `OutOfSpace` is only
+    /// returned when none of a non-empty `data` fits.
     pub fn write_file(
         &mut self,
         cluster: u64,
         data: &[u8],
     ) -> Result<usize, FileSystemError> {
-        if let Some(file) = self.files.get_mut(&cluster) {
-            if file.permissions == FilePermissions::ReadOnly {
-                return Err(FileSystemError::PermissionDenied);
-            }
+        self.check_writable(cluster)?;
+        let current = self.files.get(&cluster).map(|file| file.data.len()).ok_or(FileSystemError::FileNotFound)?;
+        let room = self.room_for(current);
+        let file = self.files.get_mut(&cluster).ok_or(FileSystemError::FileNotFound)?;
+        if file.permissions == FilePermissions::ReadOnly || file.attributes.contains(FileAttributes::READ_ONLY) {
+            return Err(FileSystemError::PermissionDenied);
+        }
+        let written = data.len().min(room);
+        if written == 0 && !data.is_empty() {
+            return Err(FileSystemError::OutOfSpace);
+        }
 
-            file.data.clear();
-            file.data.extend_from_slice(data);
-            file.size = data.len();
-            file.modified_at = 0; // System time
-            Ok(data.len())
-        } else {
-            Err(FileSystemError::FileNotFound)
+        file.data.clear();
+        file.data.extend_from_slice(&data[..written]);
+        file.size = written;
+        file.modified_at = 0; // System time
+        self.notify_watchers(cluster, FileEvent::Written);
+        Ok(written)
+    }
+
+    /// Write to a file on behalf of a process that may only modify the subtrees `roots`
+    pub fn write_file_within(
+        &mut self,
+        roots: Option<Vec<u64>>,
+        cluster: u64,
+        data: &[u8],
+    ) -> Result<usize, FileSystemError> {
+        self.caller_roots = roots;
+        let result = self.write_file(cluster, data);
+        self.caller_roots = None;
+        result
+    }
+
+    /// Shrink or zero-extend a file to `new_size` bytes
+    pub fn truncate(&mut self, cluster: u64, new_size: usize) -> Result<(), FileSystemError> {
+        self.check_writable(cluster)?;
+        let current = self.files.get(&cluster).map(|file| file.data.len()).ok_or(FileSystemError::FileNotFound)?;
+        let room = self.room_for(current);
+        let file = self.files.get_mut(&cluster).ok_or(FileSystemError::FileNotFound)?;
+        if file.permissions == FilePermissions::ReadOnly || file.attributes.contains(FileAttributes::READ_ONLY) {
+            return Err(FileSystemError::PermissionDenied);
+        }
+        if new_size > current && new_size > room {
+            return Err(FileSystemError::OutOfSpace);
         }
+
+        file.data.resize(new_size, 0);
+        file.size = new_size;
+        file.modified_at = 0; // System time
+        self.notify_watchers(cluster, FileEvent::Written);
+        Ok(())
     }
 
     /// Read data from a file
@@ -219,21 +630,71 @@ impl FileSystemService {
         }
     }
 
-    /// Delete a file
-    pub fn delete_file(&mut self, cluster: u64) -> Result<(), FileSystemError> {
-        if let Some(_file) = self.files.remove(&cluster) {
-            // Remove from parent directory
-            if let Some(current_dir) = self.directories.get_mut(&self.current_directory) {
-                current_dir.children.retain(|&child| child != cluster);
-            }
-            // Free the cluster (FAT-style)
-            self.fat_table.remove(&cluster);
+    /// Change the access permissions of a file
+    pub fn set_permissions(&mut self, cluster: u64, permissions: FilePermissions) -> Result<(), FileSystemError> {
+        self.check_writable(cluster)?;
+        let file = self.files.get_mut(&cluster).ok_or(FileSystemError::FileNotFound)?;
+        file.permissions = permissions;
+        file.modified_at = 0; // System time
+        Ok(())
+    }
+
+    /// Change the attributes of a file or directory.
+    ///
+    /// The Directory bit follows the entry's kind and cannot be changed here.
+    pub fn set_attributes(&mut self, cluster: u64, mut attributes: FileAttributes) -> Result<(), FileSystemError> {
+        self.check_writable(cluster)?;
+        if let Some(file) = self.files.get_mut(&cluster) {
+            attributes.remove(FileAttributes::DIRECTORY);
+            file.attributes = attributes;
+            Ok(())
+        } else if let Some(dir) = self.directories.get_mut(&cluster) {
+            attributes.insert(FileAttributes::DIRECTORY);
+            dir.attributes = attributes;
             Ok(())
         } else {
             Err(FileSystemError::FileNotFound)
         }
     }
 
+    /// Delete a file
+    pub fn delete_file(&mut self, cluster: u64) -> Result<(), FileSystemError> {
+        self.check_writable(cluster)?;
+        if !self.files.contains_key(&cluster) {
+            return Err(FileSystemError::FileNotFound);
+        }
+        let parent = self.directories
+            .values()
+            .find(|dir| dir.children.contains(&cluster))
+            .map_or(self.current_directory, |dir| dir.cluster);
+
+        // Remove from parent directory and free the cluster (FAT-style)
+        self.journal = Some(JournalOp::Delete { parent, cluster });
+        self.discard_cluster(parent, cluster);
+        self.journal = None;
+        self.notify_watchers(cluster, FileEvent::Deleted);
+        Ok(())
+    }
+
+    /// Delete an empty directory
+    pub fn delete_directory(&mut self, cluster: u64) -> Result<(), FileSystemError> {
+        self.check_writable(cluster)?;
+        let dir = self.directories.get(&cluster).ok_or(FileSystemError::DirectoryNotFound)?;
+        if cluster == 0 || cluster == self.current_directory {
+            return Err(FileSystemError::PermissionDenied);
+        }
+        if !dir.children.is_empty() {
+            return Err(FileSystemError::DirectoryNotEmpty);
+        }
+
+        let parent = dir.parent.unwrap_or(0);
+        self.journal = Some(JournalOp::Delete { parent, cluster });
+        self.discard_cluster(parent, cluster);
+        self.journal = None;
+        self.notify_watchers(cluster, FileEvent::Deleted);
+        Ok(())
+    }
+
     /// List files in current directory
     pub fn list_files(&self) -> Vec<(String, bool)> {
         let mut result = Vec::new();
@@ -241,9 +702,9 @@ impl FileSystemService {
         if let Some(current_dir) = self.directories.get(&self.current_directory) {
             for &child_cluster in &current_dir.children {
                 if let Some(file) = self.files.get(&child_cluster) {
-                    result.push((file.name.clone(), false)); // false = file
+                    result.push((file.name.clone(), file.attributes.contains(FileAttributes::DIRECTORY)));
                 } else if let Some(dir) = self.directories.get(&child_cluster) {
-                    result.push((dir.name.clone(), true)); // true = directory
+                    result.push((dir.name.clone(), dir.attributes.contains(FileAttributes::DIRECTORY)));
                 }
             }
         }
@@ -251,6 +712,43 @@ impl FileSystemService {
         result
     }
 
+    /// Start reading the children of the directory at `cluster`
+    pub fn opendir(&self, cluster: u64) -> Result<DirHandle, FileSystemError> {
+        if !self.directories.contains_key(&cluster) {
+            return Err(FileSystemError::DirectoryNotFound);
+        }
+        Ok(DirHandle { directory: cluster, next: 0 })
+    }
+
+    /// The next child of the handle's directory, or `None` once every child has been
+    /// returned or the directory itself is gone
+    pub fn readdir(&self, handle: &mut DirHandle) -> Option<DirEntry> {
+        let links = &self.directories.get(&handle.directory)?.links;
+        for (&seq, &cluster) in links.range(handle.next..) {
+            handle.next = seq + 1;
+            if let Some(entry) = self.dir_entry(cluster) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    /// Finish with `handle`. Handles hold no resources, so this only ends the walk.
+    pub fn closedir(&self, handle: DirHandle) {
+        drop(handle);
+    }
+
+    fn dir_entry(&self, cluster: u64) -> Option<DirEntry> {
+        if let Some(file) = self.files.get(&cluster) {
+            let is_directory = file.attributes.contains(FileAttributes::DIRECTORY);
+            Some(DirEntry { cluster, name: file.name.clone(), is_directory })
+        } else {
+            let dir = self.directories.get(&cluster)?;
+            let is_directory = dir.attributes.contains(FileAttributes::DIRECTORY);
+            Some(DirEntry { cluster, name: dir.name.clone(), is_directory })
+        }
+    }
+
     /// Change current directory
     pub fn change_directory(&mut self, name: &str) -> Result<(), FileSystemError> {
         if name == ".." {
@@ -277,6 +775,47 @@ impl FileSystemService {
         Err(FileSystemError::DirectoryNotFound)
     }
 
+    /// Resolve an absolute or relative path to the cluster of a file or directory
+    pub fn resolve_path(&self, path: &str) -> Result<u64, FileSystemError> {
+        if path.is_empty() {
+            return Err(FileSystemError::InvalidPath);
+        }
+
+        let mut current = if path.starts_with('/') { 0 } else { self.current_directory };
+        let mut components = path.split('/').filter(|c| !c.is_empty() && *c != ".").peekable();
+
+        while let Some(component) = components.next() {
+            let dir = self.directories.get(&current).ok_or(FileSystemError::DirectoryNotFound)?;
+
+            if component == ".." {
+                current = dir.parent.unwrap_or(0);
+                continue;
+            }
+
+            let child = dir.children.iter().copied().find(|child| {
+                self.files.get(child).map(|f| f.name == component)
+                    .or_else(|| self.directories.get(child).map(|d| d.name == component))
+                    .unwrap_or(false)
+            });
+
+            match child {
+                Some(cluster) if components.peek().is_none() || self.directories.contains_key(&cluster) => {
+                    current = cluster;
+                }
+                Some(_) => return Err(FileSystemError::DirectoryNotFound),
+                None if components.peek().is_none() => return Err(FileSystemError::FileNotFound),
+                None => return Err(FileSystemError::DirectoryNotFound),
+            }
+        }
+
+        Ok(current)
+    }
+
+    /// Whether `cluster` names a regular file
+    pub fn is_file(&self, cluster: u64) -> bool {
+        self.files.contains_key(&cluster)
+    }
+
     /// Get current working directory path
     pub fn get_current_path(&self) -> String {
         let mut path = String::new();
@@ -304,39 +843,561 @@ impl FileSystemService {
     pub fn is_cluster_allocated(&self, cluster: u64) -> bool {
         self.fat_table.contains_key(&cluster) || cluster == 0
     }
+
+    /// Capture every file, directory and cluster allocation
+    pub fn snapshot(&self) -> FsSnapshot {
+        FsSnapshot {
+            next_cluster: self.next_cluster.load(Ordering::Relaxed),
+            files: self.files.clone(),
+            directories: self.directories.clone(),
+            current_directory: self.current_directory,
+            fat_table: self.fat_table.clone(),
+            free_clusters: self.free_clusters.clone(),
+        }
+    }
+
+    /// Replace the contents with those captured by `snapshot`.
+    /// The read-only switch is a mode rather than content, so it is left alone.
+    pub fn restore(&mut self, snapshot: FsSnapshot) {
+        self.next_cluster.store(snapshot.next_cluster, Ordering::Relaxed);
+        self.files = snapshot.files;
+        self.directories = snapshot.directories;
+        self.current_directory = snapshot.current_directory;
+        self.fat_table = snapshot.fat_table;
+        self.free_clusters = snapshot.free_clusters;
+        self.journal = None;
+    }
 }
 
 lazy_static! {
-    pub static ref FILESYSTEM_SERVICE: Mutex<FileSystemService> = Mutex::new(FileSystemService::new());
+    pub static ref FILESYSTEM_SERVICE: TrackedMutex<FileSystemService> = TrackedMutex::new("FILESYSTEM_SERVICE", FileSystemService::new());
+}
+
+/// Run `op` on behalf of the current process, which may only modify the subtrees it
+/// holds `File` write capabilities for unless it is privileged
+fn as_current_process<T>(
+    op: impl FnOnce(&mut FileSystemService) -> Result<T, FileSystemError>,
+) -> Result<T, FileSystemError> {
+    use crate::services::process_service::{get_current_process, writable_fs_roots};
+
+    let roots = get_current_process().and_then(writable_fs_roots);
+    let mut service = FILESYSTEM_SERVICE.lock();
+    service.caller_roots = roots;
+    let result = op(&mut service);
+    service.caller_roots = None;
+    let events = service.take_events();
+    drop(service);
+    deliver_events(events);
+    result
+}
+
+/// Send each notice to its watchers. Call with `FILESYSTEM_SERVICE` unlocked, and not
+/// from under the process service, since waking a blocked receiver locks it.
+pub fn deliver_events(events: Vec<WatchNotice>) {
+    for notice in events {
+        let data = MessageData::ServiceRequest(ServiceRequest {
+            service_id: FILESYSTEM_SERVICE_PID,
+            opcode: notice.event as u64,
+            payload: Vec::from(notice.cluster.to_le_bytes()),
+        });
+        crate::ipc::MESSAGE_QUEUE.multicast(FILESYSTEM_SERVICE_PID, &notice.watchers, data);
+    }
 }
 
 /// File system service API functions
 pub fn create_file(name: &str, permissions: FilePermissions) -> Result<u64, FileSystemError> {
-    FILESYSTEM_SERVICE.lock().create_file(name, permissions)
+    as_current_process(|fs| fs.create_file(name, permissions))
+}
+
+pub fn create_directory(name: &str) -> Result<u64, FileSystemError> {
+    as_current_process(|fs| fs.create_directory(name))
+}
+
+pub fn open_or_create(path: &str, permissions: FilePermissions) -> Result<u64, FileSystemError> {
+    as_current_process(|fs| fs.open_or_create(path, permissions))
 }
 
 pub fn write_file(cluster: u64, data: &[u8]) -> Result<usize, FileSystemError> {
-    FILESYSTEM_SERVICE.lock().write_file(cluster, data)
+    as_current_process(|fs| fs.write_file(cluster, data))
+}
+
+pub fn truncate(cluster: u64, new_size: usize) -> Result<(), FileSystemError> {
+    as_current_process(|fs| fs.truncate(cluster, new_size))
 }
 
 pub fn read_file(cluster: u64) -> Result<Vec<u8>, FileSystemError> {
     FILESYSTEM_SERVICE.lock().read_file(cluster)
 }
 
+pub fn set_permissions(cluster: u64, permissions: FilePermissions) -> Result<(), FileSystemError> {
+    as_current_process(|fs| fs.set_permissions(cluster, permissions))
+}
+
+pub fn set_attributes(cluster: u64, attributes: FileAttributes) -> Result<(), FileSystemError> {
+    as_current_process(|fs| fs.set_attributes(cluster, attributes))
+}
+
+/// Delete a file, revoking capabilities rooted at it so they cannot cover whatever
+/// later reuses its cluster
+pub fn delete_file(cluster: u64) -> Result<(), FileSystemError> {
+    as_current_process(|fs| fs.delete_file(cluster))?;
+    crate::services::process_service::revoke_fs_capabilities(cluster);
+    Ok(())
+}
+
+/// Delete an empty directory, revoking capabilities rooted at it
+pub fn delete_directory(cluster: u64) -> Result<(), FileSystemError> {
+    as_current_process(|fs| fs.delete_directory(cluster))?;
+    crate::services::process_service::revoke_fs_capabilities(cluster);
+    Ok(())
+}
+
+/// Whether the current process may write to `cluster`
+pub fn check_write_access(cluster: u64) -> Result<(), FileSystemError> {
+    as_current_process(|fs| fs.check_writable(cluster))
+}
+
+pub fn free_cluster_count() -> usize {
+    FILESYSTEM_SERVICE.lock().free_cluster_count()
+}
+
+pub fn set_capacity(bytes: usize) {
+    FILESYSTEM_SERVICE.lock().set_capacity(bytes)
+}
+
+pub fn used_bytes() -> usize {
+    FILESYSTEM_SERVICE.lock().used_bytes()
+}
+
+pub fn watch(cluster: u64, watcher: ProcessId) -> Result<(), FileSystemError> {
+    FILESYSTEM_SERVICE.lock().watch(cluster, watcher)
+}
+
+pub fn unwatch(cluster: u64, watcher: ProcessId) -> bool {
+    FILESYSTEM_SERVICE.lock().unwatch(cluster, watcher)
+}
+
+pub fn disk_usage(cluster: u64) -> usize {
+    FILESYSTEM_SERVICE.lock().disk_usage(cluster)
+}
+
+pub fn total_usage() -> usize {
+    FILESYSTEM_SERVICE.lock().total_usage()
+}
+
+pub fn set_readonly(readonly: bool) {
+    FILESYSTEM_SERVICE.lock().set_readonly(readonly)
+}
+
+pub fn is_readonly() -> bool {
+    FILESYSTEM_SERVICE.lock().is_readonly()
+}
+
 pub fn list_files() -> Vec<(String, bool)> {
     FILESYSTEM_SERVICE.lock().list_files()
 }
 
+pub fn opendir(cluster: u64) -> Result<DirHandle, FileSystemError> {
+    FILESYSTEM_SERVICE.lock().opendir(cluster)
+}
+
+pub fn readdir(handle: &mut DirHandle) -> Option<DirEntry> {
+    FILESYSTEM_SERVICE.lock().readdir(handle)
+}
+
+pub fn closedir(handle: DirHandle) {
+    FILESYSTEM_SERVICE.lock().closedir(handle)
+}
+
 pub fn change_directory(name: &str) -> Result<(), FileSystemError> {
     FILESYSTEM_SERVICE.lock().change_directory(name)
 }
 
+pub fn resolve_path(path: &str) -> Result<u64, FileSystemError> {
+    FILESYSTEM_SERVICE.lock().resolve_path(path)
+}
+
+pub fn is_file(cluster: u64) -> bool {
+    FILESYSTEM_SERVICE.lock().is_file(cluster)
+}
+
 pub fn get_current_path() -> String {
     FILESYSTEM_SERVICE.lock().get_current_path()
 }
 
+pub fn snapshot() -> FsSnapshot {
+    FILESYSTEM_SERVICE.lock().snapshot()
+}
+
+pub fn restore(snapshot: FsSnapshot) {
+    FILESYSTEM_SERVICE.lock().restore(snapshot)
+}
+
+pub fn recover() -> Option<JournalOp> {
+    FILESYSTEM_SERVICE.lock().recover()
+}
+
 /// Initialize the FAT-inspired filesystem
 pub fn init_fat_filesystem() -> Result<(), FileSystemError> {
-    // Filesystem is already initialized in the lazy_static
+    // Filesystem is already initialized in the lazy_static. Its journal starts empty:
+    // it is kept in RAM, so there is nothing from a previous boot to recover.
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_read_only_file_rejects_writes() {
+    let mut fs = FileSystemService::new();
+    let cluster = fs.create_file("notes.txt", FilePermissions::ReadWrite).unwrap();
+    assert_eq!(fs.write_file(cluster, b"draft"), Ok(5));
+
+    fs.set_permissions(cluster, FilePermissions::ReadOnly).unwrap();
+    assert_eq!(fs.write_file(cluster, b"edit"), Err(FileSystemError::PermissionDenied));
+    assert_eq!(fs.read_file(cluster).unwrap(), b"draft");
+
+    // The ReadOnly attribute protects the file even with write permission
+    fs.set_permissions(cluster, FilePermissions::ReadWrite).unwrap();
+    fs.set_attributes(cluster, FileAttributes::READ_ONLY).unwrap();
+    assert_eq!(fs.write_file(cluster, b"edit"), Err(FileSystemError::PermissionDenied));
+    fs.set_attributes(cluster, FileAttributes::ARCHIVE).unwrap();
+    assert_eq!(fs.write_file(cluster, b"edit"), Ok(4));
+
+    assert_eq!(fs.set_permissions(999, FilePermissions::ReadOnly), Err(FileSystemError::FileNotFound));
+}
+
+#[test_case]
+fn test_file_carries_multiple_attributes() {
+    let mut fs = FileSystemService::new();
+    let cluster = fs.create_file("secret.txt", FilePermissions::ReadWrite).unwrap();
+    fs.create_directory("bin").unwrap();
+
+    fs.set_attributes(cluster, FileAttributes::HIDDEN | FileAttributes::READ_ONLY).unwrap();
+    let attributes = fs.files[&cluster].attributes;
+    assert!(attributes.contains(FileAttributes::HIDDEN));
+    assert!(attributes.contains(FileAttributes::READ_ONLY));
+    assert!(!attributes.contains(FileAttributes::SYSTEM));
+    assert_eq!(attributes.bits(), 0x03);
+    assert_eq!(fs.write_file(cluster, b"x"), Err(FileSystemError::PermissionDenied));
+
+    let mut cleared = attributes;
+    cleared.remove(FileAttributes::READ_ONLY);
+    fs.set_attributes(cluster, cleared).unwrap();
+    assert_eq!(fs.write_file(cluster, b"x"), Ok(1));
+
+    // Directories are still told apart by their Directory bit
+    let listing = fs.list_files();
+    assert!(listing.contains(&(String::from("secret.txt"), false)));
+    assert!(listing.contains(&(String::from("bin"), true)));
+}
+
+#[test_case]
+fn test_truncate_shrinks_and_zero_extends() {
+    let mut fs = FileSystemService::new();
+    let cluster = fs.create_file("log.txt", FilePermissions::ReadWrite).unwrap();
+    let contents: Vec<u8> = (0..100u8).collect();
+    fs.write_file(cluster, &contents).unwrap();
+
+    fs.truncate(cluster, 10).unwrap();
+    assert_eq!(fs.files[&cluster].size, 10);
+    assert_eq!(fs.read_file(cluster).unwrap(), &contents[..10]);
+
+    fs.truncate(cluster, 200).unwrap();
+    let data = fs.read_file(cluster).unwrap();
+    assert_eq!(fs.files[&cluster].size, 200);
+    assert_eq!(&data[..10], &contents[..10]);
+    assert!(data[10..].iter().all(|&b| b == 0));
+
+    fs.set_permissions(cluster, FilePermissions::ReadOnly).unwrap();
+    assert_eq!(fs.truncate(cluster, 0), Err(FileSystemError::PermissionDenied));
+}
+
+#[test_case]
+fn test_deleted_cluster_is_reused() {
+    let mut fs = FileSystemService::new();
+    let first = fs.create_file("a.txt", FilePermissions::ReadWrite).unwrap();
+    fs.delete_file(first).unwrap();
+    assert_eq!(fs.free_cluster_count(), 1);
+
+    let second = fs.create_file("b.txt", FilePermissions::ReadWrite).unwrap();
+    assert_eq!(second, first);
+    assert_eq!(fs.free_cluster_count(), 0);
+
+    let dir = fs.create_directory("tmp").unwrap();
+    fs.delete_directory(dir).unwrap();
+    assert_eq!(fs.create_file("c.txt", FilePermissions::ReadWrite), Ok(dir));
+}
+
+#[test_case]
+fn test_readonly_mode_blocks_mutation() {
+    let mut fs = FileSystemService::new();
+    let cluster = fs.create_file("boot.cfg", FilePermissions::ReadWrite).unwrap();
+    assert_eq!(fs.write_file(cluster, b"v1"), Ok(2));
+
+    fs.set_readonly(true);
+    assert!(fs.is_readonly());
+    assert_eq!(fs.write_file(cluster, b"v2"), Err(FileSystemError::PermissionDenied));
+    assert_eq!(fs.create_file("new.cfg", FilePermissions::ReadWrite), Err(FileSystemError::PermissionDenied));
+    assert_eq!(fs.delete_file(cluster), Err(FileSystemError::PermissionDenied));
+    assert_eq!(fs.read_file(cluster).unwrap(), b"v1");
+
+    fs.set_readonly(false);
+    assert_eq!(fs.write_file(cluster, b"v2"), Ok(2));
+}
+
+#[test_case]
+fn test_restore_discards_files_created_after_snapshot() {
+    let before = snapshot();
+    let (existing, cwd) = (list_files(), get_current_path());
+
+    create_file("scratch.txt", FilePermissions::ReadWrite).unwrap();
+    let dir = FILESYSTEM_SERVICE.lock().create_directory("scratch_dir").unwrap();
+    assert!(resolve_path("scratch.txt").is_ok());
+    change_directory("scratch_dir").unwrap();
+
+    restore(before);
+    assert_eq!(get_current_path(), cwd);
+    assert_eq!(list_files(), existing);
+    assert_eq!(resolve_path("scratch.txt"), Err(FileSystemError::FileNotFound));
+    assert!(!FILESYSTEM_SERVICE.lock().is_cluster_allocated(dir));
+}
+
+#[test_case]
+fn test_write_beyond_capacity_is_short() {
+    let mut fs = FileSystemService::new();
+    fs.set_capacity(16);
+    let log = fs.create_file("log.txt", FilePermissions::ReadWrite).unwrap();
+    let big = fs.create_file("big.txt", FilePermissions::ReadWrite).unwrap();
+    assert_eq!(fs.write_file(log, b"0123456789"), Ok(10));
+
+    // Only 6 bytes remain; rewriting a file reuses its own space
+    assert_eq!(fs.write_file(big, b"abcdefghij"), Ok(6));
+    assert_eq!(fs.read_file(big).unwrap(), b"abcdef");
+    assert_eq!(fs.write_file(log, b"ABCDEFGHIJ"), Ok(10));
+
+    assert_eq!(fs.truncate(big, 0), Ok(()));
+    assert_eq!(fs.write_file(log, b"0123456789abcdefXYZ"), Ok(16));
+    assert_eq!(fs.write_file(big, b"x"), Err(FileSystemError::OutOfSpace));
+    assert_eq!(fs.truncate(big, 1), Err(FileSystemError::OutOfSpace));
+    assert_eq!(fs.used_bytes(), 16);
+}
+
+#[test_case]
+fn test_open_or_create_reuses_existing_file() {
+    let mut fs = FileSystemService::new();
+    let etc = fs.create_directory("etc").unwrap();
+
+    let first = fs.open_or_create("/etc/motd", FilePermissions::ReadWrite).unwrap();
+    let second = fs.open_or_create("/etc/motd", FilePermissions::ReadOnly).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(fs.directories[&etc].children, [first]);
+    assert_eq!(fs.files[&first].permissions, FilePermissions::ReadWrite);
+
+    // Only the last component is created
+    assert_eq!(fs.open_or_create("/var/log", FilePermissions::ReadWrite), Err(FileSystemError::DirectoryNotFound));
+    assert_eq!(fs.open_or_create("etc", FilePermissions::ReadWrite), Err(FileSystemError::FileExists));
+}
+
+#[test_case]
+fn test_recover_rolls_back_unlinked_cluster() {
+    let mut fs = FileSystemService::new();
+    let keep = fs.create_file("keep.txt", FilePermissions::ReadWrite).unwrap();
+    assert_eq!(fs.recover(), None); // Completed operations leave nothing behind
+
+    // Crash after allocating the cluster and writing its entry, before linking it
+    let cluster = fs.peek_cluster();
+    fs.journal = Some(JournalOp::Create { parent: 0, cluster });
+    assert_eq!(fs.allocate_cluster(), cluster);
+    fs.directories.insert(cluster, DirectoryEntry {
+        cluster,
+        name: String::from("half"),
+        parent: Some(0),
+        children: Vec::new(),
+        created_at: 0,
+        attributes: FileAttributes::DIRECTORY,
+        links: BTreeMap::new(),
+    });
+
+    assert_eq!(fs.recover(), Some(JournalOp::Create { parent: 0, cluster }));
+    assert!(!fs.directories.contains_key(&cluster));
+    assert!(!fs.is_cluster_allocated(cluster));
+    assert_eq!(fs.directories[&0].children, [keep]);
+    assert_eq!(fs.recover(), None);
+
+    // The rolled-back cluster is free for the next create
+    assert_eq!(fs.create_directory("whole"), Ok(cluster));
+}
+
+#[test_case]
+fn test_disk_usage_sums_directory_subtree() {
+    let mut fs = FileSystemService::new();
+    let docs = fs.create_directory("docs").unwrap();
+    fs.change_directory("docs").unwrap();
+    let a = fs.create_file("a.txt", FilePermissions::ReadWrite).unwrap();
+    let b = fs.create_file("b.txt", FilePermissions::ReadWrite).unwrap();
+    fs.write_file(a, &[1; 100]).unwrap();
+    fs.write_file(b, &[2; 100]).unwrap();
+
+    assert_eq!(fs.disk_usage(a), 100);
+    assert_eq!(fs.disk_usage(docs), 200);
+    assert_eq!(fs.total_usage(), 200);
+
+    // A directory linked into its own subtree is only counted once
+    fs.directories.get_mut(&docs).unwrap().children.push(0);
+    assert_eq!(fs.total_usage(), 200);
+}
+
+#[test_case]
+fn test_watcher_is_notified_of_writes() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process};
+
+    let watcher = create_process(String::from("fs_watcher"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let mut fs = FileSystemService::new();
+    let cluster = fs.create_file("watched.txt", FilePermissions::ReadWrite).unwrap();
+    assert_eq!(fs.watch(cluster + 1, watcher), Err(FileSystemError::FileNotFound));
+    fs.watch(cluster, watcher).unwrap();
+
+    fs.write_file(cluster, b"hello").unwrap();
+    assert!(crate::ipc::receive(watcher).is_none(), "notified with the service still locked");
+    deliver_events(fs.take_events());
+    let message = crate::ipc::receive(watcher).expect("no notification for the write");
+    assert_eq!(message.sender, FILESYSTEM_SERVICE_PID);
+    match message.data {
+        MessageData::ServiceRequest(notice) => {
+            assert_eq!(notice.opcode, FileEvent::Written as u64);
+            assert_eq!(notice.payload, cluster.to_le_bytes());
+        }
+        _ => panic!("unexpected notification data"),
+    }
+    assert!(crate::ipc::receive(watcher).is_none());
+
+    assert!(fs.unwatch(cluster, watcher));
+    fs.write_file(cluster, b"again").unwrap();
+    deliver_events(fs.take_events());
+    assert!(crate::ipc::receive(watcher).is_none());
+
+    let _ = terminate_process(watcher, 0);
+}
+
+#[test_case]
+fn test_exited_watcher_is_dropped() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process};
+
+    let watcher = create_process(String::from("fs_watch_exit"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let cluster = create_file("watch_exit.txt", FilePermissions::ReadWrite).unwrap();
+    watch(cluster, watcher).unwrap();
+    terminate_process(watcher, 0).unwrap();
+
+    // Still unlocked when notices go out, so delivery may take the process service lock
+    write_file(cluster, b"after exit").unwrap();
+    assert!(crate::ipc::receive(watcher).is_none());
+    assert!(!unwatch(cluster, watcher));
+    let _ = delete_file(cluster);
+}
+
+#[test_case]
+fn test_bad_names_are_rejected() {
+    let mut fs = FileSystemService::new();
+    let longest = "n".repeat(MAX_NAME_LEN);
+    fs.create_file(&longest, FilePermissions::ReadWrite).unwrap();
+    let too_long = "n".repeat(MAX_NAME_LEN + 1);
+    assert_eq!(fs.create_file(&too_long, FilePermissions::ReadWrite), Err(FileSystemError::InvalidPath));
+    assert_eq!(fs.create_directory(&too_long), Err(FileSystemError::InvalidPath));
+    assert_eq!(fs.create_file("nul\0name", FilePermissions::ReadWrite), Err(FileSystemError::InvalidPath));
+
+    // A multi-byte name counts bytes, not characters
+    assert_eq!(name_from_bytes("héllo".as_bytes()), Ok("héllo"));
+    assert_eq!(name_from_bytes("é".repeat(128).as_bytes()), Err(FileSystemError::InvalidPath));
+    assert_eq!(name_from_bytes(&[b'a', 0xff, b'b']), Err(FileSystemError::InvalidPath));
+    assert_eq!(name_from_bytes(&[0xe2, 0x82]), Err(FileSystemError::InvalidPath));
+}
+
+#[test_case]
+fn test_readdir_returns_each_child_once() {
+    let mut fs = FileSystemService::new();
+    let docs = fs.create_directory("docs").unwrap();
+    let notes = fs.create_file("notes.txt", FilePermissions::ReadWrite).unwrap();
+    let todo = fs.create_file("todo.txt", FilePermissions::ReadWrite).unwrap();
+
+    let mut handle = fs.opendir(0).unwrap();
+    let mut entries = Vec::new();
+    while let Some(entry) = fs.readdir(&mut handle) {
+        entries.push(entry);
+    }
+    fs.closedir(handle);
+    let names: Vec<(&str, bool)> = entries.iter().map(|entry| (entry.name.as_str(), entry.is_directory)).collect();
+    assert_eq!(names, [("docs", true), ("notes.txt", false), ("todo.txt", false)]);
+
+    // Deleting entries already returned neither repeats nor skips the rest
+    let mut handle = fs.opendir(0).unwrap();
+    assert_eq!(fs.readdir(&mut handle).map(|entry| entry.cluster), Some(docs));
+    assert_eq!(fs.readdir(&mut handle).map(|entry| entry.cluster), Some(notes));
+    fs.delete_directory(docs).unwrap();
+    fs.delete_file(notes).unwrap();
+    assert_eq!(fs.readdir(&mut handle).map(|entry| entry.cluster), Some(todo));
+    assert_eq!(fs.readdir(&mut handle), None);
+    assert_eq!(fs.opendir(todo), Err(FileSystemError::DirectoryNotFound));
+}
+
+#[test_case]
+fn test_readdir_sees_recreated_entries_once() {
+    let mut fs = FileSystemService::new();
+    let a = fs.create_file("a.txt", FilePermissions::ReadWrite).unwrap();
+    let b = fs.create_file("b.txt", FilePermissions::ReadWrite).unwrap();
+    let name = |entry: Option<DirEntry>| entry.map(|entry| (entry.cluster, entry.name));
+
+    // An entry returned, deleted and recreated on the same cluster comes back as new
+    let mut handle = fs.opendir(0).unwrap();
+    assert_eq!(name(fs.readdir(&mut handle)), Some((a, String::from("a.txt"))));
+    fs.delete_file(a).unwrap();
+    assert_eq!(fs.create_file("c.txt", FilePermissions::ReadWrite), Ok(a));
+    assert_eq!(name(fs.readdir(&mut handle)), Some((b, String::from("b.txt"))));
+    assert_eq!(name(fs.readdir(&mut handle)), Some((a, String::from("c.txt"))));
+    assert_eq!(fs.readdir(&mut handle), None);
+
+    // An entry not yet reached is only returned as what replaced it
+    let mut handle = fs.opendir(0).unwrap();
+    assert_eq!(name(fs.readdir(&mut handle)), Some((b, String::from("b.txt"))));
+    fs.delete_file(a).unwrap();
+    assert_eq!(fs.create_file("d.txt", FilePermissions::ReadWrite), Ok(a));
+    assert_eq!(name(fs.readdir(&mut handle)), Some((a, String::from("d.txt"))));
+    assert_eq!(fs.readdir(&mut handle), None);
+}
+
+#[test_case]
+fn test_fs_capability_confines_writes_to_subtree() {
+    use crate::process::pcb::{CapabilityPermissions, ProcessPriority};
+    use crate::services::process_service::{
+        create_process, get_current_process, grant_fs_capability, terminate_process, wait_child, writable_fs_roots,
+        yield_to,
+    };
+
+    let home = create_directory("home").unwrap();
+    let etc = create_directory("etc").unwrap();
+    let pid = create_process(String::from("confined"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let write = CapabilityPermissions { read: true, write: true, execute: false, admin: false };
+    grant_fs_capability(pid, home, write).unwrap();
+
+    yield_to(pid).unwrap();
+    let allowed = open_or_create("/home/notes.txt", FilePermissions::ReadWrite);
+    let denied = open_or_create("/etc/passwd", FilePermissions::ReadWrite);
+    let overwrite = allowed.as_ref().ok().map(|&cluster| write_file(cluster, b"mine"));
+    if get_current_process() != Some(0) {
+        yield_to(0).unwrap();
+    }
+
+    assert_eq!(denied, Err(FileSystemError::PermissionDenied));
+    let notes = allowed.unwrap();
+    assert_eq!(overwrite, Some(Ok(4)));
+    // The kernel is not confined
+    let passwd = open_or_create("/etc/passwd", FilePermissions::ReadWrite).unwrap();
+
+    delete_file(notes).unwrap();
+    delete_file(passwd).unwrap();
+    delete_directory(home).unwrap();
+    delete_directory(etc).unwrap();
+    // The capability went with its directory, so a directory reusing the cluster is not covered
+    assert_eq!(writable_fs_roots(pid), Some(Vec::new()));
+    terminate_process(pid, 0).unwrap();
+    let _ = wait_child(0, Some(pid));
+}
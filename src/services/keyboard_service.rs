@@ -1,31 +1,114 @@
-use crate::{print, println};
-use conquer_once::spin::OnceCell;
+use crate::print;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::{
     pin::Pin,
     task::{Context, Poll},
 };
-use crossbeam_queue::ArrayQueue;
 use futures_util::{
-    stream::{Stream, StreamExt},
+    stream::Stream,
     task::AtomicWaker,
 };
-use pc_keyboard::{DecodedKey, HandleControl, Keyboard, ScancodeSet1, layouts};
+use lazy_static::lazy_static;
+use pc_keyboard::{DecodedKey, HandleControl, KeyCode, KeyState, Keyboard, ScancodeSet1, layouts};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
 
-static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+pub const SCANCODE_BUFFER_CAPACITY: usize = 100;
+
+/// Decoded key presses held for consumers; a press can take several scancodes
+pub const KEY_EVENT_BUFFER_CAPACITY: usize = 32;
+
+/// What to discard when a scancode arrives and the buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+}
+
+/// Fixed-capacity ring buffer; never allocates
+pub struct Ring<T: Copy, const N: usize> {
+    buffer: [Option<T>; N],
+    head: usize, // Index of the oldest item
+    len: usize,
+    policy: OverflowPolicy,
+    overflows: u64,
+}
+
+pub type ScancodeRing = Ring<u8, SCANCODE_BUFFER_CAPACITY>;
+pub type KeyEventRing = Ring<KeyEvent, KEY_EVENT_BUFFER_CAPACITY>;
+
+impl<T: Copy, const N: usize> Ring<T, N> {
+    pub const fn new(policy: OverflowPolicy) -> Self {
+        Self {
+            buffer: [None; N],
+            head: 0,
+            len: 0,
+            policy,
+            overflows: 0,
+        }
+    }
+
+    /// Append an item, applying the overflow policy when full.
+    /// Returns false if an item was dropped.
+    pub fn push(&mut self, item: T) -> bool {
+        let full = self.len == N;
+        if full {
+            self.overflows += 1;
+            match self.policy {
+                OverflowPolicy::DropNewest => return false,
+                OverflowPolicy::DropOldest => {
+                    self.head = (self.head + 1) % N;
+                    self.len -= 1;
+                }
+            }
+        }
+        let tail = (self.head + self.len) % N;
+        self.buffer[tail] = Some(item);
+        self.len += 1;
+        !full
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.buffer[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        item
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn overflow_count(&self) -> u64 {
+        self.overflows
+    }
+}
+
+// Consumers take these locks with interrupts disabled, so the IRQ handler never spins on them.
+static SCANCODE_BUFFER: Mutex<ScancodeRing> = Mutex::new(ScancodeRing::new(OverflowPolicy::DropNewest));
+static KEY_EVENTS: Mutex<KeyEventRing> = Mutex::new(KeyEventRing::new(OverflowPolicy::DropNewest));
 static WAKER: AtomicWaker = AtomicWaker::new();
+static KEY_WAKER: AtomicWaker = AtomicWaker::new();
 
 /// Called by the keyboard interrupt handler
 ///
-/// Must not block or allocate.
+/// Must not block or allocate. The raw byte is queued for scancode consumers and also
+/// fed to the shared decoder, whose state carries multi-byte sequences across interrupts.
 pub(crate) fn add_scancode(scancode: u8) {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
-        } else {
-            WAKER.wake();
-        }
-    } else {
-        println!("WARNING: scancode queue uninitialized");
+    SCANCODE_BUFFER.lock().push(scancode);
+    WAKER.wake();
+
+    if let Some(event) = KEY_DECODER.lock().feed(scancode) {
+        KEY_EVENTS.lock().push(event);
+        KEY_WAKER.wake();
     }
 }
 
@@ -33,11 +116,22 @@ pub(crate) fn add_scancode(scancode: u8) {
 /// Returns Some(scancode) if available, None if queue is empty.
 /// This is safe to call from interrupt/syscall context.
 pub fn try_get_scancode() -> Option<u8> {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        queue.pop()
-    } else {
-        None
-    }
+    interrupts::without_interrupts(|| SCANCODE_BUFFER.lock().pop())
+}
+
+/// Take the next decoded key press without blocking
+pub fn try_get_key() -> Option<KeyEvent> {
+    interrupts::without_interrupts(|| KEY_EVENTS.lock().pop())
+}
+
+/// Choose which scancode is discarded when the buffer overflows
+pub fn set_overflow_policy(policy: OverflowPolicy) {
+    interrupts::without_interrupts(|| SCANCODE_BUFFER.lock().set_policy(policy));
+}
+
+/// Number of scancodes dropped because the buffer was full
+pub fn overflow_count() -> u64 {
+    interrupts::without_interrupts(|| SCANCODE_BUFFER.lock().overflow_count())
 }
 
 pub struct ScancodeStream {
@@ -46,9 +140,6 @@ pub struct ScancodeStream {
 
 impl ScancodeStream {
     pub fn new() -> Self {
-        SCANCODE_QUEUE
-            .try_init_once(|| ArrayQueue::new(100))
-            .expect("ScancodeStream::new should only be called once");
         ScancodeStream { _private: () }
     }
 }
@@ -57,42 +148,389 @@ impl Stream for ScancodeStream {
     type Item = u8;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
-        let queue = SCANCODE_QUEUE
-            .try_get()
-            .expect("scancode queue not initialized");
+        poll_scancode(cx).map(Some)
+    }
+}
+
+fn poll_scancode(cx: &mut Context) -> Poll<u8> {
+    // fast path
+    if let Some(scancode) = try_get_scancode() {
+        return Poll::Ready(scancode);
+    }
 
-        // fast path
-        if let Some(scancode) = queue.pop() {
-            return Poll::Ready(Some(scancode));
+    WAKER.register(&cx.waker());
+    match try_get_scancode() {
+        Some(scancode) => {
+            WAKER.take();
+            Poll::Ready(scancode)
         }
+        None => Poll::Pending,
+    }
+}
 
-        WAKER.register(&cx.waker());
-        match queue.pop() {
-            Some(scancode) => {
-                WAKER.take();
-                Poll::Ready(Some(scancode))
-            }
-            None => Poll::Pending,
+/// Modifier keys held when a key event was decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyModifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+/// A decoded key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub character: Option<char>, // None for keys without a printable character
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+/// Maps the characters of keys to what they print.
+///
+/// Scancodes are first decoded as US QWERTY, shift included, and every printable result
+/// is passed to `translate`. A US character names both a key and its shift state, so a
+/// layout only lists the characters it changes and shift composes with it naturally.
+pub trait KeyLayout: Sync {
+    fn name(&self) -> &'static str;
+
+    /// What the key that prints `qwerty` on a US keyboard prints in this layout
+    fn translate(&self, qwerty: char) -> char;
+}
+
+/// A layout given as `(US character, layout character)` pairs; unlisted keys are unchanged
+pub struct TableLayout {
+    pub name: &'static str,
+    pub table: &'static [(char, char)],
+}
+
+impl KeyLayout for TableLayout {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn translate(&self, qwerty: char) -> char {
+        self.table
+            .iter()
+            .find(|&&(from, _)| from == qwerty)
+            .map_or(qwerty, |&(_, to)| to)
+    }
+}
+
+pub static US_QWERTY: TableLayout = TableLayout { name: "us", table: &[] };
+
+pub static DVORAK: TableLayout = TableLayout {
+    name: "dvorak",
+    table: &[
+        ('-', '['), ('=', ']'), ('_', '{'), ('+', '}'),
+        ('q', '\''), ('w', ','), ('e', '.'), ('r', 'p'), ('t', 'y'), ('y', 'f'), ('u', 'g'),
+        ('i', 'c'), ('o', 'r'), ('p', 'l'), ('[', '/'), (']', '='),
+        ('Q', '"'), ('W', '<'), ('E', '>'), ('R', 'P'), ('T', 'Y'), ('Y', 'F'), ('U', 'G'),
+        ('I', 'C'), ('O', 'R'), ('P', 'L'), ('{', '?'), ('}', '+'),
+        ('s', 'o'), ('d', 'e'), ('f', 'u'), ('g', 'i'), ('h', 'd'), ('j', 'h'), ('k', 't'),
+        ('l', 'n'), (';', 's'), ('\'', '-'),
+        ('S', 'O'), ('D', 'E'), ('F', 'U'), ('G', 'I'), ('H', 'D'), ('J', 'H'), ('K', 'T'),
+        ('L', 'N'), (':', 'S'), ('"', '_'),
+        ('z', ';'), ('x', 'q'), ('c', 'j'), ('v', 'k'), ('b', 'x'), ('n', 'b'),
+        (',', 'w'), ('.', 'v'), ('/', 'z'),
+        ('Z', ':'), ('X', 'Q'), ('C', 'J'), ('V', 'K'), ('B', 'X'), ('N', 'B'),
+        ('<', 'W'), ('>', 'V'), ('?', 'Z'),
+    ],
+};
+
+lazy_static! {
+    static ref LAYOUTS: Mutex<Vec<&'static dyn KeyLayout>> = Mutex::new(alloc::vec![
+        &US_QWERTY as &'static dyn KeyLayout,
+        &DVORAK,
+    ]);
+}
+
+/// Make `layout` selectable by name with `set_layout`, replacing any layout with its name
+pub fn register_layout(layout: &'static dyn KeyLayout) {
+    let mut layouts = LAYOUTS.lock();
+    layouts.retain(|existing| existing.name() != layout.name());
+    layouts.push(layout);
+}
+
+/// Names of every registered layout
+pub fn layout_names() -> Vec<&'static str> {
+    LAYOUTS.lock().iter().map(|layout| layout.name()).collect()
+}
+
+/// Switch the shared decoder to the registered layout called `name`.
+/// Returns false, leaving the layout unchanged, if there is none.
+pub fn set_layout(name: &str) -> bool {
+    let layout = LAYOUTS.lock().iter().copied().find(|layout| layout.name() == name);
+    match layout {
+        Some(layout) => {
+            interrupts::without_interrupts(|| KEY_DECODER.lock().set_layout(layout));
+            true
         }
+        None => false,
     }
 }
 
-pub async fn print_keypresses() {
-    let mut scancodes = ScancodeStream::new();
-    let mut keyboard = Keyboard::new(
-        ScancodeSet1::new(),
-        layouts::Us104Key,
-        HandleControl::Ignore,
-    );
-
-    while let Some(scancode) = scancodes.next().await {
-        if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
-                match key {
-                    DecodedKey::Unicode(character) => print!("{}", character),
-                    DecodedKey::RawKey(key) => print!("{:?}", key),
+/// Name of the layout the shared decoder is using
+pub fn active_layout() -> &'static str {
+    interrupts::without_interrupts(|| KEY_DECODER.lock().layout().name())
+}
+
+/// Scancode decoder that tracks modifier make/break codes
+pub struct KeyDecoder {
+    keyboard: Keyboard<layouts::Us104Key, ScancodeSet1>,
+    layout: &'static dyn KeyLayout,
+    lshift: bool,
+    rshift: bool,
+    lctrl: bool,
+    rctrl: bool,
+    lalt: bool,
+    ralt: bool,
+}
+
+impl KeyDecoder {
+    pub fn new() -> Self {
+        Self {
+            keyboard: Keyboard::new(ScancodeSet1::new(), layouts::Us104Key, HandleControl::Ignore),
+            layout: &US_QWERTY,
+            lshift: false,
+            rshift: false,
+            lctrl: false,
+            rctrl: false,
+            lalt: false,
+            ralt: false,
+        }
+    }
+
+    pub fn layout(&self) -> &'static dyn KeyLayout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: &'static dyn KeyLayout) {
+        self.layout = layout;
+    }
+
+    pub fn modifiers(&self) -> KeyModifiers {
+        KeyModifiers {
+            shift: self.lshift || self.rshift,
+            ctrl: self.lctrl || self.rctrl,
+            alt: self.lalt || self.ralt,
+        }
+    }
+
+    /// Feed one scancode byte. Returns an event when it completes a key press;
+    /// releases, modifier keys, and partial multi-byte sequences yield nothing.
+    pub fn feed(&mut self, scancode: u8) -> Option<KeyEvent> {
+        let raw = self.keyboard.add_byte(scancode).ok()??;
+        let code = raw.code;
+        let down = raw.state != KeyState::Up;
+
+        let modifier = match code {
+            KeyCode::LShift => Some(&mut self.lshift),
+            KeyCode::RShift => Some(&mut self.rshift),
+            KeyCode::LControl => Some(&mut self.lctrl),
+            KeyCode::RControl => Some(&mut self.rctrl),
+            KeyCode::LAlt => Some(&mut self.lalt),
+            KeyCode::RAltGr => Some(&mut self.ralt),
+            _ => None,
+        };
+        let is_modifier = modifier.is_some();
+        if let Some(held) = modifier {
+            *held = down;
+        }
+
+        // The decoder keeps its own shift/caps state, so it sees every event
+        let decoded = self.keyboard.process_keyevent(raw);
+        if !down || is_modifier {
+            return None;
+        }
+
+        let character = match decoded {
+            Some(DecodedKey::Unicode(character)) => Some(self.layout.translate(character)),
+            _ => None,
+        };
+        Some(KeyEvent { character, code, modifiers: self.modifiers() })
+    }
+}
+
+lazy_static! {
+    // Only the keyboard interrupt handler feeds this
+    static ref KEY_DECODER: Mutex<KeyDecoder> = Mutex::new(KeyDecoder::new());
+}
+
+fn poll_key(cx: &mut Context) -> Poll<KeyEvent> {
+    if let Some(event) = try_get_key() {
+        return Poll::Ready(event);
+    }
+
+    KEY_WAKER.register(&cx.waker());
+    match try_get_key() {
+        Some(event) => {
+            KEY_WAKER.take();
+            Poll::Ready(event)
+        }
+        None => Poll::Pending,
+    }
+}
+
+/// Stream of key presses decoded by the interrupt handler
+pub struct KeyStream {
+    _private: (),
+}
+
+impl KeyStream {
+    pub fn new() -> Self {
+        KeyStream { _private: () }
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = KeyEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<KeyEvent>> {
+        poll_key(cx).map(Some)
+    }
+}
+
+/// Wait for the next key press.
+///
+/// Decoding happens once, in the interrupt handler, so modifier state stays consistent
+/// no matter which consumer takes the event.
+pub async fn next_key() -> KeyEvent {
+    core::future::poll_fn(poll_key).await
+}
+
+/// Longest line `read_line` collects; further characters are ignored until Enter
+pub const MAX_LINE_LEN: usize = 256;
+
+/// Line being typed, with backspace applied
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    line: String,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        Self { line: String::new() }
+    }
+
+    /// Apply one typed character, returning the finished line (without its newline) once
+    /// Enter is pressed. Echoes what it accepts.
+    pub fn feed(&mut self, character: char) -> Option<String> {
+        match character {
+            '\n' => {
+                print!("\n");
+                return Some(core::mem::take(&mut self.line));
+            }
+            '\u{8}' | '\u{7f}' => {
+                if self.line.pop().is_some() {
+                    print!("\u{8}");
                 }
             }
+            c if !c.is_control() && self.line.len() + c.len_utf8() <= MAX_LINE_LEN => {
+                self.line.push(c);
+                print!("{}", c);
+            }
+            _ => {}
         }
+        None
+    }
+}
+
+/// Wait for a line of typed input, echoing it as it is typed
+pub async fn read_line() -> String {
+    let mut buffer = LineBuffer::new();
+    loop {
+        if let Some(character) = next_key().await.character {
+            if let Some(line) = buffer.feed(character) {
+                return line;
+            }
+        }
+    }
+}
+
+pub async fn print_keypresses() {
+    loop {
+        let key = next_key().await;
+        match key.character {
+            Some(character) => print!("{}", character),
+            None => print!("{:?}", key.code),
+        }
+    }
+}
+
+#[test_case]
+fn test_shift_a_decodes_uppercase() {
+    let mut decoder = KeyDecoder::new();
+
+    assert_eq!(decoder.feed(0x2a), None); // LShift make
+    let event = decoder.feed(0x1e).expect("no event for 'a'"); // A make
+    assert_eq!(decoder.feed(0x9e), None); // A break
+    assert_eq!(decoder.feed(0xaa), None); // LShift break
+
+    assert_eq!(event.character, Some('A'));
+    assert_eq!(event.code, KeyCode::A);
+    assert!(event.modifiers.shift);
+    assert_eq!(decoder.modifiers(), KeyModifiers::default());
+}
+
+#[test_case]
+fn test_ring_buffer_overflow_policies() {
+    let mut ring = ScancodeRing::new(OverflowPolicy::DropNewest);
+    for byte in 0..(SCANCODE_BUFFER_CAPACITY + 5) {
+        ring.push(byte as u8);
+    }
+    assert_eq!(ring.len(), SCANCODE_BUFFER_CAPACITY);
+    assert_eq!(ring.overflow_count(), 5);
+    assert_eq!(ring.pop(), Some(0)); // the earliest bytes survive
+
+    let mut ring = ScancodeRing::new(OverflowPolicy::DropOldest);
+    for byte in 0..(SCANCODE_BUFFER_CAPACITY + 5) {
+        ring.push(byte as u8);
+    }
+    assert_eq!(ring.overflow_count(), 5);
+    assert_eq!(ring.pop(), Some(5)); // the earliest bytes were evicted
+    let mut last = 5;
+    while let Some(byte) = ring.pop() {
+        last = byte;
     }
-}
\ No newline at end of file
+    assert_eq!(last as usize, SCANCODE_BUFFER_CAPACITY + 4);
+}
+
+#[test_case]
+fn test_extended_scancode_decodes_to_one_key() {
+    let mut decoder = KeyDecoder::new();
+
+    assert_eq!(decoder.feed(0xe0), None); // Extended prefix alone is not a key
+    let event = decoder.feed(0x4d).expect("no event for right arrow");
+    assert_eq!(event.code, KeyCode::ArrowRight);
+    assert_eq!(event.character, None);
+
+    assert_eq!(decoder.feed(0xe0), None);
+    assert_eq!(decoder.feed(0xcd), None); // Right arrow break
+
+    // Without the prefix the same byte is keypad 6
+    assert_eq!(decoder.feed(0x4d).map(|e| e.code), Some(KeyCode::Numpad6));
+}
+
+#[test_case]
+fn test_layouts_translate_the_same_scancode_differently() {
+    let mut decoder = KeyDecoder::new();
+    let press = |decoder: &mut KeyDecoder, make: u8| {
+        let character = decoder.feed(make).and_then(|event| event.character);
+        decoder.feed(make | 0x80); // Break
+        character
+    };
+
+    assert_eq!(press(&mut decoder, 0x10), Some('q'));
+    assert_eq!(press(&mut decoder, 0x1e), Some('a'));
+
+    decoder.set_layout(&DVORAK);
+    assert_eq!(press(&mut decoder, 0x10), Some('\''));
+    assert_eq!(press(&mut decoder, 0x1e), Some('a')); // Same place in both layouts
+
+    // Shift composes with the layout
+    decoder.feed(0x2a); // LShift make
+    assert_eq!(press(&mut decoder, 0x10), Some('"'));
+    assert_eq!(press(&mut decoder, 0x11), Some('<'));
+    decoder.feed(0xaa); // LShift break
+
+    assert!(layout_names().contains(&"dvorak"));
+}
@@ -1,18 +1,35 @@
 // Memory Management Service for Microkernel
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
-use spin::Mutex;
+use crate::lock_debug::TrackedMutex;
+use crate::process::pcb::{FileDescriptor, ProcessId};
 use x86_64::{
-    structures::paging::{FrameAllocator, OffsetPageTable, Size4KiB},
+    structures::paging::{FrameAllocator, OffsetPageTable, PhysFrame, Size4KiB},
     PhysAddr, VirtAddr,
 };
 
+/// Start of the address range file mappings are placed in, above the heap windows of
+/// every possible pid
+pub const MMAP_BASE: u64 = 0x0000_5000_0000_0000;
+
+/// Region ids hold a slot number in the low bits and the slot's generation above them,
+/// so an id kept past its region's free is told apart from the slot's next occupant
+const REGION_SLOT_BITS: u32 = 32;
+const REGION_SLOT_MASK: u64 = (1 << REGION_SLOT_BITS) - 1;
+
 /// Memory Service - Handles memory allocation and mapping
 pub struct MemoryService {
-    next_region_id: AtomicU64,
+    next_region_id: AtomicU64, // Next never-used slot
     allocated_regions: BTreeMap<u64, MemoryRegion>,
+    free_slots: Vec<u64>,
+    slot_generations: BTreeMap<u64, u64>, // Generation of every slot that has been freed at least once
+    next_mmap_addr: u64,
+    file_mappings: BTreeMap<u64, FileMapping>, // Keyed by start address
+    next_shm_id: u64,
+    shared_segments: BTreeMap<u64, SharedSegment>,
 }
 
 #[derive(Debug, Clone)]
@@ -22,6 +39,60 @@ pub struct MemoryRegion {
     pub size: usize,
     pub permissions: MemoryPermissions,
     pub is_allocated: bool,
+    pub owner: Option<ProcessId>, // Process charged for the region; None for kernel allocations
+    pub tag: Option<String>,      // Free-form label for debugging, e.g. "pagetable"
+}
+
+/// A file's bytes copied into memory by the mmap syscall
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMapping {
+    pub addr: VirtAddr,
+    pub len: usize,
+    pub owner: ProcessId,
+    pub fd: FileDescriptor,
+    pub cluster: u64, // Kept so writeback still works after the fd is closed
+    pub offset: usize,
+    pub shared: bool, // Shared mappings are written back to the file on unmap
+}
+
+/// Anonymous memory whose frames are mapped into every attached process
+#[derive(Debug, Clone)]
+pub struct SharedSegment {
+    pub id: u64,
+    pub size: usize,
+    frames: Vec<PhysFrame>,
+    pub attachments: BTreeMap<ProcessId, VirtAddr>, // Where each attached process sees the segment
+}
+
+/// One snapshot of heap and region usage, as reported by `meminfo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemInfo {
+    pub heap_total: usize,
+    pub heap_used: usize,
+    pub region_count: usize,
+    pub region_bytes: usize,
+    pub largest_gap: usize, // Widest unused span between two regions, in address order
+}
+
+impl MemInfo {
+    pub fn heap_free(&self) -> usize {
+        self.heap_total - self.heap_used
+    }
+
+    /// Heap in use plus memory handed out as regions
+    pub fn used(&self) -> usize {
+        self.heap_used + self.region_bytes
+    }
+}
+
+impl core::fmt::Display for MemInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let kib = |bytes: usize| bytes.div_ceil(1024);
+        writeln!(f, "heap:    {} KiB used, {} KiB free, {} KiB total",
+            kib(self.heap_used), kib(self.heap_free()), kib(self.heap_total))?;
+        write!(f, "regions: {} holding {} KiB, largest gap {} KiB",
+            self.region_count, kib(self.region_bytes), kib(self.largest_gap))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,6 +110,8 @@ pub enum MemoryError {
     PermissionDenied,
     RegionNotFound,
     AlreadyAllocated,
+    NotAttached,
+    StaleRegion, // The id's region was freed; its slot may belong to a newer region
 }
 
 impl MemoryService {
@@ -46,7 +119,54 @@ impl MemoryService {
         Self {
             next_region_id: AtomicU64::new(1),
             allocated_regions: BTreeMap::new(),
+            free_slots: Vec::new(),
+            slot_generations: BTreeMap::new(),
+            next_mmap_addr: MMAP_BASE,
+            file_mappings: BTreeMap::new(),
+            next_shm_id: 1,
+            shared_segments: BTreeMap::new(),
+        }
+    }
+
+    /// Forget every region, file mapping and shared segment.
+    /// Pages still mapped for them are leaked rather than unmapped, so mapping addresses
+    /// keep counting up from where they were and never land on those pages.
+    pub fn reset(&mut self) {
+        self.take();
+    }
+
+    /// Unmap every file mapping and shared segment attachment and free the segments'
+    /// frames, for state about to be dropped. Shared mappings are not written back.
+    /// Returns the number of pages unmapped.
+    pub fn release_all(&mut self) -> usize {
+        let mut unmapped = 0;
+        for mapping in core::mem::take(&mut self.file_mappings).into_values() {
+            let end = (mapping.addr + mapping.len as u64).align_up(4096u64);
+            unmapped += crate::memory::unmap_range(mapping.addr, end);
+        }
+        for segment in core::mem::take(&mut self.shared_segments).into_values() {
+            for &addr in segment.attachments.values() {
+                unmapped += crate::memory::unmap_pages(addr, segment.frames.len());
+            }
+            crate::memory::free_frames(&segment.frames);
         }
+        unmapped
+    }
+
+    /// Hand over every region, file mapping and shared segment and start again empty.
+    /// Mapping addresses keep counting up, so later mappings never overlap the taken ones.
+    pub fn take(&mut self) -> Self {
+        let next_mmap_addr = self.next_mmap_addr;
+        let taken = core::mem::replace(self, Self::new());
+        self.next_mmap_addr = next_mmap_addr;
+        taken
+    }
+
+    /// Put back state handed over by `take`, forgetting everything created since
+    pub fn put_back(&mut self, taken: Self) {
+        let next_mmap_addr = self.next_mmap_addr.max(taken.next_mmap_addr);
+        *self = taken;
+        self.next_mmap_addr = next_mmap_addr;
     }
 
     /// Allocate a new memory region
@@ -54,16 +174,73 @@ impl MemoryService {
         &mut self,
         size: usize,
         permissions: MemoryPermissions,
+        owner: Option<ProcessId>,
+    ) -> Result<u64, MemoryError> {
+        self.insert_region(size, 1, permissions, owner, None)
+    }
+
+    /// Allocate a new memory region whose start address is a multiple of `align`, which
+    /// must be a power of two
+    pub fn allocate_aligned(
+        &mut self,
+        size: usize,
+        align: u64,
+        permissions: MemoryPermissions,
+        owner: Option<ProcessId>,
+    ) -> Result<u64, MemoryError> {
+        if !align.is_power_of_two() {
+            return Err(MemoryError::InvalidAddress);
+        }
+        self.insert_region(size, align, permissions, owner, None)
+    }
+
+    /// Allocate a new memory region labelled with `tag`
+    pub fn allocate_tagged(
+        &mut self,
+        size: usize,
+        permissions: MemoryPermissions,
+        owner: Option<ProcessId>,
+        tag: &str,
+    ) -> Result<u64, MemoryError> {
+        self.insert_region(size, 1, permissions, owner, Some(String::from(tag)))
+    }
+
+    fn insert_region(
+        &mut self,
+        size: usize,
+        align: u64,
+        permissions: MemoryPermissions,
+        owner: Option<ProcessId>,
+        tag: Option<String>,
     ) -> Result<u64, MemoryError> {
         if size == 0 {
             return Err(MemoryError::InvalidAddress);
         }
 
-        let region_id = self.next_region_id.fetch_add(1, Ordering::Relaxed);
-        
+        let slot = self.free_slots.pop()
+            .unwrap_or_else(|| self.next_region_id.fetch_add(1, Ordering::Relaxed));
+        let generation = self.slot_generations.get(&slot).copied().unwrap_or(0);
+        let region_id = (generation << REGION_SLOT_BITS) | slot;
+
         // For now, we'll use a simple allocation strategy
         // In a real implementation, you'd integrate with your frame allocator
-        let start_addr = VirtAddr::new(0x1000_0000 + (region_id * size as u64));
+        // Slots are spaced a whole number of alignments apart from an aligned base, so
+        // every slot's start is aligned
+        let align = align as u128;
+        let base = (0x1000_0000 + align - 1) & !(align - 1);
+        let stride = (size as u128 + align - 1) & !(align - 1);
+        let start_addr = match (slot as u128 + 1)
+            .checked_mul(stride)
+            .map(|len| base + len)
+            .filter(|&end| end <= 0x0000_8000_0000_0000)
+        {
+            // The whole region must stay in the lower canonical half
+            Some(_) => VirtAddr::new((base + slot as u128 * stride) as u64),
+            None => {
+                self.free_slots.push(slot);
+                return Err(MemoryError::OutOfMemory);
+            }
+        };
         
         let region = MemoryRegion {
             id: region_id,
@@ -71,6 +248,8 @@ impl MemoryService {
             size,
             permissions,
             is_allocated: true,
+            owner,
+            tag,
         };
 
         self.allocated_regions.insert(region_id, region);
@@ -82,12 +261,22 @@ impl MemoryService {
         if let Some(mut region) = self.allocated_regions.remove(&region_id) {
             region.is_allocated = false;
             // In a real implementation, you'd free the actual memory here
+            self.release_slot(region_id);
             Ok(())
+        } else if self.slot_generations.contains_key(&(region_id & REGION_SLOT_MASK)) {
+            Err(MemoryError::StaleRegion)
         } else {
             Err(MemoryError::RegionNotFound)
         }
     }
 
+    /// Bump the generation of a freed region's slot and make it available again
+    fn release_slot(&mut self, region_id: u64) {
+        let slot = region_id & REGION_SLOT_MASK;
+        self.slot_generations.insert(slot, (region_id >> REGION_SLOT_BITS) + 1);
+        self.free_slots.push(slot);
+    }
+
     /// Map a memory region to physical memory
     pub fn map_region(
         &mut self,
@@ -117,6 +306,127 @@ impl MemoryService {
         self.allocated_regions.values().collect()
     }
 
+    /// Every region labelled with exactly `tag`
+    pub fn regions_with_tag(&self, tag: &str) -> Vec<&MemoryRegion> {
+        self.allocated_regions
+            .values()
+            .filter(|region| region.tag.as_deref() == Some(tag))
+            .collect()
+    }
+
+    /// Fill `out` with the tags holding the most bytes, largest first, and return how many
+    /// were written. Does not allocate, so it is safe to call when the heap is exhausted.
+    pub fn largest_tags<'a>(&'a self, out: &mut [(&'a str, usize)]) -> usize {
+        let mut count = 0;
+        for (id, region) in &self.allocated_regions {
+            let tag = match region.tag.as_deref() {
+                Some(tag) => tag,
+                None => continue,
+            };
+            // Only total a tag at its first region
+            if self.allocated_regions.range(..id).any(|(_, r)| r.tag.as_deref() == Some(tag)) {
+                continue;
+            }
+            let size: usize = self.allocated_regions
+                .values()
+                .filter(|r| r.tag.as_deref() == Some(tag))
+                .map(|r| r.size)
+                .sum();
+
+            // Insert in size order, dropping the smallest entry once `out` is full
+            let pos = out[..count].iter().position(|&(_, s)| s < size).unwrap_or(count);
+            if pos == out.len() {
+                continue;
+            }
+            count = (count + 1).min(out.len());
+            out[pos..count].rotate_right(1);
+            out[pos] = (tag, size);
+        }
+        count
+    }
+
+    /// Ids of every region owned by `pid`
+    pub fn regions_owned_by(&self, pid: ProcessId) -> Vec<u64> {
+        self.allocated_regions
+            .values()
+            .filter(|region| region.owner == Some(pid))
+            .map(|region| region.id)
+            .collect()
+    }
+
+    /// Release every region owned by `pid`, returning the number of bytes freed
+    pub fn free_all_owned_by(&mut self, pid: ProcessId) -> usize {
+        let mut freed = 0;
+        let mut released = Vec::new();
+        self.allocated_regions.retain(|&id, region| {
+            if region.owner == Some(pid) {
+                freed += region.size;
+                released.push(id);
+                false
+            } else {
+                true
+            }
+        });
+        for id in released {
+            self.release_slot(id);
+        }
+        freed
+    }
+
+    /// All regions ordered by start address
+    pub fn regions_sorted(&self) -> Vec<MemoryRegion> {
+        let mut regions: Vec<MemoryRegion> = self.allocated_regions.values().cloned().collect();
+        regions.sort_by_key(|region| region.start_addr);
+        regions
+    }
+
+    /// Heap usage and region totals in one snapshot
+    pub fn meminfo(&self) -> MemInfo {
+        let (heap_used, _, heap_total) = crate::allocator::heap_stats();
+        let regions = self.regions_sorted();
+        let largest_gap = regions
+            .windows(2)
+            .map(|pair| pair[1].start_addr.as_u64().saturating_sub(pair[0].start_addr.as_u64() + pair[0].size as u64))
+            .max()
+            .unwrap_or(0) as usize;
+        MemInfo {
+            heap_total,
+            heap_used,
+            region_count: regions.len(),
+            region_bytes: self.get_total_allocated(),
+            largest_gap,
+        }
+    }
+
+    /// Print every region in address order along with the gaps between them
+    pub fn dump_map(&self) {
+        let regions = self.regions_sorted();
+        crate::println!("Memory map ({} regions):", regions.len());
+
+        let mut previous_end: Option<VirtAddr> = None;
+        for region in &regions {
+            if let Some(end) = previous_end {
+                if region.start_addr > end {
+                    crate::println!("  gap      {:#x}  {} bytes", end.as_u64(), region.start_addr - end);
+                } else if region.start_addr < end {
+                    crate::println!("  OVERLAP  {:#x}  {} bytes", region.start_addr.as_u64(), end - region.start_addr);
+                }
+            }
+            crate::println!(
+                "  region {} {:#x}..{:#x}  {} bytes  {:?}  owner {:?}  {}",
+                region.id,
+                region.start_addr.as_u64(),
+                region.start_addr.as_u64() + region.size as u64,
+                region.size,
+                region.permissions,
+                region.owner,
+                region.tag.as_deref().unwrap_or(""),
+            );
+            let end = region.start_addr + region.size as u64;
+            previous_end = Some(previous_end.map_or(end, |prev| prev.max(end)));
+        }
+    }
+
     /// Check if an address is within an allocated region
     pub fn is_address_valid(&self, addr: VirtAddr) -> bool {
         self.allocated_regions
@@ -128,6 +438,122 @@ impl MemoryService {
             })
     }
 
+    /// Reserve page-aligned address space for a `len`-byte file mapping.
+    /// Addresses are never reused, and an unmapped guard page follows each mapping.
+    pub fn reserve_mapping_space(&mut self, len: usize) -> Result<VirtAddr, MemoryError> {
+        let pages = (len as u64).div_ceil(4096);
+        let addr = self.next_mmap_addr;
+        let next = pages
+            .checked_add(1)
+            .and_then(|pages| pages.checked_mul(4096))
+            .and_then(|span| addr.checked_add(span))
+            .filter(|&next| next <= 0x0000_8000_0000_0000) // End of the lower canonical half
+            .ok_or(MemoryError::OutOfMemory)?;
+        self.next_mmap_addr = next;
+        Ok(VirtAddr::new(addr))
+    }
+
+    /// Record a file mapping; fails if one already starts at the same address
+    pub fn insert_file_mapping(&mut self, mapping: FileMapping) -> Result<(), MemoryError> {
+        let addr = mapping.addr.as_u64();
+        if self.file_mappings.contains_key(&addr) {
+            return Err(MemoryError::AlreadyAllocated);
+        }
+        self.file_mappings.insert(addr, mapping);
+        Ok(())
+    }
+
+    /// The file mapping starting at `addr`
+    pub fn file_mapping(&self, addr: VirtAddr) -> Option<&FileMapping> {
+        self.file_mappings.get(&addr.as_u64())
+    }
+
+    /// Forget the file mapping starting at `addr`
+    pub fn remove_file_mapping(&mut self, addr: VirtAddr) -> Result<FileMapping, MemoryError> {
+        self.file_mappings.remove(&addr.as_u64()).ok_or(MemoryError::RegionNotFound)
+    }
+
+    /// Create a zeroed shared segment of at least `size` bytes, backed by frames now
+    pub fn shm_create(&mut self, size: usize) -> Result<u64, MemoryError> {
+        if size == 0 {
+            return Err(MemoryError::InvalidAddress);
+        }
+        let frames = crate::memory::allocate_frames(size.div_ceil(4096)).ok_or(MemoryError::OutOfMemory)?;
+        let id = self.next_shm_id;
+        self.next_shm_id += 1;
+        self.shared_segments.insert(id, SharedSegment { id, size, frames, attachments: BTreeMap::new() });
+        Ok(id)
+    }
+
+    /// Map segment `id` into `pid` at a fresh address and return it
+    pub fn shm_attach(&mut self, id: u64, pid: ProcessId) -> Result<VirtAddr, MemoryError> {
+        use x86_64::structures::paging::PageTableFlags as Flags;
+
+        let segment = self.shared_segments.get(&id).ok_or(MemoryError::RegionNotFound)?;
+        if segment.attachments.contains_key(&pid) {
+            return Err(MemoryError::AlreadyAllocated);
+        }
+        let size = segment.size;
+        let addr = self.reserve_mapping_space(size)?;
+
+        let segment = self.shared_segments.get_mut(&id).ok_or(MemoryError::RegionNotFound)?;
+        let flags = Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE | Flags::NO_EXECUTE;
+        crate::memory::map_frames(addr, &segment.frames, flags).map_err(|_| MemoryError::OutOfMemory)?;
+        segment.attachments.insert(pid, addr);
+        Ok(addr)
+    }
+
+    /// Unmap segment `id` from `pid`. The last detach destroys the segment and frees its frames.
+    pub fn shm_detach(&mut self, id: u64, pid: ProcessId) -> Result<(), MemoryError> {
+        let segment = self.shared_segments.get_mut(&id).ok_or(MemoryError::RegionNotFound)?;
+        let addr = segment.attachments.remove(&pid).ok_or(MemoryError::NotAttached)?;
+        crate::memory::unmap_pages(addr, segment.frames.len());
+
+        if segment.attachments.is_empty() {
+            if let Some(segment) = self.shared_segments.remove(&id) {
+                crate::memory::free_frames(&segment.frames);
+            }
+        }
+        Ok(())
+    }
+
+    /// Release everything `pid` has mapped outside its regions, as when it exits: detach it
+    /// from every shared segment and unmap each of its file mappings, writing shared ones
+    /// back to their files first, within the filesystem subtrees `fs_roots` the process may
+    /// write (`None` for anywhere). Returns the bytes of file mappings unmapped.
+    pub fn release_mappings_of(&mut self, pid: ProcessId, fs_roots: Option<Vec<u64>>) -> usize {
+        let attached: Vec<u64> = self.shared_segments
+            .values()
+            .filter(|segment| segment.attachments.contains_key(&pid))
+            .map(|segment| segment.id)
+            .collect();
+        for id in attached {
+            let _ = self.shm_detach(id, pid);
+        }
+
+        let mut unmapped = 0;
+        let owned: Vec<u64> = self.file_mappings
+            .values()
+            .filter(|mapping| mapping.owner == pid)
+            .map(|mapping| mapping.addr.as_u64())
+            .collect();
+        for addr in owned {
+            if let Some(mapping) = self.file_mappings.remove(&addr) {
+                if mapping.shared {
+                    write_back(&mapping, &fs_roots);
+                }
+                let end = (mapping.addr + mapping.len as u64).align_up(4096u64);
+                unmapped += crate::memory::unmap_range(mapping.addr, end) * 4096;
+            }
+        }
+        unmapped
+    }
+
+    /// Information about shared segment `id`
+    pub fn shm_info(&self, id: u64) -> Option<&SharedSegment> {
+        self.shared_segments.get(&id)
+    }
+
     /// Get total allocated memory
     pub fn get_total_allocated(&self) -> usize {
         self.allocated_regions
@@ -138,13 +564,42 @@ impl MemoryService {
     }
 }
 
+/// Copy the part of a shared mapping that overlaps its file back into the file, if the
+/// owner's write capabilities `fs_roots` still cover it. Best effort: the owner is exiting, so there is no one to report a failure to. Watchers of
+/// the file are told by the next filesystem call, since this runs under the process
+/// service lock and delivery may need it.
+fn write_back(mapping: &FileMapping, fs_roots: &Option<Vec<u64>>) {
+    let mut fs = crate::services::file_system_service::FILESYSTEM_SERVICE.lock();
+    if let Ok(mut data) = fs.read_file(mapping.cluster) {
+        let start = mapping.offset.min(data.len());
+        let count = (data.len() - start).min(mapping.len);
+        let mapped = unsafe { core::slice::from_raw_parts(mapping.addr.as_ptr::<u8>(), count) };
+        data[start..start + count].copy_from_slice(mapped);
+        let _ = fs.write_file_within(fs_roots.clone(), mapping.cluster, &data);
+    }
+}
+
 lazy_static! {
-    pub static ref MEMORY_SERVICE: Mutex<MemoryService> = Mutex::new(MemoryService::new());
+    pub static ref MEMORY_SERVICE: TrackedMutex<MemoryService> = TrackedMutex::new("MEMORY_SERVICE", MemoryService::new());
 }
 
 /// Memory service API functions
+/// Allocate a region owned by the current process
 pub fn allocate_memory(size: usize, permissions: MemoryPermissions) -> Result<u64, MemoryError> {
-    MEMORY_SERVICE.lock().allocate_region(size, permissions)
+    let owner = crate::services::process_service::get_current_process();
+    MEMORY_SERVICE.lock().allocate_region(size, permissions, owner)
+}
+
+/// Allocate a region owned by the current process, labelled with `tag`
+pub fn allocate_tagged(size: usize, permissions: MemoryPermissions, tag: &str) -> Result<u64, MemoryError> {
+    let owner = crate::services::process_service::get_current_process();
+    MEMORY_SERVICE.lock().allocate_tagged(size, permissions, owner, tag)
+}
+
+/// Allocate a region owned by the current process starting at a multiple of `align`
+pub fn allocate_aligned(size: usize, align: u64, permissions: MemoryPermissions) -> Result<u64, MemoryError> {
+    let owner = crate::services::process_service::get_current_process();
+    MEMORY_SERVICE.lock().allocate_aligned(size, align, permissions, owner)
 }
 
 pub fn deallocate_memory(region_id: u64) -> Result<(), MemoryError> {
@@ -157,4 +612,262 @@ pub fn get_memory_info(region_id: u64) -> Option<MemoryRegion> {
 
 pub fn list_memory_regions() -> Vec<MemoryRegion> {
     MEMORY_SERVICE.lock().list_regions().into_iter().cloned().collect()
-}
\ No newline at end of file
+}
+
+pub fn regions_with_tag(tag: &str) -> Vec<MemoryRegion> {
+    MEMORY_SERVICE.lock().regions_with_tag(tag).into_iter().cloned().collect()
+}
+
+pub fn regions_owned_by(pid: ProcessId) -> Vec<u64> {
+    MEMORY_SERVICE.lock().regions_owned_by(pid)
+}
+
+pub fn free_all_owned_by(pid: ProcessId) -> usize {
+    MEMORY_SERVICE.lock().free_all_owned_by(pid)
+}
+
+pub fn release_mappings_of(pid: ProcessId, fs_roots: Option<Vec<u64>>) -> usize {
+    MEMORY_SERVICE.lock().release_mappings_of(pid, fs_roots)
+}
+
+pub fn regions_sorted() -> Vec<MemoryRegion> {
+    MEMORY_SERVICE.lock().regions_sorted()
+}
+
+pub fn meminfo() -> MemInfo {
+    MEMORY_SERVICE.lock().meminfo()
+}
+
+pub fn dump_memory_map() {
+    MEMORY_SERVICE.lock().dump_map()
+}
+
+pub fn reserve_mapping_space(len: usize) -> Result<VirtAddr, MemoryError> {
+    MEMORY_SERVICE.lock().reserve_mapping_space(len)
+}
+
+pub fn insert_file_mapping(mapping: FileMapping) -> Result<(), MemoryError> {
+    MEMORY_SERVICE.lock().insert_file_mapping(mapping)
+}
+
+pub fn file_mapping(addr: VirtAddr) -> Option<FileMapping> {
+    MEMORY_SERVICE.lock().file_mapping(addr).copied()
+}
+
+pub fn remove_file_mapping(addr: VirtAddr) -> Result<FileMapping, MemoryError> {
+    MEMORY_SERVICE.lock().remove_file_mapping(addr)
+}
+
+pub fn shm_create(size: usize) -> Result<u64, MemoryError> {
+    MEMORY_SERVICE.lock().shm_create(size)
+}
+
+/// Attach shared segment `shm_id` to the current process
+pub fn shm_attach(shm_id: u64) -> Result<VirtAddr, MemoryError> {
+    let pid = crate::services::process_service::get_current_process().ok_or(MemoryError::PermissionDenied)?;
+    MEMORY_SERVICE.lock().shm_attach(shm_id, pid)
+}
+
+/// Detach shared segment `shm_id` from the current process
+pub fn shm_detach(shm_id: u64) -> Result<(), MemoryError> {
+    let pid = crate::services::process_service::get_current_process().ok_or(MemoryError::PermissionDenied)?;
+    MEMORY_SERVICE.lock().shm_detach(shm_id, pid)
+}
+
+pub fn shm_info(shm_id: u64) -> Option<SharedSegment> {
+    MEMORY_SERVICE.lock().shm_info(shm_id).cloned()
+}
+
+#[test_case]
+fn test_regions_sorted_by_address() {
+    let mut service = MemoryService::new();
+    // Region addresses scale with id * size, so later ids can land lower
+    let big = service.allocate_region(0x4000, MemoryPermissions::ReadWrite, None).unwrap();
+    let small = service.allocate_region(0x1000, MemoryPermissions::ReadOnly, None).unwrap();
+    let mid = service.allocate_region(0x2000, MemoryPermissions::Execute, None).unwrap();
+
+    let regions = service.regions_sorted();
+    let ids: Vec<u64> = regions.iter().map(|r| r.id).collect();
+    assert_eq!(ids, [small, big, mid]);
+    assert!(regions.windows(2).all(|pair| pair[0].start_addr <= pair[1].start_addr));
+
+    // 0x1000_2000..0x1000_3000 is followed by a gap before 0x1000_4000
+    let gap = regions[1].start_addr - (regions[0].start_addr + regions[0].size as u64);
+    assert_eq!(gap, 0x1000);
+    service.dump_map();
+}
+
+#[test_case]
+fn test_region_past_user_half_is_refused() {
+    let mut service = MemoryService::new();
+    assert!(matches!(service.allocate_region(usize::MAX, MemoryPermissions::ReadWrite, None), Err(MemoryError::OutOfMemory)));
+    assert!(matches!(service.allocate_region(1 << 47, MemoryPermissions::ReadWrite, None), Err(MemoryError::OutOfMemory)));
+
+    // The refused slot is handed out next
+    let id = service.allocate_region(4096, MemoryPermissions::ReadWrite, None).unwrap();
+    assert_eq!(service.get_region_info(id).unwrap().start_addr.as_u64(), 0x1000_0000 + 4096);
+}
+
+#[test_case]
+fn test_stale_region_id_cannot_free_reused_slot() {
+    let mut service = MemoryService::new();
+    let old = service.allocate_region(4096, MemoryPermissions::ReadWrite, Some(1)).unwrap();
+    service.deallocate_region(old).unwrap();
+
+    let new = service.allocate_region(4096, MemoryPermissions::ReadWrite, Some(2)).unwrap();
+    assert_ne!(new, old);
+    assert!(matches!(service.deallocate_region(old), Err(MemoryError::StaleRegion)));
+    assert_eq!(service.get_region_info(new).and_then(|region| region.owner), Some(2));
+
+    service.deallocate_region(new).unwrap();
+    assert!(matches!(service.deallocate_region(new), Err(MemoryError::StaleRegion)));
+    assert!(matches!(service.deallocate_region(999), Err(MemoryError::RegionNotFound)));
+}
+
+#[test_case]
+fn test_free_all_owned_by_removes_only_owned_regions() {
+    let mut service = MemoryService::new();
+    let owned: Vec<u64> = (0..3)
+        .map(|_| service.allocate_region(4096, MemoryPermissions::ReadWrite, Some(42)).unwrap())
+        .collect();
+    let other = service.allocate_region(4096, MemoryPermissions::ReadWrite, Some(7)).unwrap();
+    let kernel = service.allocate_region(4096, MemoryPermissions::ReadWrite, None).unwrap();
+
+    assert_eq!(service.regions_owned_by(42), owned);
+    assert_eq!(service.free_all_owned_by(42), 3 * 4096);
+    assert!(service.regions_owned_by(42).is_empty());
+
+    let remaining: Vec<u64> = service.list_regions().iter().map(|r| r.id).collect();
+    assert_eq!(remaining, [other, kernel]);
+}
+
+#[test_case]
+fn test_regions_with_tag_matches_exactly() {
+    let mut service = MemoryService::new();
+    let buffers: Vec<u64> = (0..3)
+        .map(|_| service.allocate_tagged(4096, MemoryPermissions::ReadWrite, None, "buf").unwrap())
+        .collect();
+    service.allocate_tagged(4096, MemoryPermissions::ReadWrite, None, "other").unwrap();
+    service.allocate_region(4096, MemoryPermissions::ReadWrite, None).unwrap();
+
+    let tagged: Vec<u64> = service.regions_with_tag("buf").iter().map(|r| r.id).collect();
+    assert_eq!(tagged, buffers);
+    assert_eq!(service.regions_with_tag("other").len(), 1);
+    assert!(service.regions_with_tag("bu").is_empty());
+}
+
+#[test_case]
+fn test_shared_segment_writes_are_visible_to_other_process() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process, yield_to};
+
+    let other = create_process(String::from("shm_peer"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let id = shm_create(6000).unwrap();
+
+    let ours = shm_attach(id).unwrap();
+    assert!(matches!(shm_attach(id), Err(MemoryError::AlreadyAllocated)));
+    yield_to(other).unwrap();
+    let theirs = shm_attach(id).unwrap();
+    assert_ne!(ours, theirs);
+
+    // A write through the peer's mapping lands in the frames we see too, on both pages
+    unsafe {
+        theirs.as_mut_ptr::<u64>().write_volatile(0x5ba7_ed00);
+        (theirs + 4096u64).as_mut_ptr::<u8>().write_volatile(0x42);
+    }
+    shm_detach(id).unwrap();
+    yield_to(0).unwrap();
+    unsafe {
+        assert_eq!(ours.as_ptr::<u64>().read_volatile(), 0x5ba7_ed00);
+        assert_eq!((ours + 4096u64).as_ptr::<u8>().read_volatile(), 0x42);
+    }
+    assert_eq!(crate::memory::is_mapped(theirs), Some(false));
+
+    // The last detach destroys the segment
+    assert_eq!(shm_info(id).map(|segment| segment.attachments.len()), Some(1));
+    shm_detach(id).unwrap();
+    assert!(shm_info(id).is_none());
+    assert!(matches!(shm_detach(id), Err(MemoryError::RegionNotFound)));
+    let _ = terminate_process(other, 0);
+}
+
+#[test_case]
+fn test_meminfo_counts_new_region() {
+    let mut service = MemoryService::new();
+    let before = service.meminfo();
+    assert_eq!((before.region_count, before.region_bytes, before.largest_gap), (0, 0, 0));
+
+    let first = service.allocate_region(4096, MemoryPermissions::ReadWrite, None).unwrap();
+    let after = service.meminfo();
+    assert_eq!(after.region_count, 1);
+    assert!(after.region_bytes >= before.region_bytes + 4096);
+    assert!(after.used() >= before.used() + 4096);
+    assert_eq!(after.heap_used + after.heap_free(), after.heap_total);
+
+    // Region addresses scale with id * size, so a larger second region leaves a gap
+    let second = service.allocate_region(0x4000, MemoryPermissions::ReadWrite, None).unwrap();
+    let first_end = service.get_region_info(first).unwrap().start_addr + 4096u64;
+    let gap = service.get_region_info(second).unwrap().start_addr - first_end;
+    assert!(gap > 0);
+    assert_eq!(service.meminfo().largest_gap as u64, gap);
+    assert!(alloc::format!("{}", service.meminfo()).contains("regions: 2 holding 20 KiB"));
+}
+
+#[test_case]
+fn test_aligned_region_starts_on_alignment() {
+    let mut service = MemoryService::new();
+    // Odd sizes would leave an unaligned slot address
+    service.allocate_region(100, MemoryPermissions::ReadWrite, None).unwrap();
+    for _ in 0..3 {
+        let region = service.allocate_aligned(100, 4096, MemoryPermissions::ReadWrite, None).unwrap();
+        assert!(service.get_region_info(region).unwrap().start_addr.is_aligned(4096u64));
+    }
+    assert!(matches!(
+        service.allocate_aligned(4096, 3, MemoryPermissions::ReadWrite, None),
+        Err(MemoryError::InvalidAddress)
+    ));
+}
+
+#[test_case]
+fn test_reset_does_not_reuse_mapping_addresses() {
+    let mut service = MemoryService::new();
+    let before = service.reserve_mapping_space(4096).unwrap();
+    service.reset();
+    let after = service.reserve_mapping_space(4096).unwrap();
+    assert!(after > before, "mapping space rewound to {:?} after reset", after);
+}
+
+#[test_case]
+fn test_exit_releases_shared_segments_and_file_mappings() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::file_system_service::{self, FilePermissions};
+    use crate::services::process_service::{create_process, terminate_process, yield_to};
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    let cluster = file_system_service::create_file("exit_map.txt", FilePermissions::ReadWrite).unwrap();
+    file_system_service::write_file(cluster, b"before").unwrap();
+    let id = shm_create(4096).unwrap();
+    let ours = shm_attach(id).unwrap();
+
+    let exiting = create_process(String::from("exit_map"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    yield_to(exiting).unwrap();
+    let theirs = shm_attach(id).unwrap();
+    let addr = reserve_mapping_space(4096).unwrap();
+    let flags = Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE | Flags::NO_EXECUTE;
+    crate::memory::map_range(addr, addr + 4096u64, flags).unwrap();
+    unsafe { core::ptr::copy_nonoverlapping(b"after!".as_ptr(), addr.as_mut_ptr::<u8>(), 6) };
+    let mapping = FileMapping { addr, len: 4096, owner: exiting, fd: 3, cluster, offset: 0, shared: true };
+    insert_file_mapping(mapping).unwrap();
+    yield_to(0).unwrap();
+
+    terminate_process(exiting, 0).unwrap();
+    assert_eq!(shm_info(id).map(|segment| segment.attachments.len()), Some(1));
+    assert_eq!(crate::memory::is_mapped(theirs), Some(false));
+    assert!(file_mapping(addr).is_none());
+    assert_eq!(crate::memory::is_mapped(addr), Some(false));
+    assert_eq!(file_system_service::read_file(cluster).unwrap(), b"after!");
+
+    assert_eq!(crate::memory::is_mapped(ours), Some(true));
+    shm_detach(id).unwrap();
+    let _ = file_system_service::delete_file(cluster);
+}
@@ -3,3 +3,100 @@ pub mod vga_service;
 pub mod memory_service;
 pub mod file_system_service;
 pub mod process_service;
+pub mod device_service;
+
+/// Return the process, memory and filesystem services and the process scheduler to their
+/// freshly-initialized state, so a test can start from a known baseline
+pub fn reset_all() {
+    process_service::PROCESS_SERVICE.lock().reset();
+    memory_service::MEMORY_SERVICE.lock().reset();
+    file_system_service::FILESYSTEM_SERVICE.lock().reset();
+    crate::process::scheduler::SCHEDULER.lock().reset();
+}
+
+/// The live state of every service, set aside by `take_all`
+pub struct ServiceState {
+    processes: process_service::ProcessService,
+    memory: memory_service::MemoryService,
+    filesystem: file_system_service::FileSystemService,
+    scheduler: crate::process::scheduler::ProcessScheduler,
+}
+
+/// Like `reset_all`, but hand back the state it replaces so `restore_all` can put it back.
+/// Lets the test batteries run on fresh services without wiping the running kernel.
+pub fn take_all() -> ServiceState {
+    let mut processes = process_service::PROCESS_SERVICE.lock();
+    ServiceState {
+        processes: processes.take(),
+        memory: memory_service::MEMORY_SERVICE.lock().take(),
+        filesystem: core::mem::replace(
+            &mut *file_system_service::FILESYSTEM_SERVICE.lock(),
+            file_system_service::FileSystemService::new(),
+        ),
+        scheduler: core::mem::replace(
+            &mut *crate::process::scheduler::SCHEDULER.lock(),
+            crate::process::scheduler::ProcessScheduler::new(),
+        ),
+    }
+}
+
+/// Put back the services set aside by `take_all`, dropping everything created since.
+/// Pages mapped for the dropped processes, file mappings and shared segments are freed.
+pub fn restore_all(state: ServiceState) {
+    process_service::PROCESS_SERVICE.lock().release_user_pages();
+    memory_service::MEMORY_SERVICE.lock().release_all();
+    *process_service::PROCESS_SERVICE.lock() = state.processes;
+    memory_service::MEMORY_SERVICE.lock().put_back(state.memory);
+    *file_system_service::FILESYSTEM_SERVICE.lock() = state.filesystem;
+    *crate::process::scheduler::SCHEDULER.lock() = state.scheduler;
+}
+
+#[test_case]
+fn test_reset_all_leaves_only_kernel_process() {
+    use alloc::string::String;
+    use crate::process::pcb::ProcessPriority;
+
+    // Reset a scratch copy, not the services the rest of the suite runs on
+    let live = take_all();
+    process_service::create_process(String::from("before_reset"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    reset_all();
+    assert_eq!(process_service::list_processes().len(), 1);
+    assert!(memory_service::list_memory_regions().is_empty());
+    assert!(file_system_service::list_files().is_empty());
+
+    let pid = process_service::create_process(String::from("after_reset"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let pids: alloc::vec::Vec<_> = process_service::list_processes().into_iter().map(|(pid, _, _)| pid).collect();
+    assert_eq!(pids, [0, pid]);
+    assert_eq!(process_service::get_current_process(), Some(0));
+    restore_all(live);
+}
+
+#[test_case]
+fn test_restore_all_brings_back_taken_state() {
+    use alloc::string::String;
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::file_system_service::FilePermissions;
+
+    let pid = process_service::create_process(String::from("kept"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let file = file_system_service::create_file("kept.txt", FilePermissions::ReadWrite).unwrap();
+
+    let state = take_all();
+    assert_eq!(process_service::list_processes().len(), 1);
+    assert!(file_system_service::list_files().is_empty());
+    let scratch = process_service::create_process(String::from("scratch"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    assert!(scratch > pid, "pids restarted and may reuse a live process's heap window");
+    let heap = process_service::sbrk(scratch, 4096).unwrap();
+    let shm = memory_service::shm_create(4096).unwrap();
+    let shm_addr = memory_service::shm_attach(shm).unwrap();
+    restore_all(state);
+
+    // Pages mapped while the fresh state was live are gone with it
+    assert_eq!(crate::memory::is_mapped(heap), Some(false));
+    assert_eq!(crate::memory::is_mapped(shm_addr), Some(false));
+
+    assert!(process_service::list_processes().iter().any(|&(p, ref name, _)| p == pid && name == "kept"));
+    assert!(!process_service::list_processes().iter().any(|(_, name, _)| name == "scratch"));
+    assert!(file_system_service::list_files().iter().any(|(name, _)| name == "kept.txt"));
+    let _ = file_system_service::delete_file(file);
+    let _ = process_service::terminate_process(pid, 0);
+}
@@ -1,17 +1,107 @@
 // Process Management Service for EMOS Microkernel
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::string::String;
 use alloc::vec::Vec;
 use lazy_static::lazy_static;
-use spin::Mutex;
-use crate::process::pcb::{ProcessId, ProcessState, ProcessPriority, ProcessControlBlock, ProcessError};
+use crate::lock_debug::TrackedMutex;
+use crate::process::pcb::{
+    ProcessId, ProcessState, ProcessPriority, ProcessControlBlock, ProcessError, Signal,
+    FileDescriptor, OpenFile, STDERR_FD, WaitTarget, standard_fds, ALL_CPUS,
+    Capability, CapabilityPermissions, ResourceType, allocate_pid, pid_after,
+};
 use crate::process::context::context_switch;
+use crate::process::sched_trace::{self, SchedEvent, SchedReason};
+use crate::task::timer::TimerWheel;
+use x86_64::VirtAddr;
 
 /// Process Management Service - Coordinates process creation, scheduling, and context switching
 pub struct ProcessService {
     processes: BTreeMap<ProcessId, ProcessControlBlock>,
     current_process: Option<ProcessId>,
     next_pid: u64,
+    sched_credit: BTreeMap<ProcessId, i64>, // Weighted round-robin credit per process
+    watchdog: Watchdog,
+    sleepers: TimerWheel<ProcessId>, // Sleeping pids keyed by wake deadline in ticks
+    observers: Vec<ProcessObserver>,
+    slice_ticks: u64, // Ticks the current process has run since it was switched in
+    clock: u64,       // Ticks accounted since the service was created
+    responsiveness: BTreeMap<ProcessId, Responsiveness>,
+    exit_statuses: VecDeque<(ProcessId, i32)>, // Codes of reaped processes, oldest first
+    injected_switch_failures: u32, // Upcoming context switches to fail on purpose
+    max_processes: usize, // Limit on processes in the table, the kernel and zombies included
+    futex_waiters: BTreeMap<u64, VecDeque<ProcessId>>, // Pids blocked in futex_wait by futex key, oldest first
+}
+
+/// How many reaped processes `get_exit_status` remembers
+pub const EXIT_STATUS_CACHE_SIZE: usize = 16;
+
+/// Effective-priority boost for a process whose last block came within the first tick of
+/// its slice. Capped at the top of the process's band, like nice.
+pub const RESPONSIVE_BONUS: u32 = 20;
+
+/// Kernel heap one process table entry holds: the PCB, plus roughly its name, file
+/// table and map nodes
+const PROCESS_HEAP_COST: usize = core::mem::size_of::<ProcessControlBlock>() + 512;
+
+/// Processes allowed until `set_max_processes` says otherwise: as many as fit in half
+/// of the kernel heap, leaving the rest for everything else
+pub const DEFAULT_MAX_PROCESSES: usize = crate::allocator::HEAP_SIZE / 2 / PROCESS_HEAP_COST;
+
+/// Largest heap `sbrk` will grow a process to, so one call cannot try to map all of memory
+pub const MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// Start of the heap window of pid 0. Each pid has a window of `MAX_HEAP_SIZE` bytes
+/// above it, so heaps in the shared page table never share a page.
+pub const HEAP_WINDOWS_BASE: u64 = 0x1000_0000;
+
+/// Where `pid`'s heap starts
+fn heap_window(pid: ProcessId) -> x86_64::VirtAddr {
+    x86_64::VirtAddr::new(HEAP_WINDOWS_BASE + pid as u64 * MAX_HEAP_SIZE as u64)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Responsiveness {
+    blocked_early: u64, // Times the process blocked before its first tick
+    responsive: bool,   // Whether it did so the last time it left the CPU
+}
+
+/// A process lifecycle transition reported to observers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessEvent {
+    Created(ProcessId),
+    Terminated(ProcessId),
+    Blocked(ProcessId),
+    Unblocked(ProcessId),
+}
+
+/// Callback run on every `ProcessEvent`.
+///
+/// Observers run with the process service locked, possibly from the timer interrupt,
+/// so they must be short and must not call back into the process service.
+pub type ProcessObserver = fn(ProcessEvent);
+
+/// What the watchdog does to a process that runs too long without a context switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    Preempt,
+    Terminate,
+}
+
+/// Whether any lock `terminate_process` takes besides the process service is held: the
+/// memory service for regions and mappings, and the filesystem service for writing
+/// shared mappings back. Callers in interrupt context must not terminate while it is.
+fn termination_locks_held() -> bool {
+    crate::services::memory_service::MEMORY_SERVICE.holder().is_some()
+        || crate::services::file_system_service::FILESYSTEM_SERVICE.holder().is_some()
+}
+
+/// Exit code given to processes killed by the watchdog
+pub const WATCHDOG_EXIT_CODE: i32 = -9;
+
+struct Watchdog {
+    threshold: u64, // Ticks allowed without a context switch; 0 = disabled
+    action: WatchdogAction,
+    ticks: u64, // Ticks the current process has run since it was switched in
 }
 
 impl ProcessService {
@@ -20,6 +110,21 @@ impl ProcessService {
             processes: BTreeMap::new(),
             current_process: None,
             next_pid: 1,
+            sched_credit: BTreeMap::new(),
+            watchdog: Watchdog {
+                threshold: 0,
+                action: WatchdogAction::Preempt,
+                ticks: 0,
+            },
+            sleepers: TimerWheel::new(),
+            observers: Vec::new(),
+            slice_ticks: 0,
+            clock: 0,
+            responsiveness: BTreeMap::new(),
+            exit_statuses: VecDeque::new(),
+            injected_switch_failures: 0,
+            max_processes: DEFAULT_MAX_PROCESSES,
+            futex_waiters: BTreeMap::new(),
         }
     }
 
@@ -29,28 +134,82 @@ impl ProcessService {
         let kernel_pcb = ProcessControlBlock {
             pid: 0,
             parent_pid: None,
+            pgid: 0,
             name: String::from("kernel"),
             state: ProcessState::Running,
+            blocked_on: None,
+            waiting_for: None,
+            wait_status: None,
             priority: ProcessPriority::Critical,
+            nice: 0,
             registers: crate::process::pcb::CpuRegisters::default(),
             stack_pointer: x86_64::VirtAddr::new(0xFFFF_8000_0000_0000),
             stack_size: 0x10000,
-            heap_start: x86_64::VirtAddr::new(0x1000_0000),
-            heap_size: 0x1000000,
+            heap_start: heap_window(0),
+            heap_size: 0, // The kernel allocates from its own heap
             page_table: None,
             capabilities: Vec::new(),
-            open_files: Vec::new(),
+            open_files: standard_fds(),
             working_directory: String::from("/"),
             exit_code: None,
             creation_time: 0,
             cpu_time: 0,
+            total_wait_time: 0,
+            exit_time: None,
             memory_usage: 0x10000,
+            affinity_mask: ALL_CPUS,
+            user_stack_bottom: crate::userspace::USER_STACK_TOP,
+            image_pages: Vec::new(),
         };
 
         self.processes.insert(0, kernel_pcb);
         self.current_process = Some(0);
         
-        crate::println!("Process service initialized with kernel process (PID 0)");
+        crate::log::info!("Process service initialized with kernel process (PID 0)");
+    }
+
+    /// Drop every process and start over with just the kernel process, as after boot.
+    /// Pids keep counting up, as with `take`.
+    pub fn reset(&mut self) {
+        self.take();
+    }
+
+    /// Hand over every process and start again with just the kernel process.
+    /// Pids keep counting up, so processes created since never land in the heap windows
+    /// of the taken ones.
+    pub fn take(&mut self) -> Self {
+        let next_pid = self.next_pid;
+        let taken = core::mem::replace(self, Self::new());
+        self.next_pid = next_pid;
+        self.init();
+        taken
+    }
+
+    /// Unmap and free the heap, stack and image pages of every process but the kernel,
+    /// for a table about to be dropped. Returns the number of pages released.
+    pub fn release_user_pages(&self) -> usize {
+        let mut released = 0;
+        for pcb in self.processes.values().filter(|pcb| pcb.pid != 0) {
+            let stack_top = VirtAddr::new(crate::userspace::user_stack_top(pcb.pid));
+            released += crate::memory::unmap_range(VirtAddr::new(pcb.user_stack_bottom), stack_top);
+            let heap_end = (pcb.heap_start + pcb.heap_size as u64).align_up(4096u64);
+            released += crate::memory::unmap_range(pcb.heap_start, heap_end);
+            for &(start, end) in &pcb.image_pages {
+                released += crate::memory::unmap_range(VirtAddr::new(start), VirtAddr::new(end));
+            }
+        }
+        released
+    }
+
+    /// Register `observer` to be told about every process event from now on
+    pub fn on_process_event(&mut self, observer: ProcessObserver) {
+        self.observers.push(observer);
+    }
+
+    fn notify(&self, event: ProcessEvent) {
+        for observer in &self.observers {
+            observer(event);
+        }
     }
 
     /// Create a new process
@@ -61,100 +220,649 @@ impl ProcessService {
         stack_size: usize,
         heap_size: usize,
     ) -> Result<ProcessId, ProcessError> {
-        let pid = self.next_pid;
-        self.next_pid += 1;
+        // Zombies hold their PCB until reaped, so they count too
+        if self.processes.len() >= self.max_processes {
+            return Err(ProcessError::ProcessLimitReached);
+        }
+        if heap_size > MAX_HEAP_SIZE {
+            return Err(ProcessError::InsufficientMemory);
+        }
+        let pid = allocate_pid(self.next_pid, &self.processes)?;
+        self.next_pid = pid_after(pid);
+
+        // Children join their parent's process group by default
+        let pgid = self.current_process
+            .and_then(|parent| self.processes.get(&parent))
+            .map(|parent| parent.pgid)
+            .unwrap_or(pid);
 
         let pcb = ProcessControlBlock {
             pid,
             parent_pid: self.current_process,
+            pgid,
             name: name.clone(),
             state: ProcessState::Ready,
+            blocked_on: None,
+            waiting_for: None,
+            wait_status: None,
             priority,
+            nice: 0,
             registers: crate::process::pcb::CpuRegisters::default(),
-            stack_pointer: x86_64::VirtAddr::new(0x7FFF_FFFF_F000 - (pid as u64 * stack_size as u64)),
+            stack_pointer: x86_64::VirtAddr::new(crate::userspace::user_stack_top(pid)),
             stack_size,
-            heap_start: x86_64::VirtAddr::new(0x1000_0000 + (pid as u64 * heap_size as u64)),
+            heap_start: heap_window(pid),
             heap_size,
             page_table: None,
             capabilities: Vec::new(),
-            open_files: Vec::new(),
+            open_files: standard_fds(),
             working_directory: String::from("/"),
             exit_code: None,
-            creation_time: 0, // System time
+            creation_time: self.clock,
             cpu_time: 0,
+            total_wait_time: 0,
+            exit_time: None,
             memory_usage: stack_size + heap_size,
+            affinity_mask: ALL_CPUS,
+            user_stack_bottom: crate::userspace::user_stack_top(pid),
+            image_pages: Vec::new(),
         };
 
         self.processes.insert(pid, pcb);
-        crate::println!("Created process '{}' with PID {}", name, pid);
+        crate::log::debug!("Created process '{}' with PID {}", name, pid);
+        self.notify(ProcessEvent::Created(pid));
         Ok(pid)
     }
 
-    /// Terminate a process
+    /// Refuse to create processes while `max` or more are in the table, unreaped zombies
+    /// included. Processes already over the limit are left running.
+    pub fn set_max_processes(&mut self, max: usize) {
+        self.max_processes = max;
+    }
+
+    pub fn max_processes(&self) -> usize {
+        self.max_processes
+    }
+
+    /// Processes that have not exited; zombies waiting to be reaped do not count
+    pub fn live_process_count(&self) -> usize {
+        self.processes
+            .values()
+            .filter(|pcb| !matches!(pcb.state, ProcessState::Terminated | ProcessState::Zombie))
+            .count()
+    }
+
+    /// Create a process for each `(name, priority, stack_size, heap_size)` spec, returning
+    /// one result per spec in order. A failed spec does not stop the rest.
+    pub fn create_processes(
+        &mut self,
+        specs: &[(String, ProcessPriority, usize, usize)],
+    ) -> Vec<Result<ProcessId, ProcessError>> {
+        specs
+            .iter()
+            .map(|(name, priority, stack_size, heap_size)| {
+                self.create_process(name.clone(), *priority, *stack_size, *heap_size)
+            })
+            .collect()
+    }
+
+    /// Terminate a process.
+    ///
+    /// The process stays a `Zombie` holding its exit code until its parent reaps it
+    /// with `wait_child`; zombies orphaned by this exit are reaped immediately.
     pub fn terminate_process(&mut self, pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
+        let fs_roots = self.writable_fs_roots(pid);
         if let Some(pcb) = self.processes.get_mut(&pid) {
-            pcb.state = ProcessState::Terminated;
+            pcb.state = ProcessState::Zombie;
             pcb.exit_code = Some(exit_code);
+            pcb.exit_time = Some(self.clock);
+            self.sched_credit.remove(&pid);
+            self.sleepers.retain(|&sleeper| sleeper != pid);
+            self.futex_waiters.retain(|_, waiters| {
+                waiters.retain(|&waiter| waiter != pid);
+                !waiters.is_empty()
+            });
+
+            // Release any memory regions, shared segments and file mappings the process still holds
+            let freed = crate::services::memory_service::free_all_owned_by(pid)
+                + crate::services::memory_service::release_mappings_of(pid, fs_roots);
+            crate::services::file_system_service::FILESYSTEM_SERVICE.lock().remove_watcher(pid);
+            pcb.memory_usage = pcb.memory_usage.saturating_sub(freed);
             
             // If this was the current process, clear it
-            if self.current_process == Some(pid) {
-                self.current_process = None;
-            }
+            self.park_if_current(pid, SchedReason::Exit);
             
-            crate::println!("Terminated process PID {} with exit code {}", pid, exit_code);
+            crate::log::debug!("Terminated process PID {} with exit code {}", pid, exit_code);
+            self.notify(ProcessEvent::Terminated(pid));
+            self.notify_waiting_parent(pid);
+            self.reap_orphaned_zombies();
+            Ok(())
+        } else {
+            Err(ProcessError::ProcessNotFound)
+        }
+    }
+
+    /// Reap a zombie child of `parent`, removing its PCB.
+    ///
+    /// `child` selects a specific child; `None` takes any zombie child. Returns the
+    /// reaped pid and exit code, or `Ok(None)` if matching children exist but none
+    /// has exited yet.
+    pub fn wait_child(
+        &mut self,
+        parent: ProcessId,
+        child: Option<ProcessId>,
+    ) -> Result<Option<(ProcessId, i32)>, ProcessError> {
+        let children: Vec<(ProcessId, ProcessState)> = self.processes
+            .values()
+            .filter(|pcb| pcb.parent_pid == Some(parent) && child.is_none_or(|c| c == pcb.pid))
+            .map(|pcb| (pcb.pid, pcb.state))
+            .collect();
+        if children.is_empty() {
+            return Err(ProcessError::ProcessNotFound);
+        }
+
+        match children.iter().find(|(_, state)| *state == ProcessState::Zombie) {
+            Some(&(pid, _)) => Ok(self.reap(pid).map(|code| (pid, code))),
+            None => Ok(None),
+        }
+    }
+
+    /// Wait for a child of `parent` to exit.
+    ///
+    /// Returns the child's pid and exit code at once if one has already exited.
+    /// Otherwise `parent` is blocked until a matching child exits; that child is then
+    /// reaped for it and the result is collected with `take_wait_status`.
+    pub fn wait(
+        &mut self,
+        parent: ProcessId,
+        child: Option<ProcessId>,
+    ) -> Result<Option<(ProcessId, i32)>, ProcessError> {
+        if let Some(status) = self.wait_child(parent, child)? {
+            return Ok(Some(status));
+        }
+
+        let pcb = self.processes.get_mut(&parent).ok_or(ProcessError::ProcessNotFound)?;
+        pcb.state = ProcessState::Blocked;
+        pcb.waiting_for = Some(child.map_or(WaitTarget::AnyChild, WaitTarget::Child));
+        pcb.wait_status = None;
+        self.park_if_current(parent, SchedReason::Block);
+        self.notify(ProcessEvent::Blocked(parent));
+        Ok(None)
+    }
+
+    /// Collect the child reaped while `pid` was blocked in `wait`
+    pub fn take_wait_status(&mut self, pid: ProcessId) -> Option<(ProcessId, i32)> {
+        self.processes.get_mut(&pid)?.wait_status.take()
+    }
+
+    /// If the parent of the exited process `pid` is blocked waiting for it, reap it on
+    /// the parent's behalf and wake the parent with its exit code
+    fn notify_waiting_parent(&mut self, pid: ProcessId) {
+        let parent_pid = match self.processes.get(&pid).and_then(|pcb| pcb.parent_pid) {
+            Some(parent_pid) => parent_pid,
+            None => return,
+        };
+        let waiting = self.processes.get(&parent_pid).is_some_and(|parent| {
+            parent.state == ProcessState::Blocked && parent.waiting_for.is_some_and(|target| target.matches(pid))
+        });
+        if !waiting {
+            return;
+        }
+
+        if let Some(exit_code) = self.reap(pid) {
+            if let Some(parent) = self.processes.get_mut(&parent_pid) {
+                parent.waiting_for = None;
+                parent.wait_status = Some((pid, exit_code));
+                parent.state = ProcessState::Ready;
+                self.notify(ProcessEvent::Unblocked(parent_pid));
+            }
+        }
+    }
+
+    /// Reap zombies whose parent has itself exited (or never existed).
+    /// Returns the number of processes removed.
+    pub fn reap_orphaned_zombies(&mut self) -> usize {
+        let orphans: Vec<ProcessId> = self.processes
+            .values()
+            .filter(|pcb| pcb.state == ProcessState::Zombie)
+            .filter(|pcb| {
+                pcb.parent_pid
+                    .and_then(|parent| self.processes.get(&parent))
+                    .is_none_or(|parent| matches!(parent.state, ProcessState::Zombie | ProcessState::Terminated))
+            })
+            .map(|pcb| pcb.pid)
+            .collect();
+
+        for &pid in &orphans {
+            self.reap(pid);
+        }
+        orphans.len()
+    }
+
+    /// Remove a process's PCB entirely, returning its exit code
+    fn reap(&mut self, pid: ProcessId) -> Option<i32> {
+        let pcb = self.processes.remove(&pid)?;
+        self.sched_credit.remove(&pid);
+        self.responsiveness.remove(&pid);
+        crate::log::debug!("Reaped process PID {}", pid);
+
+        let exit_code = pcb.exit_code.unwrap_or(0);
+        if self.exit_statuses.len() == EXIT_STATUS_CACHE_SIZE {
+            self.exit_statuses.pop_front();
+        }
+        self.exit_statuses.push_back((pid, exit_code));
+        Some(exit_code)
+    }
+
+    /// Exit code of `pid`, whether it is still a zombie or was recently reaped.
+    ///
+    /// A live process with that pid takes precedence over a cached code from an
+    /// earlier process that used the same pid.
+    pub fn get_exit_status(&self, pid: ProcessId) -> Option<i32> {
+        match self.processes.get(&pid) {
+            Some(pcb) => pcb.exit_code,
+            None => self.exit_statuses
+                .iter()
+                .rev()
+                .find(|&&(reaped, _)| reaped == pid)
+                .map(|&(_, code)| code),
+        }
+    }
+
+    /// Replace a process image with a new ELF executable, keeping its PID,
+    /// parent, and open files.
+    ///
+    /// Every page the new PT_LOAD segments need is mapped before the old image is touched:
+    /// segments outside the user half, or over pages mapped by anything but the old image,
+    /// are rejected, and running out of frames unmaps what was mapped so far, so a failed
+    /// exec leaves the old image in place. Then the old image's remaining pages are
+    /// unmapped, and each segment is filled from the file, zeroed past its file size and
+    /// given the page permissions its flags ask for.
+    pub fn exec(&mut self, pid: ProcessId, elf: &[u8]) -> Result<(), ProcessError> {
+        use alloc::collections::BTreeSet;
+        use x86_64::structures::paging::PageTableFlags as Flags;
+
+        let image = crate::process::elf::parse(elf).map_err(|_| ProcessError::InvalidExecutable)?;
+        let spans: Vec<(u64, u64)> = image
+            .segments
+            .iter()
+            .map(|segment| {
+                let end = segment.vaddr.checked_add(segment.mem_size)?.checked_add(0xfff)? & !0xfff;
+                (end <= crate::syscalls::USER_SPACE_END).then_some((segment.vaddr & !0xfff, end))
+            })
+            .collect::<Option<_>>()
+            .ok_or(ProcessError::InvalidExecutable)?;
+
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        if matches!(pcb.state, ProcessState::Terminated | ProcessState::Zombie) {
+            return Err(ProcessError::ProcessNotFound);
+        }
+
+        let pages: BTreeSet<u64> = spans.iter().flat_map(|&(start, end)| (start..end).step_by(4096)).collect();
+        let in_old_image = |page: u64| pcb.image_pages.iter().any(|&(start, end)| (start..end).contains(&page));
+        let fresh: Vec<u64> = pages.iter().copied().filter(|&page| !in_old_image(page)).collect();
+        if fresh.iter().any(|&page| crate::memory::is_mapped(VirtAddr::new(page)) != Some(false)) {
+            return Err(ProcessError::InvalidExecutable);
+        }
+
+        // Map every segment writable while it is filled in
+        let fill_flags = Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE;
+        for (mapped, &page) in fresh.iter().enumerate() {
+            if crate::memory::map_range(VirtAddr::new(page), VirtAddr::new(page + 4096), fill_flags).is_err() {
+                for &page in &fresh[..mapped] {
+                    crate::memory::unmap_range(VirtAddr::new(page), VirtAddr::new(page + 4096));
+                }
+                return Err(ProcessError::InsufficientMemory);
+            }
+        }
+
+        // Nothing can fail from here on, so the old image can go
+        for (start, end) in core::mem::replace(&mut pcb.image_pages, spans.clone()) {
+            for page in (start..end).step_by(4096).filter(|page| !pages.contains(page)) {
+                crate::memory::unmap_range(VirtAddr::new(page), VirtAddr::new(page + 4096));
+            }
+        }
+        pcb.page_table = None;
+
+        for (segment, &(start, end)) in image.segments.iter().zip(&spans) {
+            // Pages kept from the old image, or shared with the previous segment, may be read-only
+            crate::memory::protect_range(VirtAddr::new(start), VirtAddr::new(end), fill_flags);
+
+            let file = &elf[segment.offset as usize..(segment.offset + segment.file_size) as usize];
+            let dest = segment.vaddr as *mut u8;
+            unsafe {
+                core::ptr::copy_nonoverlapping(file.as_ptr(), dest, file.len());
+                // The BSS tail
+                core::ptr::write_bytes(dest.add(file.len()), 0, (segment.mem_size - segment.file_size) as usize);
+            }
+        }
+
+        // Then drop to each page's final permissions; a page two segments share gets both
+        let mut page_access: BTreeMap<u64, (bool, bool)> = BTreeMap::new();
+        for (segment, &(start, end)) in image.segments.iter().zip(&spans) {
+            for page in (start..end).step_by(4096) {
+                let access = page_access.entry(page).or_insert((false, false));
+                access.0 |= segment.is_writable();
+                access.1 |= segment.is_executable();
+            }
+        }
+        for (page, (writable, executable)) in page_access {
+            let mut flags = Flags::PRESENT | Flags::USER_ACCESSIBLE;
+            flags.set(Flags::WRITABLE, writable);
+            flags.set(Flags::NO_EXECUTE, !executable);
+            crate::memory::protect_range(VirtAddr::new(page), VirtAddr::new(page + 4096), flags);
+        }
+
+        // Fresh register state at the new entry point
+        pcb.registers = crate::process::pcb::CpuRegisters::default();
+        pcb.registers.rip = image.entry;
+        pcb.registers.rsp = pcb.stack_pointer.as_u64();
+        pcb.memory_usage = image.memory_size() + pcb.stack_size + pcb.heap_size;
+
+        crate::log::debug!("Exec'd new image into PID {} (entry 0x{:x})", pid, image.entry);
+        Ok(())
+    }
+
+    /// Move a process into a process group
+    pub fn set_process_group(&mut self, pid: ProcessId, pgid: ProcessId) -> Result<(), ProcessError> {
+        if pgid != pid && !self.processes.contains_key(&pgid) {
+            return Err(ProcessError::InvalidProcessId);
+        }
+        if let Some(pcb) = self.processes.get_mut(&pid) {
+            pcb.pgid = pgid;
             Ok(())
         } else {
             Err(ProcessError::ProcessNotFound)
         }
     }
 
+    /// List the live members of a process group
+    pub fn list_group(&self, pgid: ProcessId) -> Vec<ProcessId> {
+        self.processes
+            .values()
+            .filter(|pcb| pcb.pgid == pgid)
+            .filter(|pcb| !matches!(pcb.state, ProcessState::Terminated | ProcessState::Zombie))
+            .map(|pcb| pcb.pid)
+            .collect()
+    }
+
+    /// Terminate every live process in `caller`'s group with `exit_code`, returning how
+    /// many were terminated. Members become zombies for their parents to reap as usual.
+    /// The kernel's group (pgid 0) cannot be torn down this way.
+    pub fn exit_group(&mut self, caller: ProcessId, exit_code: i32) -> Result<usize, ProcessError> {
+        let pgid = self.processes.get(&caller).ok_or(ProcessError::ProcessNotFound)?.pgid;
+        if pgid == 0 {
+            return Err(ProcessError::PermissionDenied);
+        }
+
+        let members = self.list_group(pgid);
+        for &pid in &members {
+            self.terminate_process(pid, exit_code)?;
+        }
+        Ok(members.len())
+    }
+
+    /// Deliver a signal. A negative `target` addresses the whole process group `-target`.
+    /// Returns the number of processes signalled.
+    pub fn send_signal(&mut self, target: i64, signal: Signal) -> Result<usize, ProcessError> {
+        let targets = if target < 0 {
+            // The kernel process is never part of a signalled group
+            let members: Vec<ProcessId> = self.list_group(target.unsigned_abs())
+                .into_iter()
+                .filter(|&pid| pid != 0)
+                .collect();
+            if members.is_empty() {
+                return Err(ProcessError::ProcessNotFound);
+            }
+            members
+        } else {
+            if !self.processes.contains_key(&(target as ProcessId)) {
+                return Err(ProcessError::ProcessNotFound);
+            }
+            if target == 0 {
+                return Err(ProcessError::PermissionDenied);
+            }
+            alloc::vec![target as ProcessId]
+        };
+
+        for &pid in &targets {
+            self.deliver_signal(pid, signal)?;
+        }
+        Ok(targets.len())
+    }
+
+    fn deliver_signal(&mut self, pid: ProcessId, signal: Signal) -> Result<(), ProcessError> {
+        match signal {
+            Signal::Interrupt | Signal::Kill | Signal::Terminate => {
+                self.terminate_process(pid, 128 + signal as i32)
+            }
+            Signal::Stop => {
+                let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+                if matches!(pcb.state, ProcessState::Ready | ProcessState::Running) {
+                    pcb.state = ProcessState::Blocked;
+                    self.park_if_current(pid, SchedReason::Block);
+                    self.notify(ProcessEvent::Blocked(pid));
+                }
+                Ok(())
+            }
+            Signal::Continue => match self.unblock_process(pid) {
+                Ok(()) | Err(ProcessError::ProcessNotBlocked) => Ok(()),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
     /// Schedule the next process to run
+    ///
+    /// Uses smooth weighted round-robin: every ready process earns credit equal to its
+    /// effective priority, the richest runs and pays back the total. Equal weights
+    /// degrade to plain round-robin; a lower nice value earns a larger share.
     pub fn schedule_next(&mut self) -> Option<ProcessId> {
+        self.schedule_next_for(SchedReason::Yield)
+    }
+
+    fn schedule_next_for(&mut self, reason: SchedReason) -> Option<ProcessId> {
         // Get ready processes
-        let ready_processes: Vec<ProcessId> = self.processes
-            .iter()
-            .filter(|(_, pcb)| pcb.state == ProcessState::Ready)
-            .map(|(pid, _)| *pid)
+        let ready_processes: Vec<(ProcessId, i64)> = self.processes
+            .values()
+            .filter(|pcb| pcb.state == ProcessState::Ready)
+            .map(|pcb| (pcb.pid, self.effective_priority_of(pcb) as i64 + 1))
             .collect();
 
         if ready_processes.is_empty() {
             return None;
         }
 
-        // Simple round-robin scheduling
-        let next_pid = if let Some(current) = self.current_process {
-            if let Some(current_idx) = ready_processes.iter().position(|&pid| pid == current) {
-                let next_idx = (current_idx + 1) % ready_processes.len();
-                ready_processes[next_idx]
-            } else {
-                ready_processes[0]
+        let total_weight: i64 = ready_processes.iter().map(|(_, weight)| weight).sum();
+        let mut candidates: Vec<(ProcessId, i64)> = ready_processes
+            .iter()
+            .map(|&(pid, weight)| {
+                let credit = self.sched_credit.entry(pid).or_insert(0);
+                *credit += weight;
+                (pid, *credit)
+            })
+            .collect();
+        // Most credit first; ties go to the lowest pid
+        candidates.sort_by_key(|&(pid, credit)| (core::cmp::Reverse(credit), pid));
+
+        // Fall back to the next candidate if switching to one fails
+        for (pid, _) in candidates {
+            if self.switch_to(pid, reason).is_some() {
+                if let Some(credit) = self.sched_credit.get_mut(&pid) {
+                    *credit -= total_weight;
+                }
+                return Some(pid);
             }
-        } else {
-            ready_processes[0]
-        };
+        }
+        None
+    }
+
+    /// Make the next `count` context switches fail, to exercise the recovery paths
+    pub fn inject_switch_failures(&mut self, count: u32) {
+        self.injected_switch_failures = count;
+    }
+
+    /// Switch directly to `target`, donating the rest of the caller's slice.
+    ///
+    /// Fails without switching if `target` does not exist or is not Ready.
+    pub fn yield_to(&mut self, target: ProcessId) -> Result<ProcessId, ProcessError> {
+        match self.processes.get(&target) {
+            None => return Err(ProcessError::ProcessNotFound),
+            Some(pcb) if pcb.state != ProcessState::Ready => return Err(ProcessError::ProcessNotReady),
+            Some(_) => {}
+        }
+
+        // Hand the caller's accumulated credit to the target
+        let donor = self.current_process;
+        let saved = donor.and_then(|current| self.sched_credit.remove(&current));
+        let donated = saved.unwrap_or(0).max(0);
+        *self.sched_credit.entry(target).or_insert(0) += donated;
+
+        if self.switch_to(target, SchedReason::Yield).is_none() {
+            // Nothing moved, so neither did the credit
+            *self.sched_credit.entry(target).or_insert(0) -= donated;
+            if let (Some(current), Some(credit)) = (donor, saved) {
+                self.sched_credit.insert(current, credit);
+            }
+            return Err(ProcessError::ContextSwitchFailed);
+        }
+        Ok(target)
+    }
 
-        // Update process states
-        if let Some(pcb) = self.processes.get_mut(&next_pid) {
-            pcb.state = ProcessState::Running;
+    /// Priority the scheduler weighs `pcb` by: its own effective priority, plus
+    /// `RESPONSIVE_BONUS` while it keeps blocking early, without leaving its band
+    fn effective_priority_of(&self, pcb: &ProcessControlBlock) -> u32 {
+        let base = pcb.effective_priority();
+        if self.responsiveness.get(&pcb.pid).is_some_and(|r| r.responsive) {
+            let band_top = (pcb.priority as u32) * 40 + 39;
+            (base + RESPONSIVE_BONUS).min(band_top.max(base))
+        } else {
+            base
         }
+    }
+
+    pub fn effective_priority(&self, pid: ProcessId) -> Option<u32> {
+        self.processes.get(&pid).map(|pcb| self.effective_priority_of(pcb))
+    }
+
+    /// Times `pid` blocked within the first tick of its slice
+    pub fn blocked_early_count(&self, pid: ProcessId) -> u64 {
+        self.responsiveness.get(&pid).map_or(0, |r| r.blocked_early)
+    }
+
+    /// Make `next_pid` the running process.
+    ///
+    /// If the context switch fails, both processes are put back as they were and `None`
+    /// is returned.
+    fn switch_to(&mut self, next_pid: ProcessId, reason: SchedReason) -> Option<ProcessId> {
+        // The outgoing process goes back to the ready set
+        let outgoing = self.current_process.filter(|current| {
+            self.processes.get(current).is_some_and(|pcb| pcb.state == ProcessState::Running)
+        });
+        self.set_state(outgoing, ProcessState::Ready);
+        self.set_state(Some(next_pid), ProcessState::Running);
 
         // Perform context switch
-        if let Err(e) = context_switch(self.current_process, next_pid, &mut self.processes) {
-            crate::println!("Context switch failed: {:?}", e);
+        let result = if self.injected_switch_failures > 0 {
+            self.injected_switch_failures -= 1;
+            Err(ProcessError::ContextSwitchFailed)
+        } else {
+            sched_trace::set_next_reason(reason);
+            context_switch(self.current_process, next_pid, &mut self.processes)
+        };
+        if let Err(e) = result {
+            crate::log::warn!("Context switch failed: {:?}", e);
+            self.set_state(Some(next_pid), ProcessState::Ready);
+            self.set_state(outgoing, ProcessState::Running);
             return None;
         }
 
+        // It ran until switched out rather than blocking early
+        if let Some(current) = outgoing {
+            if self.slice_ticks > 0 {
+                self.responsiveness.entry(current).or_default().responsive = false;
+            }
+        }
+
         self.current_process = Some(next_pid);
+        self.watchdog.ticks = 0;
+        self.slice_ticks = 0;
         Some(next_pid)
     }
 
+    fn set_state(&mut self, pid: Option<ProcessId>, state: ProcessState) {
+        if let Some(pcb) = pid.and_then(|pid| self.processes.get_mut(&pid)) {
+            pcb.state = state;
+        }
+    }
+
+    /// Take `pid` off the CPU if it is the running process
+    fn park_if_current(&mut self, pid: ProcessId, reason: SchedReason) {
+        if self.current_process == Some(pid) {
+            if reason == SchedReason::Block {
+                let record = self.responsiveness.entry(pid).or_default();
+                record.responsive = self.slice_ticks == 0;
+                if record.responsive {
+                    record.blocked_early += 1;
+                }
+            }
+            sched_trace::record_park(pid, reason);
+            self.current_process = None;
+        }
+    }
+
+    /// Disarm the watchdog with a threshold of 0
+    pub fn set_watchdog_threshold(&mut self, ticks: u64) {
+        self.watchdog.threshold = ticks;
+        self.watchdog.ticks = 0;
+    }
+
+    pub fn set_watchdog_action(&mut self, action: WatchdogAction) {
+        self.watchdog.action = action;
+    }
+
+    /// Count a timer tick against the current process.
+    ///
+    /// Once the process has run for more than the threshold without a context switch it
+    /// is preempted, or terminated in strict mode. Returns the pid acted on. The kernel
+    /// process (PID 0) runs the idle loop and is never counted, so it is never killed.
+    pub fn watchdog_tick(&mut self) -> Option<ProcessId> {
+        let pid = self.current_process?;
+        if pid == 0 || self.watchdog.threshold == 0 {
+            return None;
+        }
+        self.watchdog.ticks += 1;
+        if self.watchdog.ticks <= self.watchdog.threshold {
+            return None;
+        }
+
+        match self.watchdog.action {
+            WatchdogAction::Preempt => {
+                crate::log::warn!("Watchdog: preempting PID {} after {} ticks", pid, self.watchdog.ticks);
+            }
+            WatchdogAction::Terminate => {
+                // If the tick interrupted a holder of a lock termination takes, try again
+                // on the next tick
+                if termination_locks_held() {
+                    return None;
+                }
+                crate::log::warn!("Watchdog: terminating PID {} after {} ticks", pid, self.watchdog.ticks);
+                let _ = self.terminate_process(pid, WATCHDOG_EXIT_CODE);
+            }
+        }
+
+        self.watchdog.ticks = 0;
+        self.schedule_next_for(SchedReason::Preempt);
+        Some(pid)
+    }
+
     /// Block the current process
     pub fn block_current_process(&mut self) -> Result<(), ProcessError> {
         if let Some(pid) = self.current_process {
             if let Some(pcb) = self.processes.get_mut(&pid) {
                 pcb.state = ProcessState::Blocked;
-                self.current_process = None;
-                crate::println!("Blocked process PID {}", pid);
+                self.park_if_current(pid, SchedReason::Block);
+                crate::log::debug!("Blocked process PID {}", pid);
+                self.notify(ProcessEvent::Blocked(pid));
                 Ok(())
             } else {
                 Err(ProcessError::ProcessNotFound)
@@ -164,12 +872,110 @@ impl ProcessService {
         }
     }
 
+    /// Block a process until something unblocks it
+    pub fn block_process(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        pcb.state = ProcessState::Blocked;
+        self.park_if_current(pid, SchedReason::Block);
+        self.notify(ProcessEvent::Blocked(pid));
+        Ok(())
+    }
+
+    /// Block a process until `holder` releases the resource it is waiting for
+    pub fn block_on(&mut self, pid: ProcessId, holder: ProcessId) -> Result<(), ProcessError> {
+        if !self.processes.contains_key(&holder) {
+            return Err(ProcessError::ProcessNotFound);
+        }
+        if let Some(pcb) = self.processes.get_mut(&pid) {
+            pcb.state = ProcessState::Blocked;
+            pcb.blocked_on = Some(holder);
+            self.park_if_current(pid, SchedReason::Block);
+            crate::log::debug!("Blocked process PID {} on PID {}", pid, holder);
+            self.notify(ProcessEvent::Blocked(pid));
+            Ok(())
+        } else {
+            Err(ProcessError::ProcessNotFound)
+        }
+    }
+
+    /// Block a process until the tick count reaches `deadline`
+    pub fn sleep_until(&mut self, pid: ProcessId, deadline: u64) -> Result<(), ProcessError> {
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        pcb.state = ProcessState::Blocked;
+        self.park_if_current(pid, SchedReason::Block);
+        self.notify(ProcessEvent::Blocked(pid));
+
+        // Equal deadlines wake in the order they went to sleep
+        self.sleepers.insert(deadline, pid);
+        Ok(())
+    }
+
+    /// Make every sleeper whose deadline is at or before `now` Ready again.
+    /// Returns the number of processes woken.
+    pub fn wake_sleepers(&mut self, now: u64) -> usize {
+        let mut woken = Vec::new();
+        for pid in self.sleepers.advance(now) {
+            if let Some(pcb) = self.processes.get_mut(&pid) {
+                if pcb.state == ProcessState::Blocked {
+                    pcb.state = ProcessState::Ready;
+                    woken.push(pid);
+                }
+            }
+        }
+        for &pid in &woken {
+            self.notify(ProcessEvent::Unblocked(pid));
+        }
+        woken.len()
+    }
+
+    /// Block `pid` on the futex named `key` if `still_expected()` holds, returning whether
+    /// it blocked. The check runs under the service lock, so a `futex_wake` cannot land
+    /// between it and the block and leave the process waiting for a wake already sent.
+    pub fn futex_wait(
+        &mut self,
+        pid: ProcessId,
+        key: u64,
+        still_expected: impl FnOnce() -> bool,
+    ) -> Result<bool, ProcessError> {
+        if !self.processes.contains_key(&pid) {
+            return Err(ProcessError::ProcessNotFound);
+        }
+        if !still_expected() {
+            return Ok(false);
+        }
+        self.block_process(pid)?;
+        self.futex_waiters.entry(key).or_default().push_back(pid);
+        Ok(true)
+    }
+
+    /// Make up to `count` processes waiting on futex `key` Ready again, longest waiting
+    /// first. Returns the number woken.
+    pub fn futex_wake(&mut self, key: u64, count: usize) -> usize {
+        let mut woken = 0;
+        while woken < count {
+            let pid = match self.futex_waiters.get_mut(&key).and_then(VecDeque::pop_front) {
+                Some(pid) => pid,
+                None => break,
+            };
+            if self.unblock_process(pid).is_ok() {
+                woken += 1;
+            }
+        }
+        if self.futex_waiters.get(&key).is_some_and(VecDeque::is_empty) {
+            self.futex_waiters.remove(&key);
+        }
+        woken
+    }
+
     /// Unblock a process
     pub fn unblock_process(&mut self, pid: ProcessId) -> Result<(), ProcessError> {
         if let Some(pcb) = self.processes.get_mut(&pid) {
             if pcb.state == ProcessState::Blocked {
                 pcb.state = ProcessState::Ready;
-                crate::println!("Unblocked process PID {}", pid);
+                pcb.blocked_on = None;
+                pcb.waiting_for = None;
+                crate::log::debug!("Unblocked process PID {}", pid);
+                self.notify(ProcessEvent::Unblocked(pid));
                 Ok(())
             } else {
                 Err(ProcessError::ProcessNotBlocked)
@@ -179,6 +985,33 @@ impl ProcessService {
         }
     }
 
+    /// Look for a circular wait in the wait-for graph.
+    ///
+    /// Each blocked process waits on at most one holder, so every node has at most one
+    /// outgoing edge and a cycle is found by walking the chain from each blocked process.
+    /// Returns the processes forming the cycle, starting from the lowest PID.
+    pub fn detect_deadlock(&self) -> Option<Vec<ProcessId>> {
+        let waits_on = |pid: ProcessId| {
+            self.processes
+                .get(&pid)
+                .filter(|pcb| pcb.state == ProcessState::Blocked)
+                .and_then(|pcb| pcb.blocked_on)
+        };
+
+        for &start in self.processes.keys() {
+            let mut chain: Vec<ProcessId> = Vec::new();
+            let mut pid = start;
+            while let Some(holder) = waits_on(pid) {
+                chain.push(pid);
+                if let Some(pos) = chain.iter().position(|&p| p == holder) {
+                    return Some(chain.split_off(pos));
+                }
+                pid = holder;
+            }
+        }
+        None
+    }
+
     /// Get process information
     pub fn get_process(&self, pid: ProcessId) -> Option<&ProcessControlBlock> {
         self.processes.get(&pid)
@@ -209,41 +1042,299 @@ impl ProcessService {
         }
     }
 
-    /// Set process priority
-    pub fn set_priority(&mut self, pid: ProcessId, priority: ProcessPriority) -> Result<(), ProcessError> {
+    /// Get a process's working directory
+    pub fn get_working_directory(&self, pid: ProcessId) -> Option<String> {
+        self.processes.get(&pid).map(|pcb| pcb.working_directory.clone())
+    }
+
+    /// Set a process's working directory
+    pub fn set_working_directory(&mut self, pid: ProcessId, path: String) -> Result<(), ProcessError> {
         if let Some(pcb) = self.processes.get_mut(&pid) {
-            pcb.priority = priority;
-            crate::println!("Set priority for PID {} to {:?}", pid, priority);
+            pcb.working_directory = path;
             Ok(())
         } else {
             Err(ProcessError::ProcessNotFound)
         }
     }
 
-    /// Get process statistics
-    pub fn get_process_stats(&self, pid: ProcessId) -> Option<ProcessStats> {
-        if let Some(pcb) = self.processes.get(&pid) {
-            Some(ProcessStats {
-                pid: pcb.pid,
-                name: pcb.name.clone(),
-                state: pcb.state,
-                priority: pcb.priority,
-                cpu_time: pcb.cpu_time,
-                memory_usage: pcb.memory_usage,
-                creation_time: pcb.creation_time,
-            })
-        } else {
-            None
-        }
+    /// Install `file` in the lowest free descriptor above the standard streams
+    pub fn open_fd(&mut self, pid: ProcessId, file: OpenFile) -> Result<FileDescriptor, ProcessError> {
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        let fd = (STDERR_FD + 1..)
+            .find(|fd| !pcb.open_files.contains_key(fd))
+            .unwrap();
+        pcb.open_files.insert(fd, file);
+        Ok(fd)
     }
 
-    /// Get system statistics
-    pub fn get_system_stats(&self) -> SystemStats {
-        let total_processes = self.processes.len();
-        let running_processes = self.processes.values().filter(|pcb| pcb.state == ProcessState::Running).count();
+    /// Close a file descriptor
+    pub fn close_fd(&mut self, pid: ProcessId, fd: FileDescriptor) -> Result<(), ProcessError> {
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        pcb.open_files.remove(&fd).map(|_| ()).ok_or(ProcessError::BadFileDescriptor)
+    }
+
+    /// Duplicate a file descriptor onto a new descriptor number
+    pub fn dup_fd(&mut self, pid: ProcessId, fd: FileDescriptor) -> Result<FileDescriptor, ProcessError> {
+        let file = self.get_fd(pid, fd)?;
+        self.open_fd(pid, file)
+    }
+
+    /// Look up what a file descriptor refers to
+    pub fn get_fd(&self, pid: ProcessId, fd: FileDescriptor) -> Result<OpenFile, ProcessError> {
+        let pcb = self.processes.get(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        pcb.open_files.get(&fd).copied().ok_or(ProcessError::BadFileDescriptor)
+    }
+
+    /// Move a process's heap break by `increment` bytes, returning the previous break.
+    ///
+    /// Growth maps zeroed user pages up to the new break; shrinking unmaps pages
+    /// wholly above it. The heap may not shrink below its start or grow into the stack,
+    /// and may not grow past `MAX_HEAP_SIZE`, the end of the process's own heap window.
+    pub fn sbrk(&mut self, pid: ProcessId, increment: isize) -> Result<VirtAddr, ProcessError> {
+        use x86_64::structures::paging::PageTableFlags as Flags;
+
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        let old_break = pcb.heap_start + pcb.heap_size as u64;
+        let new_size = pcb.heap_size.checked_add_signed(increment).ok_or(ProcessError::InvalidBreak)?;
+        let new_break = pcb.heap_start.as_u64()
+            .checked_add(new_size as u64)
+            .and_then(|addr| VirtAddr::try_new(addr).ok())
+            .ok_or(ProcessError::InvalidBreak)?;
+
+        if increment > 0 {
+            let stack_bottom = pcb.stack_pointer.as_u64().saturating_sub(pcb.stack_size as u64);
+            if new_break.as_u64() > stack_bottom {
+                return Err(ProcessError::InvalidBreak);
+            }
+            if new_size > MAX_HEAP_SIZE {
+                return Err(ProcessError::InsufficientMemory);
+            }
+            let flags = Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE | Flags::NO_EXECUTE;
+            let pages = crate::memory::map_range(old_break, new_break, flags)
+                .map_err(|_| ProcessError::InsufficientMemory)?;
+            pcb.memory_usage += pages * 4096;
+        } else if increment < 0 {
+            let pages = crate::memory::unmap_range(new_break, old_break.align_up(4096u64));
+            pcb.memory_usage = pcb.memory_usage.saturating_sub(pages * 4096);
+        }
+
+        pcb.heap_size = new_size;
+        Ok(old_break)
+    }
+
+    /// Adjust a process's memory usage by `delta` bytes
+    pub fn adjust_memory_usage(&mut self, pid: ProcessId, delta: isize) {
+        if let Some(pcb) = self.processes.get_mut(&pid) {
+            pcb.memory_usage = pcb.memory_usage.saturating_add_signed(delta);
+        }
+    }
+
+    /// Lowest page of the user stack `pid` has faulted in
+    pub fn user_stack_bottom(&self, pid: ProcessId) -> Option<u64> {
+        self.processes.get(&pid).map(|pcb| pcb.user_stack_bottom)
+    }
+
+    /// Record that `pid`'s stack now reaches down to `page`, charging it `pages` new pages
+    pub fn record_stack_growth(&mut self, pid: ProcessId, page: u64, pages: usize) {
+        if let Some(pcb) = self.processes.get_mut(&pid) {
+            pcb.user_stack_bottom = pcb.user_stack_bottom.min(page);
+            pcb.memory_usage = pcb.memory_usage.saturating_add(pages * 4096);
+        }
+    }
+
+    /// Set process priority
+    pub fn set_priority(&mut self, pid: ProcessId, priority: ProcessPriority) -> Result<(), ProcessError> {
+        if let Some(pcb) = self.processes.get_mut(&pid) {
+            pcb.priority = priority;
+            crate::log::debug!("Set priority for PID {} to {:?}", pid, priority);
+            Ok(())
+        } else {
+            Err(ProcessError::ProcessNotFound)
+        }
+    }
+
+    /// Whether `pid` may act on other processes: the kernel process, or any process
+    /// holding an admin capability on the system
+    pub fn is_privileged(&self, pid: ProcessId) -> bool {
+        let admin = CapabilityPermissions { read: false, write: false, execute: false, admin: true };
+        pid == 0 || self.has_capability(pid, ResourceType::System, 0, admin)
+    }
+
+    /// Set `target`'s priority on behalf of `caller`.
+    ///
+    /// Privileged callers may set any priority on any process; everyone else may only
+    /// lower their own.
+    pub fn change_priority(
+        &mut self,
+        caller: ProcessId,
+        target: ProcessId,
+        priority: ProcessPriority,
+    ) -> Result<(), ProcessError> {
+        let current = self.processes.get(&target).ok_or(ProcessError::ProcessNotFound)?.priority;
+        if !self.is_privileged(caller) && (caller != target || priority > current) {
+            return Err(ProcessError::PermissionDenied);
+        }
+        self.set_priority(target, priority)
+    }
+
+    /// Set a process's nice value, clamped to `NICE_MIN..=NICE_MAX`.
+    /// Returns the value actually applied.
+    pub fn set_nice(&mut self, pid: ProcessId, nice: i8) -> Result<i8, ProcessError> {
+        use crate::process::pcb::{NICE_MAX, NICE_MIN};
+
+        if let Some(pcb) = self.processes.get_mut(&pid) {
+            pcb.nice = nice.clamp(NICE_MIN, NICE_MAX);
+            Ok(pcb.nice)
+        } else {
+            Err(ProcessError::ProcessNotFound)
+        }
+    }
+
+    /// Restrict `pid` to the logical CPUs whose bits are set in `mask`. A mask naming no
+    /// CPU would leave the process unschedulable, so it is refused.
+    pub fn set_affinity(&mut self, pid: ProcessId, mask: u64) -> Result<(), ProcessError> {
+        if mask == 0 {
+            return Err(ProcessError::InvalidAffinity);
+        }
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        pcb.affinity_mask = mask;
+        Ok(())
+    }
+
+    /// Give `pid` a capability, returning its index in the process's table
+    pub fn grant_capability(&mut self, pid: ProcessId, capability: Capability) -> Result<usize, ProcessError> {
+        let pcb = self.processes.get_mut(&pid).ok_or(ProcessError::ProcessNotFound)?;
+        pcb.capabilities.push(capability);
+        Ok(pcb.capabilities.len() - 1)
+    }
+
+    /// Copy capability `index` of `from` to `to`, restricted to `new_perms`.
+    ///
+    /// Delegation can only narrow rights: `new_perms` must be a subset of what `from`
+    /// holds. The copy is appended to the receiver's table.
+    pub fn delegate_capability(
+        &mut self,
+        from: ProcessId,
+        to: ProcessId,
+        index: usize,
+        new_perms: CapabilityPermissions,
+    ) -> Result<(), ProcessError> {
+        let source = self.processes.get(&from).ok_or(ProcessError::ProcessNotFound)?;
+        let capability = source.capabilities.get(index).ok_or(ProcessError::InvalidCapability)?;
+        if !new_perms.is_subset_of(&capability.permissions) {
+            return Err(ProcessError::PermissionDenied);
+        }
+
+        let delegated = Capability { permissions: new_perms, ..capability.clone() };
+        self.grant_capability(to, delegated).map(|_| ())
+    }
+
+    /// Give `pid` `perms` on the filesystem subtree rooted at the directory `root_cluster`
+    pub fn grant_fs_capability(
+        &mut self,
+        pid: ProcessId,
+        root_cluster: u64,
+        perms: CapabilityPermissions,
+    ) -> Result<usize, ProcessError> {
+        self.grant_capability(pid, Capability { resource_type: ResourceType::File, resource_id: root_cluster, permissions: perms })
+    }
+
+    /// Drop every filesystem capability rooted at `cluster`, which has been deleted
+    pub fn revoke_fs_capabilities(&mut self, cluster: u64) {
+        for pcb in self.processes.values_mut() {
+            pcb.capabilities.retain(|cap| !(cap.resource_type == ResourceType::File && cap.resource_id == cluster));
+        }
+    }
+
+    /// Roots of the filesystem subtrees `pid` holds write capabilities for, or `None` if
+    /// it is privileged and may write anywhere
+    pub fn writable_fs_roots(&self, pid: ProcessId) -> Option<Vec<u64>> {
+        if self.is_privileged(pid) {
+            return None;
+        }
+        let capabilities = self.processes.get(&pid).map_or(&[][..], |pcb| &pcb.capabilities[..]);
+        Some(capabilities.iter()
+            .filter(|cap| cap.resource_type == ResourceType::File && cap.permissions.write)
+            .map(|cap| cap.resource_id)
+            .collect())
+    }
+
+    /// Whether `pid` holds a capability on the resource granting every right in `needed`
+    pub fn has_capability(
+        &self,
+        pid: ProcessId,
+        resource_type: ResourceType,
+        resource_id: u64,
+        needed: CapabilityPermissions,
+    ) -> bool {
+        self.processes.get(&pid).is_some_and(|pcb| {
+            pcb.capabilities.iter().any(|cap| {
+                cap.resource_type == resource_type
+                    && cap.resource_id == resource_id
+                    && needed.is_subset_of(&cap.permissions)
+            })
+        })
+    }
+
+    /// Get process statistics
+    pub fn get_process_stats(&self, pid: ProcessId) -> Option<ProcessStats> {
+        if let Some(pcb) = self.processes.get(&pid) {
+            Some(ProcessStats {
+                pid: pcb.pid,
+                name: pcb.name.clone(),
+                state: pcb.state,
+                priority: pcb.priority,
+                cpu_time: pcb.cpu_time,
+                memory_usage: pcb.memory_usage,
+                creation_time: pcb.creation_time,
+                total_wait_time: pcb.total_wait_time,
+                turnaround_time: pcb.exit_time.map(|exit| exit - pcb.creation_time),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Statistics for every process, in pid order
+    pub fn list_process_stats(&self) -> Vec<ProcessStats> {
+        self.processes
+            .keys()
+            .filter_map(|&pid| self.get_process_stats(pid))
+            .collect()
+    }
+
+    /// The `n` processes that have used the most CPU time, busiest first
+    pub fn top(&self, n: usize) -> Vec<ProcessStats> {
+        let mut stats = self.list_process_stats();
+        stats.sort_by(|a, b| b.cpu_time.cmp(&a.cpu_time));
+        stats.truncate(n);
+        stats
+    }
+
+    /// Charge one timer tick of CPU time to the current process, and of waiting time
+    /// to every process that was Ready but not running
+    pub fn account_tick(&mut self) {
+        self.clock += 1;
+        if let Some(pid) = self.current_process {
+            self.update_cpu_time(pid, 1);
+            self.slice_ticks += 1;
+        }
+        for pcb in self.processes.values_mut() {
+            if pcb.state == ProcessState::Ready {
+                pcb.total_wait_time += 1;
+            }
+        }
+    }
+
+    /// Get system statistics
+    pub fn get_system_stats(&self) -> SystemStats {
+        let total_processes = self.processes.len();
+        let running_processes = self.processes.values().filter(|pcb| pcb.state == ProcessState::Running).count();
         let ready_processes = self.processes.values().filter(|pcb| pcb.state == ProcessState::Ready).count();
         let blocked_processes = self.processes.values().filter(|pcb| pcb.state == ProcessState::Blocked).count();
-        let terminated_processes = self.processes.values().filter(|pcb| pcb.state == ProcessState::Terminated).count();
+        let terminated_processes = self.processes.values()
+            .filter(|pcb| matches!(pcb.state, ProcessState::Terminated | ProcessState::Zombie))
+            .count();
 
         SystemStats {
             total_processes,
@@ -266,6 +1357,8 @@ pub struct ProcessStats {
     pub cpu_time: u64,
     pub memory_usage: usize,
     pub creation_time: u64,
+    pub total_wait_time: u64,
+    pub turnaround_time: Option<u64>, // Exit tick minus creation tick; None until it exits
 }
 
 /// System statistics
@@ -280,26 +1373,101 @@ pub struct SystemStats {
 }
 
 lazy_static! {
-    pub static ref PROCESS_SERVICE: Mutex<ProcessService> = Mutex::new(ProcessService::new());
+    pub static ref PROCESS_SERVICE: TrackedMutex<ProcessService> = TrackedMutex::new("PROCESS_SERVICE", ProcessService::new());
 }
 
 /// Process service API functions
 pub fn init_process_service() {
     PROCESS_SERVICE.lock().init();
+    crate::process::context::set_switch_hook(sched_trace::record_switch);
+}
+
+pub fn effective_priority(pid: ProcessId) -> Option<u32> {
+    PROCESS_SERVICE.lock().effective_priority(pid)
+}
+
+pub fn blocked_early_count(pid: ProcessId) -> u64 {
+    PROCESS_SERVICE.lock().blocked_early_count(pid)
+}
+
+/// Register a callback for process lifecycle events; see `ProcessObserver`
+pub fn on_process_event(observer: ProcessObserver) {
+    PROCESS_SERVICE.lock().on_process_event(observer)
 }
 
 pub fn create_process(name: String, priority: ProcessPriority, stack_size: usize, heap_size: usize) -> Result<ProcessId, ProcessError> {
     PROCESS_SERVICE.lock().create_process(name, priority, stack_size, heap_size)
 }
 
+pub fn set_max_processes(max: usize) {
+    PROCESS_SERVICE.lock().set_max_processes(max)
+}
+
+pub fn max_processes() -> usize {
+    PROCESS_SERVICE.lock().max_processes()
+}
+
+pub fn live_process_count() -> usize {
+    PROCESS_SERVICE.lock().live_process_count()
+}
+
+/// Create several processes under a single acquisition of the service lock
+pub fn create_processes(specs: &[(String, ProcessPriority, usize, usize)]) -> Vec<Result<ProcessId, ProcessError>> {
+    PROCESS_SERVICE.lock().create_processes(specs)
+}
+
 pub fn terminate_process(pid: ProcessId, exit_code: i32) -> Result<(), ProcessError> {
     PROCESS_SERVICE.lock().terminate_process(pid, exit_code)
 }
 
+pub fn get_exit_status(pid: ProcessId) -> Option<i32> {
+    PROCESS_SERVICE.lock().get_exit_status(pid)
+}
+
+pub fn wait_child(parent: ProcessId, child: Option<ProcessId>) -> Result<Option<(ProcessId, i32)>, ProcessError> {
+    PROCESS_SERVICE.lock().wait_child(parent, child)
+}
+
+pub fn wait(parent: ProcessId, child: Option<ProcessId>) -> Result<Option<(ProcessId, i32)>, ProcessError> {
+    PROCESS_SERVICE.lock().wait(parent, child)
+}
+
+pub fn take_wait_status(pid: ProcessId) -> Option<(ProcessId, i32)> {
+    PROCESS_SERVICE.lock().take_wait_status(pid)
+}
+
+pub fn reap_orphaned_zombies() -> usize {
+    PROCESS_SERVICE.lock().reap_orphaned_zombies()
+}
+
+pub fn exec(pid: ProcessId, elf: &[u8]) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().exec(pid, elf)
+}
+
+pub fn set_process_group(pid: ProcessId, pgid: ProcessId) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().set_process_group(pid, pgid)
+}
+
+pub fn list_group(pgid: ProcessId) -> Vec<ProcessId> {
+    PROCESS_SERVICE.lock().list_group(pgid)
+}
+
+pub fn exit_group(caller: ProcessId, exit_code: i32) -> Result<usize, ProcessError> {
+    PROCESS_SERVICE.lock().exit_group(caller, exit_code)
+}
+
+pub fn send_signal(target: i64, signal: Signal) -> Result<usize, ProcessError> {
+    PROCESS_SERVICE.lock().send_signal(target, signal)
+}
+
 pub fn schedule_next_process() -> Option<ProcessId> {
     PROCESS_SERVICE.lock().schedule_next()
 }
 
+pub fn yield_to(target: ProcessId) -> Result<ProcessId, ProcessError> {
+    PROCESS_SERVICE.lock().yield_to(target)
+}
+
 pub fn block_current_process() -> Result<(), ProcessError> {
     PROCESS_SERVICE.lock().block_current_process()
 }
@@ -308,6 +1476,26 @@ pub fn unblock_process(pid: ProcessId) -> Result<(), ProcessError> {
     PROCESS_SERVICE.lock().unblock_process(pid)
 }
 
+pub fn futex_wait(pid: ProcessId, key: u64, still_expected: impl FnOnce() -> bool) -> Result<bool, ProcessError> {
+    PROCESS_SERVICE.lock().futex_wait(pid, key, still_expected)
+}
+
+pub fn futex_wake(key: u64, count: usize) -> usize {
+    PROCESS_SERVICE.lock().futex_wake(key, count)
+}
+
+pub fn block_process(pid: ProcessId) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().block_process(pid)
+}
+
+pub fn block_on(pid: ProcessId, holder: ProcessId) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().block_on(pid, holder)
+}
+
+pub fn detect_deadlock() -> Option<Vec<ProcessId>> {
+    PROCESS_SERVICE.lock().detect_deadlock()
+}
+
 pub fn get_current_process() -> Option<ProcessId> {
     PROCESS_SERVICE.lock().get_current_process()
 }
@@ -324,10 +1512,744 @@ pub fn set_process_priority(pid: ProcessId, priority: ProcessPriority) -> Result
     PROCESS_SERVICE.lock().set_priority(pid, priority)
 }
 
+pub fn is_privileged(pid: ProcessId) -> bool {
+    PROCESS_SERVICE.lock().is_privileged(pid)
+}
+
+pub fn change_priority(caller: ProcessId, target: ProcessId, priority: ProcessPriority) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().change_priority(caller, target, priority)
+}
+
+pub fn get_working_directory(pid: ProcessId) -> Option<String> {
+    PROCESS_SERVICE.lock().get_working_directory(pid)
+}
+
+pub fn set_working_directory(pid: ProcessId, path: String) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().set_working_directory(pid, path)
+}
+
+pub fn set_nice(pid: ProcessId, nice: i8) -> Result<i8, ProcessError> {
+    PROCESS_SERVICE.lock().set_nice(pid, nice)
+}
+
+pub fn set_affinity(pid: ProcessId, mask: u64) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().set_affinity(pid, mask)
+}
+
+pub fn open_fd(pid: ProcessId, file: OpenFile) -> Result<FileDescriptor, ProcessError> {
+    PROCESS_SERVICE.lock().open_fd(pid, file)
+}
+
+pub fn close_fd(pid: ProcessId, fd: FileDescriptor) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().close_fd(pid, fd)
+}
+
+pub fn dup_fd(pid: ProcessId, fd: FileDescriptor) -> Result<FileDescriptor, ProcessError> {
+    PROCESS_SERVICE.lock().dup_fd(pid, fd)
+}
+
+pub fn get_fd(pid: ProcessId, fd: FileDescriptor) -> Result<OpenFile, ProcessError> {
+    PROCESS_SERVICE.lock().get_fd(pid, fd)
+}
+
+pub fn sbrk(pid: ProcessId, increment: isize) -> Result<VirtAddr, ProcessError> {
+    PROCESS_SERVICE.lock().sbrk(pid, increment)
+}
+
+pub fn adjust_memory_usage(pid: ProcessId, delta: isize) {
+    PROCESS_SERVICE.lock().adjust_memory_usage(pid, delta)
+}
+
+/// Put `pid` to sleep for at least `ms` milliseconds of timer ticks
+pub fn sleep_ms(pid: ProcessId, ms: u64) -> Result<(), ProcessError> {
+    let deadline = crate::time::ticks() + crate::time::ms_to_ticks(ms);
+    PROCESS_SERVICE.lock().sleep_until(pid, deadline)
+}
+
+/// Timer interrupt hook; a missed tick is caught up on the next since deadlines are absolute
+pub fn wake_sleepers(now: u64) -> usize {
+    PROCESS_SERVICE.try_lock().map_or(0, |mut service| service.wake_sleepers(now))
+}
+
+/// Recent context switches, oldest first
+pub fn dump_trace() -> Vec<SchedEvent> {
+    sched_trace::events()
+}
+
+pub fn clear_trace() {
+    sched_trace::clear()
+}
+
+pub fn set_watchdog_threshold(ticks: u64) {
+    PROCESS_SERVICE.lock().set_watchdog_threshold(ticks)
+}
+
+pub fn set_watchdog_action(action: WatchdogAction) {
+    PROCESS_SERVICE.lock().set_watchdog_action(action)
+}
+
+/// Timer interrupt hook; skips the tick if the interrupted code holds the process service
+pub fn watchdog_tick() -> Option<ProcessId> {
+    PROCESS_SERVICE.try_lock()?.watchdog_tick()
+}
+
+pub fn grant_capability(pid: ProcessId, capability: Capability) -> Result<usize, ProcessError> {
+    PROCESS_SERVICE.lock().grant_capability(pid, capability)
+}
+
+pub fn delegate_capability(
+    from: ProcessId,
+    to: ProcessId,
+    index: usize,
+    new_perms: CapabilityPermissions,
+) -> Result<(), ProcessError> {
+    PROCESS_SERVICE.lock().delegate_capability(from, to, index, new_perms)
+}
+
+pub fn grant_fs_capability(pid: ProcessId, root_cluster: u64, perms: CapabilityPermissions) -> Result<usize, ProcessError> {
+    PROCESS_SERVICE.lock().grant_fs_capability(pid, root_cluster, perms)
+}
+
+pub fn writable_fs_roots(pid: ProcessId) -> Option<Vec<u64>> {
+    PROCESS_SERVICE.lock().writable_fs_roots(pid)
+}
+
+pub fn revoke_fs_capabilities(cluster: u64) {
+    PROCESS_SERVICE.lock().revoke_fs_capabilities(cluster)
+}
+
+pub fn has_capability(pid: ProcessId, resource_type: ResourceType, resource_id: u64, needed: CapabilityPermissions) -> bool {
+    PROCESS_SERVICE.lock().has_capability(pid, resource_type, resource_id, needed)
+}
+
 pub fn get_process_stats(pid: ProcessId) -> Option<ProcessStats> {
     PROCESS_SERVICE.lock().get_process_stats(pid)
 }
 
+pub fn list_process_stats() -> Vec<ProcessStats> {
+    PROCESS_SERVICE.lock().list_process_stats()
+}
+
+pub fn top(n: usize) -> Vec<ProcessStats> {
+    PROCESS_SERVICE.lock().top(n)
+}
+
+/// Timer interrupt hook; the tick goes uncharged if the process service is busy
+pub fn account_tick() {
+    if let Some(mut service) = PROCESS_SERVICE.try_lock() {
+        service.account_tick();
+    }
+}
+
 pub fn get_system_stats() -> SystemStats {
     PROCESS_SERVICE.lock().get_system_stats()
 }
+
+#[test_case]
+fn test_exec_preserves_pid() {
+    use alloc::string::ToString;
+
+    let pid = create_process("exec_test".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let parent = PROCESS_SERVICE.lock().get_process(pid).unwrap().parent_pid;
+
+    let image = crate::process::elf::build_test_image(0x0040_1000, &[0x90, 0x90, 0xeb, 0xfe]);
+    assert_eq!(exec(pid, &image), Ok(()));
+
+    let service = PROCESS_SERVICE.lock();
+    let pcb = service.get_process(pid).expect("process vanished across exec");
+    assert_eq!(pcb.pid, pid);
+    assert_eq!(pcb.parent_pid, parent);
+    assert_eq!(pcb.registers.rip, 0x0040_1000);
+    drop(service);
+
+    assert_eq!(exec(pid, b"not an elf"), Err(ProcessError::InvalidExecutable));
+    let _ = terminate_process(pid, 0);
+}
+
+#[test_case]
+fn test_exec_maps_segments_and_unmaps_old_image() {
+    use alloc::string::ToString;
+    use crate::memory::{is_mapped, translate_verbose};
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    // Clear of the shell at `USER_SHELL_BASE`, its stack and the heap windows
+    const CODE: u64 = 0x0200_1000;
+    const DATA: u64 = 0x0210_0000;
+    const TAKEN: u64 = 0x0220_0000;
+    let pid = create_process("exec_map_test".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+
+    let code = [0x90, 0x90, 0xeb, 0xfe];
+    assert_eq!(exec(pid, &crate::process::elf::build_test_image(CODE, &code)), Ok(()));
+    let text = translate_verbose(VirtAddr::new(CODE)).expect("code segment not mapped");
+    assert!(text.user && text.executable && !text.writable);
+    assert_eq!(unsafe { core::slice::from_raw_parts(CODE as *const u8, code.len()) }, &code);
+
+    // A read-write segment twice as large in memory as in the file
+    let mut image = crate::process::elf::build_test_image(DATA, &[0xaa; 16]);
+    image[64 + 4..64 + 8].copy_from_slice(&0x6u32.to_le_bytes()); // p_flags: R+W
+    image[64 + 40..64 + 48].copy_from_slice(&32u64.to_le_bytes()); // p_memsz
+    assert_eq!(exec(pid, &image), Ok(()));
+
+    assert_eq!(is_mapped(VirtAddr::new(CODE)), Some(false), "old image left mapped");
+    let data = translate_verbose(VirtAddr::new(DATA)).expect("data segment not mapped");
+    assert!(data.user && data.writable && !data.executable);
+    let bytes = unsafe { core::slice::from_raw_parts(DATA as *const u8, 32) };
+    assert_eq!(&bytes[..16], &[0xaa; 16]);
+    assert_eq!(&bytes[16..], &[0; 16]);
+
+    // Segments may not reach into the kernel half
+    let kernel = crate::process::elf::build_test_image(0xffff_8000_0000_0000, &code);
+    assert_eq!(exec(pid, &kernel), Err(ProcessError::InvalidExecutable));
+
+    // Nor land on pages something else has mapped; the old image survives the refusal
+    let flags = Flags::PRESENT | Flags::WRITABLE;
+    crate::memory::map_range(VirtAddr::new(TAKEN), VirtAddr::new(TAKEN + 4096), flags).unwrap();
+    let clash = crate::process::elf::build_test_image(TAKEN, &code);
+    assert_eq!(exec(pid, &clash), Err(ProcessError::InvalidExecutable));
+    crate::memory::unmap_range(VirtAddr::new(TAKEN), VirtAddr::new(TAKEN + 4096));
+    assert_eq!(unsafe { core::slice::from_raw_parts(DATA as *const u8, 16) }, &[0xaa; 16]);
+
+    assert_eq!(exec(pid, &crate::process::elf::build_test_image(CODE, &code)), Ok(()));
+    assert_eq!(is_mapped(VirtAddr::new(DATA)), Some(false));
+    let _ = terminate_process(pid, 0);
+    crate::memory::unmap_range(VirtAddr::new(CODE), VirtAddr::new(CODE + 4096));
+}
+
+#[test_case]
+fn test_group_signal_terminates_all_members() {
+    use alloc::format;
+
+    let members: Vec<ProcessId> = (0..3)
+        .map(|i| create_process(format!("job_{}", i), ProcessPriority::Normal, 4096, 8192).unwrap())
+        .collect();
+    let leader = members[0];
+    for &pid in &members {
+        set_process_group(pid, leader).unwrap();
+    }
+    assert_eq!(list_group(leader), members);
+
+    assert_eq!(send_signal(-(leader as i64), Signal::Terminate), Ok(3));
+    for &pid in &members {
+        assert_eq!(get_process_stats(pid).unwrap().state, ProcessState::Zombie);
+    }
+    assert!(list_group(leader).is_empty());
+}
+
+#[test_case]
+fn test_lower_nice_is_scheduled_more_often() {
+    use alloc::string::ToString;
+
+    let favoured = create_process("nice_low".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let background = create_process("nice_high".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    assert_eq!(set_nice(favoured, -20), Ok(-20));
+    assert_eq!(set_nice(background, 100), Ok(19)); // clamped
+
+    let (mut favoured_runs, mut background_runs) = (0, 0);
+    for _ in 0..60 {
+        match schedule_next_process() {
+            Some(pid) if pid == favoured => favoured_runs += 1,
+            Some(pid) if pid == background => background_runs += 1,
+            _ => {}
+        }
+    }
+    assert!(favoured_runs > background_runs);
+
+    let _ = terminate_process(favoured, 0);
+    let _ = terminate_process(background, 0);
+}
+
+#[test_case]
+fn test_detects_two_process_circular_wait() {
+    use alloc::string::ToString;
+
+    let a = create_process("lock_a".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let b = create_process("lock_b".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+
+    block_on(a, b).unwrap();
+    assert_eq!(detect_deadlock(), None);
+
+    block_on(b, a).unwrap();
+    assert_eq!(detect_deadlock(), Some(alloc::vec![a, b]));
+
+    // Releasing either side breaks the cycle
+    unblock_process(b).unwrap();
+    assert_eq!(detect_deadlock(), None);
+
+    let _ = terminate_process(a, 0);
+    let _ = terminate_process(b, 0);
+}
+
+#[test_case]
+fn test_yield_to_switches_to_target() {
+    use alloc::string::ToString;
+
+    let server = create_process("yield_server".to_string(), ProcessPriority::Low, 4096, 8192).unwrap();
+    assert_eq!(yield_to(server), Ok(server));
+    assert_eq!(get_current_process(), Some(server));
+
+    // The running process is not Ready, so it cannot be a directed-yield target
+    assert_eq!(yield_to(server), Err(ProcessError::ProcessNotReady));
+    assert_eq!(yield_to(u64::MAX), Err(ProcessError::ProcessNotFound));
+
+    // Hand the CPU back to the kernel before tearing down
+    assert_eq!(yield_to(0), Ok(0));
+    let _ = terminate_process(server, 0);
+}
+
+#[test_case]
+fn test_exited_child_is_zombie_until_reaped() {
+    use alloc::string::ToString;
+
+    let parent = create_process("zombie_parent".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let child = create_process("zombie_child".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let orphan = create_process("zombie_orphan".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    {
+        let mut service = PROCESS_SERVICE.lock();
+        for pid in [child, orphan] {
+            service.processes.get_mut(&pid).unwrap().parent_pid = Some(parent);
+        }
+    }
+
+    assert_eq!(wait_child(parent, Some(child)), Ok(None)); // still running
+    terminate_process(child, 7).unwrap();
+    assert_eq!(get_process_stats(child).unwrap().state, ProcessState::Zombie);
+
+    assert_eq!(wait_child(parent, Some(child)), Ok(Some((child, 7))));
+    assert!(get_process_stats(child).is_none());
+    assert_eq!(wait_child(parent, Some(child)), Err(ProcessError::ProcessNotFound));
+
+    // Once the parent exits nobody will wait for the orphan, so the kernel reaps it
+    terminate_process(orphan, 1).unwrap();
+    terminate_process(parent, 0).unwrap();
+    assert!(get_process_stats(orphan).is_none());
+}
+
+#[test_case]
+fn test_watchdog_preempts_non_yielding_process() {
+    use alloc::string::ToString;
+
+    let hog = create_process("spinner".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    yield_to(hog).unwrap();
+
+    {
+        let mut service = PROCESS_SERVICE.lock();
+        service.set_watchdog_threshold(5);
+        for _ in 0..5 {
+            assert_eq!(service.watchdog_tick(), None);
+        }
+        assert_eq!(service.current_process, Some(hog));
+        assert_eq!(service.watchdog_tick(), Some(hog));
+        assert_ne!(service.current_process, Some(hog));
+        assert_eq!(service.get_process(hog).unwrap().state, ProcessState::Ready);
+    }
+
+    // Strict mode kills the hog instead
+    set_watchdog_action(WatchdogAction::Terminate);
+    yield_to(hog).unwrap();
+    {
+        let mut service = PROCESS_SERVICE.lock();
+        while service.watchdog_tick().is_none() {}
+        assert_eq!(service.get_process(hog).unwrap().exit_code, Some(WATCHDOG_EXIT_CODE));
+    }
+
+    // The kernel process is exempt, even in strict mode
+    if get_current_process() != Some(0) {
+        let _ = yield_to(0);
+    }
+    {
+        let mut service = PROCESS_SERVICE.lock();
+        for _ in 0..20 {
+            assert_eq!(service.watchdog_tick(), None);
+        }
+        assert_eq!(service.get_process(0).unwrap().state, ProcessState::Running);
+    }
+
+    set_watchdog_threshold(0);
+    set_watchdog_action(WatchdogAction::Preempt);
+}
+
+#[test_case]
+fn test_watchdog_defers_kill_while_filesystem_is_locked() {
+    use alloc::string::ToString;
+    use crate::services::file_system_service::FILESYSTEM_SERVICE;
+
+    let hog = create_process("fs_hog".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    yield_to(hog).unwrap();
+    set_watchdog_action(WatchdogAction::Terminate);
+    {
+        let mut service = PROCESS_SERVICE.lock();
+        service.set_watchdog_threshold(1);
+        // As if the tick had interrupted a filesystem call
+        let fs = FILESYSTEM_SERVICE.lock();
+        for _ in 0..5 {
+            assert_eq!(service.watchdog_tick(), None);
+        }
+        drop(fs);
+        assert_eq!(service.watchdog_tick(), Some(hog));
+        assert_eq!(service.get_process(hog).unwrap().exit_code, Some(WATCHDOG_EXIT_CODE));
+    }
+
+    set_watchdog_threshold(0);
+    set_watchdog_action(WatchdogAction::Preempt);
+    if get_current_process() != Some(0) {
+        let _ = yield_to(0);
+    }
+}
+
+#[test_case]
+fn test_sleeper_wakes_at_deadline() {
+    use alloc::string::ToString;
+    use crate::time::{ms_to_ticks_at, ticks};
+
+    let sleeper = create_process("sleeper".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    // Far in the future so the real timer interrupt cannot wake it early
+    let start = ticks() + 1_000_000;
+    let duration = ms_to_ticks_at(50, 100);
+    assert_eq!(duration, 5);
+
+    let mut service = PROCESS_SERVICE.lock();
+    service.sleep_until(sleeper, start + duration).unwrap();
+    for now in start..start + duration {
+        assert_eq!(service.wake_sleepers(now), 0);
+        assert_eq!(service.get_process(sleeper).unwrap().state, ProcessState::Blocked);
+    }
+    assert_eq!(service.wake_sleepers(start + duration), 1);
+    assert_eq!(service.get_process(sleeper).unwrap().state, ProcessState::Ready);
+    drop(service);
+
+    let _ = terminate_process(sleeper, 0);
+}
+
+#[test_case]
+fn test_wait_delivers_child_exit_code() {
+    use alloc::string::ToString;
+
+    // Fork two children from a parent process
+    let parent = create_process("waiter".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    yield_to(parent).unwrap();
+    let early = create_process("early_exit".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let late = create_process("late_exit".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    yield_to(0).unwrap();
+
+    // A child that exits before the parent waits is kept until reaped
+    terminate_process(early, 7).unwrap();
+    assert_eq!(wait(parent, Some(early)), Ok(Some((early, 7))));
+
+    // Waiting on a running child blocks the parent until the child exits
+    assert_eq!(wait(parent, None), Ok(None));
+    assert_eq!(get_process_stats(parent).unwrap().state, ProcessState::Blocked);
+    terminate_process(late, 42).unwrap();
+    assert_eq!(get_process_stats(parent).unwrap().state, ProcessState::Ready);
+    assert_eq!(take_wait_status(parent), Some((late, 42)));
+    assert!(get_process_stats(late).is_none());
+
+    let _ = terminate_process(parent, 0);
+}
+
+#[test_case]
+fn test_top_ranks_by_cpu_time() {
+    use alloc::string::ToString;
+
+    let busy = create_process("busy".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let idle = create_process("idle".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    yield_to(busy).unwrap();
+
+    {
+        let mut service = PROCESS_SERVICE.lock();
+        let busiest = service.top(1)[0].cpu_time;
+        for _ in 0..=busiest {
+            service.account_tick();
+        }
+
+        let ranking = service.top(3);
+        assert_eq!(ranking.len(), 3);
+        assert_eq!(ranking[0].pid, busy);
+        assert!(ranking.windows(2).all(|pair| pair[0].cpu_time >= pair[1].cpu_time));
+        assert!(service.top(usize::MAX).iter().any(|stats| stats.pid == idle));
+    }
+
+    yield_to(0).unwrap();
+    let _ = terminate_process(busy, 0);
+    let _ = terminate_process(idle, 0);
+}
+
+#[test_case]
+fn test_trace_records_switches_in_order() {
+    use alloc::string::ToString;
+
+    let a = create_process("trace_a".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let b = create_process("trace_b".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+
+    clear_trace();
+    yield_to(a).unwrap();
+    yield_to(b).unwrap();
+    yield_to(0).unwrap();
+    terminate_process(a, 0).unwrap();
+
+    let switches: Vec<(Option<ProcessId>, Option<ProcessId>, SchedReason)> = dump_trace()
+        .iter()
+        .map(|event| (event.from, event.to, event.reason))
+        .collect();
+    assert_eq!(switches, [
+        (Some(0), Some(a), SchedReason::Yield),
+        (Some(a), Some(b), SchedReason::Yield),
+        (Some(b), Some(0), SchedReason::Yield),
+    ]);
+
+    let _ = terminate_process(b, 0);
+}
+
+#[test_case]
+fn test_delegated_capability_only_narrows() {
+    use alloc::string::ToString;
+
+    let perms = |read, write| CapabilityPermissions { read, write, execute: false, admin: false };
+    let owner = create_process("cap_owner".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let reader = create_process("cap_reader".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+
+    let index = grant_capability(owner, Capability {
+        resource_type: ResourceType::File,
+        resource_id: 7,
+        permissions: perms(true, true),
+    }).unwrap();
+    delegate_capability(owner, reader, index, perms(true, false)).unwrap();
+
+    assert!(has_capability(reader, ResourceType::File, 7, perms(true, false)));
+    assert!(!has_capability(reader, ResourceType::File, 7, perms(false, true)));
+    assert!(has_capability(owner, ResourceType::File, 7, perms(true, true)));
+
+    // The reader cannot pass on more than it holds
+    assert_eq!(delegate_capability(reader, owner, 0, perms(true, true)), Err(ProcessError::PermissionDenied));
+    assert_eq!(delegate_capability(owner, reader, 99, perms(true, false)), Err(ProcessError::InvalidCapability));
+
+    let _ = terminate_process(owner, 0);
+    let _ = terminate_process(reader, 0);
+}
+
+#[test_case]
+fn test_pid_allocation_skips_live_pids_and_wraps() {
+    use alloc::string::ToString;
+    use crate::process::pcb::PID_MAX;
+
+    let mut service = ProcessService::new();
+    service.init();
+    let first = service.create_process("first".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let second = service.create_process("second".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+
+    // Point the counter at a live pid: it is skipped
+    service.next_pid = first;
+    let third = service.create_process("third".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    assert_eq!(third, second + 1);
+
+    // At the top of the range the counter wraps past the kernel and live pids
+    service.next_pid = PID_MAX - 1;
+    let last = service.create_process("last".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    assert_eq!(last, PID_MAX - 1);
+    let wrapped = service.create_process("wrapped".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    assert_eq!(wrapped, third + 1);
+}
+
+#[test_case]
+fn test_observer_sees_process_lifecycle() {
+    use spin::Mutex;
+
+    static EVENTS: Mutex<Vec<ProcessEvent>> = Mutex::new(Vec::new());
+    fn record(event: ProcessEvent) {
+        EVENTS.lock().push(event);
+    }
+
+    let mut service = ProcessService::new();
+    service.init();
+    service.on_process_event(record);
+
+    let pid = service.create_process(String::from("observed"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    service.block_process(pid).unwrap();
+    service.unblock_process(pid).unwrap();
+    service.terminate_process(pid, 0).unwrap();
+
+    assert_eq!(
+        *EVENTS.lock(),
+        [
+            ProcessEvent::Created(pid),
+            ProcessEvent::Blocked(pid),
+            ProcessEvent::Unblocked(pid),
+            ProcessEvent::Terminated(pid),
+        ]
+    );
+}
+
+#[test_case]
+fn test_process_that_blocks_early_keeps_high_priority() {
+    let mut service = ProcessService::new();
+    service.init();
+    let waiter = service.create_process(String::from("waiter"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let hog = service.create_process(String::from("hog"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let base = service.effective_priority(hog).unwrap();
+
+    for _ in 0..3 {
+        // The waiter blocks as soon as it runs; the hog burns a tick before yielding
+        service.yield_to(waiter).unwrap();
+        service.block_process(waiter).unwrap();
+        service.unblock_process(waiter).unwrap();
+        service.yield_to(hog).unwrap();
+        service.account_tick();
+        service.yield_to(0).unwrap();
+    }
+
+    assert_eq!(service.blocked_early_count(waiter), 3);
+    assert_eq!(service.blocked_early_count(hog), 0);
+    assert_eq!(service.effective_priority(waiter), Some(base + RESPONSIVE_BONUS));
+    assert_eq!(service.effective_priority(hog), Some(base));
+
+    // Blocking late drops the boost
+    service.yield_to(waiter).unwrap();
+    service.account_tick();
+    service.block_process(waiter).unwrap();
+    assert_eq!(service.effective_priority(waiter), Some(base));
+    assert_eq!(service.blocked_early_count(waiter), 3);
+}
+
+#[test_case]
+fn test_waiting_process_accumulates_wait_time() {
+    let mut service = ProcessService::new();
+    service.init();
+    let first = service.create_process(String::from("first"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    service.account_tick();
+    let second = service.create_process(String::from("second"), ProcessPriority::Normal, 4096, 4096).unwrap();
+
+    // `second` sits Ready while `first` runs for three ticks
+    service.yield_to(first).unwrap();
+    for _ in 0..3 {
+        service.account_tick();
+    }
+    service.terminate_process(first, 0).unwrap();
+
+    let waiting = service.get_process_stats(second).unwrap();
+    assert_eq!(waiting.total_wait_time, 3);
+    assert_eq!(waiting.turnaround_time, None);
+
+    let finished = service.get_process_stats(first).unwrap();
+    assert_eq!(finished.total_wait_time, 1);
+    assert_eq!(finished.turnaround_time, Some(4));
+}
+
+#[test_case]
+fn test_exit_status_survives_reaping_until_evicted() {
+    let mut service = ProcessService::new();
+    service.init();
+    let child = service.create_process(String::from("child"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    service.terminate_process(child, 7).unwrap();
+    assert_eq!(service.get_exit_status(child), Some(7));
+
+    assert_eq!(service.wait_child(0, Some(child)), Ok(Some((child, 7))));
+    assert!(service.get_process(child).is_none());
+    assert_eq!(service.get_exit_status(child), Some(7));
+
+    // Enough later exits push it out of the cache
+    for i in 0..EXIT_STATUS_CACHE_SIZE {
+        let pid = service.create_process(alloc::format!("filler{}", i), ProcessPriority::Normal, 4096, 4096).unwrap();
+        service.terminate_process(pid, 0).unwrap();
+        service.wait_child(0, Some(pid)).unwrap();
+    }
+    assert_eq!(service.get_exit_status(child), None);
+}
+
+#[test_case]
+fn test_batch_creation_yields_distinct_pids() {
+    let mut service = ProcessService::new();
+    service.init();
+    let specs: Vec<_> = (0..5)
+        .map(|i| (alloc::format!("batch{}", i), ProcessPriority::Normal, 4096, 4096))
+        .collect();
+
+    let pids: Vec<ProcessId> = service.create_processes(&specs).into_iter().map(Result::unwrap).collect();
+    assert_eq!(pids.len(), 5);
+    for (i, pid) in pids.iter().enumerate() {
+        assert!(!pids[..i].contains(pid));
+        assert_eq!(service.get_process(*pid).unwrap().name, specs[i].0);
+    }
+}
+
+#[test_case]
+fn test_failed_context_switch_leaves_no_phantom_running_process() {
+    let mut service = ProcessService::new();
+    service.init();
+    let a = service.create_process(String::from("a"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let b = service.create_process(String::from("b"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let running = |service: &ProcessService| -> Vec<ProcessId> {
+        service.processes.values().filter(|pcb| pcb.state == ProcessState::Running).map(|pcb| pcb.pid).collect()
+    };
+
+    // The first candidate fails; the scheduler moves on to the other
+    service.inject_switch_failures(1);
+    let chosen = service.schedule_next().expect("no process scheduled");
+    assert!(chosen == a || chosen == b);
+    assert_eq!(running(&service), [chosen]);
+    assert_eq!(service.get_current_process(), Some(chosen));
+
+    // When every candidate fails, the caller keeps running and nothing else is marked Running
+    service.inject_switch_failures(u32::MAX);
+    assert_eq!(service.schedule_next(), None);
+    assert_eq!(running(&service), [chosen]);
+    assert_eq!(service.yield_to(0), Err(ProcessError::ContextSwitchFailed));
+    assert_eq!(service.get_current_process(), Some(chosen));
+    assert_eq!(service.get_process(0).unwrap().state, ProcessState::Ready);
+}
+
+#[test_case]
+fn test_create_process_stops_at_max_processes() {
+    use alloc::format;
+
+    let mut service = ProcessService::new();
+    service.init();
+    service.set_max_processes(4);
+
+    // The kernel counts towards the limit
+    let children: Vec<ProcessId> = (0..3)
+        .map(|i| service.create_process(format!("limited_{}", i), ProcessPriority::Normal, 4096, 4096).unwrap())
+        .collect();
+    assert_eq!(service.live_process_count(), 4);
+    assert_eq!(
+        service.create_process(String::from("one_too_many"), ProcessPriority::Normal, 4096, 4096),
+        Err(ProcessError::ProcessLimitReached)
+    );
+
+    // An exited child keeps its place until it is reaped
+    service.terminate_process(children[0], 0).unwrap();
+    assert_eq!(
+        service.create_process(String::from("replacement"), ProcessPriority::Normal, 4096, 4096),
+        Err(ProcessError::ProcessLimitReached)
+    );
+    assert_eq!(service.wait_child(0, Some(children[0])), Ok(Some((children[0], 0))));
+    assert!(service.create_process(String::from("replacement"), ProcessPriority::Normal, 4096, 4096).is_ok());
+}
+
+#[test_case]
+fn test_heaps_of_different_processes_do_not_overlap() {
+    let a = create_process(String::from("heap_a"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let b = create_process(String::from("heap_b"), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let a_break = sbrk(a, 0).unwrap();
+    let b_break = sbrk(b, 0).unwrap();
+    // Each heap's start is at least a whole window from the other's
+    assert!((b_break.as_u64() - 8192).abs_diff(a_break.as_u64() - 4096) >= MAX_HEAP_SIZE as u64);
+
+    assert_eq!(sbrk(a, 8192), Ok(a_break));
+    assert_eq!(sbrk(b, 8192), Ok(b_break));
+    let word = (b_break + 4096u64).as_mut_ptr::<u64>();
+    unsafe { word.write_volatile(0x2337) };
+
+    // Shrinking one heap leaves the other's pages mapped
+    assert_eq!(sbrk(a, -8192), Ok(a_break + 8192u64));
+    assert_eq!(crate::memory::is_mapped(b_break + 4096u64), Some(true));
+    assert_eq!(unsafe { word.read_volatile() }, 0x2337);
+
+    assert_eq!(sbrk(b, -8192), Ok(b_break + 8192u64));
+    assert_eq!(
+        create_process(String::from("heap_huge"), ProcessPriority::Normal, 4096, MAX_HEAP_SIZE + 1),
+        Err(ProcessError::InsufficientMemory)
+    );
+    for pid in [a, b] {
+        terminate_process(pid, 0).unwrap();
+        let _ = wait_child(0, Some(pid));
+    }
+}
@@ -9,14 +9,28 @@ pub struct VgaService {
     column_position: usize,
     color_code: ColorCode,
     buffer: &'static mut Buffer,
+    live: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT], // Live screen, kept while scrolled back
+    history: [[ScreenChar; BUFFER_WIDTH]; SCROLLBACK_LINES], // Ring of lines scrolled off the top
+    history_start: usize,
+    history_len: usize,
+    scroll_offset: usize, // Lines scrolled back from the live view; 0 = live
 }
 
 impl VgaService {
     pub fn new() -> Self {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: ColorCode::new(Color::Yellow, Color::Black),
+        };
         Self {
             column_position: 0,
             color_code: ColorCode::new(Color::Yellow, Color::Black),
             buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+            live: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            history: [[blank; BUFFER_WIDTH]; SCROLLBACK_LINES],
+            history_start: 0,
+            history_len: 0,
+            scroll_offset: 0,
         }
     }
 
@@ -51,7 +65,7 @@ impl VgaService {
                 let col = self.column_position;
 
                 let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
+                self.put_char(row, col, ScreenChar {
                     ascii_character: byte,
                     color_code,
                 });
@@ -73,11 +87,46 @@ impl VgaService {
         self.color_code = ColorCode::new(foreground, background);
     }
 
+    /// Scroll the view back through history by up to `lines` lines
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll_offset = (self.scroll_offset + lines).min(self.history_len);
+        self.repaint();
+    }
+
+    /// Scroll the view toward live output by up to `lines` lines
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+        self.repaint();
+    }
+
+    /// Return to live output
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_offset = 0;
+        self.repaint();
+    }
+
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
+        // Retire the top line into the history ring
+        let top = self.live[0];
+        if self.history_len < SCROLLBACK_LINES {
+            self.history[(self.history_start + self.history_len) % SCROLLBACK_LINES] = top;
+            self.history_len += 1;
+            // Keep a scrolled-back view pinned to the same lines
+            if self.scroll_offset > 0 {
+                self.scroll_offset += 1;
+            }
+        } else {
+            self.history[self.history_start] = top;
+            self.history_start = (self.history_start + 1) % SCROLLBACK_LINES;
+        }
+
+        self.live.copy_within(1.., 0);
+        if self.scroll_offset == 0 {
+            for row in 1..BUFFER_HEIGHT {
+                for col in 0..BUFFER_WIDTH {
+                    let character = self.buffer.chars[row][col].read();
+                    self.buffer.chars[row - 1][col].write(character);
+                }
             }
         }
         self.clear_row(BUFFER_HEIGHT - 1);
@@ -90,7 +139,31 @@ impl VgaService {
             color_code: self.color_code,
         };
         for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+            self.put_char(row, col, blank);
+        }
+    }
+
+    /// Write to the live screen, and to the display unless scrolled back
+    fn put_char(&mut self, row: usize, col: usize, character: ScreenChar) {
+        self.live[row][col] = character;
+        if self.scroll_offset == 0 {
+            self.buffer.chars[row][col].write(character);
+        }
+    }
+
+    /// Redraw the display from history and the live screen
+    fn repaint(&mut self) {
+        let first = self.history_len - self.scroll_offset;
+        for row in 0..BUFFER_HEIGHT {
+            let line = first + row;
+            let source = if line < self.history_len {
+                self.history[(self.history_start + line) % SCROLLBACK_LINES]
+            } else {
+                self.live[line - self.history_len]
+            };
+            for col in 0..BUFFER_WIDTH {
+                self.buffer.chars[row][col].write(source[col]);
+            }
         }
     }
 }
@@ -98,6 +171,7 @@ impl VgaService {
 // VGA Constants and Types
 const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
+const SCROLLBACK_LINES: usize = 100;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -163,4 +237,32 @@ pub fn vga_print(args: fmt::Arguments) {
 
 lazy_static! {
     static ref VGA_SERVICE: Mutex<VgaService> = Mutex::new(VgaService::new());
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_scroll_up_reveals_scrolled_off_lines() {
+    use alloc::format;
+
+    let mut vga = VgaService::new();
+    let top_row = |vga: &VgaService| vga.buffer.chars[0][0].read().ascii_character;
+
+    // 24 blank lines retire first, then 'a'..'f' scroll off the top
+    for i in 0..30u8 {
+        vga.write_string(&format!("{}\n", (b'a' + i) as char));
+    }
+    assert_eq!(top_row(&vga), b'g');
+
+    vga.scroll_up(1);
+    assert_eq!(top_row(&vga), b'f');
+    vga.scroll_up(5);
+    assert_eq!(top_row(&vga), b'a');
+
+    // New output while scrolled back leaves the view alone
+    vga.write_string("z\n");
+    assert_eq!(top_row(&vga), b'a');
+
+    vga.scroll_down(2);
+    assert_eq!(top_row(&vga), b'c');
+    vga.scroll_to_bottom();
+    assert_eq!(top_row(&vga), b'h');
+}
@@ -0,0 +1,308 @@
+// Kernel shell for EMOS Microkernel
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use crate::process::pcb::{ProcessError, Signal};
+use crate::services::file_system_service::{self, FileSystemError};
+use crate::services::keyboard_service::read_line;
+use crate::services::{memory_service, process_service};
+
+/// Why a command line failed
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShellError {
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    BadArgument(String),
+    UnterminatedQuote,
+    FileSystem(FileSystemError),
+    Process(ProcessError),
+    Output,
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShellError::UnknownCommand(name) => write!(f, "{}: command not found", name),
+            ShellError::MissingArgument(what) => write!(f, "missing {}", what),
+            ShellError::BadArgument(arg) => write!(f, "bad argument: {}", arg),
+            ShellError::UnterminatedQuote => write!(f, "unterminated quote"),
+            ShellError::FileSystem(e) => write!(f, "{}", e),
+            ShellError::Process(e) => write!(f, "process error: {:?}", e),
+            ShellError::Output => write!(f, "cannot write output"),
+        }
+    }
+}
+
+impl From<FileSystemError> for ShellError {
+    fn from(e: FileSystemError) -> Self {
+        ShellError::FileSystem(e)
+    }
+}
+
+impl From<ProcessError> for ShellError {
+    fn from(e: ProcessError) -> Self {
+        ShellError::Process(e)
+    }
+}
+
+impl From<fmt::Error> for ShellError {
+    fn from(_: fmt::Error) -> Self {
+        ShellError::Output
+    }
+}
+
+/// A built-in command: receives its arguments (without the command name) and writes its
+/// output to `out`
+type Builtin = fn(&[&str], &mut dyn Write) -> Result<(), ShellError>;
+
+const BUILTINS: &[(&str, Builtin)] = &[
+    ("help", builtin_help),
+    ("ps", builtin_ps),
+    ("ls", builtin_ls),
+    ("cat", builtin_cat),
+    ("echo", builtin_echo),
+    ("mkdir", builtin_mkdir),
+    ("cd", builtin_cd),
+    ("kill", builtin_kill),
+    ("free", builtin_free),
+    ("clear", builtin_clear),
+];
+
+/// Split a command line into words. Whitespace separates words unless inside double
+/// quotes, which are removed, and an unquoted `>` is always a word of its own.
+pub fn tokenize(line: &str) -> Result<Vec<String>, ShellError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if quoted => word.get_or_insert_with(String::new).push(c),
+            '>' => {
+                words.extend(word.take());
+                words.push(String::from(">"));
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(ShellError::UnterminatedQuote);
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Run one command line, writing its output to `out`. A blank line does nothing.
+/// `command > path` writes the command's output to the file at `path` instead.
+pub fn execute(line: &str, out: &mut dyn Write) -> Result<(), ShellError> {
+    let words = tokenize(line)?;
+    let (command, target) = match words.iter().position(|word| word == ">") {
+        Some(at) => match &words[at + 1..] {
+            [path] => (&words[..at], Some(path.as_str())),
+            [] => return Err(ShellError::MissingArgument("file to redirect to")),
+            extra => return Err(ShellError::BadArgument(extra.join(" "))),
+        },
+        None => (&words[..], None),
+    };
+    let args: Vec<&str> = command.iter().map(String::as_str).collect();
+    let (&name, args) = match args.split_first() {
+        Some(split) => split,
+        None if target.is_some() => return Err(ShellError::MissingArgument("command")),
+        None => return Ok(()),
+    };
+    let run = match BUILTINS.iter().find(|&&(builtin, _)| builtin == name) {
+        Some(&(_, run)) => run,
+        None => return Err(ShellError::UnknownCommand(name.to_string())),
+    };
+    match target {
+        Some(path) => {
+            let mut captured = String::new();
+            run(args, &mut captured)?;
+            echo(&captured, path)
+        }
+        None => run(args, out),
+    }
+}
+
+/// Write the file at `path` to `out`: as text if it is printable UTF-8, otherwise as a
+/// hex dump
+pub fn cat(path: &str, out: &mut dyn Write) -> Result<(), ShellError> {
+    let cluster = file_system_service::resolve_path(path)?;
+    if !file_system_service::is_file(cluster) {
+        return Err(ShellError::BadArgument(path.to_string()));
+    }
+    let data = file_system_service::read_file(cluster)?;
+    match core::str::from_utf8(&data) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t')) => {
+            write!(out, "{}", text)?;
+        }
+        _ => {
+            for (line, chunk) in data.chunks(16).enumerate() {
+                write!(out, "{:08x}:", line * 16)?;
+                for byte in chunk {
+                    write!(out, " {:02x}", byte)?;
+                }
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace the contents of the file at `path` with `text`, creating the file if needed
+pub fn echo(text: &str, path: &str) -> Result<(), ShellError> {
+    use crate::services::file_system_service::FilePermissions;
+
+    let cluster = file_system_service::open_or_create(path, FilePermissions::ReadWrite)?;
+    let written = file_system_service::write_file(cluster, text.as_bytes())?;
+    if written < text.len() {
+        return Err(ShellError::FileSystem(FileSystemError::OutOfSpace));
+    }
+    Ok(())
+}
+
+/// Prints straight to the screen
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::print!("{}", s);
+        Ok(())
+    }
+}
+
+/// Read, run and print command lines from the keyboard forever
+pub async fn run() {
+    loop {
+        crate::print!("{}> ", file_system_service::get_current_path());
+        let line = read_line().await;
+        if let Err(e) = execute(&line, &mut Console) {
+            crate::println!("{}", e);
+        }
+    }
+}
+
+fn builtin_help(_args: &[&str], out: &mut dyn Write) -> Result<(), ShellError> {
+    let names: Vec<&str> = BUILTINS.iter().map(|&(name, _)| name).collect();
+    writeln!(out, "{}", names.join(" "))?;
+    Ok(())
+}
+
+fn builtin_ps(_args: &[&str], out: &mut dyn Write) -> Result<(), ShellError> {
+    writeln!(out, "{:>5} {:<10} {:<8} {:>8} NAME", "PID", "STATE", "PRIO", "CPU")?;
+    for stats in process_service::list_process_stats() {
+        let state = alloc::format!("{:?}", stats.state);
+        let priority = alloc::format!("{:?}", stats.priority);
+        writeln!(out, "{:>5} {:<10} {:<8} {:>8} {}", stats.pid, state, priority, stats.cpu_time, stats.name)?;
+    }
+    Ok(())
+}
+
+fn builtin_ls(_args: &[&str], out: &mut dyn Write) -> Result<(), ShellError> {
+    for (name, is_directory) in file_system_service::list_files() {
+        let suffix = if is_directory { "/" } else { "" };
+        writeln!(out, "{}{}", name, suffix)?;
+    }
+    Ok(())
+}
+
+fn builtin_cat(args: &[&str], out: &mut dyn Write) -> Result<(), ShellError> {
+    if args.is_empty() {
+        return Err(ShellError::MissingArgument("file"));
+    }
+    for &path in args {
+        cat(path, out)?;
+    }
+    Ok(())
+}
+
+fn builtin_echo(args: &[&str], out: &mut dyn Write) -> Result<(), ShellError> {
+    writeln!(out, "{}", args.join(" "))?;
+    Ok(())
+}
+
+fn builtin_mkdir(args: &[&str], _out: &mut dyn Write) -> Result<(), ShellError> {
+    if args.is_empty() {
+        return Err(ShellError::MissingArgument("directory name"));
+    }
+    for &name in args {
+        file_system_service::create_directory(name)?;
+    }
+    Ok(())
+}
+
+fn builtin_cd(args: &[&str], _out: &mut dyn Write) -> Result<(), ShellError> {
+    match args {
+        [name] => Ok(file_system_service::change_directory(name)?),
+        [] => Err(ShellError::MissingArgument("directory name")),
+        _ => Err(ShellError::BadArgument(args.join(" "))),
+    }
+}
+
+fn builtin_kill(args: &[&str], out: &mut dyn Write) -> Result<(), ShellError> {
+    if args.is_empty() {
+        return Err(ShellError::MissingArgument("pid"));
+    }
+    for &arg in args {
+        let pid: i64 = arg.parse().map_err(|_| ShellError::BadArgument(arg.to_string()))?;
+        let signalled = process_service::send_signal(pid, Signal::Kill)?;
+        writeln!(out, "killed {} process(es)", signalled)?;
+    }
+    Ok(())
+}
+
+fn builtin_free(_args: &[&str], out: &mut dyn Write) -> Result<(), ShellError> {
+    writeln!(out, "{}", memory_service::meminfo())?;
+    Ok(())
+}
+
+fn builtin_clear(_args: &[&str], _out: &mut dyn Write) -> Result<(), ShellError> {
+    crate::vga_buffer::clear_screen();
+    Ok(())
+}
+
+#[test_case]
+fn test_ls_lists_created_file() {
+    use crate::services::file_system_service::{create_file, FilePermissions, FILESYSTEM_SERVICE};
+    use crate::services::keyboard_service::LineBuffer;
+
+    let cluster = create_file("shell_ls.txt", FilePermissions::ReadWrite).unwrap();
+
+    let mut input = LineBuffer::new();
+    let line = "ls\n".chars().find_map(|c| input.feed(c)).expect("no line after newline");
+    let mut out = String::new();
+    execute(&line, &mut out).unwrap();
+    assert!(out.lines().any(|entry| entry == "shell_ls.txt"), "ls printed {:?}", out);
+
+    assert_eq!(execute("frobnicate now", &mut out), Err(ShellError::UnknownCommand(String::from("frobnicate"))));
+    assert_eq!(execute("kill nine", &mut out), Err(ShellError::BadArgument(String::from("nine"))));
+    FILESYSTEM_SERVICE.lock().delete_file(cluster).unwrap();
+}
+
+#[test_case]
+fn test_echo_redirect_then_cat() {
+    use crate::services::file_system_service::{resolve_path, write_file, FILESYSTEM_SERVICE};
+
+    let mut out = String::new();
+    execute("echo \"hi\" > shell_echo.txt", &mut out).unwrap();
+    assert!(out.is_empty());
+    execute("cat shell_echo.txt", &mut out).unwrap();
+    assert_eq!(out, "hi\n");
+
+    // Binary contents come out as hex
+    let cluster = resolve_path("shell_echo.txt").unwrap();
+    write_file(cluster, &[0x00, 0xff, b'a']).unwrap();
+    out.clear();
+    cat("shell_echo.txt", &mut out).unwrap();
+    assert_eq!(out, "00000000: 00 ff 61\n");
+
+    let error = execute("cat shell_missing.txt", &mut out).unwrap_err();
+    assert_eq!(alloc::format!("{}", error), "no such file or directory");
+    assert_eq!(tokenize("echo \"a  b\">x"), Ok(alloc::vec![String::from("echo"), String::from("a  b"), String::from(">"), String::from("x")]));
+    assert_eq!(tokenize("echo \"open"), Err(ShellError::UnterminatedQuote));
+    FILESYSTEM_SERVICE.lock().delete_file(cluster).unwrap();
+}
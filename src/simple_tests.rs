@@ -1,6 +1,7 @@
 // Simple tests for EMOS Microkernel
 use alloc::string::ToString;
 use crate::println;
+use crate::tests::{fail, TestReport};
 use crate::process::pcb::ProcessPriority;
 use crate::services::process_service::{
     create_process, terminate_process, list_processes, get_system_stats,
@@ -14,15 +15,17 @@ use crate::services::file_system_service::{
 };
 
 /// Run simple microkernel tests
-pub fn run_simple_tests() {
+pub fn run_simple_tests() -> TestReport {
     println!("\n SIMPLE TESTS");
     
-    test_process_creation();
-    test_memory_allocation();
-    test_file_operations();
-    test_system_integration();
+    let mut report = TestReport::default();
+    report.run(test_process_creation);
+    report.run(test_memory_allocation);
+    report.run(test_file_operations);
+    report.run(test_system_integration);
     
-    println!("\n Simple tests completed!");
+    println!("\n Simple tests completed: {} passed, {} failed", report.passed, report.failed);
+    report
 }
 
 /// Test process creation and management
@@ -51,10 +54,10 @@ fn test_process_creation() {
             // Terminate process
             match terminate_process(pid, 0) {
                 Ok(_) => println!("   Terminated process {}", pid),
-                Err(e) => println!("   Failed to terminate: {:?}", e),
+                Err(e) => fail!("   Failed to terminate: {:?}", e),
             }
         }
-        Err(e) => println!("   Failed to create process: {:?}", e),
+        Err(e) => fail!("   Failed to create process: {:?}", e),
     }
 }
 
@@ -74,10 +77,10 @@ fn test_memory_allocation() {
             // Deallocate
             match deallocate_memory(region_id) {
                 Ok(_) => println!("   Deallocated region {}", region_id),
-                Err(e) => println!("   Failed to deallocate: {:?}", e),
+                Err(e) => fail!("   Failed to deallocate: {:?}", e),
             }
         }
-        Err(e) => println!("   Failed to allocate memory: {:?}", e),
+        Err(e) => fail!("   Failed to allocate memory: {:?}", e),
     }
 }
 
@@ -94,7 +97,7 @@ fn test_file_operations() {
             let data = b"Hello, EMOS!";
             match write_file(cluster, data) {
                 Ok(size) => println!("   Wrote {} bytes", size),
-                Err(e) => println!("   Failed to write: {:?}", e),
+                Err(e) => fail!("   Failed to write: {:?}", e),
             }
             
             // Read from file
@@ -103,14 +106,14 @@ fn test_file_operations() {
                     let content = core::str::from_utf8(&data).unwrap_or("Invalid UTF-8");
                     println!("   Read: {}", content);
                 }
-                Err(e) => println!("   Failed to read: {:?}", e),
+                Err(e) => fail!("   Failed to read: {:?}", e),
             }
             
             // List files
             let files = list_files();
             println!("   Files in directory: {}", files.len());
         }
-        Err(e) => println!("   Failed to create file: {:?}", e),
+        Err(e) => fail!("   Failed to create file: {:?}", e),
     }
 }
 
@@ -125,7 +128,7 @@ fn test_system_integration() {
             pid
         }
         Err(e) => {
-            println!("   Failed to create process: {:?}", e);
+            fail!("   Failed to create process: {:?}", e);
             return;
         }
     };
@@ -137,7 +140,7 @@ fn test_system_integration() {
             region
         }
         Err(e) => {
-            println!("   Failed to allocate memory: {:?}", e);
+            fail!("   Failed to allocate memory: {:?}", e);
             return;
         }
     };
@@ -149,7 +152,7 @@ fn test_system_integration() {
             cluster
         }
         Err(e) => {
-            println!("   Failed to create file: {:?}", e);
+            fail!("   Failed to create file: {:?}", e);
             return;
         }
     };
@@ -158,7 +161,7 @@ fn test_system_integration() {
     let process_data = b"Integration test data";
     match write_file(file_cluster, process_data) {
         Ok(size) => println!("   Wrote {} bytes of process data", size),
-        Err(e) => println!("   Failed to write process data: {:?}", e),
+        Err(e) => fail!("   Failed to write process data: {:?}", e),
     }
     
     // Schedule process
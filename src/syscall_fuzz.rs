@@ -0,0 +1,127 @@
+// Syscall fuzzer for EMOS Microkernel
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use crate::process::pcb::ProcessPriority;
+use crate::random::Rng;
+use crate::syscalls::{handle_syscall, SyscallArgs, SyscallNumber, SyscallResult, MAX_SYSCALLS};
+
+/// Syscalls the fuzzer never issues: they power off the machine, wipe every service,
+/// or take the fuzzing process off the CPU, none of which says anything about argument
+/// validation
+const SKIPPED: &[SyscallNumber] = &[
+    SyscallNumber::ExitProcess,
+    SyscallNumber::Yield,
+    SyscallNumber::YieldTo,
+    SyscallNumber::Shutdown,
+    SyscallNumber::Sleep,
+    SyscallNumber::RunSelfTest,
+    SyscallNumber::ExitGroup,
+    SyscallNumber::FutexWait,
+];
+
+/// Outcome counts for one syscall number
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FuzzTally {
+    pub successes: u64,
+    pub errors: u64,
+}
+
+/// What a fuzzing run issued, keyed by syscall number. Numbers with no handler show up
+/// here too, and should only ever have errors.
+#[derive(Debug, Clone, Default)]
+pub struct FuzzReport {
+    pub seed: u64,
+    pub calls: BTreeMap<u64, FuzzTally>,
+}
+
+impl FuzzReport {
+    pub fn total(&self) -> u64 {
+        self.calls.values().map(|tally| tally.successes + tally.errors).sum()
+    }
+
+    pub fn print(&self) {
+        crate::println!("Syscall fuzz (seed 0x{:x}): {} calls", self.seed, self.total());
+        for (number, tally) in &self.calls {
+            crate::println!("  #{:<3} ok {:>4}  err {:>4}", number, tally.successes, tally.errors);
+        }
+    }
+}
+
+/// A register value biased towards the edges where validation bugs hide
+fn fuzz_value(rng: &mut Rng) -> u64 {
+    match rng.next_u64() % 4 {
+        0 => rng.next_u64() % 16,
+        1 => u64::MAX - rng.next_u64() % 16,
+        2 => 1 << (rng.next_u64() % 64),
+        _ => rng.next_u64(),
+    }
+}
+
+/// Issue `iterations` syscalls with numbers (including unassigned and out-of-range ones)
+/// and registers drawn from `seed`, from a throwaway process, and tally the results.
+/// A panic or fault anywhere in here is a validation bug in a handler; the seed is
+/// printed first so the run can be replayed.
+///
+/// The calls run on fresh services set aside with `take_all`, so they cannot touch the
+/// live processes and files, and everything they create is dropped afterwards.
+pub fn fuzz_syscalls(seed: u64, iterations: u64) -> FuzzReport {
+    use crate::services::process_service::{create_process, yield_to};
+
+    crate::println!("Syscall fuzz: seed 0x{:x}", seed);
+    let mut rng = Rng::new(seed);
+    let mut report = FuzzReport { seed, calls: BTreeMap::new() };
+
+    let live = crate::services::take_all();
+    let fuzzer = create_process("syscall_fuzz".to_string(), ProcessPriority::Low, 4096, 4096)
+        .expect("cannot create fuzzing process");
+    yield_to(fuzzer).expect("cannot switch to fuzzing process");
+
+    let mut issued = 0;
+    while issued < iterations {
+        let number = match rng.next_u64() % 8 {
+            0 => rng.next_u64(),
+            _ => rng.next_u64() % (MAX_SYSCALLS as u64 + 8),
+        };
+        if SKIPPED.iter().any(|&skipped| skipped as u64 == number) {
+            continue;
+        }
+        let args = SyscallArgs {
+            arg0: fuzz_value(&mut rng),
+            arg1: fuzz_value(&mut rng),
+            arg2: fuzz_value(&mut rng),
+            arg3: fuzz_value(&mut rng),
+            arg4: fuzz_value(&mut rng),
+            arg5: fuzz_value(&mut rng),
+        };
+
+        let tally = report.calls.entry(number).or_default();
+        match handle_syscall(number, args) {
+            SyscallResult::Success(_) => tally.successes += 1,
+            SyscallResult::Error(_) => tally.errors += 1,
+        }
+        issued += 1;
+    }
+
+    yield_to(0).expect("cannot switch back from fuzzing process");
+    // Unmaps whatever the calls grew the heaps and stacks to
+    crate::services::restore_all(live);
+    report
+}
+
+#[test_case]
+fn test_fuzzed_syscalls_only_return_results() {
+    // Fixed, so a failure reproduces on every run
+    const SEED: u64 = 0x0123_4567_89ab_cdef;
+
+    let report = fuzz_syscalls(SEED, 2000);
+    assert_eq!(report.seed, SEED);
+    report.print();
+    assert_eq!(report.total(), 2000);
+
+    // Unassigned numbers can never succeed
+    for (&number, tally) in &report.calls {
+        if !crate::syscalls::is_registered(number) {
+            assert_eq!(tally.successes, 0, "unregistered syscall {} succeeded", number);
+        }
+    }
+}
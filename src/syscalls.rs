@@ -1,10 +1,13 @@
 // src/syscalls.rs
 use core::fmt;
 use alloc::string::ToString;
+use alloc::vec::Vec;
 use crate::serial;
+use spin::Mutex;
 
 
 /// System call numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u64)]
 pub enum SyscallNumber {
     SendMessage = 0,
@@ -17,6 +20,61 @@ pub enum SyscallNumber {
     GetPid = 7,
     MapMemory = 8,
     UnmapMemory = 9,
+    Exec = 10,
+    GetWallClock = 11,
+    Getcwd = 12,
+    YieldTo = 13,
+    Open = 14,
+    Close = 15,
+    Nice = 16,
+    Shutdown = 17,
+    Sbrk = 18,
+    Sleep = 19,
+    RunSelfTest = 20,
+    GetRandom = 21,
+    GetPriority = 22,
+    SetPriority = 23,
+    ListProcesses = 24,
+    ExitGroup = 25,
+    DeviceIoctl = 26,
+    FutexWait = 27,
+    FutexWake = 28,
+    Dup = 29,
+}
+
+impl SyscallNumber {
+    pub const ALL: &'static [SyscallNumber] = &[
+        SyscallNumber::SendMessage,
+        SyscallNumber::ReceiveMessage,
+        SyscallNumber::AllocateMemory,
+        SyscallNumber::DeallocateMemory,
+        SyscallNumber::CreateProcess,
+        SyscallNumber::ExitProcess,
+        SyscallNumber::Yield,
+        SyscallNumber::GetPid,
+        SyscallNumber::MapMemory,
+        SyscallNumber::UnmapMemory,
+        SyscallNumber::Exec,
+        SyscallNumber::GetWallClock,
+        SyscallNumber::Getcwd,
+        SyscallNumber::YieldTo,
+        SyscallNumber::Open,
+        SyscallNumber::Close,
+        SyscallNumber::Nice,
+        SyscallNumber::Shutdown,
+        SyscallNumber::Sbrk,
+        SyscallNumber::Sleep,
+        SyscallNumber::RunSelfTest,
+        SyscallNumber::GetRandom,
+        SyscallNumber::GetPriority,
+        SyscallNumber::SetPriority,
+        SyscallNumber::ListProcesses,
+        SyscallNumber::ExitGroup,
+        SyscallNumber::DeviceIoctl,
+        SyscallNumber::FutexWait,
+        SyscallNumber::FutexWake,
+        SyscallNumber::Dup,
+    ];
 }
 
 /// System call arguments (up to 6 arguments in x86_64)
@@ -51,6 +109,7 @@ pub enum SyscallError {
     InvalidMemoryRegion,
     CapabilityDenied,
     NoCurrentProcess,
+    WouldBlock,
 }
 
 impl fmt::Display for SyscallError {
@@ -67,6 +126,7 @@ impl fmt::Display for SyscallError {
             SyscallError::InvalidMemoryRegion => write!(f, "Invalid memory region"),
             SyscallError::CapabilityDenied => write!(f, "Capability denied"),
             SyscallError::NoCurrentProcess => write!(f, "No current process"),
+            SyscallError::WouldBlock => write!(f, "Operation would block"),
         }
     }
 }
@@ -105,97 +165,304 @@ pub type SyscallHandler = fn(SyscallArgs) -> SyscallResult;
 //         _ => SyscallResult::Error(SyscallError::InvalidSyscall),
 //     }
 // }
+
+/// Number of slots in the dispatch table
+pub const MAX_SYSCALLS: usize = 64;
+
+/// How a syscall interprets one argument register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Plain integer; any value is accepted
+    Value,
+    /// Userspace pointer that must not be null
+    Pointer,
+}
+
+/// A registered syscall: its handler and the meaningful argument registers
+#[derive(Clone, Copy)]
+pub struct SyscallEntry {
+    pub handler: SyscallHandler,
+    pub args: &'static [ArgKind],
+}
+
+impl SyscallEntry {
+    /// Check the arguments against the spec and clear the unused registers
+    fn validate(&self, args: SyscallArgs) -> Result<SyscallArgs, SyscallError> {
+        let mut regs = [args.arg0, args.arg1, args.arg2, args.arg3, args.arg4, args.arg5];
+        if self.args.len() > regs.len() {
+            return Err(SyscallError::InvalidArgument);
+        }
+        for (i, reg) in regs.iter_mut().enumerate() {
+            match self.args.get(i) {
+                Some(ArgKind::Pointer) if *reg == 0 => return Err(SyscallError::InvalidArgument),
+                Some(_) => {}
+                None => *reg = 0,
+            }
+        }
+        let [arg0, arg1, arg2, arg3, arg4, arg5] = regs;
+        Ok(SyscallArgs { arg0, arg1, arg2, arg3, arg4, arg5 })
+    }
+}
+
+static SYSCALL_TABLE: Mutex<[Option<SyscallEntry>; MAX_SYSCALLS]> = Mutex::new([None; MAX_SYSCALLS]);
+
+/// Install `handler` for `syscall_num`, replacing any previous handler.
+/// `args` describes each argument register the handler reads, in order.
+pub fn register_syscall(
+    syscall_num: u64,
+    handler: SyscallHandler,
+    args: &'static [ArgKind],
+) -> Result<(), SyscallError> {
+    let mut table = SYSCALL_TABLE.lock();
+    let slot = table.get_mut(syscall_num as usize).ok_or(SyscallError::InvalidSyscall)?;
+    *slot = Some(SyscallEntry { handler, args });
+    Ok(())
+}
+
+/// Remove the handler for `syscall_num`
+pub fn unregister_syscall(syscall_num: u64) {
+    if let Some(slot) = SYSCALL_TABLE.lock().get_mut(syscall_num as usize) {
+        *slot = None;
+    }
+}
+
+pub fn is_registered(syscall_num: u64) -> bool {
+    matches!(SYSCALL_TABLE.lock().get(syscall_num as usize), Some(Some(_)))
+}
+
+/// Populate the dispatch table with the kernel's syscalls
+pub fn init() {
+    use ArgKind::{Pointer as Ptr, Value as Val};
+    use SyscallNumber::*;
+
+    let handlers: &[(SyscallNumber, SyscallHandler, &'static [ArgKind])] = &[
+        // BRING-UP PATH (safe in interrupt/syscall context); shadows SendMessage/ReceiveMessage
+        (SendMessage, syscall_bringup_read_byte, &[]),
+        (ReceiveMessage, syscall_bringup_write_byte, &[Val]),
+        // Everything below is NOT interrupt-safe yet (println!, alloc, services, locks, etc.)
+        (AllocateMemory, syscall_allocate_memory, &[Val, Val]),
+        (DeallocateMemory, syscall_deallocate_memory, &[Val]),
+        (CreateProcess, syscall_create_process, &[Ptr, Val, Val, Val, Val]),
+        (ExitProcess, syscall_exit_process, &[Val]),
+        (Yield, syscall_yield, &[]),
+        (GetPid, syscall_get_pid, &[]),
+        (MapMemory, syscall_map_memory, &[Val, Val, Val, Val]),
+        (UnmapMemory, syscall_unmap_memory, &[Val]),
+        (Exec, syscall_exec, &[Ptr, Val]),
+        (GetWallClock, syscall_get_wall_clock, &[]),
+        (Getcwd, syscall_getcwd, &[Ptr, Val]),
+        (YieldTo, syscall_yield_to, &[Val]),
+        (Open, syscall_open, &[Ptr, Val, Val]),
+        (Close, syscall_close, &[Val]),
+        (Nice, syscall_nice, &[Val]),
+        (Shutdown, syscall_shutdown, &[Val]),
+        (Sbrk, syscall_sbrk, &[Val]),
+        (Sleep, syscall_sleep, &[Val]),
+        (RunSelfTest, syscall_run_self_test, &[Val]),
+        (GetRandom, syscall_get_random, &[Ptr, Val]),
+        (GetPriority, syscall_get_priority, &[Val]),
+        (SetPriority, syscall_set_priority, &[Val, Val]),
+        (ListProcesses, syscall_list_processes, &[Ptr, Val]),
+        (ExitGroup, syscall_exit_group, &[Val]),
+        (DeviceIoctl, syscall_device_ioctl, &[Val, Val, Val, Val]),
+        (FutexWait, syscall_futex_wait, &[Ptr, Val]),
+        (FutexWake, syscall_futex_wake, &[Ptr, Val]),
+        (Dup, syscall_dup, &[Val]),
+    ];
+    for &(number, handler, args) in handlers {
+        register_syscall(number as u64, handler, args).expect("syscall number out of range");
+    }
+}
+
+/// Dispatch a system call through the handler table
 pub fn handle_syscall(syscall_num: u64, args: SyscallArgs) -> SyscallResult {
-    // BRING-UP PATH (safe in interrupt/syscall context)
-    // syscall 0: read a single byte from keyboard
-    if syscall_num == 0 {
-        match syscall_read_byte() {
-            Some(byte) => return SyscallResult::Success(byte as u64),
-            None => return SyscallResult::Error(SyscallError::NoMessageAvailable),
+    // Copy the entry out so the handler runs without the table locked
+    let entry = SYSCALL_TABLE.lock().get(syscall_num as usize).copied().flatten();
+    match entry {
+        Some(entry) => {
+            let start = crate::tsc::read();
+            let result = match entry.validate(args) {
+                Ok(args) => (entry.handler)(args),
+                Err(e) => SyscallResult::Error(e),
+            };
+            record_latency(syscall_num, crate::tsc::read().wrapping_sub(start));
+            result
         }
+        None => SyscallResult::Error(SyscallError::InvalidSyscall),
     }
-    // syscall 1: write a single byte in arg0 (rdi) to VGA
-    if syscall_num == 1 {
-        vga_write_byte(args.arg0 as u8);
-        return SyscallResult::Success(0);
+}
+
+/// Accumulated running time of one syscall, in TSC cycles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallLatency {
+    pub count: u64,
+    pub total: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl SyscallLatency {
+    const fn new() -> Self {
+        SyscallLatency { count: 0, total: 0, min: u64::MAX, max: 0 }
     }
 
-    // Everything below is NOT interrupt-safe yet (println!, alloc, services, locks, etc.)
-    SyscallResult::Error(SyscallError::InvalidSyscall)
+    pub fn average(&self) -> u64 {
+        if self.count == 0 { 0 } else { self.total / self.count }
+    }
 }
 
-pub fn vga_write_byte(byte: u8) {
-    const VGA_BUFFER: *mut u8 = 0xb8000 as *mut u8;
-    const BUFFER_WIDTH: usize = 80;
-    const BUFFER_HEIGHT: usize = 25;
-    static mut CURSOR_ROW: usize = 0;
-    static mut CURSOR_COL: usize = 0;
-    static mut INITIALIZED: bool = false;
+static SYSCALL_LATENCY: Mutex<[SyscallLatency; MAX_SYSCALLS]> = Mutex::new([SyscallLatency::new(); MAX_SYSCALLS]);
 
-    unsafe {
-        if !INITIALIZED {
+fn record_latency(syscall_num: u64, cycles: u64) {
+    if let Some(stats) = SYSCALL_LATENCY.lock().get_mut(syscall_num as usize) {
+        stats.count += 1;
+        stats.total = stats.total.saturating_add(cycles);
+        stats.min = stats.min.min(cycles);
+        stats.max = stats.max.max(cycles);
+    }
+}
+
+/// Latency figures for syscall `number`, if it has been called
+pub fn syscall_latency(number: SyscallNumber) -> Option<SyscallLatency> {
+    SYSCALL_LATENCY.lock().get(number as usize).copied().filter(|stats| stats.count > 0)
+}
+
+/// Call count and average cycles of every syscall that has been called
+pub fn syscall_stats() -> Vec<(SyscallNumber, u64, u64)> {
+    let latency = SYSCALL_LATENCY.lock();
+    SyscallNumber::ALL
+        .iter()
+        .map(|&number| (number, latency[number as usize]))
+        .filter(|(_, stats)| stats.count > 0)
+        .map(|(number, stats)| (number, stats.count, stats.average()))
+        .collect()
+}
+
+/// syscall 0: read a single byte from keyboard
+fn syscall_bringup_read_byte(_args: SyscallArgs) -> SyscallResult {
+    match syscall_read_byte() {
+        Some(byte) => SyscallResult::Success(byte as u64),
+        None => SyscallResult::Error(SyscallError::NoMessageAvailable),
+    }
+}
+
+/// syscall 1: write a single byte in arg0 (rdi) to VGA
+fn syscall_bringup_write_byte(args: SyscallArgs) -> SyscallResult {
+    match vga_write_byte(args.arg0 as u8) {
+        Ok(()) => SyscallResult::Success(0),
+        Err(e) => SyscallResult::Error(e),
+    }
+}
+
+const VGA_BUFFER: *mut u8 = 0xb8000 as *mut u8;
+const VGA_WIDTH: usize = 80;
+const VGA_HEIGHT: usize = 25;
+const VGA_CELLS_BYTES: usize = VGA_WIDTH * VGA_HEIGHT * 2;
+const VGA_ATTRIBUTE: u8 = 0x0f; // White on black
+const TAB_WIDTH: usize = 8;
+
+/// Cursor for the raw byte path to VGA memory, shared by the bring-up write syscall and
+/// the keyboard IRQ echo
+struct RawConsole {
+    row: usize,
+    col: usize,
+    initialized: bool,
+}
+
+static RAW_CONSOLE: Mutex<RawConsole> = Mutex::new(RawConsole::new());
+
+impl RawConsole {
+    const fn new() -> Self {
+        RawConsole { row: 0, col: 0, initialized: false }
+    }
+
+    /// Write `byte` into the `VGA_CELLS_BYTES`-byte text buffer at `buffer`.
+    ///
+    /// Printable ASCII is drawn at the cursor; `\n`, `\r`, `\t` and backspace move it.
+    /// Writing past the last row scrolls the screen up one line. Other bytes are rejected.
+    ///
+    /// Safety: `buffer` must be valid for `VGA_CELLS_BYTES` bytes of reads and writes.
+    unsafe fn write_byte(&mut self, buffer: *mut u8, byte: u8) -> Result<(), SyscallError> {
+        if !self.initialized {
             // Clear screen on first write
-            for i in 0..(BUFFER_WIDTH * BUFFER_HEIGHT * 2) {
-                *VGA_BUFFER.add(i) = if i % 2 == 0 { b' ' as u8 } else { 0x0f };
+            for row in 0..VGA_HEIGHT {
+                unsafe { clear_row(buffer, row) };
             }
-            INITIALIZED = true;
-            CURSOR_ROW = 0;
-            CURSOR_COL = 0;
+            self.initialized = true;
+            self.row = 0;
+            self.col = 0;
         }
 
         match byte {
-            b'\n' => {
-                CURSOR_COL = 0;
-                CURSOR_ROW += 1;
-                if CURSOR_ROW >= BUFFER_HEIGHT {
-                    // Scroll up
-                    for row in 1..BUFFER_HEIGHT {
-                        for col in 0..BUFFER_WIDTH {
-                            let src_pos = (row * BUFFER_WIDTH + col) * 2;
-                            let dst_pos = ((row - 1) * BUFFER_WIDTH + col) * 2;
-                            *VGA_BUFFER.add(dst_pos) = *VGA_BUFFER.add(src_pos);
-                            *VGA_BUFFER.add(dst_pos + 1) = *VGA_BUFFER.add(src_pos + 1);
-                        }
-                    }
-                    // Clear last row
-                    let last_row_start = (BUFFER_HEIGHT - 1) * BUFFER_WIDTH * 2;
-                    for i in 0..(BUFFER_WIDTH * 2) {
-                        *VGA_BUFFER.add(last_row_start + i) = if i % 2 == 0 { b' ' as u8 } else { 0x0f };
-                    }
-                    CURSOR_ROW = BUFFER_HEIGHT - 1;
+            b'\n' => unsafe { self.new_line(buffer) },
+            b'\r' => self.col = 0,
+            b'\t' => {
+                self.col = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                if self.col >= VGA_WIDTH {
+                    unsafe { self.new_line(buffer) };
                 }
             }
-            b'\r' => {
-                CURSOR_COL = 0;
-            }
             b'\x08' => {
                 // Backspace
-                if CURSOR_COL > 0 {
-                    CURSOR_COL -= 1;
-                    let pos = (CURSOR_ROW * BUFFER_WIDTH + CURSOR_COL) * 2;
-                    *VGA_BUFFER.add(pos) = b' ';
-                    *VGA_BUFFER.add(pos + 1) = 0x0f;
+                if self.col > 0 {
+                    self.col -= 1;
+                    unsafe { put_cell(buffer, self.row, self.col, b' ') };
                 }
             }
-            _ => {
-                if byte >= 32 && byte <= 126 {
-                    let pos = (CURSOR_ROW * BUFFER_WIDTH + CURSOR_COL) * 2;
-                    *VGA_BUFFER.add(pos) = byte;
-                    *VGA_BUFFER.add(pos + 1) = 0x0f;
-                    CURSOR_COL += 1;
-                    if CURSOR_COL >= BUFFER_WIDTH {
-                        CURSOR_COL = 0;
-                        CURSOR_ROW += 1;
-                        if CURSOR_ROW >= BUFFER_HEIGHT {
-                            CURSOR_ROW = BUFFER_HEIGHT - 1;
-                        }
-                    }
+            b' '..=b'~' => {
+                unsafe { put_cell(buffer, self.row, self.col, byte) };
+                self.col += 1;
+                if self.col >= VGA_WIDTH {
+                    unsafe { self.new_line(buffer) };
                 }
             }
+            _ => return Err(SyscallError::InvalidArgument),
+        }
+        Ok(())
+    }
+
+    unsafe fn new_line(&mut self, buffer: *mut u8) {
+        self.col = 0;
+        if self.row + 1 < VGA_HEIGHT {
+            self.row += 1;
+            return;
+        }
+        // Scroll up
+        unsafe {
+            core::ptr::copy(buffer.add(VGA_WIDTH * 2), buffer, VGA_CELLS_BYTES - VGA_WIDTH * 2);
+            clear_row(buffer, VGA_HEIGHT - 1);
+        }
+    }
+}
+
+/// Byte offset of a cell in the text buffer, or `None` off screen
+fn cell_offset(row: usize, col: usize) -> Option<usize> {
+    (row < VGA_HEIGHT && col < VGA_WIDTH).then(|| (row * VGA_WIDTH + col) * 2)
+}
+
+unsafe fn put_cell(buffer: *mut u8, row: usize, col: usize, byte: u8) {
+    if let Some(offset) = cell_offset(row, col) {
+        unsafe {
+            buffer.add(offset).write_volatile(byte);
+            buffer.add(offset + 1).write_volatile(VGA_ATTRIBUTE);
         }
     }
 }
 
+unsafe fn clear_row(buffer: *mut u8, row: usize) {
+    for col in 0..VGA_WIDTH {
+        unsafe { put_cell(buffer, row, col, b' ') };
+    }
+}
+
+/// Write one byte straight to VGA memory. Safe to call from interrupt handlers.
+pub fn vga_write_byte(byte: u8) -> Result<(), SyscallError> {
+    // Interrupts stay off while the cursor is locked so the keyboard IRQ can't deadlock on it
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        RAW_CONSOLE.lock().write_byte(VGA_BUFFER, byte)
+    })
+}
+
 /// Synchronous syscall to read a byte from keyboard input.
 /// Returns Some(scancode) if available, None if not.
 /// Simple raw scancode for debugging.
@@ -203,6 +470,72 @@ pub fn syscall_read_byte() -> Option<u8> {
     crate::services::keyboard_service::try_get_scancode()
 }
 
+/// Lowest non-canonical address; everything below is the user half of the address space.
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// Check that `[ptr, ptr + len)` is mapped user-accessible (and writable if `write`).
+///
+/// Userspace currently shares the kernel's page tables, so the caller's regions are
+/// exactly the pages mapped with `USER_ACCESSIBLE`.
+fn validate_user_range(ptr: u64, len: usize, write: bool) -> Result<(), SyscallError> {
+    use x86_64::structures::paging::{PageTableFlags, Translate};
+    use x86_64::structures::paging::mapper::TranslateResult;
+    use x86_64::VirtAddr;
+
+    if len == 0 {
+        return Ok(());
+    }
+    let end = match ptr.checked_add(len as u64) {
+        Some(end) if ptr != 0 && end <= USER_SPACE_END => end,
+        _ => return Err(SyscallError::InvalidMemoryRegion),
+    };
+
+    let mut required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if write {
+        required |= PageTableFlags::WRITABLE;
+    }
+
+    crate::memory::with_kernel_paging(|mapper, _| {
+        let mut page = ptr & !0xfff;
+        while page < end {
+            match mapper.translate(VirtAddr::new(page)) {
+                TranslateResult::Mapped { flags, .. } if flags.contains(required) => {}
+                TranslateResult::Mapped { .. } => return Err(SyscallError::PermissionDenied),
+                _ => return Err(SyscallError::InvalidMemoryRegion),
+            }
+            page += 4096;
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(SyscallError::InvalidMemoryRegion))
+}
+
+/// Most bytes one `copy_from_user` will bring onto the kernel heap
+pub const MAX_USER_COPY: usize = 16 * 1024;
+
+/// Copy `len` bytes out of userspace after validating the whole range.
+/// Lengths above `MAX_USER_COPY` are refused rather than allocated.
+pub fn copy_from_user(ptr: u64, len: usize) -> Result<Vec<u8>, SyscallError> {
+    if len > MAX_USER_COPY {
+        return Err(SyscallError::InvalidArgument);
+    }
+    validate_user_range(ptr, len, false)?;
+    let mut data = Vec::with_capacity(len);
+    unsafe {
+        core::ptr::copy_nonoverlapping(ptr as *const u8, data.as_mut_ptr(), len);
+        data.set_len(len);
+    }
+    Ok(data)
+}
+
+/// Copy `data` into userspace at `ptr` after validating the whole range is writable.
+pub fn copy_to_user(ptr: u64, data: &[u8]) -> Result<(), SyscallError> {
+    validate_user_range(ptr, data.len(), true)?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+    }
+    Ok(())
+}
 
 // Individual syscall implementations
 pub fn syscall_send_message(args: SyscallArgs) -> SyscallResult {
@@ -219,40 +552,82 @@ pub fn syscall_receive_message(args: SyscallArgs) -> SyscallResult {
 }
 
 pub fn syscall_allocate_memory(args: SyscallArgs) -> SyscallResult {
-    // TODO: Implement memory allocation
+    use crate::services::memory_service::{allocate_memory, MemoryPermissions};
+    use crate::services::process_service::{adjust_memory_usage, get_current_process};
+
+    // Extract arguments: size, permissions
     let size = args.arg0 as usize;
-    crate::println!("[SYSCALL] AllocateMemory called with size: {}", size);
-    SyscallResult::Success(0)
+    let permissions = match args.arg1 {
+        0 => MemoryPermissions::ReadOnly,
+        1 => MemoryPermissions::ReadWrite,
+        2 => MemoryPermissions::Execute,
+        3 => MemoryPermissions::ReadWriteExecute,
+        _ => MemoryPermissions::ReadWrite,
+    };
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+
+    match allocate_memory(size, permissions) {
+        Ok(region_id) => {
+            adjust_memory_usage(pid, size as isize);
+            SyscallResult::Success(region_id)
+        }
+        Err(e) => {
+            crate::println!("[SYSCALL] AllocateMemory failed: {:?}", e);
+            SyscallResult::Error(SyscallError::OutOfMemory)
+        }
+    }
 }
 
 pub fn syscall_deallocate_memory(args: SyscallArgs) -> SyscallResult {
-    // TODO: Implement memory deallocation
-    let addr = args.arg0;
-    crate::println!("[SYSCALL] DeallocateMemory called with addr: 0x{:x}", addr);
-    SyscallResult::Success(0)
+    use crate::services::memory_service::{deallocate_memory, get_memory_info};
+    use crate::services::process_service::{adjust_memory_usage, get_current_process};
+
+    // Extract arguments: region_id
+    let region_id = args.arg0;
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    let size = match get_memory_info(region_id) {
+        Some(region) if region.owner == Some(pid) => region.size,
+        Some(_) => return SyscallResult::Error(SyscallError::PermissionDenied),
+        None => return SyscallResult::Error(SyscallError::InvalidMemoryRegion),
+    };
+
+    match deallocate_memory(region_id) {
+        Ok(()) => {
+            adjust_memory_usage(pid, -(size as isize));
+            SyscallResult::Success(0)
+        }
+        Err(_) => SyscallResult::Error(SyscallError::InvalidMemoryRegion),
+    }
 }
 
 pub fn syscall_create_process(args: SyscallArgs) -> SyscallResult {
     use crate::services::process_service::create_process;
-    use crate::process::pcb::ProcessPriority;
+    use crate::process::pcb::{process_name_from_bytes, ProcessPriority, MAX_PROCESS_NAME_LEN};
     
     // Extract arguments: name_ptr, name_len, priority, stack_size, heap_size
-    let name_ptr = args.arg0 as *const u8;
+    let name_ptr = args.arg0;
     let name_len = args.arg1 as usize;
-    let priority = match args.arg2 {
-        0 => ProcessPriority::Low,
-        1 => ProcessPriority::Normal,
-        2 => ProcessPriority::High,
-        3 => ProcessPriority::Critical,
-        _ => ProcessPriority::Normal,
-    };
+    let priority = ProcessPriority::from_level(args.arg2).unwrap_or(ProcessPriority::Normal);
     let stack_size = args.arg3 as usize;
     let heap_size = args.arg4 as usize;
     
-    // Convert name from C string
-    let name = unsafe {
-        let slice = core::slice::from_raw_parts(name_ptr, name_len);
-        core::str::from_utf8(slice).unwrap_or("unknown").to_string()
+    if name_len > MAX_PROCESS_NAME_LEN {
+        return SyscallResult::Error(SyscallError::InvalidArgument);
+    }
+    let name = match copy_from_user(name_ptr, name_len) {
+        Ok(bytes) => match process_name_from_bytes(&bytes) {
+            Some(name) => name.to_string(),
+            None => return SyscallResult::Error(SyscallError::InvalidArgument),
+        },
+        Err(e) => return SyscallResult::Error(e),
     };
     
     match create_process(name, priority, stack_size, heap_size) {
@@ -304,6 +679,27 @@ pub fn syscall_yield(_args: SyscallArgs) -> SyscallResult {
     }
 }
 
+/// Directed yield: run `arg0` next if it is Ready, otherwise schedule normally
+/// and report why the handoff failed.
+pub fn syscall_yield_to(args: SyscallArgs) -> SyscallResult {
+    use crate::process::pcb::ProcessError;
+    use crate::services::process_service::{schedule_next_process, yield_to};
+
+    let target = args.arg0;
+
+    match yield_to(target) {
+        Ok(pid) => SyscallResult::Success(pid),
+        Err(e) => {
+            crate::println!("[SYSCALL] YieldTo: PID {} not runnable ({:?}), scheduling normally", target, e);
+            schedule_next_process();
+            SyscallResult::Error(match e {
+                ProcessError::ProcessNotFound => SyscallError::ProcessNotFound,
+                _ => SyscallError::InvalidProcessId,
+            })
+        }
+    }
+}
+
 pub fn syscall_get_pid(_args: SyscallArgs) -> SyscallResult {
     use crate::services::process_service::get_current_process;
     
@@ -318,18 +714,1199 @@ pub fn syscall_get_pid(_args: SyscallArgs) -> SyscallResult {
     }
 }
 
-pub fn syscall_map_memory(args: SyscallArgs) -> SyscallResult {
-    // TODO: Implement memory mapping
+pub fn syscall_exec(args: SyscallArgs) -> SyscallResult {
+    use crate::services::file_system_service::{read_file, resolve_path};
+    use crate::services::process_service::{exec, get_current_process};
+
+    // Extract arguments: path_ptr, path_len
+    let path_ptr = args.arg0;
+    let path_len = args.arg1 as usize;
+
+    let path = match copy_from_user(path_ptr, path_len) {
+        Ok(bytes) => match core::str::from_utf8(&bytes) {
+            Ok(path) => path.to_string(),
+            Err(_) => return SyscallResult::Error(SyscallError::InvalidArgument),
+        },
+        Err(e) => return SyscallResult::Error(e),
+    };
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+
+    let image = match resolve_path(&path).and_then(read_file) {
+        Ok(image) => image,
+        Err(e) => {
+            crate::println!("[SYSCALL] Exec: cannot load '{}': {:?}", path, e);
+            return SyscallResult::Error(SyscallError::InvalidArgument);
+        }
+    };
+
+    match exec(pid, &image) {
+        Ok(()) => {
+            crate::println!("[SYSCALL] Exec: PID {} now running '{}'", pid, path);
+            SyscallResult::Success(0)
+        }
+        Err(e) => {
+            crate::println!("[SYSCALL] Exec failed: {:?}", e);
+            SyscallResult::Error(SyscallError::InvalidArgument)
+        }
+    }
+}
+
+/// Returns the RTC wall-clock time as seconds since the Unix epoch
+pub fn syscall_get_wall_clock(_args: SyscallArgs) -> SyscallResult {
+    let now = crate::rtc::read_datetime();
+    SyscallResult::Success(now.to_unix_timestamp())
+}
+
+pub fn syscall_getcwd(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::{get_current_process, get_working_directory};
+
+    // Extract arguments: buf_ptr, buf_len
+    let buf_ptr = args.arg0;
+    let buf_len = args.arg1 as usize;
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    let path = match get_working_directory(pid) {
+        Some(path) => path,
+        None => return SyscallResult::Error(SyscallError::ProcessNotFound),
+    };
+
+    if path.len() > buf_len {
+        return SyscallResult::Error(SyscallError::InvalidArgument);
+    }
+    match copy_to_user(buf_ptr, path.as_bytes()) {
+        Ok(()) => SyscallResult::Success(path.len() as u64),
+        Err(e) => SyscallResult::Error(e),
+    }
+}
+
+/// Fill a user buffer with bytes from the kernel generator, returning the length written
+pub fn syscall_get_random(args: SyscallArgs) -> SyscallResult {
+    // Extract arguments: buf_ptr, buf_len
+    let buf_ptr = args.arg0;
+    let buf_len = args.arg1 as usize;
+
+    if let Err(e) = validate_user_range(buf_ptr, buf_len, true) {
+        return SyscallResult::Error(e);
+    }
+    // Fill through a stack chunk so the length never sizes a kernel allocation
+    let mut chunk = [0u8; 256];
+    let mut filled = 0;
+    while filled < buf_len {
+        let count = chunk.len().min(buf_len - filled);
+        crate::random::fill_bytes(&mut chunk[..count]);
+        if let Err(e) = copy_to_user(buf_ptr + filled as u64, &chunk[..count]) {
+            return SyscallResult::Error(e);
+        }
+        filled += count;
+    }
+    SyscallResult::Success(buf_len as u64)
+}
+
+pub fn syscall_open(args: SyscallArgs) -> SyscallResult {
+    use crate::process::pcb::{OpenFile, OPEN_READ, OPEN_WRITE};
+    use crate::services::file_system_service::{check_write_access, is_file, resolve_path};
+    use crate::services::process_service::{get_current_process, open_fd};
+
+    // Extract arguments: path_ptr, path_len, flags
+    let path_ptr = args.arg0;
+    let path_len = args.arg1 as usize;
+    let flags = args.arg2 as u32;
+
+    if flags & !(OPEN_READ | OPEN_WRITE) != 0 {
+        return SyscallResult::Error(SyscallError::InvalidArgument);
+    }
+    let path = match copy_from_user(path_ptr, path_len) {
+        Ok(bytes) => match core::str::from_utf8(&bytes) {
+            Ok(path) => path.to_string(),
+            Err(_) => return SyscallResult::Error(SyscallError::InvalidArgument),
+        },
+        Err(e) => return SyscallResult::Error(e),
+    };
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    let cluster = match resolve_path(&path) {
+        Ok(cluster) if is_file(cluster) => cluster,
+        _ => return SyscallResult::Error(SyscallError::InvalidArgument),
+    };
+    // A writable descriptor outlives the call (a shared mapping writes back through it at
+    // exit), so the write capability is checked now rather than on each write
+    if flags & OPEN_WRITE != 0 && check_write_access(cluster).is_err() {
+        return SyscallResult::Error(SyscallError::PermissionDenied);
+    }
+
+    match open_fd(pid, OpenFile::File { cluster, flags }) {
+        Ok(fd) => SyscallResult::Success(fd),
+        Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
+    }
+}
+
+pub fn syscall_close(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::{close_fd, get_current_process};
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    match close_fd(pid, args.arg0) {
+        Ok(()) => SyscallResult::Success(0),
+        Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+    }
+}
+
+pub fn syscall_dup(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::{dup_fd, get_current_process};
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    match dup_fd(pid, args.arg0) {
+        Ok(fd) => SyscallResult::Success(fd),
+        Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+    }
+}
+
+pub fn syscall_nice(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::{get_current_process, set_nice};
+
+    // Extract arguments: nice (sign-extended); out-of-range values are clamped
+    let nice = (args.arg0 as i64).clamp(i8::MIN as i64, i8::MAX as i64) as i8;
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+
+    match set_nice(pid, nice) {
+        Ok(applied) => SyscallResult::Success(applied as i64 as u64),
+        Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
+    }
+}
+
+/// Priority of process `arg0` as its level (0 = Low .. 3 = Critical)
+pub fn syscall_get_priority(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::get_process_stats;
+
+    match get_process_stats(args.arg0) {
+        Some(stats) => SyscallResult::Success(stats.priority as u64),
+        None => SyscallResult::Error(SyscallError::ProcessNotFound),
+    }
+}
+
+/// Set process `arg0`'s priority to level `arg1`. Only privileged callers may raise a
+/// priority or change another process's.
+pub fn syscall_set_priority(args: SyscallArgs) -> SyscallResult {
+    use crate::process::pcb::{ProcessError, ProcessPriority};
+    use crate::services::process_service::{change_priority, get_current_process};
+
+    let priority = match ProcessPriority::from_level(args.arg1) {
+        Some(priority) => priority,
+        None => return SyscallResult::Error(SyscallError::InvalidArgument),
+    };
+    let caller = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+
+    match change_priority(caller, args.arg0, priority) {
+        Ok(()) => SyscallResult::Success(0),
+        Err(ProcessError::PermissionDenied) => SyscallResult::Error(SyscallError::PermissionDenied),
+        Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
+    }
+}
+
+/// Terminate every process in the caller's group, the caller included, with exit code
+/// `arg0`. Returns the number terminated.
+pub fn syscall_exit_group(args: SyscallArgs) -> SyscallResult {
+    use crate::process::pcb::ProcessError;
+    use crate::services::process_service::{exit_group, get_current_process};
+
+    let caller = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+
+    match exit_group(caller, args.arg0 as i32) {
+        Ok(count) => SyscallResult::Success(count as u64),
+        Err(ProcessError::PermissionDenied) => SyscallResult::Error(SyscallError::PermissionDenied),
+        Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
+    }
+}
+
+/// Longest buffer `syscall_device_ioctl` reads or writes in one call
+pub const MAX_IOCTL_LEN: usize = 4096;
+
+/// Run device command `arg1` (`DEVICE_READ` or `DEVICE_WRITE`) on device `arg0` with the
+/// `arg3`-byte buffer at `arg2`, at most `MAX_IOCTL_LEN` bytes. Returns the number of
+/// bytes read or written.
+///
+/// The caller must hold a Device capability for the device granting read (for reads) or
+/// write (for writes).
+pub fn syscall_device_ioctl(args: SyscallArgs) -> SyscallResult {
+    use crate::ipc::DeviceRequest;
+    use crate::process::pcb::{CapabilityPermissions, ResourceType};
+    use crate::services::device_service::{execute, DEVICE_READ, DEVICE_WRITE};
+    use crate::services::process_service::{get_current_process, has_capability};
+
+    // Extract arguments: device_id, command, buf_ptr, buf_len
+    let device_id = args.arg0;
+    let command = args.arg1;
+    let buf_ptr = args.arg2;
+    let buf_len = args.arg3 as usize;
+
+    let write = match command {
+        DEVICE_READ => false,
+        DEVICE_WRITE => true,
+        _ => return SyscallResult::Error(SyscallError::InvalidArgument),
+    };
+    if buf_len > MAX_IOCTL_LEN {
+        return SyscallResult::Error(SyscallError::InvalidArgument);
+    }
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    let needed = CapabilityPermissions { read: !write, write, execute: false, admin: false };
+    if !has_capability(pid, ResourceType::Device, device_id, needed) {
+        return SyscallResult::Error(SyscallError::CapabilityDenied);
+    }
+
+    let payload = if write {
+        match copy_from_user(buf_ptr, buf_len) {
+            Ok(bytes) => bytes,
+            Err(e) => return SyscallResult::Error(e),
+        }
+    } else {
+        // Check the destination before the device consumes any input
+        if let Err(e) = validate_user_range(buf_ptr, buf_len, true) {
+            return SyscallResult::Error(e);
+        }
+        (buf_len as u64).to_le_bytes().to_vec()
+    };
+
+    match execute(&DeviceRequest { device_id, command, payload }) {
+        Ok(bytes) if write => {
+            // A write answers with the byte count as a little-endian u64
+            let written = bytes.get(..8).and_then(|raw| raw.try_into().ok()).map_or(0, u64::from_le_bytes);
+            SyscallResult::Success(written)
+        }
+        Ok(bytes) => match copy_to_user(buf_ptr, &bytes) {
+            Ok(()) => SyscallResult::Success(bytes.len() as u64),
+            Err(e) => SyscallResult::Error(e),
+        },
+        Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Size and alignment of a futex word
+const FUTEX_WORD_SIZE: u64 = 4;
+
+/// Validate the futex word at `addr` and return its physical address, which names the
+/// futex however many mappings of it there are
+fn futex_key(addr: u64) -> Result<u64, SyscallError> {
+    if addr % FUTEX_WORD_SIZE != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    validate_user_range(addr, FUTEX_WORD_SIZE as usize, false)?;
+    crate::memory::physical_address(x86_64::VirtAddr::new(addr))
+        .map(|phys| phys.as_u64())
+        .ok_or(SyscallError::InvalidMemoryRegion)
+}
+
+/// Block until woken by `FutexWake` if the u32 at `arg0` still holds `arg1`, or fail with
+/// `WouldBlock` straight away if it does not
+pub fn syscall_futex_wait(args: SyscallArgs) -> SyscallResult {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use crate::services::process_service::{futex_wait, get_current_process, schedule_next_process};
+
+    // Extract arguments: addr, expected
     let addr = args.arg0;
-    let size = args.arg1;
-    crate::println!("[SYSCALL] MapMemory called with addr: 0x{:x}, size: {}", addr, size);
-    SyscallResult::Success(0)
+    let expected = args.arg1 as u32;
+
+    let key = match futex_key(addr) {
+        Ok(key) => key,
+        Err(e) => return SyscallResult::Error(e),
+    };
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+
+    // The word was validated as mapped and aligned above
+    let word = unsafe { &*(addr as *const AtomicU32) };
+    match futex_wait(pid, key, || word.load(Ordering::SeqCst) == expected) {
+        Ok(true) => {
+            // FutexWake makes the process Ready again
+            schedule_next_process();
+            SyscallResult::Success(0)
+        }
+        Ok(false) => SyscallResult::Error(SyscallError::WouldBlock),
+        Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
+    }
 }
 
-pub fn syscall_unmap_memory(args: SyscallArgs) -> SyscallResult {
-    // TODO: Implement memory unmapping
+/// Wake up to `arg1` processes waiting on the futex word at `arg0`, through whichever
+/// mapping they waited on it. Returns the number woken.
+pub fn syscall_futex_wake(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::futex_wake;
+
+    // Extract arguments: addr, count
     let addr = args.arg0;
-    crate::println!("[SYSCALL] UnmapMemory called with addr: 0x{:x}", addr);
+    let count = args.arg1 as usize;
+
+    match futex_key(addr) {
+        Ok(key) => SyscallResult::Success(futex_wake(key, count) as u64),
+        Err(e) => SyscallResult::Error(e),
+    }
+}
+
+/// One entry of the `ListProcesses` buffer: pid (u64), state (u32) and priority level
+/// (u32), little-endian with no padding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessRecord {
+    pub pid: u64,
+    pub state: u32, // `ProcessState` discriminant: 0 = Running .. 4 = Zombie
+    pub priority: u32,
+}
+
+impl ProcessRecord {
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.pid.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.state.to_le_bytes());
+        bytes[12..].copy_from_slice(&self.priority.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let word = |range: core::ops::Range<usize>| {
+            bytes[range].iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+        };
+        ProcessRecord { pid: word(0..8), state: word(8..12) as u32, priority: word(12..16) as u32 }
+    }
+}
+
+/// Fill the buffer at `arg0` with up to `arg1` `ProcessRecord`s in pid order.
+/// Returns the total number of processes, which exceeds `arg1` if the list was truncated.
+pub fn syscall_list_processes(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::list_process_stats;
+
+    // Extract arguments: buf_ptr, capacity (in records)
+    let buf_ptr = args.arg0;
+    let capacity = args.arg1 as usize;
+
+    // The whole buffer must belong to the caller, not just the part written this time
+    let buf_len = match capacity.checked_mul(ProcessRecord::SIZE) {
+        Some(len) => len,
+        None => return SyscallResult::Error(SyscallError::InvalidArgument),
+    };
+    if let Err(e) = validate_user_range(buf_ptr, buf_len, true) {
+        return SyscallResult::Error(e);
+    }
+
+    let stats = list_process_stats();
+    let bytes: Vec<u8> = stats
+        .iter()
+        .take(capacity)
+        .flat_map(|stats| ProcessRecord {
+            pid: stats.pid,
+            state: stats.state as u32,
+            priority: stats.priority as u32,
+        }.to_bytes())
+        .collect();
+    match copy_to_user(buf_ptr, &bytes) {
+        Ok(()) => SyscallResult::Success(stats.len() as u64),
+        Err(e) => SyscallResult::Error(e),
+    }
+}
+
+/// arg0: 0 = power off, 1 = reboot. Does not return on success.
+pub fn syscall_shutdown(args: SyscallArgs) -> SyscallResult {
+    match args.arg0 {
+        0 => crate::power::shutdown(),
+        1 => crate::power::reboot(),
+        _ => SyscallResult::Error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Move the current process's heap break by `arg0` (signed) bytes and return the old break
+pub fn syscall_sbrk(args: SyscallArgs) -> SyscallResult {
+    use crate::process::pcb::ProcessError;
+    use crate::services::process_service::{get_current_process, sbrk};
+
+    let increment = args.arg0 as i64 as isize;
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    match sbrk(pid, increment) {
+        Ok(old_break) => SyscallResult::Success(old_break.as_u64()),
+        Err(ProcessError::InsufficientMemory) => SyscallResult::Error(SyscallError::OutOfMemory),
+        Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Block the current process for at least `arg0` milliseconds
+pub fn syscall_sleep(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::{get_current_process, schedule_next_process, sleep_ms};
+
+    let ms = args.arg0;
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    if ms == 0 {
+        return SyscallResult::Success(0);
+    }
+    match sleep_ms(pid, ms) {
+        Ok(()) => {
+            // The timer handler makes the process Ready again once the deadline passes
+            schedule_next_process();
+            SyscallResult::Success(0)
+        }
+        Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
+    }
+}
+
+/// `syscall_run_self_test` selector for `tests::run_all_tests`
+pub const SELF_TEST_ALL: u64 = 0;
+/// `syscall_run_self_test` selector for `simple_tests::run_simple_tests`
+pub const SELF_TEST_SIMPLE: u64 = 1;
+
+/// Run the in-kernel test battery chosen by `arg0` and return the passed count in the
+/// low 32 bits and the failed count in the high 32 bits.
+///
+/// The battery runs on fresh services so repeated runs see the same starting state;
+/// the live processes, memory regions and files are set aside and put back afterwards,
+/// and the pages the battery mapped are freed. Only privileged callers may run it.
+pub fn syscall_run_self_test(args: SyscallArgs) -> SyscallResult {
+    use crate::services::process_service::{get_current_process, is_privileged};
+
+    if !is_privileged(get_current_process().unwrap_or(0)) {
+        return SyscallResult::Error(SyscallError::PermissionDenied);
+    }
+    let run: fn() -> crate::tests::TestReport = match args.arg0 {
+        SELF_TEST_ALL => crate::tests::run_all_tests,
+        SELF_TEST_SIMPLE => crate::simple_tests::run_simple_tests,
+        _ => return SyscallResult::Error(SyscallError::InvalidArgument),
+    };
+
+    let live = crate::services::take_all();
+    let report = run();
+    crate::services::restore_all(live);
+    SyscallResult::Success((report.failed as u64) << 32 | report.passed as u64)
+}
+
+/// `syscall_map_memory` flag: write the mapping back to the file on unmap
+pub const MAP_SHARED: u64 = 0x1;
+
+/// Longest file mapping `syscall_map_memory` makes; a shared one is copied back through
+/// `copy_from_user` on unmap
+pub const MAX_MAP_LEN: usize = MAX_USER_COPY;
+
+/// Map `arg2` bytes of the file open on fd `arg0`, starting at byte `arg1`, and return
+/// the address. Bytes past the end of the file read as zero. `arg3` takes `MAP_SHARED`.
+/// Mappings are limited to `MAX_MAP_LEN` bytes.
+pub fn syscall_map_memory(args: SyscallArgs) -> SyscallResult {
+    use crate::process::pcb::{OpenFile, OPEN_READ, OPEN_WRITE};
+    use crate::services::file_system_service::read_file;
+    use crate::services::memory_service::{insert_file_mapping, reserve_mapping_space, FileMapping};
+    use crate::services::process_service::{adjust_memory_usage, get_current_process, get_fd};
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    // Extract arguments: fd, offset, len, flags
+    let fd = args.arg0;
+    let offset = args.arg1 as usize;
+    let len = args.arg2 as usize;
+    let flags = args.arg3;
+
+    if len == 0 || len > MAX_MAP_LEN || flags & !MAP_SHARED != 0 {
+        return SyscallResult::Error(SyscallError::InvalidArgument);
+    }
+    let shared = flags & MAP_SHARED != 0;
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    let cluster = match get_fd(pid, fd) {
+        Ok(OpenFile::File { cluster, flags }) if flags & OPEN_READ != 0 => {
+            if shared && flags & OPEN_WRITE == 0 {
+                return SyscallResult::Error(SyscallError::PermissionDenied);
+            }
+            cluster
+        }
+        Ok(_) => return SyscallResult::Error(SyscallError::PermissionDenied),
+        Err(_) => return SyscallResult::Error(SyscallError::InvalidArgument),
+    };
+    let data = match read_file(cluster) {
+        Ok(data) if offset <= data.len() => data,
+        Ok(_) => return SyscallResult::Error(SyscallError::InvalidArgument),
+        Err(_) => return SyscallResult::Error(SyscallError::PermissionDenied),
+    };
+
+    let addr = match reserve_mapping_space(len) {
+        Ok(addr) => addr,
+        Err(_) => return SyscallResult::Error(SyscallError::OutOfMemory),
+    };
+    let end = addr + len as u64;
+    let page_flags = Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE | Flags::NO_EXECUTE;
+    let pages = match crate::memory::map_range(addr, end, page_flags) {
+        Ok(pages) => pages,
+        Err(_) => return SyscallResult::Error(SyscallError::OutOfMemory),
+    };
+
+    // Freshly mapped pages are zeroed, so only the file's bytes need copying
+    let file_bytes = &data[offset..data.len().min(offset.saturating_add(len))];
+    let mapping = FileMapping { addr, len, owner: pid, fd, cluster, offset, shared };
+    if let Err(e) = copy_to_user(addr.as_u64(), file_bytes) {
+        crate::memory::unmap_range(addr, end.align_up(4096u64));
+        return SyscallResult::Error(e);
+    }
+    if insert_file_mapping(mapping).is_err() {
+        crate::memory::unmap_range(addr, end.align_up(4096u64));
+        return SyscallResult::Error(SyscallError::InvalidMemoryRegion);
+    }
+    adjust_memory_usage(pid, (pages * 4096) as isize);
+    SyscallResult::Success(addr.as_u64())
+}
+
+/// Unmap the file mapping starting at `arg0`, writing a shared mapping back to its file
+pub fn syscall_unmap_memory(args: SyscallArgs) -> SyscallResult {
+    use crate::services::file_system_service::{read_file, write_file};
+    use crate::services::memory_service::{file_mapping, remove_file_mapping};
+    use crate::services::process_service::{adjust_memory_usage, get_current_process};
+    use x86_64::VirtAddr;
+
+    let addr = match VirtAddr::try_new(args.arg0) {
+        Ok(addr) => addr,
+        Err(_) => return SyscallResult::Error(SyscallError::InvalidMemoryRegion),
+    };
+
+    let pid = match get_current_process() {
+        Some(pid) => pid,
+        None => return SyscallResult::Error(SyscallError::NoCurrentProcess),
+    };
+    let mapping = match file_mapping(addr) {
+        Some(mapping) if mapping.owner == pid => mapping,
+        Some(_) => return SyscallResult::Error(SyscallError::PermissionDenied),
+        None => return SyscallResult::Error(SyscallError::InvalidMemoryRegion),
+    };
+
+    if mapping.shared {
+        // Only the part that overlapped the file is written back; the file never grows
+        let mut data = match read_file(mapping.cluster) {
+            Ok(data) => data,
+            Err(_) => return SyscallResult::Error(SyscallError::InvalidArgument),
+        };
+        let start = mapping.offset.min(data.len());
+        let count = (data.len() - start).min(mapping.len);
+        match copy_from_user(addr.as_u64(), count) {
+            Ok(bytes) => data[start..start + count].copy_from_slice(&bytes),
+            Err(e) => return SyscallResult::Error(e),
+        }
+        match write_file(mapping.cluster, &data) {
+            Ok(written) if written == data.len() => {}
+            _ => return SyscallResult::Error(SyscallError::PermissionDenied),
+        }
+    }
+
+    let _ = remove_file_mapping(addr);
+    let end = (addr + mapping.len as u64).align_up(4096u64);
+    let pages = crate::memory::unmap_range(addr, end);
+    adjust_memory_usage(pid, -((pages * 4096) as isize));
     SyscallResult::Success(0)
 }
 
+#[test_case]
+fn test_getcwd_reports_calling_process_directory() {
+    use crate::services::process_service::{get_current_process, set_working_directory};
+
+    let pid = get_current_process().expect("no current process");
+    set_working_directory(pid, "/home/user".to_string()).unwrap();
+
+    // The buffer must live in user memory; borrow the top of the user stack
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let buf_ptr = crate::userspace::USER_STACK_TOP - 64;
+    let args = |len: u64| SyscallArgs { arg0: buf_ptr, arg1: len, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+    match handle_syscall(SyscallNumber::Getcwd as u64, args(32)) {
+        SyscallResult::Success(len) => assert_eq!(copy_from_user(buf_ptr, len as usize).unwrap(), b"/home/user"),
+        SyscallResult::Error(e) => panic!("getcwd failed: {}", e),
+    }
+
+    // Too small a buffer is rejected rather than truncated
+    match handle_syscall(SyscallNumber::Getcwd as u64, args(4)) {
+        SyscallResult::Error(e) => assert_eq!(e, SyscallError::InvalidArgument),
+        SyscallResult::Success(_) => panic!("getcwd wrote into a short buffer"),
+    }
+
+    set_working_directory(pid, "/".to_string()).unwrap();
+}
+
+#[test_case]
+fn test_get_random_fills_buffer_longer_than_one_chunk() {
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let buf_ptr = crate::userspace::USER_STACK_TOP - 4096;
+    let args = |len: u64| SyscallArgs { arg0: buf_ptr, arg1: len, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+
+    copy_to_user(buf_ptr, &[0u8; 1000]).unwrap();
+    assert!(matches!(syscall_get_random(args(1000)), SyscallResult::Success(1000)));
+    let bytes = copy_from_user(buf_ptr, 1000).unwrap();
+    // Each 256-byte chunk was filled, including the short last one
+    for chunk in bytes.chunks(256) {
+        assert!(chunk.iter().any(|&byte| byte != 0));
+    }
+
+    // A huge length is refused by the range check, not by running out of heap
+    assert!(matches!(syscall_get_random(args(u64::MAX / 2)), SyscallResult::Error(_)));
+}
+
+#[test_case]
+fn test_user_copy_rejects_kernel_and_unmapped_pointers() {
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let user_ptr = crate::userspace::USER_STACK_TOP - 16;
+    copy_to_user(user_ptr, b"ok").unwrap();
+    assert_eq!(copy_from_user(user_ptr, 2).unwrap(), b"ok");
+
+    // Kernel data is mapped but not user-accessible
+    let kernel_buf = [0u8; 8];
+    assert!(copy_from_user(kernel_buf.as_ptr() as u64, kernel_buf.len()).is_err());
+
+    // Ranges running past the mapped stack or wrapping around are rejected
+    assert!(copy_from_user(user_ptr, 4096).is_err());
+    assert_eq!(copy_from_user(u64::MAX - 4, 16), Err(SyscallError::InvalidMemoryRegion));
+    assert_eq!(copy_to_user(0, b"x"), Err(SyscallError::InvalidMemoryRegion));
+
+    // Lengths too large to copy onto the heap are refused before anything is allocated
+    assert_eq!(copy_from_user(user_ptr, usize::MAX), Err(SyscallError::InvalidArgument));
+    assert_eq!(copy_from_user(user_ptr, MAX_USER_COPY + 1), Err(SyscallError::InvalidArgument));
+}
+
+#[test_case]
+fn test_allocate_memory_is_charged_to_caller() {
+    use crate::services::process_service::{get_current_process, get_process_stats};
+
+    let pid = get_current_process().expect("no current process");
+    let usage = |pid| get_process_stats(pid).unwrap().memory_usage;
+    let before = usage(pid);
+
+    let args = SyscallArgs { arg0: 8192, arg1: 1, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+    let region_id = match handle_syscall(SyscallNumber::AllocateMemory as u64, args) {
+        SyscallResult::Success(id) => id,
+        SyscallResult::Error(e) => panic!("allocate failed: {}", e),
+    };
+    assert_eq!(usage(pid), before + 8192);
+
+    let args = SyscallArgs { arg0: region_id, ..args };
+    assert!(matches!(handle_syscall(SyscallNumber::DeallocateMemory as u64, args), SyscallResult::Success(0)));
+    assert_eq!(usage(pid), before);
+}
+
+#[test_case]
+fn test_open_dup_close_file_descriptors() {
+    use crate::process::pcb::{OpenFile, OPEN_READ};
+    use crate::services::file_system_service::{create_file, FilePermissions};
+    use crate::services::process_service::{get_current_process, get_fd};
+
+    let pid = get_current_process().expect("no current process");
+    let a = create_file("fd_a.txt", FilePermissions::ReadWrite).unwrap();
+    let b = create_file("fd_b.txt", FilePermissions::ReadWrite).unwrap();
+
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let path_ptr = crate::userspace::USER_STACK_TOP - 64;
+    let call = |num: SyscallNumber, arg0: u64, arg1: u64| {
+        match handle_syscall(num as u64, SyscallArgs { arg0, arg1, arg2: OPEN_READ as u64, arg3: 0, arg4: 0, arg5: 0 }) {
+            SyscallResult::Success(value) => Ok(value),
+            SyscallResult::Error(e) => Err(e),
+        }
+    };
+    let open = |path: &str| {
+        copy_to_user(path_ptr, path.as_bytes()).unwrap();
+        call(SyscallNumber::Open, path_ptr, path.len() as u64)
+    };
+
+    let fd_a = open("/fd_a.txt").unwrap();
+    let fd_b = open("/fd_b.txt").unwrap();
+    assert!(fd_a > 2 && fd_b > 2, "standard streams are reserved");
+    assert_ne!(fd_a, fd_b);
+    assert_eq!(get_fd(pid, fd_b), Ok(OpenFile::File { cluster: b, flags: OPEN_READ }));
+
+    let dup = call(SyscallNumber::Dup, fd_a, 0).unwrap();
+    assert!(dup != fd_a && dup != fd_b);
+    assert_eq!(get_fd(pid, dup), Ok(OpenFile::File { cluster: a, flags: OPEN_READ }));
+
+    for fd in [fd_a, fd_b, dup] {
+        assert_eq!(call(SyscallNumber::Close, fd, 0), Ok(0));
+    }
+    assert_eq!(call(SyscallNumber::Close, fd_a, 0), Err(SyscallError::InvalidArgument));
+    assert_eq!(get_fd(pid, 1), Ok(OpenFile::Console));
+}
+
+#[test_case]
+fn test_open_for_write_requires_file_capability() {
+    use crate::process::pcb::{ProcessPriority, OPEN_READ, OPEN_WRITE};
+    use crate::services::file_system_service::{create_file, delete_file, FilePermissions};
+    use crate::services::process_service::{create_process, get_current_process, terminate_process, wait_child, yield_to};
+
+    let cluster = create_file("fd_locked.txt", FilePermissions::ReadWrite).unwrap();
+    let pid = create_process("unprivileged".to_string(), ProcessPriority::Normal, 4096, 4096).unwrap();
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let path = b"/fd_locked.txt";
+    let path_ptr = crate::userspace::USER_STACK_TOP - 64;
+    copy_to_user(path_ptr, path).unwrap();
+    let open = |flags: u32| {
+        let args = SyscallArgs { arg0: path_ptr, arg1: path.len() as u64, arg2: flags as u64, arg3: 0, arg4: 0, arg5: 0 };
+        handle_syscall(SyscallNumber::Open as u64, args)
+    };
+
+    yield_to(pid).unwrap();
+    let write = open(OPEN_READ | OPEN_WRITE);
+    let read = open(OPEN_READ);
+    if get_current_process() != Some(0) {
+        yield_to(0).unwrap();
+    }
+
+    assert!(matches!(write, SyscallResult::Error(SyscallError::PermissionDenied)));
+    assert!(matches!(read, SyscallResult::Success(fd) if fd > 2));
+    terminate_process(pid, 0).unwrap();
+    let _ = wait_child(0, Some(pid));
+    delete_file(cluster).unwrap();
+}
+
+#[test_case]
+fn test_every_syscall_number_is_registered() {
+    for &number in SyscallNumber::ALL {
+        assert!(is_registered(number as u64), "{:?} has no handler", number);
+    }
+    assert!(matches!(
+        handle_syscall(MAX_SYSCALLS as u64, SyscallArgs { arg0: 0, arg1: 0, arg2: 0, arg3: 0, arg4: 0, arg5: 0 }),
+        SyscallResult::Error(SyscallError::InvalidSyscall)
+    ));
+}
+
+#[test_case]
+fn test_mock_syscall_handler() {
+    fn mock(args: SyscallArgs) -> SyscallResult {
+        SyscallResult::Success(args.arg0 * 2)
+    }
+
+    let number = (MAX_SYSCALLS - 1) as u64;
+    let args = SyscallArgs { arg0: 21, arg1: 0, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+    assert!(matches!(handle_syscall(number, args), SyscallResult::Error(SyscallError::InvalidSyscall)));
+
+    register_syscall(number, mock, &[ArgKind::Value]).unwrap();
+    assert!(matches!(handle_syscall(number, args), SyscallResult::Success(42)));
+    unregister_syscall(number);
+    assert!(!is_registered(number));
+}
+
+#[test_case]
+fn test_create_process_rejects_null_name() {
+    let args = SyscallArgs { arg0: 0, arg1: 16, arg2: 1, arg3: 4096, arg4: 8192, arg5: 0 };
+    assert!(matches!(
+        handle_syscall(SyscallNumber::CreateProcess as u64, args),
+        SyscallResult::Error(SyscallError::InvalidArgument)
+    ));
+}
+
+#[test_case]
+fn test_create_process_checks_name_length_and_characters() {
+    use crate::process::pcb::MAX_PROCESS_NAME_LEN;
+
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let name_ptr = crate::userspace::USER_STACK_TOP - 128;
+    let create = |name: &[u8], name_len: usize| {
+        copy_to_user(name_ptr, name).unwrap();
+        let args = SyscallArgs { arg0: name_ptr, arg1: name_len as u64, arg2: 1, arg3: 4096, arg4: 8192, arg5: 0 };
+        handle_syscall(SyscallNumber::CreateProcess as u64, args)
+    };
+
+    // Refused before anything is copied, however long the claimed name
+    let long = [b'p'; MAX_PROCESS_NAME_LEN + 1];
+    assert!(matches!(create(&long, long.len()), SyscallResult::Error(SyscallError::InvalidArgument)));
+    assert!(matches!(create(b"", usize::MAX), SyscallResult::Error(SyscallError::InvalidArgument)));
+    assert!(matches!(create(b"bell\x07", 5), SyscallResult::Error(SyscallError::InvalidArgument)));
+
+    // Process names are not paths, so a slash is fine
+    let pid = match create(b"worker/1", 8) {
+        SyscallResult::Success(pid) => pid,
+        other => panic!("create failed: {:?}", other),
+    };
+    let _ = crate::services::process_service::terminate_process(pid, 0);
+    let _ = crate::services::process_service::wait_child(0, Some(pid));
+}
+
+#[test_case]
+fn test_sbrk_grows_and_shrinks_heap() {
+    use crate::process::pcb::{ProcessError, ProcessPriority};
+    use crate::services::process_service::{create_process, sbrk as process_sbrk, terminate_process, PROCESS_SERVICE};
+
+    let sbrk = |increment: i64| {
+        let args = SyscallArgs { arg0: increment as u64, arg1: 0, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+        match handle_syscall(SyscallNumber::Sbrk as u64, args) {
+            SyscallResult::Success(old_break) => Ok(old_break),
+            SyscallResult::Error(e) => Err(e),
+        }
+    };
+
+    let start = sbrk(0).unwrap();
+    assert_eq!(sbrk(8192), Ok(start));
+
+    // The new pages are mapped, zeroed and writable
+    let word = (start + 4096) as *mut u64;
+    unsafe {
+        assert_eq!(word.read_volatile(), 0);
+        word.write_volatile(0x5bbb_1234);
+        assert_eq!(word.read_volatile(), 0x5bbb_1234);
+    }
+    assert_eq!(copy_from_user(start, 8192).map(|bytes| bytes.len()), Ok(8192));
+
+    assert_eq!(sbrk(-8192), Ok(start + 8192));
+    assert_eq!(sbrk(0), Ok(start));
+    assert!(copy_from_user(start + 4096, 8).is_err());
+
+    // Shrinking below the heap start or growing into the stack is refused
+    assert_eq!(sbrk(-(1 << 40)), Err(SyscallError::InvalidArgument));
+    let pid = create_process("sbrk_test".to_string(), ProcessPriority::Normal, 4096, 8192).unwrap();
+    let (heap_end, stack_bottom) = {
+        let service = PROCESS_SERVICE.lock();
+        let pcb = service.get_process(pid).unwrap();
+        (pcb.heap_start.as_u64() + pcb.heap_size as u64, pcb.stack_pointer.as_u64() - pcb.stack_size as u64)
+    };
+    let increment = (stack_bottom - heap_end + 4096) as isize;
+    assert_eq!(process_sbrk(pid, increment), Err(ProcessError::InvalidBreak));
+    let _ = terminate_process(pid, 0);
+}
+
+#[test_case]
+fn test_map_file_reads_contents_and_writes_back_on_unmap() {
+    use crate::process::pcb::{OpenFile, OPEN_READ, OPEN_WRITE};
+    use crate::services::file_system_service::{create_file, read_file, write_file, FilePermissions};
+    use crate::services::process_service::{close_fd, get_current_process, open_fd};
+
+    let pid = get_current_process().expect("no current process");
+    let cluster = create_file("mmap.txt", FilePermissions::ReadWrite).unwrap();
+    write_file(cluster, b"hello, mapped world").unwrap();
+    let fd = open_fd(pid, OpenFile::File { cluster, flags: OPEN_READ | OPEN_WRITE }).unwrap();
+
+    let call = |num: SyscallNumber, arg0: u64, arg1: u64, arg2: u64, arg3: u64| {
+        match handle_syscall(num as u64, SyscallArgs { arg0, arg1, arg2, arg3, arg4: 0, arg5: 0 }) {
+            SyscallResult::Success(value) => Ok(value),
+            SyscallResult::Error(e) => Err(e),
+        }
+    };
+
+    // A private mapping from offset 7 sees the file's bytes, then zeroes past the end
+    let addr = call(SyscallNumber::MapMemory, fd, 7, 16, 0).unwrap();
+    let mapped = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, 16) };
+    assert_eq!(&mapped[..12], b"mapped world");
+    assert_eq!(&mapped[12..], &[0; 4]);
+    mapped[0] = b'M';
+    assert_eq!(call(SyscallNumber::UnmapMemory, addr, 0, 0, 0), Ok(0));
+    assert_eq!(read_file(cluster).unwrap(), b"hello, mapped world");
+    assert_eq!(call(SyscallNumber::MapMemory, fd, 0, MAX_MAP_LEN as u64 + 1, 0), Err(SyscallError::InvalidArgument));
+
+    // A shared mapping is flushed to the file on unmap, even after the fd is closed
+    let addr = call(SyscallNumber::MapMemory, fd, 0, 5, MAP_SHARED).unwrap();
+    close_fd(pid, fd).unwrap();
+    unsafe { core::ptr::copy_nonoverlapping(b"HELLO".as_ptr(), addr as *mut u8, 5) };
+    assert_eq!(call(SyscallNumber::UnmapMemory, addr, 0, 0, 0), Ok(0));
+    assert_eq!(read_file(cluster).unwrap(), b"HELLO, mapped world");
+    assert!(copy_from_user(addr, 1).is_err());
+    assert_eq!(call(SyscallNumber::UnmapMemory, addr, 0, 0, 0), Err(SyscallError::InvalidMemoryRegion));
+}
+
+#[test_case]
+fn test_syscall_stats_count_get_pid_calls() {
+    let count = || syscall_latency(SyscallNumber::GetPid).map_or(0, |stats| stats.count);
+    let before = count();
+
+    let args = SyscallArgs { arg0: 0, arg1: 0, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+    for _ in 0..5 {
+        assert!(matches!(handle_syscall(SyscallNumber::GetPid as u64, args), SyscallResult::Success(_)));
+    }
+    assert_eq!(count(), before + 5);
+
+    let (_, calls, average) = syscall_stats()
+        .into_iter()
+        .find(|&(number, _, _)| number == SyscallNumber::GetPid)
+        .expect("GetPid missing from stats");
+    assert_eq!(calls, before + 5);
+    assert!(average > 0);
+    let stats = syscall_latency(SyscallNumber::GetPid).unwrap();
+    assert!(stats.min <= average && average <= stats.max);
+}
+
+#[test_case]
+fn test_raw_console_scrolls_within_bounds() {
+    const GUARD: usize = 16;
+    let mut memory = [0xaau8; VGA_CELLS_BYTES + 2 * GUARD];
+    let buffer = unsafe { memory.as_mut_ptr().add(GUARD) };
+    let mut console = RawConsole::new();
+
+    for line in 0..30 {
+        for &byte in alloc::format!("line {}\n", line).as_bytes() {
+            unsafe { console.write_byte(buffer, byte).unwrap() };
+        }
+    }
+    // A full-width row at the bottom wraps and scrolls once more
+    for _ in 0..VGA_WIDTH {
+        unsafe { console.write_byte(buffer, b'x').unwrap() };
+    }
+    assert_eq!(unsafe { console.write_byte(buffer, 0x01) }, Err(SyscallError::InvalidArgument));
+
+    let row_text = |row: usize| -> Vec<u8> {
+        let cells = &memory[GUARD + row * VGA_WIDTH * 2..GUARD + (row + 1) * VGA_WIDTH * 2];
+        cells.iter().step_by(2).copied().collect()
+    };
+    assert!(row_text(0).starts_with(b"line 7 "));
+    assert!(row_text(VGA_HEIGHT - 3).starts_with(b"line 29 "));
+    assert_eq!(row_text(VGA_HEIGHT - 2), [b'x'; VGA_WIDTH]);
+    assert_eq!(row_text(VGA_HEIGHT - 1), [b' '; VGA_WIDTH]);
+    assert_eq!((console.row, console.col), (VGA_HEIGHT - 1, 0));
+    assert!(memory[..GUARD].iter().chain(&memory[GUARD + VGA_CELLS_BYTES..]).all(|&b| b == 0xaa));
+}
+
+#[test_case]
+fn test_run_self_test_reports_passes() {
+    let args = |selector| SyscallArgs { arg0: selector, arg1: 0, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+    let run = |selector| match handle_syscall(SyscallNumber::RunSelfTest as u64, args(selector)) {
+        SyscallResult::Success(counts) => Ok((counts & 0xffff_ffff, counts >> 32)),
+        SyscallResult::Error(e) => Err(e),
+    };
+
+    let first = run(SELF_TEST_SIMPLE).unwrap();
+    assert!(first.0 > 0);
+    assert_eq!(first.1, 0);
+    assert_eq!(run(SELF_TEST_SIMPLE), Ok(first), "a second run saw leftover state");
+    assert_eq!(run(99), Err(SyscallError::InvalidArgument));
+}
+
+#[test_case]
+fn test_run_self_test_requires_privilege() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process, wait_child, yield_to};
+
+    let child = create_process("unprivileged".to_string(), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let args = SyscallArgs { arg0: SELF_TEST_SIMPLE, arg1: 0, arg2: 0, arg3: 0, arg4: 0, arg5: 0 };
+    yield_to(child).unwrap();
+    let result = handle_syscall(SyscallNumber::RunSelfTest as u64, args);
+    yield_to(0).unwrap();
+    assert!(matches!(result, SyscallResult::Error(SyscallError::PermissionDenied)));
+
+    terminate_process(child, 0).unwrap();
+    let _ = wait_child(0, Some(child));
+}
+
+#[test_case]
+fn test_get_and_set_priority() {
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process, wait_child, yield_to};
+
+    let child = create_process("prio".to_string(), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let call = |num: SyscallNumber, arg0, arg1| {
+        match handle_syscall(num as u64, SyscallArgs { arg0, arg1, arg2: 0, arg3: 0, arg4: 0, arg5: 0 }) {
+            SyscallResult::Success(value) => Ok(value),
+            SyscallResult::Error(e) => Err(e),
+        }
+    };
+
+    assert_eq!(call(SyscallNumber::GetPriority, child, 0), Ok(ProcessPriority::Normal as u64));
+    assert_eq!(call(SyscallNumber::SetPriority, child, ProcessPriority::High as u64), Ok(0));
+    assert_eq!(call(SyscallNumber::GetPriority, child, 0), Ok(ProcessPriority::High as u64));
+    assert_eq!(call(SyscallNumber::SetPriority, child, 9), Err(SyscallError::InvalidArgument));
+
+    // Without a system capability the child may lower itself but not raise anyone
+    yield_to(child).unwrap();
+    let raise_self = call(SyscallNumber::SetPriority, child, ProcessPriority::Critical as u64);
+    let lower_self = call(SyscallNumber::SetPriority, child, ProcessPriority::Low as u64);
+    let touch_kernel = call(SyscallNumber::SetPriority, 0, ProcessPriority::Low as u64);
+    yield_to(0).unwrap();
+    assert_eq!(raise_self, Err(SyscallError::PermissionDenied));
+    assert_eq!(lower_self, Ok(0));
+    assert_eq!(touch_kernel, Err(SyscallError::PermissionDenied));
+    assert_eq!(call(SyscallNumber::GetPriority, child, 0), Ok(ProcessPriority::Low as u64));
+
+    terminate_process(child, 0).unwrap();
+    wait_child(0, Some(child)).unwrap();
+}
+
+#[test_case]
+fn test_list_processes_fills_records_and_reports_total() {
+    use crate::process::pcb::{ProcessPriority, ProcessState};
+    use crate::services::process_service::{create_process, get_process_count, terminate_process, wait_child};
+
+    let child = create_process("lister".to_string(), ProcessPriority::High, 4096, 4096).unwrap();
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let buf_ptr = crate::userspace::USER_STACK_TOP - 4096;
+    let list = |ptr: u64, capacity: u64| match handle_syscall(
+        SyscallNumber::ListProcesses as u64,
+        SyscallArgs { arg0: ptr, arg1: capacity, arg2: 0, arg3: 0, arg4: 0, arg5: 0 },
+    ) {
+        SyscallResult::Success(total) => Ok(total),
+        SyscallResult::Error(e) => Err(e),
+    };
+    let record = |index: usize| {
+        let bytes = copy_from_user(buf_ptr + (index * ProcessRecord::SIZE) as u64, ProcessRecord::SIZE).unwrap();
+        ProcessRecord::from_bytes(bytes.as_slice().try_into().unwrap())
+    };
+
+    let total = list(buf_ptr, 64).unwrap() as usize;
+    assert_eq!(total, get_process_count());
+    let records: Vec<ProcessRecord> = (0..total).map(record).collect();
+    assert_eq!(records[0].pid, 0);
+    let mine = records.iter().find(|r| r.pid == child).expect("child missing");
+    assert_eq!(mine.state, ProcessState::Ready as u32);
+    assert_eq!(mine.priority, ProcessPriority::High as u32);
+
+    // A short buffer still learns the full count
+    assert_eq!(list(buf_ptr, 1), Ok(total as u64));
+    // The buffer must be the caller's memory
+    assert_eq!(list(0xffff_8000_0000_0000, 1), Err(SyscallError::InvalidMemoryRegion));
+    assert_eq!(list(buf_ptr, u64::MAX), Err(SyscallError::InvalidArgument));
+
+    terminate_process(child, 0).unwrap();
+    wait_child(0, Some(child)).unwrap();
+}
+
+#[test_case]
+fn test_exit_group_terminates_every_member() {
+    use crate::process::pcb::{ProcessPriority, ProcessState};
+    use crate::services::process_service::{
+        create_process, get_process_stats, list_group, set_process_group, wait_child, yield_to,
+    };
+
+    let members: Vec<u64> = (0..3)
+        .map(|i| create_process(alloc::format!("group_{}", i), ProcessPriority::Normal, 4096, 4096).unwrap())
+        .collect();
+    let leader = members[0];
+    for &pid in &members {
+        set_process_group(pid, leader).unwrap();
+    }
+    let exit_group = || {
+        match handle_syscall(SyscallNumber::ExitGroup as u64, SyscallArgs { arg0: 9, arg1: 0, arg2: 0, arg3: 0, arg4: 0, arg5: 0 }) {
+            SyscallResult::Success(value) => Ok(value),
+            SyscallResult::Error(e) => Err(e),
+        }
+    };
+
+    // The kernel's own group is off limits
+    assert_eq!(exit_group(), Err(SyscallError::PermissionDenied));
+
+    yield_to(members[1]).unwrap();
+    let result = exit_group();
+    yield_to(0).unwrap();
+    assert_eq!(result, Ok(3));
+
+    assert!(list_group(leader).is_empty());
+    for &pid in &members {
+        let stats = get_process_stats(pid).unwrap();
+        assert_eq!(stats.state, ProcessState::Zombie);
+        assert_eq!(wait_child(0, Some(pid)), Ok(Some((pid, 9))));
+    }
+}
+
+#[test_case]
+fn test_device_ioctl_requires_device_capability() {
+    use crate::process::pcb::{Capability, CapabilityPermissions, ProcessPriority, ResourceType};
+    use crate::services::device_service::{DEVICE_READ, DEVICE_WRITE, VGA_DEVICE};
+    use crate::services::process_service::{create_process, grant_capability, terminate_process, wait_child, yield_to};
+
+    let pid = create_process("ioctl".to_string(), ProcessPriority::Normal, 4096, 4096).unwrap();
+    crate::userspace::map_initial_user_stack().expect("map top stack page");
+    let buf_ptr = crate::userspace::USER_STACK_TOP - 64;
+    copy_to_user(buf_ptr, b"!").unwrap();
+    let ioctl_len = |command, len| {
+        let args = SyscallArgs { arg0: VGA_DEVICE, arg1: command, arg2: buf_ptr, arg3: len, arg4: 0, arg5: 0 };
+        match handle_syscall(SyscallNumber::DeviceIoctl as u64, args) {
+            SyscallResult::Success(value) => Ok(value),
+            SyscallResult::Error(e) => Err(e),
+        }
+    };
+    let ioctl = |command| ioctl_len(command, 1);
+
+    yield_to(pid).unwrap();
+    let without = ioctl(DEVICE_WRITE);
+    yield_to(0).unwrap();
+    assert_eq!(without, Err(SyscallError::CapabilityDenied));
+
+    let write_only = CapabilityPermissions { read: false, write: true, execute: false, admin: false };
+    grant_capability(pid, Capability { resource_type: ResourceType::Device, resource_id: VGA_DEVICE, permissions: write_only }).unwrap();
+    yield_to(pid).unwrap();
+    let with = ioctl(DEVICE_WRITE);
+    let read = ioctl(DEVICE_READ);
+    let oversized = ioctl_len(DEVICE_WRITE, MAX_IOCTL_LEN as u64 + 1);
+    yield_to(0).unwrap();
+    assert_eq!(with, Ok(1));
+    assert_eq!(read, Err(SyscallError::CapabilityDenied));
+    assert_eq!(oversized, Err(SyscallError::InvalidArgument));
+
+    terminate_process(pid, 0).unwrap();
+    wait_child(0, Some(pid)).unwrap();
+}
+
+#[test_case]
+fn test_futex_hands_off_through_shared_memory() {
+    use crate::process::pcb::{ProcessPriority, ProcessState};
+    use crate::services::memory_service::{shm_attach, shm_create, shm_detach};
+    use crate::services::process_service::{
+        create_process, get_current_process, get_process_stats, terminate_process, wait_child, yield_to,
+    };
+
+    let futex = |number: SyscallNumber, addr: u64, value: u64| {
+        match handle_syscall(number as u64, SyscallArgs { arg0: addr, arg1: value, arg2: 0, arg3: 0, arg4: 0, arg5: 0 }) {
+            SyscallResult::Success(value) => Ok(value),
+            SyscallResult::Error(e) => Err(e),
+        }
+    };
+
+    let consumer = create_process("futex_consumer".to_string(), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let producer = create_process("futex_producer".to_string(), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let id = shm_create(4096).unwrap();
+    yield_to(consumer).unwrap();
+    let consumer_word = shm_attach(id).unwrap();
+    yield_to(producer).unwrap();
+    let producer_word = shm_attach(id).unwrap();
+    assert_ne!(consumer_word, producer_word);
+
+    // The consumer waits for the word to leave 0
+    yield_to(consumer).unwrap();
+    assert_eq!(futex(SyscallNumber::FutexWait, consumer_word.as_u64() + 1, 0), Err(SyscallError::InvalidArgument));
+    assert_eq!(futex(SyscallNumber::FutexWait, consumer_word.as_u64(), 1), Err(SyscallError::WouldBlock));
+    assert_eq!(futex(SyscallNumber::FutexWait, consumer_word.as_u64(), 0), Ok(0));
+    assert_eq!(get_process_stats(consumer).unwrap().state, ProcessState::Blocked);
+
+    // The producer publishes through its own mapping and wakes it. Blocking already ran
+    // the scheduler, which may have picked the producer.
+    if get_current_process() != Some(producer) {
+        yield_to(producer).unwrap();
+    }
+    unsafe { producer_word.as_mut_ptr::<u32>().write_volatile(42) };
+    assert_eq!(futex(SyscallNumber::FutexWake, producer_word.as_u64(), 1), Ok(1));
+    assert_eq!(futex(SyscallNumber::FutexWake, producer_word.as_u64(), 1), Ok(0));
+    shm_detach(id).unwrap();
+
+    yield_to(consumer).unwrap();
+    assert_eq!(unsafe { consumer_word.as_ptr::<u32>().read_volatile() }, 42);
+    shm_detach(id).unwrap();
+    yield_to(0).unwrap();
+
+    for pid in [consumer, producer] {
+        let _ = terminate_process(pid, 0);
+        let _ = wait_child(0, Some(pid));
+    }
+}
@@ -1,93 +1,152 @@
-use super::{Task, TaskId};
+use super::{JoinHandle, Task, TaskId};
 use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::task::{Context, Poll, Waker};
 use crossbeam_queue::ArrayQueue;
+use lazy_static::lazy_static;
+use spin::Mutex;
 
+/// Most tasks that can be waiting in the ready queue at once
+const READY_CAPACITY: usize = 256;
+
+/// The kernel's single async executor.
+///
+/// Only tasks that have been woken are polled. A task's waker puts its id on the ready
+/// queue (once, however often it is woken before the next poll) and asks for a pass;
+/// `run` does the passes and halts while nothing is ready.
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
+    waker_cache: BTreeMap<TaskId, Arc<TaskWaker>>,
+}
+
+static EXECUTOR: Mutex<Executor> = Mutex::new(Executor::new());
+
+lazy_static! {
+    /// Ids of woken tasks in wake order. Lock-free, so wakers may run in interrupt handlers.
+    static ref READY: ArrayQueue<TaskId> = ArrayQueue::new(READY_CAPACITY);
 }
 
 impl Executor {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Executor {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
             waker_cache: BTreeMap::new(),
         }
     }
 
-    pub fn spawn(&mut self, task: Task) {
-        let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
+    pub fn spawn(&mut self, task: Task) -> JoinHandle {
+        let handle = task.handle();
+        let id = task.id();
+        let waker = Arc::new(TaskWaker { id, queued: AtomicBool::new(false) });
+        if self.tasks.insert(id, task).is_some() {
             panic!("task with same ID already in tasks");
         }
-        self.task_queue.push(task_id).expect("queue full");
+        self.waker_cache.insert(id, waker.clone());
+        waker.wake_task();
+        handle
     }
 
-    pub fn run(&mut self) -> ! {
-        loop {
-            self.run_ready_tasks();
-            self.sleep_if_idle();
-        }
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
     }
+}
 
-    fn run_ready_tasks(&mut self) {
-        // destructure `self` to avoid borrow checker errors
-        let Self {
-            tasks,
-            task_queue,
-            waker_cache,
-        } = self;
-
-        while let Some(task_id) = task_queue.pop() {
-            let task = match tasks.get_mut(&task_id) {
-                Some(task) => task,
-                None => continue, // task no longer exists
-            };
-            let waker = waker_cache
-                .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
-            let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    // task done -> remove it and its cached waker
-                    tasks.remove(&task_id);
-                    waker_cache.remove(&task_id);
-                }
-                Poll::Pending => {}
-            }
+/// Queue a task on the global executor
+pub fn spawn(task: Task) -> JoinHandle {
+    with_executor(|executor| executor.spawn(task))
+}
+
+/// Number of tasks that have not yet completed
+pub fn task_count() -> usize {
+    with_executor(|executor| executor.task_count())
+}
+
+/// Mark task `id` ready, as its waker would. Does nothing if the task has finished.
+pub fn wake_task(id: TaskId) {
+    if let Some(waker) = with_executor(|executor| executor.waker_cache.get(&id).cloned()) {
+        waker.wake_task();
+    }
+}
+
+/// Poll each task that was ready at the start of the pass once, dropping cancelled tasks
+/// without polling them. Tasks woken during the pass wait for the next one.
+/// Returns the number of tasks polled.
+///
+/// The executor lock is only held (with interrupts off) to move a task in or out of the
+/// table, never while polling, so tasks may spawn and wake each other.
+pub fn run_pass() -> usize {
+    let ready = READY.len();
+    let mut polled = 0;
+    for _ in 0..ready {
+        let id = match READY.pop() {
+            Some(id) => id,
+            None => break,
+        };
+        let entry = with_executor(|executor| {
+            let waker = executor.waker_cache.get(&id)?.clone();
+            // Cleared before polling, so a wake during the poll queues the task again
+            waker.queued.store(false, Ordering::Release);
+            executor.tasks.remove(&id).map(|task| (task, waker))
+        });
+        let (mut task, waker) = match entry {
+            Some(entry) => entry,
+            None => continue, // finished, or woken after completing
+        };
+        if task.is_cancelled() {
+            with_executor(|executor| executor.waker_cache.remove(&id));
+            continue;
+        }
+        polled += 1;
+        let waker = Waker::from(waker);
+        let mut context = Context::from_waker(&waker);
+        match task.poll(&mut context) {
+            Poll::Pending if !task.is_cancelled() => with_executor(|executor| {
+                executor.tasks.insert(id, task);
+            }),
+            _ => with_executor(|executor| {
+                executor.waker_cache.remove(&id);
+            }),
         }
     }
+    polled
+}
 
-    fn sleep_if_idle(&self) {
-        use x86_64::instructions::interrupts::{self, enable_and_hlt};
+/// Run the scheduling passes that wakeups and timer ticks request forever, halting
+/// until the next interrupt when no pass is due
+pub fn run() -> ! {
+    loop {
+        crate::scheduler::run_bottom_half();
+        sleep_if_idle();
+    }
+}
 
-        interrupts::disable();
-        if self.task_queue.is_empty() {
-            enable_and_hlt();
-        } else {
-            interrupts::enable();
-        }
+fn sleep_if_idle() {
+    use x86_64::instructions::interrupts::{self, enable_and_hlt};
+
+    interrupts::disable();
+    if crate::scheduler::pass_pending() {
+        interrupts::enable();
+    } else {
+        enable_and_hlt();
     }
 }
 
+fn with_executor<R>(f: impl FnOnce(&mut Executor) -> R) -> R {
+    x86_64::instructions::interrupts::without_interrupts(|| f(&mut EXECUTOR.lock()))
+}
+
+/// Waker shared by every poll of one task
 struct TaskWaker {
-    task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    id: TaskId,
+    queued: AtomicBool, // Already on the ready queue
 }
 
 impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
-        }))
-    }
-
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task_queue full");
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            READY.push(self.id).expect("task ready queue full");
+        }
+        crate::scheduler::request_pass();
     }
 }
 
@@ -99,4 +158,95 @@ impl Wake for TaskWaker {
     fn wake_by_ref(self: &Arc<Self>) {
         self.wake_task();
     }
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_round_robin_polls_every_task() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static PROGRESS: [AtomicUsize; 3] = [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+    const STEPS: usize = 3;
+
+    for slot in 0..PROGRESS.len() {
+        spawn(Task::new(async move {
+            for _ in 0..STEPS {
+                PROGRESS[slot].fetch_add(1, Ordering::SeqCst);
+                crate::scheduler::yield_task().await;
+            }
+        }));
+    }
+
+    run_pass();
+    for counter in &PROGRESS {
+        assert!(counter.load(Ordering::SeqCst) >= 1);
+    }
+
+    for _ in 0..STEPS {
+        run_pass();
+    }
+    for counter in &PROGRESS {
+        assert_eq!(counter.load(Ordering::SeqCst), STEPS);
+    }
+}
+
+#[test_case]
+fn test_cancelled_task_stops_and_is_dropped() {
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    static DROPPED: AtomicBool = AtomicBool::new(false);
+
+    struct DropFlag;
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            DROPPED.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let handle = spawn(Task::cancellable(|token| async move {
+        let _flag = DropFlag;
+        while !token.is_cancelled() {
+            COUNT.fetch_add(1, Ordering::SeqCst);
+            crate::scheduler::yield_task().await;
+        }
+    }));
+
+    for _ in 0..3 {
+        run_pass();
+    }
+    assert!(COUNT.load(Ordering::SeqCst) >= 3);
+
+    handle.cancel();
+    let at_cancel = COUNT.load(Ordering::SeqCst);
+    run_pass();
+    run_pass();
+    assert!(COUNT.load(Ordering::SeqCst) <= at_cancel + 1);
+    assert!(DROPPED.load(Ordering::SeqCst), "cancelled future was not dropped");
+}
+
+#[test_case]
+fn test_pass_polls_only_woken_tasks() {
+    use core::future::poll_fn;
+    use core::sync::atomic::AtomicUsize;
+
+    static POLLS: AtomicUsize = AtomicUsize::new(0);
+
+    let handle = spawn(Task::new(poll_fn(|_| {
+        POLLS.fetch_add(1, Ordering::SeqCst);
+        Poll::<()>::Pending
+    })));
+
+    run_pass();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1);
+    run_pass();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1, "a task that was not woken was polled");
+
+    wake_task(handle.id());
+    wake_task(handle.id());
+    run_pass();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 2, "two wakes before a pass poll the task once");
+
+    handle.cancel();
+    run_pass();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 2);
+}
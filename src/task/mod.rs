@@ -1,33 +1,62 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, sync::Arc};
 use core::{
     future::Future,
     pin::Pin,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     task::{Context, Poll},
 };
 
 pub mod executor;
+pub mod mutex;
+pub mod timer;
 
 pub struct Task {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    token: CancelToken,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
 }
 
 impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+    pub fn new(future: impl Future<Output = ()> + Send + 'static) -> Task {
         Task {
             id: TaskId::new(),
+            token: CancelToken::new(),
             future: Box::pin(future),
         }
     }
 
+    /// Build a task whose future can watch its own cancel token
+    pub fn cancellable<F>(make_future: impl FnOnce(CancelToken) -> F) -> Task
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let token = CancelToken::new();
+        Task {
+            id: TaskId::new(),
+            future: Box::pin(make_future(token.clone())),
+            token,
+        }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn handle(&self) -> JoinHandle {
+        JoinHandle { id: self.id, token: self.token.clone() }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
         self.future.as_mut().poll(context)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
@@ -35,3 +64,48 @@ impl TaskId {
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
 }
+
+/// Shared flag asking a task to stop.
+///
+/// Cancellation is cooperative: a task checks `is_cancelled` at its await points and
+/// returns early. Cancelling wakes the task, and the executor drops it instead of
+/// polling it again.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken(Arc::new(AtomicBool::new(false)))
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Returned by `spawn` to identify and cancel a running task
+#[derive(Debug, Clone)]
+pub struct JoinHandle {
+    id: TaskId,
+    token: CancelToken,
+}
+
+impl JoinHandle {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Ask the task to stop; it is dropped no later than its next scheduling pass
+    pub fn cancel(&self) {
+        self.token.cancel();
+        executor::wake_task(self.id);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
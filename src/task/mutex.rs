@@ -0,0 +1,135 @@
+use alloc::collections::VecDeque;
+use core::{
+    cell::UnsafeCell,
+    future::poll_fn,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Poll, Waker},
+};
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// A mutex for async tasks.
+///
+/// A contended `lock().await` returns `Pending` and leaves the task's waker behind
+/// instead of spinning, so holding the lock across an await never stalls the CPU.
+/// Unlocking wakes the longest-waiting task.
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    // Taken with interrupts off: a pass run from the timer may poll a waiting task
+    waiters: spin::Mutex<VecDeque<Waker>>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex {
+            locked: AtomicBool::new(false),
+            waiters: spin::Mutex::new(VecDeque::new()),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Take the lock if it is free, without waiting
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// Wait until the lock is free and take it
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        poll_fn(|cx| {
+            if let Some(guard) = self.try_lock() {
+                return Poll::Ready(guard);
+            }
+            without_interrupts(|| {
+                // A task polled again while still waiting keeps its place in line
+                let mut waiters = self.waiters.lock();
+                if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    waiters.push_back(cx.waker().clone());
+                }
+            });
+
+            // The holder may have unlocked before the waker was queued
+            match self.try_lock() {
+                Some(guard) => Poll::Ready(guard),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+        if let Some(waker) = without_interrupts(|| self.waiters.lock().pop_front()) {
+            waker.wake();
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+#[test_case]
+fn test_contending_tasks_take_turns() {
+    use super::{executor, Task};
+    use alloc::vec::Vec;
+    use core::sync::atomic::AtomicUsize;
+
+    static LOG: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+    const ROUNDS: usize = 2;
+
+    for id in [1u8, 2] {
+        executor::spawn(Task::new(async move {
+            for _ in 0..ROUNDS {
+                // Hold the lock across an await; the other task must wait it out
+                let mut log = LOG.lock().await;
+                log.push(id);
+                crate::scheduler::yield_task().await;
+                log.push(id);
+                drop(log);
+                crate::scheduler::yield_task().await;
+            }
+            DONE.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+
+    for _ in 0..20 {
+        if DONE.load(Ordering::SeqCst) == 2 {
+            break;
+        }
+        executor::run_pass();
+    }
+    assert_eq!(DONE.load(Ordering::SeqCst), 2);
+
+    let log = LOG.try_lock().expect("mutex still held");
+    assert_eq!(log.len(), 2 * 2 * ROUNDS);
+    assert!(log.chunks(2).all(|pair| pair[0] == pair[1]));
+    assert!(log.contains(&1) && log.contains(&2));
+}
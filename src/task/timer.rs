@@ -0,0 +1,251 @@
+// Timer wheel and async sleeping for EMOS Microkernel
+use alloc::vec::Vec;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Each level has 2^LEVEL_BITS slots
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+const LEVELS: usize = 4;
+/// Timers further out than this wait in the overflow list
+const WHEEL_SPAN: u64 = 1 << (LEVEL_BITS * LEVELS as u32);
+
+struct Timer<T> {
+    deadline: u64,
+    seq: u64, // Insertion order, so equal deadlines fire first-in first-out
+    item: T,
+}
+
+/// Hierarchical timer wheel keyed by absolute tick.
+///
+/// Level `l` buckets timers due within 64^(l+1) ticks by bits `6l..6l+6` of their
+/// deadline. Each tick fires one level-0 slot, and whenever the low bits roll over a
+/// higher level's slot is cascaded down, so a timer is touched at most once per level
+/// no matter how many others are pending.
+pub struct TimerWheel<T> {
+    now: u64, // Last tick processed
+    levels: Vec<Vec<Vec<Timer<T>>>>,
+    overflow: Vec<Timer<T>>,
+    expired: Vec<Timer<T>>, // Inserted with a deadline already passed
+    len: usize,
+    next_seq: u64,
+    work: u64, // Timers placed or fired, for measuring cost
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new() -> Self {
+        Self {
+            now: 0,
+            levels: (0..LEVELS).map(|_| (0..SLOTS).map(|_| Vec::new()).collect()).collect(),
+            overflow: Vec::new(),
+            expired: Vec::new(),
+            len: 0,
+            next_seq: 0,
+            work: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Timers placed or fired since creation; stays proportional to the number of timers
+    /// rather than to timers times ticks
+    pub fn work(&self) -> u64 {
+        self.work
+    }
+
+    /// Fire `item` from the first `advance` that reaches `deadline`
+    pub fn insert(&mut self, deadline: u64, item: T) {
+        let timer = Timer { deadline, seq: self.next_seq, item };
+        self.next_seq += 1;
+        self.len += 1;
+        if deadline <= self.now {
+            self.expired.push(timer);
+        } else {
+            self.place(timer);
+        }
+    }
+
+    fn place(&mut self, timer: Timer<T>) {
+        self.work += 1;
+        let delta = timer.deadline - self.now;
+        match (0..LEVELS).find(|&level| delta < 1 << (LEVEL_BITS * (level as u32 + 1))) {
+            Some(level) => {
+                let slot = (timer.deadline >> (LEVEL_BITS * level as u32)) & SLOT_MASK;
+                self.levels[level][slot as usize].push(timer);
+            }
+            None => self.overflow.push(timer),
+        }
+    }
+
+    /// Move the wheel forward to tick `now` and return the items that came due, soonest
+    /// first. An empty wheel simply adopts `now`, even if it is in the past.
+    pub fn advance(&mut self, now: u64) -> Vec<T> {
+        if self.len == 0 {
+            self.now = now;
+            return Vec::new();
+        }
+
+        let mut due = mem::take(&mut self.expired);
+        while self.now < now {
+            if due.len() == self.len {
+                // Nothing left to cascade; skip the empty ticks
+                self.now = now;
+                break;
+            }
+            self.now += 1;
+            let tick = self.now;
+
+            if tick % WHEEL_SPAN == 0 {
+                for timer in mem::take(&mut self.overflow) {
+                    self.place(timer);
+                }
+            }
+            for level in (1..LEVELS).rev() {
+                let shift = LEVEL_BITS * level as u32;
+                if tick & ((1 << shift) - 1) == 0 {
+                    let slot = ((tick >> shift) & SLOT_MASK) as usize;
+                    for timer in mem::take(&mut self.levels[level][slot]) {
+                        if timer.deadline <= tick {
+                            self.work += 1;
+                            due.push(timer);
+                        } else {
+                            self.place(timer);
+                        }
+                    }
+                }
+            }
+            let fired = mem::take(&mut self.levels[0][(tick & SLOT_MASK) as usize]);
+            self.work += fired.len() as u64;
+            due.extend(fired);
+        }
+
+        self.len -= due.len();
+        due.sort_by_key(|timer| (timer.deadline, timer.seq));
+        due.into_iter().map(|timer| timer.item).collect()
+    }
+
+    /// Drop every pending timer whose item fails `keep`
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        let mut removed = 0;
+        let lists = self.levels.iter_mut().flatten().chain([&mut self.overflow, &mut self.expired]);
+        for list in lists {
+            let before = list.len();
+            list.retain(|timer| keep(&timer.item));
+            removed += before - list.len();
+        }
+        self.len -= removed;
+    }
+}
+
+lazy_static! {
+    /// Wakers of tasks sleeping in `sleep_ticks`
+    static ref TASK_TIMERS: Mutex<TimerWheel<Waker>> = Mutex::new(TimerWheel::new());
+}
+
+/// Wake the tasks whose sleep ends by tick `now`; called from the timer interrupt.
+/// Returns the number woken.
+pub fn wake_expired(now: u64) -> usize {
+    // Skip this tick rather than spin if a task is registering; it is caught up next tick
+    let due = match TASK_TIMERS.try_lock() {
+        Some(mut timers) => timers.advance(now),
+        None => return 0,
+    };
+    let count = due.len();
+    for waker in due {
+        waker.wake();
+    }
+    count
+}
+
+/// Future returned by `sleep_ticks`
+pub struct Sleep {
+    deadline: u64,
+    registered: bool, // Whether a waker is already in `TASK_TIMERS` for the deadline
+}
+
+/// Complete once at least `ticks` timer interrupts have passed
+pub fn sleep_ticks(ticks: u64) -> Sleep {
+    Sleep { deadline: crate::time::ticks().saturating_add(ticks), registered: false }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if crate::time::ticks() >= self.deadline {
+            return Poll::Ready(());
+        }
+        // Register once; the executor keeps one waker per task, so later polls need nothing
+        if !self.registered {
+            let waker = cx.waker().clone();
+            without_interrupts(|| TASK_TIMERS.lock().insert(self.deadline, waker));
+            self.registered = true;
+        }
+        Poll::Pending
+    }
+}
+
+#[test_case]
+fn test_thousand_timers_fire_on_their_deadlines() {
+    use crate::random::Rng;
+
+    const TIMERS: usize = 1000;
+    let start = 5_000;
+    let mut rng = Rng::new(0x7173);
+    let deadlines: Vec<u64> = (0..TIMERS).map(|_| start + 1 + rng.next_u64() % 20_000).collect();
+
+    let mut wheel = TimerWheel::new();
+    wheel.advance(start);
+    for (id, &deadline) in deadlines.iter().enumerate() {
+        wheel.insert(deadline, id);
+    }
+    assert_eq!(wheel.len(), TIMERS);
+
+    let mut fired = 0;
+    let last = *deadlines.iter().max().unwrap();
+    for tick in start + 1..=last {
+        for id in wheel.advance(tick) {
+            assert_eq!(deadlines[id], tick, "timer {} fired at the wrong tick", id);
+            fired += 1;
+        }
+    }
+    assert_eq!(fired, TIMERS);
+    assert!(wheel.is_empty());
+
+    // Each timer is placed at most once per level and fired once, however many ticks pass
+    assert!(wheel.work() <= (TIMERS * (LEVELS + 1)) as u64, "{} timer moves", wheel.work());
+}
+
+#[test_case]
+fn test_sleep_registers_one_waker_however_often_polled() {
+    const TIMEOUT_NS: u64 = 500_000_000;
+
+    let mut sleep = sleep_ticks(2);
+    let mut context = Context::from_waker(Waker::noop());
+    let added = without_interrupts(|| {
+        let before = TASK_TIMERS.lock().len();
+        for _ in 0..100 {
+            assert_eq!(Pin::new(&mut sleep).poll(&mut context), Poll::Pending);
+        }
+        TASK_TIMERS.lock().len() - before
+    });
+    assert_eq!(added, 1);
+
+    let began = crate::tsc::read();
+    while crate::time::ticks() < sleep.deadline && crate::tsc::elapsed_ns(began) < TIMEOUT_NS {
+        core::hint::spin_loop();
+    }
+    assert_eq!(Pin::new(&mut sleep).poll(&mut context), Poll::Ready(()));
+}
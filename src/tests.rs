@@ -3,33 +3,75 @@ use alloc::format;
 use alloc::string::ToString;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
 use crate::println;
 use crate::process::pcb::ProcessPriority;
+use crate::process::scheduler::WorkloadResult;
 use crate::services::process_service::{
-    create_process, terminate_process, list_processes, get_system_stats,
+    create_process, create_processes, terminate_process, list_processes, get_system_stats,
     get_current_process, schedule_next_process, set_process_priority
 };
 use crate::services::memory_service::{
     allocate_memory, deallocate_memory, list_memory_regions, MemoryPermissions
 };
 use crate::services::file_system_service::{
-    create_file, write_file, read_file, list_files, FilePermissions
+    create_file, write_file, read_file, list_files, FilePermissions, FileSystemError
 };
 
+/// Failures reported with `fail!` since boot; test cases compare it before and after
+static FAILURES: AtomicU32 = AtomicU32::new(0);
+
+/// Print a failure message and count it against the running test case
+macro_rules! fail {
+    ($($arg:tt)*) => {{
+        crate::println!($($arg)*);
+        crate::tests::note_failure();
+    }};
+}
+pub(crate) use fail;
+
+pub(crate) fn note_failure() {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Pass/fail tally from one run of a test battery
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TestReport {
+    pub passed: u32,
+    pub failed: u32,
+}
+
+impl TestReport {
+    /// Run one test case, counting it failed if it reported any failure
+    pub fn run(&mut self, case: fn()) {
+        let before = FAILURES.load(Ordering::Relaxed);
+        case();
+        if FAILURES.load(Ordering::Relaxed) == before {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+}
+
 /// Run all microkernel tests
-pub fn run_all_tests() {
+pub fn run_all_tests() -> TestReport {
 
     println!(" TESTS");
 
-    
-    test_process_management();
-    test_memory_management();
-    test_file_system();
-    test_system_calls();
-    test_service_integration();
-    
-    println!("    ALL TESTS COMPLETED SUCCESSFULLY!");
-
+    let mut report = TestReport::default();
+    report.run(test_process_management);
+    report.run(test_memory_management);
+    report.run(test_file_system);
+    report.run(test_system_calls);
+    report.run(test_service_integration);
+    
+    if report.failed == 0 {
+        println!("    ALL TESTS COMPLETED SUCCESSFULLY!");
+    } else {
+        println!("    {} passed, {} FAILED", report.passed, report.failed);
+    }
+    report
 }
 
 /// Test process management functionality
@@ -44,7 +86,7 @@ fn test_process_management() {
             pid
         }
         Err(e) => {
-            println!("     Failed to create process: {:?}", e);
+            fail!("     Failed to create process: {:?}", e);
             return;
         }
     };
@@ -55,7 +97,7 @@ fn test_process_management() {
             pid
         }
         Err(e) => {
-            println!("     Failed to create process: {:?}", e);
+            fail!("     Failed to create process: {:?}", e);
             return;
         }
     };
@@ -78,7 +120,7 @@ fn test_process_management() {
     println!("   Testing priority changes...");
     match set_process_priority(pid1, ProcessPriority::Critical) {
         Ok(_) => println!("    Set PID {} priority to Critical", pid1),
-        Err(e) => println!("     Failed to set priority: {:?}", e),
+        Err(e) => fail!("     Failed to set priority: {:?}", e),
     }
     
     // Test 5: Get system statistics
@@ -94,7 +136,7 @@ fn test_process_management() {
     println!("   Testing process termination...");
     match terminate_process(pid1, 0) {
         Ok(_) => println!("    Terminated process PID {}", pid1),
-        Err(e) => println!("     Failed to terminate process: {:?}", e),
+        Err(e) => fail!("     Failed to terminate process: {:?}", e),
     }
     
     // Test 7: Get current process
@@ -119,7 +161,7 @@ fn test_memory_management() {
             region_id
         }
         Err(e) => {
-            println!("     Failed to allocate memory: {:?}", e);
+            fail!("     Failed to allocate memory: {:?}", e);
             return;
         }
     };
@@ -130,7 +172,7 @@ fn test_memory_management() {
             region_id
         }
         Err(e) => {
-            println!("     Failed to allocate memory: {:?}", e);
+            fail!("     Failed to allocate memory: {:?}", e);
             return;
         }
     };
@@ -147,7 +189,7 @@ fn test_memory_management() {
     println!("   Deallocating memory...");
     match deallocate_memory(region1) {
         Ok(_) => println!("    Deallocated region {}", region1),
-        Err(e) => println!("     Failed to deallocate memory: {:?}", e),
+        Err(e) => fail!("     Failed to deallocate memory: {:?}", e),
     }
     
     // Test 4: Verify deallocation
@@ -169,7 +211,7 @@ fn test_file_system() {
             cluster
         }
         Err(e) => {
-            println!("    Failed to create file: {:?}", e);
+            fail!("    Failed to create file: {:?}", e);
             return;
         }
     };
@@ -180,7 +222,7 @@ fn test_file_system() {
             cluster
         }
         Err(e) => {
-            println!("    Failed to create file: {:?}", e);
+            fail!("    Failed to create file: {:?}", e);
             return;
         }
     };
@@ -190,13 +232,14 @@ fn test_file_system() {
     let test_data1 = b"Hello, EMOS Microkernel! This is test data for file 1.";
     match write_file(file1, test_data1) {
         Ok(size) => println!("    Wrote {} bytes to file1", size),
-        Err(e) => println!("     Failed to write to file1: {:?}", e),
+        Err(e) => fail!("     Failed to write to file1: {:?}", e),
     }
     
     let test_data2 = b"This is read-only test data for file 2.";
     match write_file(file2, test_data2) {
-        Ok(size) => println!("    Wrote {} bytes to file2", size),
-        Err(e) => println!("    Failed to write to file2: {:?}", e),
+        Ok(size) => fail!("    Wrote {} bytes to read-only file2", size),
+        Err(FileSystemError::PermissionDenied) => println!("    Write to read-only file2 denied"),
+        Err(e) => fail!("    Unexpected error writing to file2: {:?}", e),
     }
     
     // Test 3: Read from files
@@ -206,7 +249,7 @@ fn test_file_system() {
             let content = core::str::from_utf8(&data).unwrap_or("Invalid UTF-8");
             println!("    Read from file1: {}", content);
         }
-        Err(e) => println!("    Failed to read from file1: {:?}", e),
+        Err(e) => fail!("    Failed to read from file1: {:?}", e),
     }
     
     match read_file(file2) {
@@ -214,7 +257,7 @@ fn test_file_system() {
             let content = core::str::from_utf8(&data).unwrap_or("Invalid UTF-8");
             println!("    Read from file2: {}", content);
         }
-        Err(e) => println!("     Failed to read from file2: {:?}", e),
+        Err(e) => fail!("     Failed to read from file2: {:?}", e),
     }
     
     // Test 4: List files
@@ -287,7 +330,7 @@ fn test_service_integration() {
             pid
         }
         Err(e) => {
-            println!("     Failed to create integration process: {:?}", e);
+            fail!("     Failed to create integration process: {:?}", e);
             return;
         }
     };
@@ -299,7 +342,7 @@ fn test_service_integration() {
             region
         }
         Err(e) => {
-            println!("     Failed to allocate memory: {:?}", e);
+            fail!("     Failed to allocate memory: {:?}", e);
             return;
         }
     };
@@ -311,7 +354,7 @@ fn test_service_integration() {
             cluster
         }
         Err(e) => {
-            println!("     Failed to create file: {:?}", e);
+            fail!("     Failed to create file: {:?}", e);
             return;
         }
     };
@@ -320,7 +363,7 @@ fn test_service_integration() {
     let process_data = b"Process integration test data";
     match write_file(file_cluster, process_data) {
         Ok(size) => println!("    Wrote {} bytes of process data to file", size),
-        Err(e) => println!("     Failed to write process data: {:?}", e),
+        Err(e) => fail!("     Failed to write process data: {:?}", e),
     }
     
     // Schedule the process
@@ -341,33 +384,108 @@ pub fn run_performance_tests() {
     
     // Benchmark 1: Process creation speed
     println!("   Benchmarking process creation...");
-    let start_time = 0; // In real implementation, use system timer
+    let start_time = crate::tsc::read();
     
     for i in 0..10 {
         let _ = create_process(format!("bench_proc_{}", i), ProcessPriority::Normal, 4096, 8192);
     }
     
-    println!("    Created 10 processes");
+    let elapsed = crate::tsc::elapsed_ns(start_time);
+    println!("    Created 10 processes in {} us ({} ns each)", elapsed / 1000, elapsed / 10);
+
+    // Same work with the service lock taken once for the whole batch
+    let specs: Vec<_> = (0..10)
+        .map(|i| (format!("bench_batch_{}", i), ProcessPriority::Normal, 4096, 8192))
+        .collect();
+    let start_time = crate::tsc::read();
+    let _ = create_processes(&specs);
+    let elapsed = crate::tsc::elapsed_ns(start_time);
+    println!("    Batch-created 10 processes in {} us ({} ns each)", elapsed / 1000, elapsed / 10);
     
     // Benchmark 2: Memory allocation speed
     println!("   Benchmarking memory allocation...");
+    let start_time = crate::tsc::read();
     let mut regions = Vec::new();
-    for i in 0..20 {
+    for _ in 0..20 {
         if let Ok(region) = allocate_memory(512, MemoryPermissions::ReadWrite) {
             regions.push(region);
         }
     }
-    println!("    Allocated {} memory regions", regions.len());
+    let elapsed = crate::tsc::elapsed_ns(start_time);
+    println!("    Allocated {} memory regions in {} us", regions.len(), elapsed / 1000);
     
     // Benchmark 3: File operations speed
     println!("   Benchmarking file operations...");
+    let start_time = crate::tsc::read();
     for i in 0..5 {
         if let Ok(cluster) = create_file(&format!("bench_file_{}.txt", i), FilePermissions::ReadWrite) {
             let data = format!("Benchmark data for file {}", i).into_bytes();
             let _ = write_file(cluster, &data);
         }
     }
-    println!("    Created and wrote to 5 files");
+    let elapsed = crate::tsc::elapsed_ns(start_time);
+    println!("    Created and wrote to 5 files in {} us", elapsed / 1000);
+
+    // Benchmark 4: Scheduling algorithms on the same workload
+    run_scheduler_benchmark();
     
     println!("   Performance benchmarks completed!");
 }
+
+/// Run one mixed-priority workload under every scheduling algorithm and print how each
+/// did side by side. Each run starts from fresh services; the live ones are set aside
+/// for the benchmark and put back afterwards.
+pub fn run_scheduler_benchmark() -> Vec<WorkloadResult> {
+    use alloc::collections::BTreeMap;
+    use crate::process::scheduler::{ProcessScheduler, SchedulingAlgorithm};
+    use crate::services::process_service::PROCESS_SERVICE;
+
+    // (priority, memory in pages, CPU ticks needed)
+    const WORKLOAD: [(ProcessPriority, usize, u64); 6] = [
+        (ProcessPriority::Low, 8, 400),
+        (ProcessPriority::Normal, 2, 150),
+        (ProcessPriority::High, 6, 300),
+        (ProcessPriority::Normal, 4, 50),
+        (ProcessPriority::Critical, 1, 250),
+        (ProcessPriority::Low, 3, 100),
+    ];
+    let total: u64 = WORKLOAD.iter().map(|&(_, _, burst)| burst).sum();
+
+    println!("   Benchmarking scheduling algorithms ({} ticks of work)...", total);
+    println!("    {:<22} {:>9} {:>11} {:>9} {:>9}", "ALGORITHM", "AVG WAIT", "AVG TURN", "SWITCHES", "TIME us");
+    let live = crate::services::take_all();
+    let mut results = Vec::new();
+    for algorithm in SchedulingAlgorithm::ALL {
+        crate::services::reset_all();
+        let mut processes = BTreeMap::new();
+        let mut bursts = BTreeMap::new();
+        for (i, &(priority, pages, burst)) in WORKLOAD.iter().enumerate() {
+            match create_process(format!("sched_bench_{}", i), priority, 4096, 4096 * pages) {
+                Ok(pid) => {
+                    if let Some(pcb) = PROCESS_SERVICE.lock().get_process(pid) {
+                        processes.insert(pid, pcb.clone());
+                    }
+                    bursts.insert(pid, burst);
+                }
+                Err(e) => fail!("     Failed to create benchmark process: {:?}", e),
+            }
+        }
+
+        let mut scheduler = ProcessScheduler::new();
+        scheduler.set_algorithm(algorithm);
+        let start_time = crate::tsc::read();
+        let result = scheduler.run_workload(processes, &bursts, total * 2);
+        let elapsed = crate::tsc::elapsed_ns(start_time);
+
+        println!(
+            "    {:<22} {:>9.1} {:>11.1} {:>9} {:>9}",
+            format!("{:?}", algorithm), result.average_wait, result.average_turnaround, result.context_switches, elapsed / 1000
+        );
+        if result.completed != WORKLOAD.len() {
+            fail!("     {:?} finished {} of {} processes", algorithm, result.completed, WORKLOAD.len());
+        }
+        results.push(result);
+    }
+    crate::services::restore_all(live);
+    results
+}
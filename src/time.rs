@@ -0,0 +1,36 @@
+// Timer tick bookkeeping for EMOS Microkernel
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Rate used until `scheduler::init_pit` programs the PIT
+const DEFAULT_TIMER_HZ: u32 = 100;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static TIMER_HZ: AtomicU32 = AtomicU32::new(DEFAULT_TIMER_HZ);
+
+/// Count one timer interrupt; called from the timer handler
+pub fn tick() -> u64 {
+    TICKS.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Timer interrupts since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub fn set_timer_hz(hz: u32) {
+    TIMER_HZ.store(hz.max(1), Ordering::Relaxed);
+}
+
+pub fn timer_hz() -> u32 {
+    TIMER_HZ.load(Ordering::Relaxed)
+}
+
+/// Ticks needed to cover `ms` milliseconds at `hz`, rounded up
+pub fn ms_to_ticks_at(ms: u64, hz: u32) -> u64 {
+    (ms.saturating_mul(hz as u64) + 999) / 1000
+}
+
+/// Ticks needed to cover `ms` milliseconds at the current timer rate
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    ms_to_ticks_at(ms, timer_hz())
+}
@@ -0,0 +1,65 @@
+// Timestamp counter timing for EMOS Microkernel
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Assumed rate (a 1 GHz clock) until `calibrate` measures the real one
+const DEFAULT_CYCLES_PER_MS: u64 = 1_000_000;
+
+/// Timer ticks the calibration loop measures over
+const CALIBRATION_TICKS: u64 = 5;
+
+static CYCLES_PER_MS: AtomicU64 = AtomicU64::new(DEFAULT_CYCLES_PER_MS);
+
+/// Current value of the timestamp counter
+pub fn read() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Measure TSC cycles per millisecond against the PIT and remember the result.
+///
+/// Busy-waits for `CALIBRATION_TICKS` timer interrupts, so it needs the PIT programmed and
+/// interrupts enabled; otherwise it returns `None` and keeps the current estimate.
+pub fn calibrate() -> Option<u64> {
+    if !x86_64::instructions::interrupts::are_enabled() {
+        return None;
+    }
+    let hz = crate::time::timer_hz() as u64;
+
+    // Start on a tick edge so the measured span is whole ticks
+    let edge = crate::time::ticks();
+    while crate::time::ticks() == edge {
+        core::hint::spin_loop();
+    }
+    let start_tick = crate::time::ticks();
+    let start = read();
+    while crate::time::ticks() < start_tick + CALIBRATION_TICKS {
+        core::hint::spin_loop();
+    }
+    let cycles = read().wrapping_sub(start);
+
+    let per_ms = (cycles * hz / (CALIBRATION_TICKS * 1000)).max(1);
+    CYCLES_PER_MS.store(per_ms, Ordering::Relaxed);
+    Some(per_ms)
+}
+
+pub fn cycles_per_ms() -> u64 {
+    CYCLES_PER_MS.load(Ordering::Relaxed)
+}
+
+/// Convert a cycle count into nanoseconds at the calibrated rate
+pub fn cycles_to_ns(cycles: u64) -> u64 {
+    (cycles as u128 * 1_000_000 / cycles_per_ms() as u128) as u64
+}
+
+/// Nanoseconds elapsed since `start`, a value returned by `read`
+pub fn elapsed_ns(start: u64) -> u64 {
+    cycles_to_ns(read().wrapping_sub(start))
+}
+
+#[test_case]
+fn test_tsc_increases_between_reads() {
+    let first = read();
+    let second = read();
+    assert!(second > first);
+    assert_eq!(cycles_to_ns(0), 0);
+    assert_eq!(cycles_to_ns(cycles_per_ms()), 1_000_000);
+}
@@ -45,12 +45,141 @@
 //     }
 // }
 use core::arch::asm;
+use x86_64::VirtAddr;
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags as Flags, Size4KiB};
+use x86_64::structures::paging::FrameAllocator;
+use crate::process::pcb::ProcessId;
 
 pub static SHELL_BIN: &[u8] = include_bytes!("bin/emos_shell.bin");
 
 pub const USER_SHELL_BASE: u64 = 0x0040_0000;
+/// Stack range of the kernel process, which runs the shell
 pub const USER_STACK_BOTTOM: u64 = 0x0070_0000;
 pub const USER_STACK_TOP: u64 = 0x0080_0000;
+/// Size of every process's stack range
+pub const USER_STACK_SIZE: u64 = USER_STACK_TOP - USER_STACK_BOTTOM;
+
+/// Stacks of the other processes sit one `USER_STACK_SLOT` apart above this address,
+/// clear of the heap windows and file mappings
+pub const USER_STACKS_BASE: u64 = 0x0000_6000_0000_0000;
+/// Room per process: its stack, the guard area below it and an unmapped gap
+pub const USER_STACK_SLOT: u64 = 2 * 1024 * 1024;
+
+/// How far below a process's stack bottom a fault may land and still grow the stack.
+pub const STACK_GROWTH_WINDOW: u64 = 64 * 1024;
+/// Unmapped guard area below each stack range; faults here are stack overflows.
+pub const STACK_GUARD_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFault {
+    /// Address is not part of the current process's stack area at all
+    NotStackAddress,
+    /// Address is in the stack area but beyond what may be grown
+    LimitExceeded,
+    /// Out of frames or the page could not be mapped
+    MapFailed,
+}
+
+/// Top of `pid`'s stack range. No two processes' ranges or guard areas overlap.
+pub fn user_stack_top(pid: ProcessId) -> u64 {
+    if pid == 0 {
+        USER_STACK_TOP
+    } else {
+        USER_STACKS_BASE + pid * USER_STACK_SLOT
+    }
+}
+
+/// The process whose stack range or guard area holds `addr`, with the top of that range
+fn stack_slot_of(addr: u64) -> Option<(ProcessId, u64)> {
+    let pid = if addr < USER_STACK_TOP {
+        0
+    } else if addr >= USER_STACKS_BASE {
+        (addr - USER_STACKS_BASE) / USER_STACK_SLOT + 1
+    } else {
+        return None;
+    };
+    if pid >= crate::process::pcb::PID_MAX {
+        return None;
+    }
+    let top = user_stack_top(pid);
+    (addr < top && addr >= top - USER_STACK_SIZE - STACK_GUARD_SIZE).then_some((pid, top))
+}
+
+/// Lowest user stack page the current process has faulted in.
+pub fn stack_mapped_bottom() -> u64 {
+    use crate::services::process_service::PROCESS_SERVICE;
+
+    let service = PROCESS_SERVICE.lock();
+    service.get_current_process()
+        .and_then(|pid| service.user_stack_bottom(pid))
+        .unwrap_or(USER_STACK_TOP)
+}
+
+/// Map only the top page of the current process's user stack; the rest is faulted in
+/// on demand.
+pub fn map_initial_user_stack() -> Result<(), StackFault> {
+    let pid = crate::services::process_service::get_current_process().unwrap_or(0);
+    let top = user_stack_top(pid);
+    if stack_mapped_bottom() < top {
+        return Ok(());
+    }
+    grow_user_stack(VirtAddr::new(top - 1)).map(|_| ())
+}
+
+/// Grow the current process's user stack to cover `addr`.
+///
+/// Called from the page fault handler. Maps just the faulting page, as long as it is in
+/// the process's own stack range and within `STACK_GROWTH_WINDOW` of its stack bottom
+/// or above it, charges it to the process, and returns the number of pages mapped: 0 if
+/// the page was already mapped.
+///
+/// Stack faults come from user code or from kernel code touching a stack directly,
+/// never from inside a process service critical section (`copy_to_user` checks the
+/// range first), so the service lock is simply taken.
+pub fn grow_user_stack(addr: VirtAddr) -> Result<usize, StackFault> {
+    use crate::services::process_service::PROCESS_SERVICE;
+
+    let addr = addr.as_u64();
+    let (owner, top) = stack_slot_of(addr).ok_or(StackFault::NotStackAddress)?;
+
+    let mut service = PROCESS_SERVICE.lock();
+    if service.get_current_process() != Some(owner) {
+        return Err(StackFault::NotStackAddress);
+    }
+    if addr < top - USER_STACK_SIZE {
+        return Err(StackFault::LimitExceeded); // In the guard area
+    }
+    let bottom = service.user_stack_bottom(owner).ok_or(StackFault::MapFailed)?;
+    if addr < bottom && bottom - addr > STACK_GROWTH_WINDOW {
+        return Err(StackFault::LimitExceeded);
+    }
+
+    let stack_flags = Flags::PRESENT | Flags::USER_ACCESSIBLE | Flags::WRITABLE | Flags::NO_EXECUTE;
+    let user_table_flags = Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE;
+    let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(addr));
+
+    let mapped = crate::memory::with_kernel_paging(|mapper, frame_allocator| {
+        if mapper.translate_page(page).is_ok() {
+            return Ok(0);
+        }
+        let frame = frame_allocator.allocate_frame().ok_or(StackFault::MapFailed)?;
+        unsafe {
+            mapper
+                .map_to_with_table_flags(page, frame, stack_flags, user_table_flags, frame_allocator)
+                .map_err(|_| StackFault::MapFailed)?
+                .flush();
+        }
+        Ok(1)
+    });
+    let pages = match mapped {
+        Some(Ok(pages)) => pages,
+        Some(Err(e)) => return Err(e),
+        None => return Err(StackFault::MapFailed),
+    };
+
+    service.record_stack_growth(owner, page.start_address().as_u64(), pages);
+    Ok(pages)
+}
 
 /// Copy the embedded shell binary to the mapped userspace region.
 pub fn load_shell_to_memory() {
@@ -102,3 +231,77 @@ pub fn enter_userspace(entry: u64, user_stack_top: u64) -> ! {
     }
 }
 
+#[test_case]
+fn test_user_stack_grows_on_demand() {
+    map_initial_user_stack().expect("map top stack page");
+
+    // Each write lands one page below the mapped bottom; the page fault handler
+    // maps it and the write is retried.
+    for _ in 0..4 {
+        let bottom = stack_mapped_bottom();
+        let addr = (bottom - 4096 + 8) as *mut u64;
+        unsafe {
+            addr.write_volatile(0xdead_beef);
+            assert_eq!(addr.read_volatile(), 0xdead_beef);
+        }
+        assert_eq!(stack_mapped_bottom(), bottom - 4096);
+    }
+
+    // A fault further down maps that page alone, leaving the gap to be faulted in later
+    let bottom = stack_mapped_bottom();
+    let addr = (bottom - 3 * 4096) as *mut u64;
+    unsafe { addr.write_volatile(0xfeed) };
+    assert_eq!(stack_mapped_bottom(), bottom - 3 * 4096);
+    assert_eq!(crate::memory::is_mapped(VirtAddr::new(bottom - 2 * 4096)), Some(false));
+    assert_eq!(crate::memory::is_mapped(VirtAddr::new(bottom - 4096)), Some(false));
+
+    // Far below the window is refused, and the guard area is never mapped
+    let too_far = VirtAddr::new(stack_mapped_bottom() - STACK_GROWTH_WINDOW - 4096);
+    assert_eq!(grow_user_stack(too_far), Err(StackFault::LimitExceeded));
+    assert_eq!(grow_user_stack(VirtAddr::new(USER_STACK_BOTTOM - 8)), Err(StackFault::LimitExceeded));
+}
+
+#[test_case]
+fn test_stack_bottom_is_tracked_per_process() {
+    use alloc::string::String;
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process, wait_child, PROCESS_SERVICE};
+
+    map_initial_user_stack().expect("map top stack page");
+    let pid = create_process(String::from("stack_view"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    assert!(stack_mapped_bottom() < USER_STACK_TOP);
+    assert_eq!(PROCESS_SERVICE.lock().user_stack_bottom(pid), Some(user_stack_top(pid)));
+    terminate_process(pid, 0).unwrap();
+    let _ = wait_child(0, Some(pid));
+}
+
+#[test_case]
+fn test_each_process_grows_only_its_own_stack() {
+    use alloc::string::String;
+    use crate::process::pcb::ProcessPriority;
+    use crate::services::process_service::{create_process, terminate_process, wait_child, yield_to};
+
+    let a = create_process(String::from("stack_a"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let b = create_process(String::from("stack_b"), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let (a_top, b_top) = (user_stack_top(a), user_stack_top(b));
+    assert!(a_top.abs_diff(b_top) >= USER_STACK_SIZE + STACK_GUARD_SIZE);
+    assert!(a_top - USER_STACK_SIZE - STACK_GUARD_SIZE >= USER_STACK_TOP);
+
+    yield_to(a).unwrap();
+    let own = grow_user_stack(VirtAddr::new(a_top - 8));
+    let other = grow_user_stack(VirtAddr::new(b_top - 8));
+    let guard = grow_user_stack(VirtAddr::new(a_top - USER_STACK_SIZE - 8));
+    let bottom = stack_mapped_bottom();
+    yield_to(0).unwrap();
+    assert_eq!(own, Ok(1));
+    assert_eq!(other, Err(StackFault::NotStackAddress));
+    assert_eq!(guard, Err(StackFault::LimitExceeded));
+    assert_eq!(bottom, a_top - 4096);
+    assert_eq!(crate::memory::is_mapped(VirtAddr::new(b_top - 8)), Some(false));
+
+    for pid in [a, b] {
+        terminate_process(pid, 0).unwrap();
+        let _ = wait_child(0, Some(pid));
+    }
+    crate::memory::unmap_range(VirtAddr::new(a_top - 4096), VirtAddr::new(a_top));
+}
@@ -1,4 +1,5 @@
 use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use volatile::Volatile;
@@ -11,9 +12,16 @@ lazy_static! {
         column_position: 0,
         color_code: ColorCode::new(Color::Yellow, Color::Black),
         buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+        // Only `WRITER` ever refers to the storage
+        scrollback: Some(unsafe { &mut *core::ptr::addr_of_mut!(SCROLLBACK) }),
     });
 }
 
+/// Lines scrolled off the top of the screen that `scroll_up` can bring back
+const SCROLLBACK_LINES: usize = 100;
+
+static mut SCROLLBACK: Scrollback = Scrollback::new();
+
 /// The standard color palette in VGA text mode.
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -44,7 +52,7 @@ struct ColorCode(u8);
 
 impl ColorCode {
     /// Create a new `ColorCode` with the given foreground and background colors.
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
 }
@@ -68,23 +76,86 @@ struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
+/// Lines that scrolled off the top of the screen, and a copy of the live screen to
+/// return to after scrolling back through them.
+struct Scrollback {
+    live: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    history: [[ScreenChar; BUFFER_WIDTH]; SCROLLBACK_LINES], // Ring of retired lines
+    history_start: usize,
+    history_len: usize,
+    scroll_offset: usize, // Lines scrolled back from the live view; 0 = live
+}
+
+impl Scrollback {
+    const fn new() -> Self {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: ColorCode::new(Color::Yellow, Color::Black),
+        };
+        Scrollback {
+            live: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            history: [[blank; BUFFER_WIDTH]; SCROLLBACK_LINES],
+            history_start: 0,
+            history_len: 0,
+            scroll_offset: 0,
+        }
+    }
+
+    /// Move the top live line into the history ring and shift the live screen up
+    fn retire_top_line(&mut self) {
+        let top = self.live[0];
+        if self.history_len < SCROLLBACK_LINES {
+            self.history[(self.history_start + self.history_len) % SCROLLBACK_LINES] = top;
+            self.history_len += 1;
+        } else {
+            self.history[self.history_start] = top;
+            self.history_start = (self.history_start + 1) % SCROLLBACK_LINES;
+        }
+        // Keep a scrolled-back view pinned to the same lines while they are kept
+        if self.scroll_offset > 0 {
+            self.scroll_offset = (self.scroll_offset + 1).min(self.history_len);
+        }
+        self.live.copy_within(1.., 0);
+    }
+
+    /// Line `row` of the view `scroll_offset` lines back
+    fn view_line(&self, row: usize) -> &[ScreenChar; BUFFER_WIDTH] {
+        let line = self.history_len - self.scroll_offset + row;
+        if line < self.history_len {
+            &self.history[(self.history_start + line) % SCROLLBACK_LINES]
+        } else {
+            &self.live[line - self.history_len]
+        }
+    }
+}
+
 /// A writer type that allows writing ASCII bytes and strings to an underlying `Buffer`.
 ///
 /// Wraps lines at `BUFFER_WIDTH`. Supports newline characters and implements the
-/// `core::fmt::Write` trait.
+/// `core::fmt::Write` trait. With a scrollback, lines scrolled off the top are kept and
+/// output written while scrolled back goes only to the live copy until `scroll_to_bottom`.
 pub struct Writer {
     column_position: usize,
     color_code: ColorCode,
     buffer: &'static mut Buffer,
+    scrollback: Option<&'static mut Scrollback>,
 }
 
 impl Writer {
     /// Writes an ASCII byte to the buffer.
     ///
-    /// Wraps lines at `BUFFER_WIDTH`. Supports the `\n` newline character.
+    /// Wraps lines at `BUFFER_WIDTH`. Supports the `\n` newline and `\x08` backspace characters.
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
             b'\n' => self.new_line(),
+            // Backspace erases the previous character on the current row
+            b'\x08' => {
+                if self.column_position > 0 {
+                    self.column_position -= 1;
+                    let blank = ScreenChar { ascii_character: b' ', color_code: self.color_code };
+                    self.put_char(BUFFER_HEIGHT - 1, self.column_position, blank);
+                }
+            }
             byte => {
                 if self.column_position >= BUFFER_WIDTH {
                     self.new_line();
@@ -94,7 +165,7 @@ impl Writer {
                 let col = self.column_position;
 
                 let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
+                self.put_char(row, col, ScreenChar {
                     ascii_character: byte,
                     color_code,
                 });
@@ -111,26 +182,90 @@ impl Writer {
     fn write_string(&mut self, s: &str) {
         for byte in s.bytes() {
             match byte {
-                // printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
+                // printable ASCII byte, newline or backspace
+                0x20..=0x7e | b'\n' | b'\x08' => self.write_byte(byte),
                 // not part of printable ASCII range
                 _ => self.write_byte(0xfe),
             }
         }
     }
 
-    /// Shifts all lines one line up and clears the last row.
+    /// Shifts all lines one line up, keeping the top one in the scrollback, and clears
+    /// the last row.
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
+        if let Some(scrollback) = self.scrollback.as_deref_mut() {
+            scrollback.retire_top_line();
+        }
+        if !self.scrolled_back() {
+            for row in 1..BUFFER_HEIGHT {
+                for col in 0..BUFFER_WIDTH {
+                    let character = self.buffer.chars[row][col].read();
+                    self.buffer.chars[row - 1][col].write(character);
+                }
             }
         }
         self.clear_row(BUFFER_HEIGHT - 1);
         self.column_position = 0;
     }
 
+    /// Show the view up to `lines` further back in the scrollback
+    pub fn scroll_up(&mut self, lines: usize) {
+        if let Some(scrollback) = self.scrollback.as_deref_mut() {
+            scrollback.scroll_offset = (scrollback.scroll_offset + lines).min(scrollback.history_len);
+        }
+        self.repaint();
+    }
+
+    /// Show the view up to `lines` closer to live output
+    pub fn scroll_down(&mut self, lines: usize) {
+        if let Some(scrollback) = self.scrollback.as_deref_mut() {
+            scrollback.scroll_offset = scrollback.scroll_offset.saturating_sub(lines);
+        }
+        self.repaint();
+    }
+
+    /// Return to live output, showing whatever was written while scrolled back
+    pub fn scroll_to_bottom(&mut self) {
+        if let Some(scrollback) = self.scrollback.as_deref_mut() {
+            scrollback.scroll_offset = 0;
+        }
+        self.repaint();
+    }
+
+    fn scrolled_back(&self) -> bool {
+        self.scrollback.as_deref().is_some_and(|scrollback| scrollback.scroll_offset > 0)
+    }
+
+    /// Write to the live screen, and to the display unless scrolled back
+    fn put_char(&mut self, row: usize, col: usize, character: ScreenChar) {
+        if let Some(scrollback) = self.scrollback.as_deref_mut() {
+            scrollback.live[row][col] = character;
+        }
+        if !self.scrolled_back() {
+            self.buffer.chars[row][col].write(character);
+        }
+    }
+
+    /// Redraw the display from the scrollback and the live screen
+    fn repaint(&mut self) {
+        if let Some(scrollback) = self.scrollback.as_deref() {
+            for row in 0..BUFFER_HEIGHT {
+                let line = scrollback.view_line(row);
+                for col in 0..BUFFER_WIDTH {
+                    self.buffer.chars[row][col].write(line[col]);
+                }
+            }
+        }
+    }
+
+    /// Blanks every row and returns to the start of the last one.
+    fn clear(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.column_position = 0;
+    }
+
     /// Clears a row by overwriting it with blank characters.
     fn clear_row(&mut self, row: usize) {
         let blank = ScreenChar {
@@ -138,7 +273,7 @@ impl Writer {
             color_code: self.color_code,
         };
         for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+            self.put_char(row, col, blank);
         }
     }
 }
@@ -163,18 +298,200 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/// Output printed from interrupt context while `WRITER` was held elsewhere
+struct PendingOutput {
+    bytes: [u8; PENDING_CAPACITY],
+    len: usize,
+}
+
+const PENDING_CAPACITY: usize = 512;
+
+impl fmt::Write for PendingOutput {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = PENDING_CAPACITY - self.len;
+        let taken = s.len().min(room);
+        self.bytes[self.len..self.len + taken].copy_from_slice(&s.as_bytes()[..taken]);
+        self.len += taken;
+        DROPPED_BYTES.fetch_add(s.len() - taken, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+static PENDING: Mutex<PendingOutput> = Mutex::new(PendingOutput { bytes: [0; PENDING_CAPACITY], len: 0 });
+
+/// Nesting depth of interrupt handlers currently running
+static INTERRUPT_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes printed from interrupt context that could not be buffered
+static DROPPED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Marks the current code as running inside an interrupt handler until dropped
+pub struct InterruptContext(());
+
+impl Drop for InterruptContext {
+    fn drop(&mut self) {
+        INTERRUPT_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Enter interrupt context; `print!` will no longer block on the writer lock
+pub fn enter_interrupt() -> InterruptContext {
+    INTERRUPT_DEPTH.fetch_add(1, Ordering::SeqCst);
+    InterruptContext(())
+}
+
+pub fn in_interrupt_context() -> bool {
+    INTERRUPT_DEPTH.load(Ordering::SeqCst) > 0
+}
+
+/// Number of interrupt handlers the current code is nested in
+pub fn interrupt_depth() -> usize {
+    INTERRUPT_DEPTH.load(Ordering::SeqCst)
+}
+
+pub fn dropped_bytes() -> usize {
+    DROPPED_BYTES.load(Ordering::Relaxed)
+}
+
+/// Write out anything buffered by interrupt handlers, oldest first
+fn flush_pending(writer: &mut Writer) {
+    if let Some(mut pending) = PENDING.try_lock() {
+        let len = pending.len;
+        for &byte in &pending.bytes[..len] {
+            writer.write_byte(byte);
+        }
+        pending.len = 0;
+    }
+}
+
 /// Prints the given formatted string to the VGA text buffer
 /// through the global `WRITER` instance.
+///
+/// Lock order is `WRITER` then `PENDING`. Normal code takes `WRITER` with interrupts
+/// disabled, so an IRQ cannot fire while it is held on this core. Interrupt handlers
+/// (and exceptions raised while printing) only ever try-lock: if `WRITER` is busy the
+/// text goes to `PENDING` and is flushed by the next print, and if that is busy or full
+/// the text is dropped and counted in `dropped_bytes`.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
     use x86_64::instructions::interrupts;
 
+    if crate::panicking() {
+        force_print(args);
+        return;
+    }
+    crate::serial::mirror(args);
+    if in_interrupt_context() {
+        if let Some(mut writer) = WRITER.try_lock() {
+            flush_pending(&mut writer);
+            writer.write_fmt(args).unwrap();
+        } else if let Some(mut pending) = PENDING.try_lock() {
+            pending.write_fmt(args).unwrap();
+        } else {
+            DROPPED_BYTES.fetch_add(1, Ordering::Relaxed);
+        }
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        flush_pending(&mut writer);
+        writer.write_fmt(args).unwrap();
+    });
+}
+
+/// Column `force_print` left off at; `usize::MAX` until its first call
+static FORCED_COLUMN: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Print without taking `WRITER`, for the panic path: the panicking code may hold the
+/// lock, and waiting for it would hide the panic. The first call starts a new line so the
+/// output stands apart from whatever `WRITER` was in the middle of.
+pub fn force_print(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    let column = FORCED_COLUMN.load(Ordering::SeqCst);
+    let mut writer = Writer {
+        column_position: column.min(BUFFER_WIDTH),
+        color_code: ColorCode::new(Color::White, Color::Red),
+        // Aliases `WRITER`'s buffer; the holder of that lock is not coming back
+        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+        scrollback: None,
+    };
+    if column == usize::MAX {
+        writer.new_line();
+    }
+    let _ = writer.write_fmt(args);
+    FORCED_COLUMN.store(writer.column_position, Ordering::SeqCst);
+}
+
+/// Whether `text` appears on screen, reading the buffer without taking `WRITER`.
+/// Rows are read as one run of text, so a match may wrap from one row to the next.
+pub fn screen_contains(text: &str) -> bool {
+    let buffer = unsafe { &*(0xb8000 as *const Buffer) };
+    let mut screen = [0u8; BUFFER_WIDTH * BUFFER_HEIGHT];
+    for (row, chars) in buffer.chars.iter().enumerate() {
+        for (col, cell) in chars.iter().enumerate() {
+            screen[row * BUFFER_WIDTH + col] = cell.read().ascii_character;
+        }
+    }
+    let needle = text.as_bytes();
+    needle.is_empty() || screen.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Write raw bytes to the screen; bytes outside printable ASCII show as a block
+pub fn write_bytes(bytes: &[u8]) {
+    use x86_64::instructions::interrupts;
+
     interrupts::without_interrupts(|| {
-        WRITER.lock().write_fmt(args).unwrap();
+        let mut writer = WRITER.lock();
+        for &byte in bytes {
+            match byte {
+                0x20..=0x7e | b'\n' => writer.write_byte(byte),
+                _ => writer.write_byte(0xfe),
+            }
+        }
     });
 }
 
+/// Blank the whole screen
+pub fn clear_screen() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| WRITER.lock().clear());
+}
+
+/// The character currently shown at `row`, `col`
+pub fn char_at(row: usize, col: usize) -> u8 {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| WRITER.lock().buffer.chars[row][col].read().ascii_character)
+}
+
+/// Scroll the screen back through up to `lines` lines of earlier output
+pub fn scroll_up(lines: usize) {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| WRITER.lock().scroll_up(lines));
+}
+
+/// Scroll the screen toward live output by up to `lines` lines
+pub fn scroll_down(lines: usize) {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| WRITER.lock().scroll_down(lines));
+}
+
+/// Return the screen to live output
+pub fn scroll_to_bottom() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| WRITER.lock().scroll_to_bottom());
+}
+
+/// Row new output is written to
+pub const LAST_ROW: usize = BUFFER_HEIGHT - 1;
+
 #[test_case]
 fn test_println_simple() {
     println!("test_println_simple output");
@@ -201,4 +518,58 @@ fn test_println_output() {
             assert_eq!(char::from(screen_char.ascii_character), c);
         }
     });
-}
\ No newline at end of file
+}
+#[test_case]
+fn test_println_from_interrupt_context_does_not_block() {
+    use x86_64::instructions::interrupts;
+
+    let s = "printed while the writer was held";
+    interrupts::without_interrupts(|| {
+        // Simulate an IRQ arriving while normal code holds the writer
+        let writer = WRITER.lock();
+        {
+            let _irq = enter_interrupt();
+            print!("{}", s);
+        }
+        drop(writer);
+        assert!(!in_interrupt_context());
+    });
+
+    // The next normal print flushes the buffered text ahead of its own
+    println!();
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.lock();
+        for (i, c) in s.chars().enumerate() {
+            let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            assert_eq!(char::from(screen_char.ascii_character), c);
+        }
+    });
+}
+
+#[test_case]
+fn test_printed_lines_scrolled_off_are_kept_for_scroll_up() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    let s = "scrolled off the top by later output";
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writeln!(writer, "\n{}", s).expect("writeln failed");
+        for _ in 0..BUFFER_HEIGHT {
+            writeln!(writer).expect("writeln failed");
+        }
+        writer.scroll_up(BUFFER_HEIGHT);
+        for (i, c) in s.chars().enumerate() {
+            let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            assert_eq!(char::from(screen_char.ascii_character), c);
+        }
+
+        // Output while scrolled back leaves the view alone until returning to live
+        writeln!(writer, "live").expect("writeln failed");
+        let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][0].read();
+        assert_eq!(char::from(screen_char.ascii_character), 's');
+        writer.scroll_to_bottom();
+        let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][0].read();
+        assert_eq!(char::from(screen_char.ascii_character), 'l');
+    });
+}
@@ -7,7 +7,7 @@
 extern crate alloc;
 
 use alloc::{boxed::Box, vec::Vec};
-use emos::allocator::HEAP_SIZE;
+use emos::allocator::{HEAP_SIZE, heap_stats};
 use bootloader::{BootInfo, entry_point};
 use core::panic::PanicInfo;
 
@@ -64,6 +64,17 @@ fn many_boxes_long_lived() {
     assert_eq!(*long_lived, 1); // new
 }
 
+#[test_case]
+fn heap_stats_track_large_vec() {
+    let (used_before, _, total) = heap_stats();
+    let vec: Vec<u8> = Vec::with_capacity(16 * 1024);
+    let (used_after, free_after, _) = heap_stats();
+    assert!(used_after >= used_before + vec.capacity());
+    assert_eq!(used_after + free_after, total);
+    drop(vec);
+    assert_eq!(heap_stats().0, used_before);
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     emos::test_panic_handler(info)
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+use emos::{QemuExitCode, exit_qemu, serial_print, serial_println};
+use core::panic::PanicInfo;
+
+const MESSAGE: &str = "panicked holding the screen lock";
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    serial_print!("panic_in_lock::panic_in_lock...\t");
+
+    // A plain println! here would wait forever on this guard
+    let _writer = emos::vga_buffer::WRITER.lock();
+    panic!("{}", MESSAGE);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // What the kernel's handler does, minus the halt
+    if !emos::report_panic(info) {
+        serial_println!("[failed]\nPanicked while reporting a panic");
+        exit_qemu(QemuExitCode::Failed);
+        loop {}
+    }
+
+    if emos::vga_buffer::screen_contains(MESSAGE) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\nPanic message is not on screen");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+use emos::{serial_print, serial_println};
+use core::panic::PanicInfo;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    serial_print!("shutdown::shutdown_exits_qemu...\t");
+    // Reaching the success exit code is the assertion: shutdown must end the VM
+    serial_println!("[ok]");
+    emos::power::shutdown();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    emos::test_panic_handler(info)
+}
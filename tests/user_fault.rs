@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::string::String;
+use bootloader::{BootInfo, entry_point};
+use core::panic::PanicInfo;
+use emos::process::pcb::{ProcessId, ProcessPriority, ProcessState};
+use emos::services::process_service::{create_process, get_process_stats, yield_to};
+use emos::task::Task;
+use emos::userspace::{user_stack_top, USER_SHELL_BASE, USER_STACK_SIZE};
+use emos::{QemuExitCode, exit_qemu, serial_print, serial_println};
+
+/// mov rax, <stack bottom - 8>; mov byte [rax], 1; jmp $
+/// Writes into the stack guard area, as a runaway stack would. `run_stage` fills in the
+/// address for the process running it.
+const OVERFLOW_STACK: [u8; 15] = [
+    0x48, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc6, 0x00, 0x01, 0xeb, 0xfe,
+];
+/// Where the address operand of `OVERFLOW_STACK` starts
+const OVERFLOW_ADDR_OFFSET: usize = 2;
+
+/// int3; jmp $
+/// The breakpoint gate is kernel-only, so this raises #GP, which has no handler and
+/// escalates to a double fault taken from ring 3.
+const DOUBLE_FAULT: [u8; 3] = [0xcc, 0xeb, 0xfe];
+
+/// Each stage runs its code in a fresh user process, which the fault must kill
+const STAGES: [(&str, &[u8]); 2] = [
+    ("user_stack_overflow_terminates_process", &OVERFLOW_STACK),
+    ("user_double_fault_terminates_process", &DOUBLE_FAULT),
+];
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use emos::allocator;
+    use emos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    emos::init();
+    x86_64::instructions::interrupts::disable();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install_kernel_paging(mapper, frame_allocator);
+    emos::services::process_service::init_process_service();
+    map_user_code_page();
+
+    run_stage(0)
+}
+
+/// Start stage `stage` in ring 3; a task checks the outcome once the kernel is idle again
+fn run_stage(stage: usize) -> ! {
+    let (name, code) = STAGES[stage];
+    serial_print!("user_fault::{}...\t", name);
+    let pid = create_process(String::from(name), ProcessPriority::Normal, 4096, 4096).unwrap();
+    let stack_top = user_stack_top(pid);
+    unsafe {
+        core::ptr::copy_nonoverlapping(code.as_ptr(), USER_SHELL_BASE as *mut u8, code.len());
+        if code == OVERFLOW_STACK {
+            let guard = (stack_top - USER_STACK_SIZE - 8).to_le_bytes();
+            let operand = (USER_SHELL_BASE as *mut u8).add(OVERFLOW_ADDR_OFFSET);
+            core::ptr::copy_nonoverlapping(guard.as_ptr(), operand, guard.len());
+        }
+    }
+
+    yield_to(pid).unwrap();
+    emos::task::executor::spawn(Task::new(async move { check_terminated(stage, pid) }));
+    emos::userspace::enter_userspace(USER_SHELL_BASE, stack_top);
+}
+
+/// Map a user-accessible, writable page at `USER_SHELL_BASE` for the stages' code
+fn map_user_code_page() {
+    use x86_64::VirtAddr;
+    use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags as Flags, Size4KiB};
+
+    let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(USER_SHELL_BASE));
+    let flags = Flags::PRESENT | Flags::USER_ACCESSIBLE | Flags::WRITABLE;
+    emos::memory::with_kernel_paging(|mapper, frame_allocator| {
+        let frame = frame_allocator.allocate_frame().expect("no frame for user code");
+        unsafe {
+            mapper
+                .map_to_with_table_flags(page, frame, flags, flags, frame_allocator)
+                .expect("map user code page")
+                .flush();
+        }
+    })
+    .expect("kernel paging not installed");
+}
+
+fn check_terminated(stage: usize, pid: ProcessId) {
+    match get_process_stats(pid) {
+        Some(stats) if stats.state == ProcessState::Zombie => {
+            serial_println!("[ok]");
+            if stage + 1 < STAGES.len() {
+                run_stage(stage + 1);
+            }
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!("[failed]\nProcess after fault: {:?}", other.map(|stats| stats.state));
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    emos::test_panic_handler(info)
+}